@@ -4,7 +4,7 @@ import { BayesianCalculator } from '../services/bayesianCalculator'
 import { AnalysisOrchestrator } from '../services/analysisOrchestrator'
 import { StreamingEntityBuffer } from '../services/entityBuffer'
 import type { HAConnection, HAEntity } from '../types/homeAssistant'
-import type { TimePeriod, EntityProbability, BayesianSensorConfig } from '../types/bayesian'
+import type { TimePeriod, EntityProbability, BayesianSensorConfig, NumericStateStats } from '../types/bayesian'
 
 export const useBayesianAnalysis = () => {
   const haConnection = ref<HAConnection | null>(null)
@@ -160,6 +160,13 @@ export const useBayesianAnalysis = () => {
     }
   }
   
+  // Chunk data of a numeric entity for the threshold editor, loaded on demand
+  const loadNumericStats = async (entityId: string): Promise<NumericStateStats | null> => {
+    const history = cachedHistoricalData.value.get(entityId)
+    if (!history || history.length === 0) return null
+    return orchestrator.getNumericStats(entityId, { [entityId]: history }, periods.value)
+  }
+  
   const updateGeneratedConfig = (config: BayesianSensorConfig) => {
     generatedConfig.value = config
   }
@@ -197,6 +204,7 @@ export const useBayesianAnalysis = () => {
     connectToHA,
     updatePeriods,
     analyzeEntities,
+    loadNumericStats,
     updateGeneratedConfig,
     resetAnalysis
   }
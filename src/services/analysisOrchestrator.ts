@@ -1,5 +1,5 @@
 import type { HAEntity, HAHistoryResponse } from '../types/homeAssistant'
-import type { TimePeriod, EntityProbability } from '../types/bayesian'
+import type { TimePeriod, EntityProbability, NumericStateStats } from '../types/bayesian'
 
 interface IHomeAssistantAPI {
   testConnection(): Promise<boolean>
@@ -173,6 +173,22 @@ export class AnalysisOrchestrator {
     }
   }
 
+  // Chunk data of a numeric entity, fetched when the user drills into it
+  async getNumericStats(
+    entityId: string,
+    history: HAHistoryResponse,
+    periods: TimePeriod[]
+  ): Promise<NumericStateStats | null> {
+    const serializedPeriods = periods.map(p => ({
+      ...p,
+      start: p.start.toISOString(),
+      end: p.end.toISOString()
+    }))
+
+    const stats = await this.workerPool.getNumericStats(entityId, history, serializedPeriods)
+    return stats ?? null
+  }
+
   private getScoredAndSortedEntityIds(entities: HAEntity[], selectedEntityIds?: string[]): string[] {
     const sortedIds = this.entityScorer.filterAndSortEntities(entities, selectedEntityIds)
     
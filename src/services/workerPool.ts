@@ -2,6 +2,7 @@ import AnalysisWorker from '../workers/analysisWorker?worker'
 
 interface WorkerTask {
   id: string
+  type: 'ANALYZE_ENTITY' | 'GET_NUMERIC_STATS'
   task: any
   resolve: (result: any) => void
   reject: (error: Error) => void
@@ -120,13 +121,22 @@ export class WorkerPool {
     this.activeTasks.set(task.id, task)
     
     worker.postMessage({
-      type: 'ANALYZE_ENTITY',
+      type: task.type,
       id: task.id,
       data: task.task
     })
   }
 
-  async analyzeEntity(entityHistory: any, periods: any): Promise<any> {
+  analyzeEntity(entityHistory: any, periods: any): Promise<any> {
+    return this.submit('ANALYZE_ENTITY', { entityHistory, periods })
+  }
+
+  // Chunk data of one numeric entity, analysing its history again if needed
+  getNumericStats(entityId: string, entityHistory: any, periods: any): Promise<any> {
+    return this.submit('GET_NUMERIC_STATS', { entityHistory, periods, entityId })
+  }
+
+  private async submit(type: WorkerTask['type'], task: any): Promise<any> {
     // Wait for at least one worker to be initialized
     if (this.availableWorkers.length === 0 && this.wasmInitPromises.size > 0) {
       await Promise.race(Array.from(this.wasmInitPromises.values()))
@@ -137,7 +147,8 @@ export class WorkerPool {
       
       const task = {
         id: taskId,
-        task: { entityHistory, periods },
+        type,
+        task,
         resolve,
         reject
       }
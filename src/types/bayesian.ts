@@ -4,28 +4,216 @@ export interface TimePeriod {
   end: Date
   isTruePeriod: boolean
   label?: string
+  /** Outcome of a multi-class target during the period, for calculatePerClass */
+  class?: string
+  confidence?: number
+}
+
+/** Periods to analyse: an explicit list, or derived from another entity's history */
+export type PeriodSource =
+  | { type: 'list'; periods: TimePeriod[] }
+  | { type: 'entity'; entityId: string; states: string[]; invert?: boolean; start?: string; end?: string }
+  | {
+      type: 'recurring'
+      timezone: string
+      weekdays?: number[]
+      startHour: number
+      endHour: number
+      days: number
+      end?: string
+    }
+
+export interface ClassDistribution {
+  histogram: number[]
+  mean: number
+  median: number
+  stdDev: number
+}
+
+export interface ClassDensity {
+  mean: number
+  stdDev: number
+  bandwidth?: number | null
 }
 
 export interface NumericStateStats {
   isNumeric: boolean
   min?: number
   max?: number
+  trueChunkCount?: number
+  falseChunkCount?: number
+  outlierChunks?: number
+  cumulative?: boolean
+  cycle?: number
+  auc?: number
+  distribution?: {
+    binEdges: number[]
+    givenTrue: ClassDistribution
+    givenFalse: ClassDistribution
+  }
+  density?: {
+    model: 'gaussian' | 'kde'
+    givenTrue: ClassDensity
+    givenFalse: ClassDensity
+    discrimination: number
+    meanAbsLogRatio: number
+  }
   trueChunks?: Array<{ value: number; duration: number }>
   falseChunks?: Array<{ value: number; duration: number }>
 }
 
+export interface TracePoint {
+  time: string
+  value?: number | null
+  state?: string | null
+  inTruePeriod?: boolean | null
+}
+
+export interface RocPoint {
+  threshold: number | null
+  truePositiveRate: number
+  falsePositiveRate: number
+}
+
+export interface RocCurve {
+  points: RocPoint[]
+  auc: number
+}
+
+export interface RowChange {
+  entityId: string
+  state: string
+  previousState?: string | null
+  probGivenTrue: number
+  probGivenFalse: number
+  probGivenTrueDelta: number
+  probGivenFalseDelta: number
+  discriminationDelta: number
+}
+
+export interface ResultsDiff {
+  changed: RowChange[]
+  addedRows: Array<{ entityId: string; state: string }>
+  removedRows: Array<{ entityId: string; state: string }>
+  newEntities: string[]
+  droppedEntities: string[]
+}
+
+export interface PeriodContribution {
+  periodId: string
+  isTruePeriod: boolean
+  confidence?: number | null
+  matchedDurationMs: number
+  totalDurationMs: number
+  matched: boolean
+}
+
+export interface ChunkDetail {
+  start: string
+  durationMs: number
+  value?: number | null
+  state?: string | null
+  matched: boolean
+}
+
+export interface PeriodChunks {
+  periodId: string
+  isTruePeriod: boolean
+  chunks: ChunkDetail[]
+}
+
 export interface EntityProbability {
   entityId: string
   state: string
   probGivenTrue: number
   probGivenFalse: number
   discriminationPower: number
+  score?: number
   trueOccurrences: number
   falseOccurrences: number
   totalTruePeriods: number
   totalFalsePeriods: number
   numericStats?: NumericStateStats
-  optimalThresholds?: { above?: number; below?: number }
+  optimalThresholds?: { above?: number; below?: number; hysteresis?: number }
+  thresholdSensitivity?: {
+    points: Array<{
+      shift: number
+      probGivenTrue: number
+      probGivenFalse: number
+      discrimination: number
+      score: number
+    }>
+    razorEdge: boolean
+  }
+  pooled?: {
+    group: string
+    probGivenTrue: number
+    probGivenFalse: number
+    deviationTrue: number
+    deviationFalse: number
+  }
+  groupedStates?: string[]
+  correlatedWith?: string[]
+  crossValidation?: {
+    folds: number
+    probGivenTrueVariance: number
+    probGivenFalseVariance: number
+    minDiscrimination: number
+    overfit: boolean
+  }
+  stability?: {
+    resamples: number
+    present: number
+    discriminationMean: number
+    discriminationStdDev: number
+    aboveStdDev?: number | null
+    belowStdDev?: number | null
+  }
+  probGivenTrueInterval?: { lower: number; upper: number }
+  probGivenFalseInterval?: { lower: number; upper: number }
+  durationShares?: { givenTrue: number; givenFalse: number }
+  occurrenceShares?: { givenTrue: number; givenFalse: number }
+  absence?: { probGivenTrue: number; probGivenFalse: number; preferred: boolean }
+  periodBreakdown?: PeriodContribution[]
+  chunks?: PeriodChunks[]
+  explanation?: string
+  lowCoverage?: boolean
+  coverage?: number
+  lowConfidence?: boolean
+  possibleLeakage?: boolean
+  unitOfMeasurement?: string
+  deviceClass?: string
+}
+
+export interface EntityCoverage {
+  entityId: string
+  periods: Array<{ periodId: string; lengthMs: number; coveredMs: number; fraction: number }>
+  fraction: number
+  low: boolean
+}
+
+export interface ClassResults {
+  class: string
+  periods: number
+  results: EntityProbability[]
+  warnings: Array<{ entityId: string | null; kind: string; message: string }>
+}
+
+export interface InputIssue {
+  severity: 'error' | 'warning'
+  kind:
+    | 'invalidTimestamp'
+    | 'reversedPeriod'
+    | 'emptyPeriod'
+    | 'noTruePeriods'
+    | 'noFalsePeriods'
+    | 'overlappingPeriods'
+    | 'emptyHistory'
+    | 'emptyState'
+    | 'periodWithoutHistory'
+  entityId: string | null
+  periodId: string | null
+  message: string
 }
 
 export interface BayesianObservation {
@@ -1,5 +1,5 @@
-import type { EntityProbability, TimePeriod } from '../types/bayesian'
-import type { HAHistoryResponse } from '../types/homeAssistant'
+import type { ClassResults, EntityCoverage, EntityProbability, InputIssue, NumericStateStats, ResultsDiff, RocCurve, TimePeriod, TracePoint } from '../types/bayesian'
+import type { HAHistoryEntry, HAHistoryResponse } from '../types/homeAssistant'
 // Import the generated WASM types
 import type { 
   HAHistoryEntry as WasmHAHistoryEntry,
@@ -19,6 +19,29 @@ export async function initWasm(): Promise<void> {
   BayesianCalculator = wasmModule.BayesianCalculator
 }
 
+// The WASM module expects camelCase for HAHistoryEntry fields
+function toWasmEntries(entries: HAHistoryEntry[]): WasmHAHistoryEntry[] {
+  return entries.map(entry => ({
+    state: entry.state,
+    lastChanged: entry.last_changed, // Convert snake_case to camelCase
+    lastUpdated: entry.last_updated, // Convert snake_case to camelCase
+    attributes: entry.attributes as any
+  }))
+}
+
+// Convert periods to WASM format (Date to epoch milliseconds)
+function toWasmPeriods(periods: TimePeriod[]): WasmTimePeriod[] {
+  return periods.map(p => ({
+    id: p.id,
+    start: p.start instanceof Date ? p.start.getTime() : p.start,
+    end: p.end instanceof Date ? p.end.getTime() : p.end,
+    isTruePeriod: p.isTruePeriod,
+    label: p.label || null,
+    class: p.class || null,
+    confidence: p.confidence ?? null
+  }))
+}
+
 export class WasmBayesianCalculator {
   private calculator: any
 
@@ -29,38 +52,35 @@ export class WasmBayesianCalculator {
     this.calculator = new BayesianCalculator()
   }
 
+  // Problems with the input, found before the heavy calculation runs
+  validateInput(history: HAHistoryResponse, periods: TimePeriod[]): InputIssue[] {
+    const wasmHistory: Record<string, WasmHAHistoryEntry[]> = {}
+    for (const [entityId, entries] of Object.entries(history)) {
+      wasmHistory[entityId] = toWasmEntries(entries)
+    }
+    return this.calculator.validate_input(wasmHistory, toWasmPeriods(periods)) as InputIssue[]
+  }
+
   calculateEntityProbabilities(
     history: HAHistoryResponse,
-    periods: TimePeriod[]
+    periods: TimePeriod[],
+    onProgress?: (entitiesDone: number, entitiesTotal: number, currentEntityId: string | null) => void
   ): EntityProbability[] {
     try {
-      // Convert TypeScript types to WASM expected format
-      // The WASM module expects camelCase for HAHistoryEntry fields
       const wasmHistory: Record<string, WasmHAHistoryEntry[]> = {}
       for (const [entityId, entries] of Object.entries(history)) {
-        wasmHistory[entityId] = entries.map(entry => ({
-          state: entry.state,
-          lastChanged: entry.last_changed, // Convert snake_case to camelCase
-          lastUpdated: entry.last_updated, // Convert snake_case to camelCase
-          attributes: entry.attributes as any
-        }))
+        wasmHistory[entityId] = toWasmEntries(entries)
       }
-
-      // Convert periods to WASM format (Date to ISO string)
-      const wasmPeriods: WasmTimePeriod[] = periods.map(p => ({
-        id: p.id,
-        start: p.start instanceof Date ? p.start.toISOString() : p.start,
-        end: p.end instanceof Date ? p.end.toISOString() : p.end,
-        isTruePeriod: p.isTruePeriod,
-        label: p.label || null
-      }))
+      const wasmPeriods = toWasmPeriods(periods)
 
       // Call WASM function with properly typed data
       const result = this.calculator.calculate_entity_probabilities(
         wasmHistory,
-        wasmPeriods
+        wasmPeriods,
+        onProgress
       )
 
+      // Numeric rows only carry a summary; getNumericStats fetches the chunks on demand
       return result as EntityProbability[]
     } catch (error) {
       console.error('WASM calculation error:', error)
@@ -68,6 +88,80 @@ export class WasmBayesianCalculator {
     }
   }
 
+  // One-vs-rest analysis per period label, e.g. home / away / asleep
+  calculatePerClass(history: HAHistoryResponse, periods: TimePeriod[]): ClassResults[] {
+    const wasmHistory: Record<string, WasmHAHistoryEntry[]> = {}
+    for (const [entityId, entries] of Object.entries(history)) {
+      wasmHistory[entityId] = toWasmEntries(entries)
+    }
+    return this.calculator.calculate_per_class(wasmHistory, toWasmPeriods(periods)) as ClassResults[]
+  }
+
+  // Yields to the event loop between entities; for hosts without Web Workers
+  async calculateEntityProbabilitiesAsync(
+    history: HAHistoryResponse,
+    periods: TimePeriod[]
+  ): Promise<EntityProbability[]> {
+    const wasmHistory: Record<string, WasmHAHistoryEntry[]> = {}
+    for (const [entityId, entries] of Object.entries(history)) {
+      wasmHistory[entityId] = toWasmEntries(entries)
+    }
+    return await this.calculator.calculate_entity_probabilities_async(wasmHistory, toWasmPeriods(periods)) as EntityProbability[]
+  }
+
+  // Analyse one entity as soon as its history is fetched; call finalize() once all are in
+  analyzeSingleEntity(entityId: string, entries: HAHistoryEntry[], periods: TimePeriod[]): EntityProbability[] {
+    return this.calculator.analyze_single_entity(entityId, toWasmEntries(entries), toWasmPeriods(periods)) as EntityProbability[]
+  }
+
+  finalize(): EntityProbability[] {
+    return this.calculator.finalize() as EntityProbability[]
+  }
+
+  // Fold newly fetched history for one entity into the last calculation
+  appendHistory(entityId: string, entries: HAHistoryEntry[]): EntityProbability[] {
+    return this.calculator.append_history(entityId, toWasmEntries(entries)) as EntityProbability[]
+  }
+
+  // How much of each analysed period every entity has recorded data for
+  getCoverageReport(): EntityCoverage[] {
+    return this.calculator.get_coverage_report() as EntityCoverage[]
+  }
+
+  // Full chunk data of a numeric entity from the last calculation, for the threshold editor
+  getNumericStats(entityId: string): NumericStateStats | null {
+    return (this.calculator.get_numeric_stats(entityId) as NumericStateStats | undefined) ?? null
+  }
+
+  // Duration-weighted ROC curve of a numeric entity, for plotting
+  getRocCurve(entityId: string, maxPoints?: number): RocCurve | null {
+    return this.calculator.get_roc_curve(entityId, maxPoints) as RocCurve | null
+  }
+
+  // Chart points for one entity over the labeled periods, optionally downsampled
+  getEntityTimeline(entityId: string, entries: HAHistoryEntry[], periods: TimePeriod[], resolutionMs?: number): TracePoint[] {
+    const wasmHistory = { [entityId]: toWasmEntries(entries) }
+    return this.calculator.get_entity_timeline(entityId, wasmHistory, toWasmPeriods(periods), resolutionMs) as TracePoint[]
+  }
+
+  // What changed between two runs' results, e.g. after labeling more periods
+  diffResults(previous: EntityProbability[], current: EntityProbability[]): ResultsDiff {
+    return this.calculator.diff_results(previous, current) as ResultsDiff
+  }
+
+  // Binary snapshot of the analysis, e.g. for IndexedDB
+  serializeState(): Uint8Array {
+    return this.calculator.serialize_state()
+  }
+
+  restoreState(state: Uint8Array): EntityProbability[] {
+    return this.calculator.restore_state(state) as EntityProbability[]
+  }
+
+  cancel() {
+    this.calculator?.cancel()
+  }
+
   destroy() {
     if (this.calculator && this.calculator.free) {
       this.calculator.free()
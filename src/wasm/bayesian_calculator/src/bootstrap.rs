@@ -0,0 +1,94 @@
+use crate::cross_validation::{row_keys, RowKey};
+use crate::rng::SplitMix64;
+use crate::types::{EntityProbability, TimePeriod};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tsify::Tsify;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct BootstrapOptions {
+    /// Analyses over periods drawn with replacement; each costs one full analysis
+    pub resamples: usize,
+    /// Same seed, same resamples, so stability scores are reproducible
+    pub seed: u64,
+}
+
+impl Default for BootstrapOptions {
+    fn default() -> Self {
+        Self { resamples: 50, seed: 0 }
+    }
+}
+
+/// How much a row moves across the bootstrap resamples
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct Stability {
+    /// Resamples that could be analysed (each needs a TRUE and a FALSE period)
+    pub resamples: usize,
+    /// Resamples the row appeared in
+    pub present: usize,
+    /// Discrimination over the resamples, 0 where the row is missing
+    pub discrimination_mean: f64,
+    pub discrimination_std_dev: f64,
+    /// Spread of the chosen lower and upper thresholds of numeric rows over the
+    /// resamples that chose one
+    pub above_std_dev: Option<f64>,
+    pub below_std_dev: Option<f64>,
+}
+
+/// Draw `options.resamples` sets of as many periods as given, with replacement
+pub fn resample(periods: &[TimePeriod], options: &BootstrapOptions) -> Vec<Vec<TimePeriod>> {
+    let mut rng = SplitMix64::new(options.seed);
+    (0..options.resamples)
+        .map(|_| (0..periods.len()).map(|_| periods[rng.index(periods.len())].clone()).collect())
+        .collect()
+}
+
+/// Fill each row's `stability` from the resampled analyses
+///
+/// Rows are matched by `cross_validation::row_keys`, as for cross-validation.
+pub fn apply(results: &mut [EntityProbability], resamples: &[Vec<EntityProbability>]) {
+    let resample_rows: Vec<HashMap<(String, RowKey), &EntityProbability>> = resamples
+        .iter()
+        .map(|rows| row_keys(rows).into_iter().zip(rows).collect())
+        .collect();
+    let keys = row_keys(results);
+
+    for (row, key) in results.iter_mut().zip(&keys) {
+        let matches: Vec<Option<&EntityProbability>> =
+            resample_rows.iter().map(|rows| rows.get(key).copied()).collect();
+
+        let discrimination: Vec<f64> = matches
+            .iter()
+            .map(|row| row.map_or(0.0, |row| row.discrimination_power))
+            .collect();
+        let threshold_spread = |bound: fn(&EntityProbability) -> Option<f64>| {
+            let values: Vec<f64> = matches.iter().flatten().filter_map(|row| bound(row)).collect();
+            (!values.is_empty()).then(|| mean_and_std_dev(&values).1)
+        };
+        let (discrimination_mean, discrimination_std_dev) = mean_and_std_dev(&discrimination);
+
+        row.stability = Some(Stability {
+            resamples: resamples.len(),
+            present: matches.iter().flatten().count(),
+            discrimination_mean,
+            discrimination_std_dev,
+            above_std_dev: threshold_spread(|row| row.optimal_thresholds.as_ref()?.above),
+            below_std_dev: threshold_spread(|row| row.optimal_thresholds.as_ref()?.below),
+        });
+    }
+}
+
+/// Mean and population standard deviation; zeros for no values
+fn mean_and_std_dev(values: &[f64]) -> (f64, f64) {
+    if values.is_empty() {
+        return (0.0, 0.0);
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / n;
+    (mean, variance.sqrt())
+}
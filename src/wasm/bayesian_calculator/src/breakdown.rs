@@ -0,0 +1,174 @@
+use crate::sensor_analysis::ValueDuration;
+use crate::threshold;
+use crate::time;
+use crate::types::{EntityProbability, TimePeriod};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Where an entity spent one labeled period, kept so rows can be broken down by period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeriodDurations {
+    pub period_id: String,
+    pub is_true_period: bool,
+    pub confidence: Option<f64>,
+    pub durations: Durations,
+    /// The time slices behind `durations`, in order, with `include_chunks`
+    pub chunks: Vec<TimeChunk>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Durations {
+    /// Time at each distinct value
+    Numeric(Vec<ValueDuration>),
+    /// Time in each state
+    States(FxHashMap<String, i64>),
+}
+
+/// A stretch of time the entity held one value or state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeChunk {
+    pub start: i64,
+    pub duration: i64,
+    pub reading: ChunkReading,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ChunkReading {
+    Value(f64),
+    State(String),
+}
+
+impl PeriodDurations {
+    pub fn new(period: &TimePeriod, durations: Durations) -> Self {
+        Self {
+            period_id: period.id.clone(),
+            is_true_period: period.is_true_period,
+            confidence: period.confidence,
+            durations,
+            chunks: Vec::new(),
+        }
+    }
+}
+
+/// How one labeled period contributed to a row's probabilities
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PeriodContribution {
+    pub period_id: String,
+    pub is_true_period: bool,
+    /// Soft label of the period, if it has one
+    pub confidence: Option<f64>,
+    /// Time in the row's state or threshold range
+    pub matched_duration_ms: i64,
+    /// Time the entity had a value in effect
+    pub total_duration_ms: i64,
+    /// The row's state or range was seen in the period at all
+    pub matched: bool,
+}
+
+/// Break a row down over the periods its entity was analysed in
+pub fn contributions(row: &EntityProbability, periods: &[PeriodDurations]) -> Vec<PeriodContribution> {
+    let states = row.grouped_states.as_deref().unwrap_or(std::slice::from_ref(&row.state));
+
+    periods
+        .iter()
+        .map(|period| {
+            let (matched_duration_ms, total_duration_ms) = match &period.durations {
+                Durations::Numeric(values) => values.iter().fold((0, 0), |(matched, total), chunk| {
+                    let in_range = row
+                        .optimal_thresholds
+                        .as_ref()
+                        .is_some_and(|thresholds| threshold::value_matches_thresholds(chunk.value, thresholds));
+                    (matched + if in_range { chunk.duration } else { 0 }, total + chunk.duration)
+                }),
+                Durations::States(durations) => (
+                    states.iter().filter_map(|state| durations.get(state)).sum(),
+                    durations.values().sum(),
+                ),
+            };
+            PeriodContribution {
+                period_id: period.period_id.clone(),
+                is_true_period: period.is_true_period,
+                confidence: period.confidence,
+                matched_duration_ms,
+                total_duration_ms,
+                matched: matched_duration_ms > 0,
+            }
+        })
+        .collect()
+}
+
+/// Periods in which a numeric entity held a value within `thresholds`
+pub fn periods_in_range(thresholds: &threshold::OptimalThresholds, periods: &[PeriodDurations]) -> usize {
+    periods
+        .iter()
+        .filter(|period| match &period.durations {
+            Durations::Numeric(values) => values
+                .iter()
+                .any(|chunk| chunk.duration > 0 && threshold::value_matches_thresholds(chunk.value, thresholds)),
+            Durations::States(_) => false,
+        })
+        .count()
+}
+
+/// The time slices of one labeled period, for drilling into a row
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PeriodChunks {
+    pub period_id: String,
+    pub is_true_period: bool,
+    pub chunks: Vec<ChunkDetail>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ChunkDetail {
+    pub start: String, // ISO 8601 string
+    pub duration_ms: i64,
+    /// Numeric entities: the value held
+    pub value: Option<f64>,
+    /// Categorical entities: the state held
+    pub state: Option<String>,
+    /// The value is in the row's threshold range, or the state is the row's
+    pub matched: bool,
+}
+
+/// Mark which of the slices of each period match a row
+pub fn chunk_details(row: &EntityProbability, periods: &[PeriodDurations]) -> Vec<PeriodChunks> {
+    let states = row.grouped_states.as_deref().unwrap_or(std::slice::from_ref(&row.state));
+
+    periods
+        .iter()
+        .map(|period| PeriodChunks {
+            period_id: period.period_id.clone(),
+            is_true_period: period.is_true_period,
+            chunks: period
+                .chunks
+                .iter()
+                .map(|chunk| {
+                    let (value, state, matched) = match &chunk.reading {
+                        ChunkReading::Value(value) => (
+                            Some(*value),
+                            None,
+                            row.optimal_thresholds
+                                .as_ref()
+                                .is_some_and(|thresholds| threshold::value_matches_thresholds(*value, thresholds)),
+                        ),
+                        ChunkReading::State(state) => (None, Some(state.clone()), states.contains(state)),
+                    };
+                    ChunkDetail {
+                        start: time::format_millis(chunk.start),
+                        duration_ms: chunk.duration,
+                        value,
+                        state,
+                        matched,
+                    }
+                })
+                .collect(),
+        })
+        .collect()
+}
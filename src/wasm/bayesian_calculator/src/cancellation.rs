@@ -0,0 +1,44 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use wasm_bindgen::prelude::*;
+
+/// Flag shared between a calculator and everything working on its current analysis
+#[derive(Debug, Clone, Default)]
+pub struct CancellationFlag(Arc<AtomicBool>);
+
+impl CancellationFlag {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Cancels a calculator's analysis while the calculator itself is busy
+///
+/// wasm-bindgen rejects calls into an object from inside one of its own methods, so
+/// a progress callback cannot call `cancel()` on the calculator that invoked it; it
+/// can call it on a handle taken beforehand.
+#[wasm_bindgen]
+pub struct CancellationHandle {
+    flag: CancellationFlag,
+}
+
+impl CancellationHandle {
+    pub fn new(flag: CancellationFlag) -> Self {
+        Self { flag }
+    }
+}
+
+#[wasm_bindgen]
+impl CancellationHandle {
+    pub fn cancel(&self) {
+        self.flag.cancel();
+    }
+}
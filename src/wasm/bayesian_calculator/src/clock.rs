@@ -0,0 +1,118 @@
+use crate::sensor_analysis::{split_virtual_entity_id, virtual_entity_id};
+use crate::time;
+use crate::types::{HAHistoryEntry, TimePeriod, Timestamp};
+use chrono::{Datelike, Duration, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Which clock-time virtual entities are synthesized from the period timestamps
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ClockFeatures {
+    /// IANA time zone of the HA instance, which `now()` in the exported templates uses
+    pub timezone: String,
+    /// Hours per `clock::hour_of_day` state (3 gives `00-03`, `03-06`, ...); 0 leaves
+    /// the entity out
+    pub hour_bucket: u32,
+    /// Add `clock::day_type` with `weekday` and `weekend` states
+    pub weekend: bool,
+}
+
+impl Default for ClockFeatures {
+    fn default() -> Self {
+        Self {
+            timezone: "UTC".to_string(),
+            hour_bucket: 3,
+            weekend: true,
+        }
+    }
+}
+
+const CLOCK: &str = "clock";
+const HOUR_OF_DAY: &str = "hour_of_day";
+const DAY_TYPE: &str = "day_type";
+
+/// History of each clock entity over the span of the periods
+pub fn histories(periods: &[TimePeriod], features: &ClockFeatures) -> Result<Vec<(String, Vec<HAHistoryEntry>)>, String> {
+    let zone = time::parse_zone(&features.timezone)?;
+    let (Some(start), Some(end)) = (
+        periods.iter().map(|period| period.start.millis()).min(),
+        periods.iter().map(|period| period.end.millis()).max(),
+    ) else {
+        return Ok(Vec::new());
+    };
+
+    let mut histories = Vec::new();
+    if features.hour_bucket > 0 {
+        let bucket = features.hour_bucket.min(24);
+        let boundaries = (0..24).step_by(bucket as usize).map(|hour| {
+            let label = format!("{:02}-{:02}", hour, (hour + bucket).min(24));
+            (hour as f64, label)
+        });
+        histories.push((virtual_entity_id(CLOCK, HOUR_OF_DAY), day_changes(zone, start, end, boundaries.collect())));
+    }
+    if features.weekend {
+        let day_type = |date: chrono::NaiveDate| {
+            let weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+            if weekend { "weekend" } else { "weekday" }
+        };
+        let mut entries = Vec::new();
+        let mut date = time::local_date(zone, start);
+        while time::local_instant(zone, date, 0.0) < end {
+            push_change(&mut entries, time::local_instant(zone, date, 0.0).max(start), day_type(date));
+            date += Duration::days(1);
+        }
+        histories.push((virtual_entity_id(CLOCK, DAY_TYPE), entries));
+    }
+    Ok(histories)
+}
+
+/// A state change at each of the local `(hour, state)` boundaries of every day in `[start, end)`
+fn day_changes(zone: Tz, start: i64, end: i64, boundaries: Vec<(f64, String)>) -> Vec<HAHistoryEntry> {
+    let mut entries = Vec::new();
+    let mut date = time::local_date(zone, start);
+    while time::local_instant(zone, date, 0.0) < end {
+        for (i, (hour, state)) in boundaries.iter().enumerate() {
+            let until = boundaries.get(i + 1).map_or(24.0, |next| next.0);
+            let (from, to) = (time::local_instant(zone, date, *hour), time::local_instant(zone, date, until));
+            if to > start && from < end {
+                push_change(&mut entries, from.max(start), state);
+            }
+        }
+        date += Duration::days(1);
+    }
+    entries
+}
+
+fn push_change(entries: &mut Vec<HAHistoryEntry>, millis: i64, state: &str) {
+    if entries.last().is_some_and(|last: &HAHistoryEntry| last.state == state) {
+        return;
+    }
+    entries.push(HAHistoryEntry {
+        state: state.to_string(),
+        last_changed: Timestamp::from_millis(millis),
+        last_updated: Timestamp::from_millis(millis),
+        attributes: None,
+    });
+}
+
+pub fn is_clock_entity(entity_id: &str) -> bool {
+    split_virtual_entity_id(entity_id).is_some_and(|(source, _)| source == CLOCK)
+}
+
+/// Jinja expression that is true while a clock entity is in `state`; `None` for
+/// other entities
+pub fn template(entity_id: &str, state: &str) -> Option<String> {
+    if entity_id == virtual_entity_id(CLOCK, HOUR_OF_DAY) {
+        let (from, to) = state.split_once('-')?;
+        let (from, to): (u32, u32) = (from.parse().ok()?, to.parse().ok()?);
+        Some(format!("(now().hour >= {} and now().hour < {})", from, to))
+    } else if entity_id == virtual_entity_id(CLOCK, DAY_TYPE) {
+        let operator = if state == "weekend" { ">=" } else { "<" };
+        Some(format!("now().weekday() {} 5", operator))
+    } else {
+        None
+    }
+}
@@ -0,0 +1,174 @@
+use crate::sensor_analysis::{self, UnavailableMode};
+use crate::timeline::TimelineEngine;
+use crate::types::{EntityProbability, HAHistoryEntry};
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Fewest periods two entities must share before their correlation is trusted
+const MIN_SHARED_PERIODS: usize = 3;
+
+/// How an entity behaved in each analysed period, aligned across entities by period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum EntityProfile {
+    /// Time-weighted mean value per period
+    Numeric(Vec<Option<f64>>),
+    /// Per state, the share of each period spent in it; only meaningful where `covered`
+    States {
+        covered: Vec<bool>,
+        shares: FxHashMap<String, Vec<f64>>,
+    },
+}
+
+impl EntityProfile {
+    pub fn build(
+        entity_history: &[HAHistoryEntry],
+        timeline: &TimelineEngine,
+        is_numeric: bool,
+        unavailable: UnavailableMode,
+    ) -> Self {
+        if is_numeric {
+            EntityProfile::Numeric(sensor_analysis::numeric_period_means(entity_history, timeline, unavailable))
+        } else {
+            let (covered, shares) = sensor_analysis::state_period_shares(entity_history, timeline, unavailable);
+            EntityProfile::States { covered, shares }
+        }
+    }
+
+    pub fn empty(is_numeric: bool) -> Self {
+        if is_numeric {
+            EntityProfile::Numeric(Vec::new())
+        } else {
+            EntityProfile::States {
+                covered: Vec::new(),
+                shares: FxHashMap::default(),
+            }
+        }
+    }
+
+    /// Append the profile of later periods, `offset` being how many periods came before them
+    ///
+    /// Periods between this profile's end and `offset` (calls the entity was missing
+    /// from) count as not covered.
+    pub fn extend(&mut self, later: EntityProfile, offset: usize) {
+        match (self, later) {
+            (EntityProfile::Numeric(means), EntityProfile::Numeric(later)) => {
+                means.resize(offset, None);
+                means.extend(later);
+            }
+            (
+                EntityProfile::States { covered, shares },
+                EntityProfile::States {
+                    covered: later_covered,
+                    shares: later_shares,
+                },
+            ) => {
+                covered.resize(offset, false);
+                for (state, values) in shares.iter_mut() {
+                    values.resize(offset, 0.0);
+                    values.extend(later_shares.get(state).cloned().unwrap_or_else(|| vec![0.0; later_covered.len()]));
+                }
+                for (state, values) in later_shares {
+                    shares.entry(state).or_insert_with(|| {
+                        let mut padded = vec![0.0; offset];
+                        padded.extend(values);
+                        padded
+                    });
+                }
+                covered.extend(later_covered);
+            }
+            // An entity keeps the side it started on, so the kinds never differ
+            _ => {}
+        }
+    }
+
+    /// Per-period values behind a result row, `None` where the entity has no data
+    fn row_values(&self, row: &EntityProbability, periods: usize) -> Vec<Option<f64>> {
+        let mut values: Vec<Option<f64>> = match self {
+            EntityProfile::Numeric(means) => means.clone(),
+            EntityProfile::States { covered, shares } => {
+                let members = row.grouped_states.as_deref().unwrap_or(std::slice::from_ref(&row.state));
+                covered
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &covered)| {
+                        covered.then(|| {
+                            members
+                                .iter()
+                                .filter_map(|state| shares.get(state))
+                                .fold(0.0, |sum, shares| sum + shares.get(i).copied().unwrap_or(0.0))
+                        })
+                    })
+                    .collect()
+            }
+        };
+        values.resize(periods, None);
+        values
+    }
+}
+
+/// Pearson correlation over the periods both profiles cover
+///
+/// `None` with fewer than `MIN_SHARED_PERIODS` shared periods or when either side
+/// is constant over them.
+fn pearson(a: &[Option<f64>], b: &[Option<f64>]) -> Option<f64> {
+    let pairs: Vec<(f64, f64)> = a.iter().zip(b).filter_map(|(&x, &y)| x.zip(y)).collect();
+    if pairs.len() < MIN_SHARED_PERIODS {
+        return None;
+    }
+
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().fold(0.0, |sum, &(x, _)| sum + x) / n;
+    let mean_y = pairs.iter().fold(0.0, |sum, &(_, y)| sum + y) / n;
+    let (mut covariance, mut variance_x, mut variance_y) = (0.0, 0.0, 0.0);
+    for &(x, y) in &pairs {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance_x += (x - mean_x) * (x - mean_x);
+        variance_y += (y - mean_y) * (y - mean_y);
+    }
+
+    (variance_x > 0.0 && variance_y > 0.0).then(|| covariance / (variance_x * variance_y).sqrt())
+}
+
+/// Fill `correlated_with` for entities whose best rows move together across periods
+///
+/// Each entity is represented by its most discriminating row. Two entities are
+/// correlated when the absolute correlation of those rows' per-period profiles
+/// reaches `threshold`, in either direction: a door that is open exactly when a
+/// light is off carries the same information as the light.
+pub fn mark_correlated(
+    rows: &mut [EntityProbability],
+    profiles: &HashMap<String, EntityProfile>,
+    periods: usize,
+    threshold: f64,
+) {
+    let mut best: FxHashMap<&str, &EntityProbability> = FxHashMap::default();
+    for row in rows.iter() {
+        let entry = best.entry(row.entity_id.as_str()).or_insert(row);
+        if row.discrimination_power > entry.discrimination_power {
+            *entry = row;
+        }
+    }
+
+    let mut representatives: Vec<(&str, Vec<Option<f64>>)> = best
+        .into_iter()
+        .filter_map(|(entity_id, row)| Some((entity_id, profiles.get(entity_id)?.row_values(row, periods))))
+        .collect();
+    representatives.sort_by(|a, b| a.0.cmp(b.0));
+
+    let mut correlated: FxHashMap<String, Vec<String>> = FxHashMap::default();
+    for (i, (entity_a, values_a)) in representatives.iter().enumerate() {
+        for (entity_b, values_b) in &representatives[i + 1..] {
+            if pearson(values_a, values_b).is_some_and(|r| r.abs() >= threshold) {
+                correlated.entry(entity_a.to_string()).or_default().push(entity_b.to_string());
+                correlated.entry(entity_b.to_string()).or_default().push(entity_a.to_string());
+            }
+        }
+    }
+
+    for row in rows.iter_mut() {
+        if let Some(entities) = correlated.get(&row.entity_id) {
+            row.correlated_with = entities.clone();
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use crate::sensor_analysis;
+use crate::types::TimePeriod;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// How much of one labeled period an entity has recorded data for
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PeriodCoverage {
+    pub period_id: String,
+    pub length_ms: i64,
+    /// Time with a value in effect; gaps are recorder purges, time before the first
+    /// recorded state and, unless configured otherwise, `unavailable`/`unknown` time
+    pub covered_ms: i64,
+    pub fraction: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct EntityCoverage {
+    pub entity_id: String,
+    pub periods: Vec<PeriodCoverage>,
+    /// Covered share of all the entity's labeled time
+    pub fraction: f64,
+    /// `fraction` is below `minCoverage`, so the entity's rows carry `lowCoverage`
+    pub low: bool,
+}
+
+pub fn measure(periods: &[TimePeriod], covered: Vec<i64>) -> Vec<PeriodCoverage> {
+    periods
+        .iter()
+        .zip(covered)
+        .map(|(period, covered_ms)| {
+            let length_ms = sensor_analysis::period_length(period);
+            PeriodCoverage {
+                period_id: period.id.clone(),
+                length_ms,
+                covered_ms,
+                fraction: if length_ms > 0 { covered_ms as f64 / length_ms as f64 } else { 0.0 },
+            }
+        })
+        .collect()
+}
+
+/// Covered share of the total length of the periods; 1 without any labeled time
+pub fn overall(periods: &[PeriodCoverage]) -> f64 {
+    let (covered, length) = periods
+        .iter()
+        .fold((0, 0), |(covered, length), period| (covered + period.covered_ms, length + period.length_ms));
+    if length > 0 {
+        covered as f64 / length as f64
+    } else {
+        1.0
+    }
+}
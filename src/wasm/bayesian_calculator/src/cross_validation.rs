@@ -0,0 +1,117 @@
+use crate::types::{AnalysisWarning, EntityProbability, WarningKind};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tsify::Tsify;
+
+/// Share of its full-data discrimination a row must keep in every fold not to count as overfit
+const COLLAPSE_RATIO: f64 = 0.5;
+
+/// Rows this weak are noise either way and are not flagged
+const MIN_FLAGGED_DISCRIMINATION: f64 = 0.1;
+
+/// How a row's probabilities move when each labeled period is held out in turn
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct CrossValidation {
+    /// Folds that could be analysed (each needs a TRUE and a FALSE period left)
+    pub folds: usize,
+    pub prob_given_true_variance: f64,
+    pub prob_given_false_variance: f64,
+    /// Lowest discrimination over the folds; 0 when the row disappears in some fold
+    pub min_discrimination: f64,
+    /// Discrimination fell below half of the full-data value in at least one fold
+    pub overfit: bool,
+}
+
+/// What identifies a row of an entity across analyses of different periods
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RowKey {
+    /// A categorical row's state
+    State(String),
+    /// Position of a numeric row's range in value order: 0 for a single threshold,
+    /// the bin index with `numeric_bins`
+    Bin(usize),
+}
+
+/// (entity, key) of each row, in the order of `rows`
+///
+/// Numeric rows cannot be matched by their thresholds, since each analysis searches
+/// its own, so they are matched by position instead.
+pub fn row_keys(rows: &[EntityProbability]) -> Vec<(String, RowKey)> {
+    let lower = |row: &EntityProbability| row.optimal_thresholds.as_ref().and_then(|t| t.above).unwrap_or(f64::NEG_INFINITY);
+    let mut lower_bounds: HashMap<&str, Vec<f64>> = HashMap::new();
+    for row in rows.iter().filter(|row| row.numeric_stats.is_some()) {
+        lower_bounds.entry(row.entity_id.as_str()).or_default().push(lower(row));
+    }
+    for bounds in lower_bounds.values_mut() {
+        bounds.sort_by(f64::total_cmp);
+    }
+
+    rows.iter()
+        .map(|row| {
+            let key = match lower_bounds.get(row.entity_id.as_str()) {
+                Some(bounds) if row.numeric_stats.is_some() => {
+                    RowKey::Bin(bounds.partition_point(|bound| bound.total_cmp(&lower(row)).is_lt()))
+                }
+                _ => RowKey::State(row.state.clone()),
+            };
+            (row.entity_id.clone(), key)
+        })
+        .collect()
+}
+
+/// Fill each row's `cross_validation` from the leave-one-period-out folds
+///
+/// Rows are matched by `row_keys`. Returns a warning for every overfit entity.
+pub fn apply(results: &mut [EntityProbability], folds: &[Vec<EntityProbability>]) -> Vec<AnalysisWarning> {
+    let fold_rows: Vec<HashMap<(String, RowKey), &EntityProbability>> = folds
+        .iter()
+        .map(|rows| row_keys(rows).into_iter().zip(rows).collect())
+        .collect();
+    let keys = row_keys(results);
+
+    let mut warnings = Vec::new();
+    for (row, key) in results.iter_mut().zip(&keys) {
+        let matches: Vec<Option<&EntityProbability>> =
+            fold_rows.iter().map(|rows| rows.get(key).copied()).collect();
+        let present: Vec<&EntityProbability> = matches.iter().flatten().copied().collect();
+
+        let variance = |value: fn(&EntityProbability) -> f64| {
+            if present.is_empty() {
+                return 0.0;
+            }
+            let n = present.len() as f64;
+            let mean = present.iter().fold(0.0, |sum, row| sum + value(row)) / n;
+            present.iter().fold(0.0, |sum, row| sum + (value(row) - mean) * (value(row) - mean)) / n
+        };
+        let min_discrimination = matches
+            .iter()
+            .map(|row| row.map_or(0.0, |row| row.discrimination_power))
+            .fold(f64::INFINITY, f64::min);
+        let min_discrimination = if min_discrimination.is_finite() { min_discrimination } else { row.discrimination_power };
+
+        let overfit = row.discrimination_power >= MIN_FLAGGED_DISCRIMINATION
+            && min_discrimination < row.discrimination_power * COLLAPSE_RATIO;
+        if overfit {
+            warnings.push(AnalysisWarning {
+                entity_id: Some(row.entity_id.clone()),
+                kind: WarningKind::Overfit,
+                message: format!(
+                    "{} ({}) drops from {:.2} to {:.2} discrimination when a single period is left out",
+                    row.entity_id, row.state, row.discrimination_power, min_discrimination
+                ),
+            });
+        }
+
+        row.cross_validation = Some(CrossValidation {
+            folds: folds.len(),
+            prob_given_true_variance: variance(|row| row.prob_given_true),
+            prob_given_false_variance: variance(|row| row.prob_given_false),
+            min_discrimination,
+            overfit,
+        });
+    }
+
+    warnings
+}
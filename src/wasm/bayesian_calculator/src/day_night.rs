@@ -0,0 +1,237 @@
+use crate::export::{self, best_row_per_entity, Observation, ObservationCondition, SensorSettings};
+use crate::sensor_analysis::{period_length, split_duration, true_weight};
+use crate::time;
+use crate::types::{EntityProbability, TimePeriod};
+use chrono::Duration;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Where night starts and ends
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(tag = "type", rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum NightBoundary {
+    /// Fixed local hours; `end_hour` at or before `start_hour` wraps past midnight
+    #[serde(rename_all = "camelCase")]
+    Fixed { start_hour: f64, end_hour: f64 },
+    /// Sunset to sunrise at the given coordinates, matching `sun.sun` below the horizon
+    #[serde(rename_all = "camelCase")]
+    Sun { latitude: f64, longitude: f64 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DayNightOptions {
+    /// IANA time zone fixed boundaries are interpreted in
+    pub timezone: String,
+    pub boundary: NightBoundary,
+    /// Discrimination an entity needs in the part of the day where it is active
+    pub min_discrimination: f64,
+    /// Discrimination an entity may have at most in the other part to count as time-dependent
+    pub max_inactive_discrimination: f64,
+    /// Entities (best row each) in the merged configuration
+    pub observation_count: usize,
+    pub sensor: SensorSettings,
+}
+
+impl Default for DayNightOptions {
+    fn default() -> Self {
+        Self {
+            timezone: "UTC".to_string(),
+            boundary: NightBoundary::Fixed {
+                start_hour: 22.0,
+                end_hour: 6.0,
+            },
+            min_discrimination: 0.3,
+            max_inactive_discrimination: 0.1,
+            observation_count: 8,
+            sensor: SensorSettings::default(),
+        }
+    }
+}
+
+/// An entity that only discriminates during the day or only at night
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct TimeDependentEntity {
+    pub entity_id: String,
+    pub state: String,
+    /// `day` or `night`
+    pub active: String,
+    pub day_discrimination: f64,
+    pub night_discrimination: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DayNightAnalysis {
+    pub day: Vec<EntityProbability>,
+    pub night: Vec<EntityProbability>,
+    pub time_dependent: Vec<TimeDependentEntity>,
+    /// One bayesian sensor; time-dependent entities become template observations
+    /// that are only met in their active part of the day
+    pub yaml: String,
+}
+
+/// Night intervals (UTC millis, sorted) covering the periods
+pub fn night_intervals(periods: &[TimePeriod], boundary: &NightBoundary, zone: Tz) -> Vec<(i64, i64)> {
+    let Some(range_start) = periods.iter().map(|p| p.start.millis()).min() else {
+        return Vec::new();
+    };
+    let range_end = periods.iter().map(|p| p.end.millis()).max().unwrap_or(range_start);
+
+    // Start a day early so a night running into the first period is included
+    let mut date = time::local_date(zone, range_start) - Duration::days(1);
+    let last_date = time::local_date(zone, range_end);
+    let mut intervals = Vec::new();
+
+    while date <= last_date {
+        let night = match boundary {
+            NightBoundary::Fixed { start_hour, end_hour } => {
+                let end_hour = if end_hour <= start_hour { end_hour + 24.0 } else { *end_hour };
+                Some((time::local_instant(zone, date, *start_hour), time::local_instant(zone, date, end_hour)))
+            }
+            NightBoundary::Sun { latitude, longitude } => {
+                let sunset = time::sun_times(date, *latitude, *longitude).map(|(_, set)| set);
+                let sunrise = time::sun_times(date + Duration::days(1), *latitude, *longitude).map(|(rise, _)| rise);
+                sunset.zip(sunrise)
+            }
+        };
+        if let Some((start, end)) = night.filter(|(start, end)| end > start) {
+            intervals.push((start, end));
+        }
+        date += Duration::days(1);
+    }
+
+    intervals
+}
+
+/// Find time-dependent entities and build the merged configuration
+///
+/// A night-only observation must also account for daytime, when its template is
+/// unmet. Its probabilities are therefore P(night and condition | class): the
+/// night-time estimate scaled by the share of that class's time that falls at night.
+pub fn analyze(
+    full: &[EntityProbability],
+    day: Vec<EntityProbability>,
+    night: Vec<EntityProbability>,
+    day_periods: &[TimePeriod],
+    night_periods: &[TimePeriod],
+    options: &DayNightOptions,
+) -> DayNightAnalysis {
+    let share = |pieces: &[TimePeriod], other: &[TimePeriod], is_true: bool| {
+        let time = |periods: &[TimePeriod]| -> i64 {
+            periods
+                .iter()
+                .map(|p| split_duration(period_length(p), true_weight(p)))
+                .map(|(true_part, false_part)| if is_true { true_part } else { false_part })
+                .sum()
+        };
+        let (own, total) = (time(pieces), time(pieces) + time(other));
+        if total > 0 { own as f64 / total as f64 } else { 0.0 }
+    };
+
+    let best_day = best_row_per_entity(&day, usize::MAX);
+    let best_night = best_row_per_entity(&night, usize::MAX);
+    let discrimination = |rows: &[EntityProbability], entity_id: &str| {
+        rows.iter().find(|r| r.entity_id == entity_id).map_or(0.0, |r| r.discrimination_power)
+    };
+
+    let mut time_dependent = Vec::new();
+    let mut conditioned: Vec<(Observation, f64)> = Vec::new();
+
+    for (active, all_rows, rows, other_rows, own_periods, other_periods) in [
+        ("night", &night, &best_night, &best_day, night_periods, day_periods),
+        ("day", &day, &best_day, &best_night, day_periods, night_periods),
+    ] {
+        let (true_share, false_share) = (share(own_periods, other_periods, true), share(own_periods, other_periods, false));
+
+        for best in rows {
+            let inactive = discrimination(other_rows, &best.entity_id);
+            if best.discrimination_power < options.min_discrimination || inactive > options.max_inactive_discrimination {
+                continue;
+            }
+
+            // Scaling by the time shares changes which of the entity's rows separates best
+            let when = time_condition(&options.boundary, active == "night");
+            let Some((row, observation, conditioned_discrimination)) = all_rows
+                .iter()
+                .filter(|row| row.entity_id == best.entity_id)
+                .filter_map(|row| {
+                    let base = Observation::from_result(row)?;
+                    let prob_given_true = (row.prob_given_true * true_share).clamp(0.01, 0.99);
+                    let prob_given_false = (row.prob_given_false * false_share).clamp(0.01, 0.99);
+                    let observation = Observation {
+                        entity_id: row.entity_id.clone(),
+                        condition: ObservationCondition::Template {
+                            value_template: format!("({}) and {}", when, base.jinja_condition()),
+                        },
+                        prob_given_true,
+                        prob_given_false,
+                    };
+                    Some((row, observation, (prob_given_true - prob_given_false).abs()))
+                })
+                .max_by(|a, b| a.2.total_cmp(&b.2))
+            else {
+                continue;
+            };
+            conditioned.push((observation, conditioned_discrimination));
+
+            let (day_discrimination, night_discrimination) = if active == "night" {
+                (inactive, best.discrimination_power)
+            } else {
+                (best.discrimination_power, inactive)
+            };
+            time_dependent.push(TimeDependentEntity {
+                entity_id: row.entity_id.clone(),
+                state: row.state.clone(),
+                active: active.to_string(),
+                day_discrimination,
+                night_discrimination,
+            });
+        }
+    }
+
+    let mut candidates: Vec<(Observation, f64)> = best_row_per_entity(full, usize::MAX)
+        .iter()
+        .filter(|row| !time_dependent.iter().any(|t| t.entity_id == row.entity_id))
+        .filter_map(|row| Observation::from_result(row).map(|o| (o, row.discrimination_power)))
+        .chain(conditioned)
+        .collect();
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let observations: Vec<Observation> = candidates
+        .into_iter()
+        .take(options.observation_count)
+        .map(|(observation, _)| observation)
+        .collect();
+
+    DayNightAnalysis {
+        day,
+        night,
+        time_dependent,
+        yaml: export::generate_bayesian_yaml_for_observations(&observations, &options.sensor),
+    }
+}
+
+/// Jinja expression that is true at night (or during the day when `night` is false)
+fn time_condition(boundary: &NightBoundary, night: bool) -> String {
+    match boundary {
+        NightBoundary::Sun { .. } => {
+            let state = if night { "below_horizon" } else { "above_horizon" };
+            format!("is_state('sun.sun', '{}')", state)
+        }
+        NightBoundary::Fixed { start_hour, end_hour } => {
+            let hour = "(now().hour + now().minute / 60)";
+            let is_night = if end_hour <= start_hour {
+                format!("{} >= {} or {} < {}", hour, start_hour, hour, end_hour)
+            } else {
+                format!("{} >= {} and {} < {}", hour, start_hour, hour, end_hour)
+            };
+            if night { is_night } else { format!("not ({})", is_night) }
+        }
+    }
+}
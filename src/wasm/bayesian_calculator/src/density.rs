@@ -0,0 +1,173 @@
+use crate::sensor_analysis::{NumericStateStats, ValueDuration};
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+use tsify::Tsify;
+
+/// Points the fitted densities are compared at
+const GRID_POINTS: usize = 512;
+/// Most kernels a `kde` fit places; values are pooled into equal-width bins beyond that
+const MAX_KERNELS: usize = 1024;
+
+/// Continuous model fitted to each class of a numeric entity's values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum LikelihoodModel {
+    /// One normal distribution per class
+    Gaussian,
+    /// Gaussian kernel density estimate per class, with Silverman's bandwidth; follows
+    /// several modes and skew that a single cut point or a normal fit miss
+    Kde,
+}
+
+/// The model fitted to one class
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ClassDensity {
+    pub mean: f64,
+    pub std_dev: f64,
+    /// Kernel width of a `kde` fit
+    pub bandwidth: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DensityFit {
+    pub model: LikelihoodModel,
+    pub given_true: ClassDensity,
+    pub given_false: ClassDensity,
+    /// Share of TRUE time minus share of FALSE time where the likelihood ratio
+    /// favours TRUE; comparable to a threshold row's discrimination power
+    pub discrimination: f64,
+    /// Expected absolute log likelihood ratio over both classes' time
+    pub mean_abs_log_ratio: f64,
+}
+
+/// A fitted class density
+enum Density {
+    Normal { mean: f64, std_dev: f64 },
+    /// (value, share of the class's time) per distinct value
+    Kernel { points: Vec<(f64, f64)>, bandwidth: f64 },
+}
+
+impl Density {
+    fn pdf(&self, x: f64) -> f64 {
+        match self {
+            Density::Normal { mean, std_dev } => normal_pdf(x, *mean, *std_dev),
+            Density::Kernel { points, bandwidth } => points
+                .iter()
+                .map(|&(value, weight)| weight * normal_pdf(x, value, *bandwidth))
+                .sum(),
+        }
+    }
+}
+
+fn normal_pdf(x: f64, mean: f64, std_dev: f64) -> f64 {
+    let z = (x - mean) / std_dev;
+    libm::exp(-0.5 * z * z) / (std_dev * libm::sqrt(2.0 * PI))
+}
+
+/// Fit `model` to both classes and measure how well the densities separate them;
+/// `None` without TRUE and FALSE time
+pub fn fit(stats: &NumericStateStats, model: LikelihoodModel) -> Option<DensityFit> {
+    let true_points = weighted_points(&stats.true_chunks)?;
+    let false_points = weighted_points(&stats.false_chunks)?;
+
+    let low = true_points[0].0.min(false_points[0].0);
+    let high = true_points[true_points.len() - 1].0.max(false_points[false_points.len() - 1].0);
+    // Keeps a class held at one value from collapsing to a spike
+    let floor = ((high - low) * 1e-3).max(1e-9);
+
+    let (given_true, true_density) = fit_class(true_points, model, floor);
+    let (given_false, false_density) = fit_class(false_points, model, floor);
+
+    // Trapezoidal integration over the values plus the tails of the widest kernel
+    let spread = |class: &ClassDensity| class.bandwidth.unwrap_or(class.std_dev);
+    let margin = 4.0 * spread(&given_true).max(spread(&given_false));
+    let (start, end) = (low - margin, high + margin);
+    let step = (end - start) / (GRID_POINTS - 1) as f64;
+
+    let mut discrimination = 0.0;
+    let mut mean_abs_log_ratio = 0.0;
+    for i in 0..GRID_POINTS {
+        let x = start + step * i as f64;
+        let weight = if i == 0 || i == GRID_POINTS - 1 { step / 2.0 } else { step };
+        let (p_true, p_false) = (true_density.pdf(x), false_density.pdf(x));
+        discrimination += weight * (p_true - p_false).max(0.0);
+        if p_true > 0.0 && p_false > 0.0 {
+            mean_abs_log_ratio += weight * (p_true + p_false) / 2.0 * libm::log(p_true / p_false).abs();
+        }
+    }
+
+    Some(DensityFit {
+        model,
+        given_true,
+        given_false,
+        discrimination: discrimination.clamp(0.0, 1.0),
+        mean_abs_log_ratio,
+    })
+}
+
+fn fit_class(points: Vec<(f64, f64)>, model: LikelihoodModel, floor: f64) -> (ClassDensity, Density) {
+    let mean: f64 = points.iter().map(|&(value, weight)| value * weight).sum();
+    let variance: f64 = points.iter().map(|&(value, weight)| (value - mean).powi(2) * weight).sum();
+    let std_dev = libm::sqrt(variance).max(floor);
+
+    match model {
+        LikelihoodModel::Gaussian => (
+            ClassDensity { mean, std_dev, bandwidth: None },
+            Density::Normal { mean, std_dev },
+        ),
+        LikelihoodModel::Kde => {
+            // Silverman's rule, with the effective sample size of the duration weights
+            let effective_count = 1.0 / points.iter().map(|&(_, weight)| weight * weight).sum::<f64>();
+            let bandwidth = (1.06 * std_dev * libm::pow(effective_count, -0.2)).max(floor);
+            (
+                ClassDensity { mean, std_dev, bandwidth: Some(bandwidth) },
+                Density::Kernel { points: pooled(points, floor), bandwidth },
+            )
+        }
+    }
+}
+
+/// Distinct values in increasing order with their share of the class's time; `None` without any time
+fn weighted_points(chunks: &[ValueDuration]) -> Option<Vec<(f64, f64)>> {
+    let mut sorted: Vec<&ValueDuration> = chunks.iter().filter(|chunk| chunk.value.is_finite()).collect();
+    sorted.sort_by(|a, b| a.value.total_cmp(&b.value));
+    let total: i64 = sorted.iter().map(|chunk| chunk.duration).sum();
+    if total <= 0 {
+        return None;
+    }
+
+    let mut points: Vec<(f64, f64)> = Vec::new();
+    for chunk in sorted {
+        let weight = chunk.duration as f64 / total as f64;
+        match points.last_mut() {
+            Some((value, total_weight)) if *value == chunk.value => *total_weight += weight,
+            _ => points.push((chunk.value, weight)),
+        }
+    }
+    (!points.is_empty()).then_some(points)
+}
+
+/// Sorted weighted points pooled into at most `MAX_KERNELS` bins, each at its weighted mean
+fn pooled(points: Vec<(f64, f64)>, floor: f64) -> Vec<(f64, f64)> {
+    if points.len() <= MAX_KERNELS {
+        return points;
+    }
+    let low = points[0].0;
+    let width = ((points[points.len() - 1].0 - low) / MAX_KERNELS as f64).max(floor);
+
+    let mut bins: Vec<(f64, f64)> = vec![(0.0, 0.0); MAX_KERNELS];
+    for (value, weight) in points {
+        let bin = (((value - low) / width) as usize).min(MAX_KERNELS - 1);
+        bins[bin].0 += value * weight;
+        bins[bin].1 += weight;
+    }
+    bins.into_iter()
+        .filter(|&(_, weight)| weight > 0.0)
+        .map(|(sum, weight)| (sum / weight, weight))
+        .collect()
+}
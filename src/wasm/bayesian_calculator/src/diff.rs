@@ -0,0 +1,140 @@
+use crate::types::EntityProbability;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use tsify::Tsify;
+
+/// A row present in both runs
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct RowChange {
+    pub entity_id: String,
+    pub state: String,
+    /// The row's state in the previous run, when a numeric row's thresholds moved
+    pub previous_state: Option<String>,
+    pub prob_given_true: f64,
+    pub prob_given_false: f64,
+    pub prob_given_true_delta: f64,
+    pub prob_given_false_delta: f64,
+    pub discrimination_delta: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct RowKey {
+    pub entity_id: String,
+    pub state: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ResultsDiff {
+    /// Rows found in both runs, largest change in discrimination power first
+    pub changed: Vec<RowChange>,
+    /// Rows only in the current run, of entities both runs have
+    pub added_rows: Vec<RowKey>,
+    /// Rows only in the previous run, of entities both runs have
+    pub removed_rows: Vec<RowKey>,
+    pub new_entities: Vec<String>,
+    pub dropped_entities: Vec<String>,
+}
+
+/// What pairs a row with its counterpart in another run, besides the entity
+///
+/// A numeric row's state spells out its thresholds, which move as periods are
+/// added, so numeric rows pair by which bounds they have instead, and by position
+/// among the entity's rows of that shape (as for `numeric_bins`).
+#[derive(PartialEq, Eq, Hash)]
+enum Slot {
+    State(String),
+    Numeric(&'static str, usize),
+}
+
+type RowKeys<'a> = FxHashMap<(String, Slot), &'a EntityProbability>;
+
+fn row_keys(rows: &[EntityProbability]) -> RowKeys<'_> {
+    let mut shapes: FxHashMap<(&str, &str), usize> = FxHashMap::default();
+    let mut numeric: Vec<&EntityProbability> = rows.iter().filter(|row| row.optimal_thresholds.is_some()).collect();
+    numeric.sort_by(|a, b| {
+        let lower = |row: &EntityProbability| row.optimal_thresholds.as_ref().and_then(|t| t.above).unwrap_or(f64::NEG_INFINITY);
+        a.entity_id.cmp(&b.entity_id).then_with(|| lower(a).total_cmp(&lower(b)))
+    });
+
+    let mut keys: RowKeys = numeric
+        .into_iter()
+        .map(|row| {
+            let shape = match row.optimal_thresholds.as_ref().map(|t| (t.above.is_some(), t.below.is_some())) {
+                Some((true, true)) => "range",
+                Some((true, false)) => "above",
+                Some((false, true)) => "below",
+                _ => "numeric",
+            };
+            let index = shapes.entry((row.entity_id.as_str(), shape)).or_default();
+            *index += 1;
+            ((row.entity_id.clone(), Slot::Numeric(shape, *index)), row)
+        })
+        .collect();
+    keys.extend(
+        rows.iter()
+            .filter(|row| row.optimal_thresholds.is_none())
+            .map(|row| ((row.entity_id.clone(), Slot::State(row.state.clone())), row)),
+    );
+    keys
+}
+
+/// Compare the rows of two runs, e.g. before and after adding labeled periods
+pub fn diff(previous: &[EntityProbability], current: &[EntityProbability]) -> ResultsDiff {
+    let previous_keys = row_keys(previous);
+    let current_keys = row_keys(current);
+    let previous_entities: BTreeSet<&str> = previous.iter().map(|row| row.entity_id.as_str()).collect();
+    let current_entities: BTreeSet<&str> = current.iter().map(|row| row.entity_id.as_str()).collect();
+
+    let mut changed: Vec<RowChange> = current_keys
+        .iter()
+        .filter_map(|(key, row)| {
+            let before = previous_keys.get(key)?;
+            Some(RowChange {
+                entity_id: row.entity_id.clone(),
+                state: row.state.clone(),
+                previous_state: (before.state != row.state).then(|| before.state.clone()),
+                prob_given_true: row.prob_given_true,
+                prob_given_false: row.prob_given_false,
+                prob_given_true_delta: row.prob_given_true - before.prob_given_true,
+                prob_given_false_delta: row.prob_given_false - before.prob_given_false,
+                discrimination_delta: row.discrimination_power - before.discrimination_power,
+            })
+        })
+        .collect();
+    changed.sort_by(|a, b| {
+        b.discrimination_delta
+            .abs()
+            .total_cmp(&a.discrimination_delta.abs())
+            .then_with(|| a.entity_id.cmp(&b.entity_id))
+            .then_with(|| a.state.cmp(&b.state))
+    });
+
+    // Rows of one run missing from the other, for entities both runs have
+    let unmatched = |rows: &RowKeys, other: &RowKeys, other_entities: &BTreeSet<&str>| {
+        let mut keys: Vec<RowKey> = rows
+            .iter()
+            .filter(|(key, row)| !other.contains_key(*key) && other_entities.contains(row.entity_id.as_str()))
+            .map(|(_, row)| RowKey {
+                entity_id: row.entity_id.clone(),
+                state: row.state.clone(),
+            })
+            .collect();
+        keys.sort_by(|a, b| a.entity_id.cmp(&b.entity_id).then_with(|| a.state.cmp(&b.state)));
+        keys
+    };
+
+    ResultsDiff {
+        changed,
+        added_rows: unmatched(&current_keys, &previous_keys, &previous_entities),
+        removed_rows: unmatched(&previous_keys, &current_keys, &current_entities),
+        new_entities: current_entities.difference(&previous_entities).map(|id| id.to_string()).collect(),
+        dropped_entities: previous_entities.difference(&current_entities).map(|id| id.to_string()).collect(),
+    }
+}
@@ -0,0 +1,88 @@
+use crate::sensor_analysis::{NumericStateStats, ValueDuration};
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Where a numeric entity's values sat during TRUE and FALSE time, for plotting the
+/// two classes over each other
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct Distribution {
+    /// `bins + 1` evenly spaced edges from the lowest value to the highest
+    pub bin_edges: Vec<f64>,
+    pub given_true: ClassDistribution,
+    pub given_false: ClassDistribution,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ClassDistribution {
+    /// Share of the class's time in each bin; the last bin includes its upper edge
+    pub histogram: Vec<f64>,
+    pub mean: f64,
+    pub median: f64,
+    pub std_dev: f64,
+}
+
+/// Duration-weighted histograms and moments of both classes; `None` without TRUE and FALSE time
+pub fn distribution(stats: &NumericStateStats, bins: usize) -> Option<Distribution> {
+    if bins == 0 || total(&stats.true_chunks) <= 0 || total(&stats.false_chunks) <= 0 {
+        return None;
+    }
+
+    let values = stats.true_chunks.iter().chain(&stats.false_chunks).map(|chunk| chunk.value);
+    let (low, high) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), value| {
+        (low.min(value), high.max(value))
+    });
+    let width = (high - low) / bins as f64;
+    let bin_edges = (0..=bins).map(|i| if i == bins { high } else { low + width * i as f64 }).collect();
+
+    let class = |chunks: &[ValueDuration]| {
+        let total = total(chunks) as f64;
+        let mut histogram = vec![0.0; bins];
+        for chunk in chunks {
+            let bin = if width > 0.0 { ((chunk.value - low) / width) as usize } else { 0 };
+            histogram[bin.min(bins - 1)] += chunk.duration as f64 / total;
+        }
+
+        let mean = chunks.iter().map(|chunk| chunk.value * chunk.duration as f64).sum::<f64>() / total;
+        let variance = chunks
+            .iter()
+            .map(|chunk| (chunk.value - mean).powi(2) * chunk.duration as f64)
+            .sum::<f64>()
+            / total;
+
+        ClassDistribution {
+            histogram,
+            mean,
+            median: weighted_median(chunks, total),
+            std_dev: variance.sqrt(),
+        }
+    };
+
+    Some(Distribution {
+        bin_edges,
+        given_true: class(&stats.true_chunks),
+        given_false: class(&stats.false_chunks),
+    })
+}
+
+fn total(chunks: &[ValueDuration]) -> i64 {
+    chunks.iter().map(|chunk| chunk.duration).sum()
+}
+
+/// Lowest value with at least half the time at or below it
+fn weighted_median(chunks: &[ValueDuration], total: f64) -> f64 {
+    let mut sorted: Vec<&ValueDuration> = chunks.iter().collect();
+    sorted.sort_by(|a, b| a.value.total_cmp(&b.value));
+
+    let mut elapsed = 0.0;
+    for chunk in &sorted {
+        elapsed += chunk.duration as f64;
+        if elapsed >= total / 2.0 {
+            return chunk.value;
+        }
+    }
+    sorted.last().map_or(f64::NAN, |chunk| chunk.value)
+}
@@ -0,0 +1,195 @@
+use crate::export::{self, best_row_per_entity, observations_from_results, slugify, SensorSettings};
+use crate::posterior::{self, PredictedMetrics};
+use crate::types::EntityProbability;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct EnsembleOptions {
+    /// Predicted F1 a single bayesian sensor must reach before an ensemble is proposed
+    pub target_f1: f64,
+    /// Entities (best row each) drawn from the ranked results
+    pub max_observations: usize,
+    pub sensor: SensorSettings,
+}
+
+impl Default for EnsembleOptions {
+    fn default() -> Self {
+        Self {
+            target_f1: 0.9,
+            max_observations: 8,
+            sensor: SensorSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PredictedPerformance {
+    pub probability_threshold: f64,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct EnsembleDesign {
+    /// `any` (OR of both sensors) or `all` (AND)
+    pub combination: String,
+    pub first: PredictedPerformance,
+    pub second: PredictedPerformance,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    pub yaml: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct EnsembleSuggestion {
+    pub single: PredictedPerformance,
+    pub target_reached: bool,
+    /// Proposed only when the single sensor misses the target; compare its `f1` to `single`
+    pub ensemble: Option<EnsembleDesign>,
+}
+
+/// Check whether one bayesian sensor reaches the target, otherwise propose a two-sensor ensemble
+///
+/// The top entities are dealt alternately (snake order by rank) into two bayesian
+/// sensors with disjoint observations, each tuned to its best F1 threshold. A
+/// template binary sensor then combines them with OR or AND, whichever predicts the
+/// better F1. Because the two sensors share no entities, their outputs are treated
+/// as independent given the class when predicting the combination.
+pub fn suggest(results: &[EntityProbability], options: &EnsembleOptions) -> EnsembleSuggestion {
+    let prior = options.sensor.prior;
+    let rows = best_row_per_entity(results, options.max_observations);
+
+    let (single_threshold, single_metrics) = tuned(&rows, prior);
+    let single = performance(single_threshold, &single_metrics);
+    let target_reached = single.f1 >= options.target_f1;
+
+    if target_reached || rows.len() < 2 {
+        return EnsembleSuggestion {
+            single,
+            target_reached,
+            ensemble: None,
+        };
+    }
+
+    let (first_rows, second_rows): (Vec<_>, Vec<_>) = rows
+        .iter()
+        .enumerate()
+        .partition(|(rank, _)| matches!(rank % 4, 0 | 3));
+    let first_rows: Vec<EntityProbability> = first_rows.into_iter().map(|(_, r)| r.clone()).collect();
+    let second_rows: Vec<EntityProbability> = second_rows.into_iter().map(|(_, r)| r.clone()).collect();
+
+    let (first_threshold, first_metrics) = tuned(&first_rows, prior);
+    let (second_threshold, second_metrics) = tuned(&second_rows, prior);
+
+    let any = combine(&first_metrics, &second_metrics, prior, false);
+    let all = combine(&first_metrics, &second_metrics, prior, true);
+    let (combination, combined) = if any.f_beta(1.0) >= all.f_beta(1.0) { ("any", any) } else { ("all", all) };
+
+    let ensemble = {
+        let first_settings = SensorSettings {
+            name: format!("{} A", options.sensor.name),
+            probability_threshold: first_threshold,
+            ..options.sensor.clone()
+        };
+        let second_settings = SensorSettings {
+            name: format!("{} B", options.sensor.name),
+            probability_threshold: second_threshold,
+            ..options.sensor.clone()
+        };
+
+        let yaml = format!(
+            "{}{}\n{}",
+            export::generate_bayesian_yaml(&first_rows, &first_settings),
+            export::generate_bayesian_yaml(&second_rows, &second_settings)
+                .trim_start_matches("binary_sensor:\n"),
+            combining_template(&options.sensor, &first_settings.name, &second_settings.name, combination),
+        );
+
+        EnsembleDesign {
+            combination: combination.to_string(),
+            first: performance(first_threshold, &first_metrics),
+            second: performance(second_threshold, &second_metrics),
+            precision: combined.precision,
+            recall: combined.recall,
+            f1: combined.f_beta(1.0),
+            yaml,
+        }
+    };
+
+    EnsembleSuggestion {
+        single,
+        target_reached,
+        ensemble: Some(ensemble),
+    }
+}
+
+fn tuned(rows: &[EntityProbability], prior: f64) -> (f64, PredictedMetrics) {
+    let distribution = posterior::predicted_distribution(&observations_from_results(rows), prior);
+    posterior::best_threshold(&distribution, prior, 1.0)
+}
+
+fn performance(threshold: f64, metrics: &PredictedMetrics) -> PredictedPerformance {
+    PredictedPerformance {
+        probability_threshold: threshold,
+        precision: metrics.precision,
+        recall: metrics.recall,
+        f1: metrics.f_beta(1.0),
+    }
+}
+
+/// Metrics of two conditionally independent detectors combined with OR (`all == false`) or AND
+pub fn combine(first: &PredictedMetrics, second: &PredictedMetrics, prior: f64, all: bool) -> PredictedMetrics {
+    let (recall, false_positive_rate) = if all {
+        (first.recall * second.recall, first.false_positive_rate * second.false_positive_rate)
+    } else {
+        (
+            1.0 - (1.0 - first.recall) * (1.0 - second.recall),
+            1.0 - (1.0 - first.false_positive_rate) * (1.0 - second.false_positive_rate),
+        )
+    };
+
+    let true_positive = prior * recall;
+    let false_positive = (1.0 - prior) * false_positive_rate;
+    let precision = if true_positive + false_positive > 0.0 {
+        true_positive / (true_positive + false_positive)
+    } else {
+        0.0
+    };
+
+    PredictedMetrics {
+        precision,
+        recall,
+        false_positive_rate,
+    }
+}
+
+fn combining_template(settings: &SensorSettings, first_name: &str, second_name: &str, combination: &str) -> String {
+    let operator = if combination == "all" { "and" } else { "or" };
+    let mut yaml = String::new();
+
+    yaml.push_str("template:\n");
+    yaml.push_str("  - binary_sensor:\n");
+    yaml.push_str(&format!("      - name: {}\n", export::yaml_string(&settings.name)));
+    if let Some(device_class) = &settings.device_class {
+        yaml.push_str(&format!("        device_class: {}\n", export::yaml_string(device_class)));
+    }
+    yaml.push_str(&format!(
+        "        state: \"{{{{ is_state('binary_sensor.{}', 'on') {} is_state('binary_sensor.{}', 'on') }}}}\"\n",
+        slugify(first_name),
+        operator,
+        slugify(second_name)
+    ));
+
+    yaml
+}
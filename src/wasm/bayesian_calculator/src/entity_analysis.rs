@@ -0,0 +1,251 @@
+use crate::breakdown::PeriodDurations;
+use crate::correlation::EntityProfile;
+use crate::coverage::{self, PeriodCoverage};
+use crate::filter;
+use crate::leakage::LeakageEvidence;
+use crate::options::CalculatorOptions;
+use crate::preprocessing;
+use crate::sensor_analysis::{self, NumericStateStats};
+use crate::timeline::TimelineEngine;
+use crate::types::{
+    AnalysisWarning, EntityMetadata, HAHistoryEntry, SensorAttributes, StateDurationStats, WarningKind,
+};
+use crate::validation;
+use rustc_hash::FxHashMap;
+use std::collections::HashMap;
+
+/// One entity to analyse: its id, its history, and whether earlier calls already
+/// settled it as numeric
+pub type EntityJob<'a> = (&'a str, &'a [HAHistoryEntry], Option<bool>);
+
+pub enum EntityStats {
+    Numeric(NumericStateStats),
+    States(FxHashMap<String, StateDurationStats>),
+}
+
+/// Everything one entity contributes, computed without touching the calculator so
+/// entities can be analysed on separate threads and merged afterwards
+#[derive(Default)]
+pub struct EntityAnalysis {
+    pub warnings: Vec<AnalysisWarning>,
+    pub profile: Option<EntityProfile>,
+    pub stats: Option<EntityStats>,
+    pub coverage: Vec<PeriodCoverage>,
+    /// Per-period durations for `period_breakdown` and `include_chunks`, and for
+    /// counting the periods of numeric rows under `min_evidence`
+    pub period_durations: Vec<PeriodDurations>,
+    pub sensor: Option<SensorAttributes>,
+    pub leakage: Option<LeakageEvidence>,
+    /// Analyses of the virtual entities drawn from this one (derived features and
+    /// attributes), by virtual entity id
+    pub derived: Vec<(String, EntityAnalysis)>,
+}
+
+/// Entities per batch; progress and cancellation are checked between batches
+pub fn batch_size(options: &CalculatorOptions) -> usize {
+    if cfg!(feature = "parallel") {
+        options.entity_chunk_size.max(1)
+    } else {
+        1
+    }
+}
+
+/// Analyse a batch of entities, spreading them across the rayon pool
+#[cfg(feature = "parallel")]
+pub fn analyze_batch(
+    jobs: &[EntityJob],
+    timeline: &TimelineEngine,
+    options: &CalculatorOptions,
+    metadata: &HashMap<String, EntityMetadata>,
+) -> Vec<EntityAnalysis> {
+    use rayon::prelude::*;
+
+    jobs.par_iter()
+        .map(|&job| analyze(job, timeline, options, metadata))
+        .collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn analyze_batch(
+    jobs: &[EntityJob],
+    timeline: &TimelineEngine,
+    options: &CalculatorOptions,
+    metadata: &HashMap<String, EntityMetadata>,
+) -> Vec<EntityAnalysis> {
+    jobs.iter().map(|&job| analyze(job, timeline, options, metadata)).collect()
+}
+
+fn analyze(
+    (entity_id, entity_history, known_numeric): EntityJob,
+    timeline: &TimelineEngine,
+    options: &CalculatorOptions,
+    metadata: &HashMap<String, EntityMetadata>,
+) -> EntityAnalysis {
+    if entity_history.is_empty() {
+        return EntityAnalysis::default();
+    }
+
+    if let Some(reason) = filter::exclusion_reason(entity_id, metadata.get(entity_id), options) {
+        return EntityAnalysis {
+            warnings: vec![AnalysisWarning {
+                entity_id: Some(entity_id.to_string()),
+                kind: WarningKind::EntityFiltered,
+                message: format!("Skipped {}: {}", entity_id, reason),
+            }],
+            ..EntityAnalysis::default()
+        };
+    }
+
+    let leakage = options
+        .leakage_detection
+        .then(|| LeakageEvidence::collect(entity_history, timeline.periods()));
+
+    let mut attributes = Vec::new();
+    for attribute in options.analyzed_attributes.get(entity_id).into_iter().flatten() {
+        let attribute_history = preprocessing::attribute_history(entity_history, attribute);
+        if !attribute_history.is_empty() {
+            let attribute_id = sensor_analysis::virtual_entity_id(entity_id, attribute);
+            let job = (attribute_id.as_str(), attribute_history.as_slice(), None);
+            let attribute_analysis = analyze(job, timeline, options, metadata);
+            attributes.push((attribute_id, attribute_analysis));
+        }
+    }
+
+    let quantized;
+    let entity_history = if options.time_resolution_ms > 0 {
+        quantized = preprocessing::quantize_history(entity_history, options.time_resolution_ms);
+        &quantized
+    } else {
+        entity_history
+    };
+
+    let mut sensor = SensorAttributes::from_history(entity_history);
+    // Keep an entity on the side it started on when later history is folded in
+    let is_numeric = known_numeric
+        .unwrap_or_else(|| !sensor.is_categorical() && sensor_analysis::is_numeric_entity(entity_history));
+
+    let rates;
+    let cumulative = is_numeric
+        && options.cumulative_detection
+        && sensor_analysis::is_cumulative(entity_history, sensor.state_class.as_deref());
+    let entity_history = if cumulative {
+        rates = sensor_analysis::counter_rate_history(entity_history);
+        sensor.unit_of_measurement = sensor.unit_of_measurement.map(|unit| format!("{}/min", unit));
+        &rates
+    } else {
+        entity_history
+    };
+
+    let smoothed;
+    let entity_history = match options.rolling_average_seconds {
+        Some(seconds) if is_numeric && seconds > 0.0 => {
+            smoothed = preprocessing::rolling_average(entity_history, (seconds * 1000.0) as i64);
+            &smoothed
+        }
+        _ => entity_history,
+    };
+
+    let detrended;
+    let undetrended_history = entity_history;
+    let entity_history = match options.detrend.get(entity_id) {
+        Some(&mode) => {
+            detrended = preprocessing::detrend(entity_history, mode);
+            &detrended
+        }
+        None => entity_history,
+    };
+
+    let mut analysis = analyze_prepared(entity_id, entity_history, is_numeric, timeline, options);
+    if let Some(EntityStats::Numeric(stats)) = &mut analysis.stats {
+        stats.cumulative = cumulative;
+        // Readings outside the cycle mean the unit was not an angle after all
+        let in_range = |cycle: f64| stats.min.is_some_and(|min| min >= 0.0) && stats.max.is_some_and(|max| max <= cycle);
+        stats.cycle = options
+            .cyclic_entities
+            .get(entity_id)
+            .copied()
+            .or_else(|| sensor.cycle().filter(|&cycle| in_range(cycle)));
+    }
+
+    if is_numeric {
+        // Derived from the undetrended values; a rate of change is unaffected by slow drift
+        for &feature in &options.derived_features {
+            let derived_history = feature.derive(undetrended_history);
+            if !derived_history.is_empty() {
+                let derived_id = feature.entity_id(entity_id);
+                let mut derived = analyze_prepared(&derived_id, &derived_history, true, timeline, options);
+                derived.sensor = Some(SensorAttributes {
+                    unit_of_measurement: sensor.unit_of_measurement.as_deref().map(|unit| feature.unit(unit)),
+                    device_class: None,
+                    state_class: None,
+                });
+                analysis.derived.push((derived_id, derived));
+            }
+        }
+    }
+    analysis.derived.extend(attributes);
+    analysis.sensor = Some(sensor);
+    analysis.leakage = leakage;
+
+    analysis
+}
+
+/// Statistics of a filtered and preprocessed history
+fn analyze_prepared(
+    entity_id: &str,
+    entity_history: &[HAHistoryEntry],
+    is_numeric: bool,
+    timeline: &TimelineEngine,
+    options: &CalculatorOptions,
+) -> EntityAnalysis {
+    let mut analysis = EntityAnalysis::default();
+
+    let durations = sensor_analysis::class_durations(entity_history, timeline, is_numeric, options.unavailable);
+    if options.debug_validate {
+        analysis.warnings.extend(validation::check_period_durations(entity_id, timeline.periods(), &durations));
+    }
+    let covered = durations.iter().map(|&(true_duration, false_duration)| true_duration + false_duration).collect();
+    analysis.coverage = coverage::measure(timeline.periods(), covered);
+    if options.period_breakdown || options.include_chunks || (is_numeric && options.min_evidence.is_some()) {
+        analysis.period_durations = sensor_analysis::period_durations(entity_history, timeline, is_numeric, options.unavailable);
+    }
+    if options.include_chunks {
+        let chunks = sensor_analysis::period_chunks(entity_history, timeline, is_numeric, options.unavailable);
+        for (period, chunks) in analysis.period_durations.iter_mut().zip(chunks) {
+            period.chunks = chunks;
+        }
+    }
+
+    if options.correlation_threshold.is_some() {
+        analysis.profile = Some(EntityProfile::build(entity_history, timeline, is_numeric, options.unavailable));
+    }
+
+    if is_numeric {
+        let numeric_stats = if options.streaming_aggregation {
+            sensor_analysis::aggregate_numeric_states(
+                entity_history,
+                timeline,
+                options.outlier_filter.as_ref(),
+                options.unavailable,
+            )
+        } else {
+            sensor_analysis::analyze_numeric_states(
+                entity_history,
+                timeline,
+                options.outlier_filter.as_ref(),
+                options.unavailable,
+            )
+        };
+        if let Some(numeric_stats) = numeric_stats {
+            if options.debug_validate {
+                analysis.warnings.extend(validation::check_numeric_stats(entity_id, &numeric_stats, timeline.periods()));
+            }
+            analysis.stats = Some(EntityStats::Numeric(numeric_stats));
+        }
+    } else {
+        // Use duration-based approach for state sensors (same as numeric sensors)
+        analysis.stats = Some(EntityStats::States(sensor_analysis::analyze_state_chunks(entity_history, timeline, options.unavailable)));
+    }
+
+    analysis
+}
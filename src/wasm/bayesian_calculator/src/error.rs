@@ -0,0 +1,116 @@
+use crate::types::{AnalysisWarning, HAHistoryEntry, TimePeriod, Timestamp, WarningKind};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use tsify::Tsify;
+use wasm_bindgen::JsValue;
+
+/// Why a calculation could not run
+///
+/// Thrown to JS as an object with the variant in `type`, its fields, and a readable
+/// `message`, so callers can branch on the failure instead of matching text.
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(tag = "type", rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum CalculatorError {
+    /// An argument did not have the expected shape
+    #[serde(rename_all = "camelCase")]
+    ParseError { field: String, message: String },
+    /// A period has a timestamp that cannot be read; history entries with one are
+    /// skipped with a warning instead, so `entity_id` is only set by older versions
+    #[serde(rename_all = "camelCase")]
+    InvalidTimestamp { entity_id: Option<String>, value: String },
+    NoTruePeriods,
+    NoFalsePeriods,
+    /// A multi-class analysis got no period with a `class`
+    NoClasses,
+    /// No entity has any history entries
+    EmptyHistory,
+    /// A TRUE and a FALSE period cover the same time and `periodOverlap` is `reject`
+    #[serde(rename_all = "camelCase")]
+    OverlappingPeriods { true_period: String, false_period: String },
+    /// `cancel()` was called while the analysis was running
+    Cancelled,
+}
+
+impl fmt::Display for CalculatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalculatorError::ParseError { field, message } => write!(f, "Failed to parse {}: {}", field, message),
+            CalculatorError::InvalidTimestamp {
+                entity_id: Some(entity_id),
+                value,
+            } => write!(f, "Invalid timestamp '{}' in the history of {}", value, entity_id),
+            CalculatorError::InvalidTimestamp { entity_id: None, value } => {
+                write!(f, "Invalid period timestamp '{}'", value)
+            }
+            CalculatorError::NoTruePeriods => write!(f, "Need at least one TRUE period"),
+            CalculatorError::NoFalsePeriods => write!(f, "Need at least one FALSE period"),
+            CalculatorError::NoClasses => write!(f, "Need periods with a class to analyse per class"),
+            CalculatorError::EmptyHistory => write!(f, "History has no entries"),
+            CalculatorError::OverlappingPeriods {
+                true_period,
+                false_period,
+            } => write!(f, "TRUE period {} overlaps FALSE period {}", true_period, false_period),
+            CalculatorError::Cancelled => write!(f, "Analysis was cancelled"),
+        }
+    }
+}
+
+impl From<CalculatorError> for JsValue {
+    fn from(error: CalculatorError) -> Self {
+        let message = error.to_string();
+        let Ok(value) = serde_wasm_bindgen::to_value(&error) else {
+            return JsValue::from_str(&message);
+        };
+        let _ = js_sys::Reflect::set(&value, &JsValue::from_str("message"), &JsValue::from_str(&message));
+        value
+    }
+}
+
+/// A timestamp as the caller wrote it, for messages
+pub fn describe(timestamp: &Timestamp) -> String {
+    match timestamp {
+        Timestamp::EpochMillis(millis) => millis.to_string(),
+        Timestamp::Iso(iso) => iso.clone(),
+    }
+}
+
+/// The first unreadable timestamp in the periods, if any
+pub fn check_timestamps(periods: &[TimePeriod]) -> Result<(), CalculatorError> {
+    match periods.iter().flat_map(|p| [&p.start, &p.end]).find(|t| !t.is_valid()) {
+        Some(timestamp) => Err(CalculatorError::InvalidTimestamp {
+            entity_id: None,
+            value: describe(timestamp),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Remove history entries with unreadable timestamps, with a warning per affected entity
+///
+/// An entry read as epoch 0 would sort before everything else and stretch its state
+/// over the whole timeline, so it is dropped instead.
+pub fn drop_invalid_timestamps(history: &mut HashMap<String, Vec<HAHistoryEntry>>) -> Vec<AnalysisWarning> {
+    let mut warnings = Vec::new();
+    for (entity_id, entries) in history.iter_mut() {
+        let Some(first) = entries.iter().find(|entry| !entry.last_changed.is_valid()) else {
+            continue;
+        };
+        let first = describe(&first.last_changed);
+        let before = entries.len();
+        entries.retain(|entry| entry.last_changed.is_valid());
+        warnings.push(AnalysisWarning {
+            entity_id: Some(entity_id.clone()),
+            kind: WarningKind::InvalidTimestamp,
+            message: format!(
+                "Skipped {} history entries of {} with unreadable timestamps (first: '{}')",
+                before - entries.len(),
+                entity_id,
+                first
+            ),
+        });
+    }
+    warnings.sort_by(|a, b| a.entity_id.cmp(&b.entity_id));
+    warnings
+}
@@ -0,0 +1,101 @@
+use crate::simulation::Segment;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// How a candidate configuration would have done against the labeled periods
+///
+/// Durations are labeled time in milliseconds; soft-labeled periods count towards
+/// both classes by their confidence, so they need not be whole numbers.
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ConfigurationEvaluation {
+    pub probability_threshold: f64,
+    /// Sensor on during labeled TRUE time
+    pub true_positive_ms: f64,
+    /// Sensor on during labeled FALSE time
+    pub false_positive_ms: f64,
+    /// Sensor off during labeled FALSE time
+    pub true_negative_ms: f64,
+    /// Sensor off during labeled TRUE time
+    pub false_negative_ms: f64,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    pub accuracy: f64,
+}
+
+/// Time-weighted confusion matrix of the replayed posterior at a threshold
+///
+/// Time outside every labeled period is ignored. Ratios without any time in their
+/// denominator are 0.
+pub fn evaluate(segments: &[Segment], probability_threshold: f64) -> ConfigurationEvaluation {
+    let (mut true_positive, mut false_positive, mut true_negative, mut false_negative) = (0.0, 0.0, 0.0, 0.0);
+
+    for segment in segments {
+        let Some(weight) = segment.label else {
+            continue;
+        };
+        let length = segment.length as f64;
+        if segment.posterior >= probability_threshold {
+            true_positive += length * weight;
+            false_positive += length * (1.0 - weight);
+        } else {
+            false_negative += length * weight;
+            true_negative += length * (1.0 - weight);
+        }
+    }
+
+    let ratio = |part: f64, whole: f64| if whole > 0.0 { part / whole } else { 0.0 };
+    let precision = ratio(true_positive, true_positive + false_positive);
+    let recall = ratio(true_positive, true_positive + false_negative);
+
+    ConfigurationEvaluation {
+        probability_threshold,
+        true_positive_ms: true_positive,
+        false_positive_ms: false_positive,
+        true_negative_ms: true_negative,
+        false_negative_ms: false_negative,
+        precision,
+        recall,
+        f1: ratio(2.0 * precision * recall, precision + recall),
+        accuracy: ratio(
+            true_positive + true_negative,
+            true_positive + false_positive + true_negative + false_negative,
+        ),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ThresholdSweep {
+    /// One evaluation per candidate threshold, in increasing order
+    pub points: Vec<ConfigurationEvaluation>,
+    /// Candidate with the highest F1 (the lowest one on a tie)
+    pub best_f1_threshold: f64,
+}
+
+/// Smallest `step` a sweep accepts; each step costs a pass over the replay
+pub const MIN_STEP: f64 = 0.001;
+
+/// Evaluate the replayed posterior at every multiple of `step` strictly between 0 and 1
+///
+/// `step` is snapped to the nearest fraction 1/n, so a step of 0.05 gives the
+/// thresholds 0.05, 0.10, ..., 0.95 without rounding drift.
+pub fn sweep(segments: &[Segment], step: f64) -> ThresholdSweep {
+    let steps = (1.0 / step).round().max(2.0) as usize;
+    let points: Vec<ConfigurationEvaluation> = (1..steps)
+        .map(|i| evaluate(segments, i as f64 / steps as f64))
+        .collect();
+    let best_f1_threshold = points
+        .iter()
+        .rev()
+        .max_by(|a, b| a.f1.total_cmp(&b.f1))
+        .map_or(0.5, |point| point.probability_threshold);
+
+    ThresholdSweep {
+        points,
+        best_f1_threshold,
+    }
+}
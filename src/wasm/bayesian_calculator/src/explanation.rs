@@ -0,0 +1,116 @@
+use crate::types::EntityProbability;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Sentences each row's `explanation` is filled in from, replaceable for translation
+///
+/// Placeholders are written `{name}`: sentences take `{entity}`, `{condition}`,
+/// `{trueShare}`, `{falseShare}` and `{target}`; numeric conditions take `{value}`
+/// (or `{low}` and `{high}`), state conditions `{state}` (or `{states}`, joined
+/// with `state_separator`). Values carry the row's unit.
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ExplanationTemplates {
+    /// What the labeled TRUE time is, e.g. `home office occupied`
+    pub target: String,
+    /// The condition holds for more of the TRUE time than of the rest
+    pub more_often: String,
+    /// The condition holds for less of the TRUE time than of the rest
+    pub less_often: String,
+    pub above: String,
+    pub below: String,
+    pub between: String,
+    /// A range wrapping around the end of a cyclic entity's values
+    pub outside: String,
+    pub state: String,
+    pub any_state: String,
+    pub state_separator: String,
+}
+
+impl Default for ExplanationTemplates {
+    fn default() -> Self {
+        Self {
+            target: "target".to_string(),
+            more_often: "{entity} was {condition} during {trueShare} of your '{target}' time but only {falseShare} of the rest"
+                .to_string(),
+            less_often: "{entity} was {condition} during only {trueShare} of your '{target}' time but {falseShare} of the rest"
+                .to_string(),
+            above: "above {value}".to_string(),
+            below: "at or below {value}".to_string(),
+            between: "between {low} and {high}".to_string(),
+            outside: "above {low} or at or below {high}".to_string(),
+            state: "'{state}'".to_string(),
+            any_state: "{states}".to_string(),
+            state_separator: " or ".to_string(),
+        }
+    }
+}
+
+/// Replace each `{name}` in `template` with its value
+fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |text, (name, value)| text.replace(&format!("{{{}}}", name), value))
+}
+
+/// Plain-language sentence for a row; `None` for numeric rows without thresholds
+pub fn explain(row: &EntityProbability, templates: &ExplanationTemplates) -> Option<String> {
+    let with_unit = |value: f64| match &row.unit_of_measurement {
+        Some(unit) => format!("{} {}", format_value(value), unit),
+        None => format_value(value),
+    };
+
+    let condition = match &row.optimal_thresholds {
+        Some(thresholds) => match (thresholds.above, thresholds.below) {
+            (Some(above), Some(below)) if above > below => fill(
+                &templates.outside,
+                &[("low", &with_unit(above)), ("high", &with_unit(below))],
+            ),
+            (Some(above), Some(below)) => fill(
+                &templates.between,
+                &[("low", &with_unit(above)), ("high", &with_unit(below))],
+            ),
+            (Some(above), None) => fill(&templates.above, &[("value", &with_unit(above))]),
+            (None, Some(below)) => fill(&templates.below, &[("value", &with_unit(below))]),
+            (None, None) => return None,
+        },
+        None => match row.grouped_states.as_deref() {
+            Some(states) if states.len() > 1 => {
+                let states: Vec<String> = states
+                    .iter()
+                    .map(|state| fill(&templates.state, &[("state", state)]))
+                    .collect();
+                fill(&templates.any_state, &[("states", &states.join(&templates.state_separator))])
+            }
+            Some([state]) => fill(&templates.state, &[("state", state)]),
+            _ => fill(&templates.state, &[("state", &row.state)]),
+        },
+    };
+
+    let template = if row.prob_given_true >= row.prob_given_false {
+        &templates.more_often
+    } else {
+        &templates.less_often
+    };
+    Some(fill(
+        template,
+        &[
+            ("entity", &row.entity_id),
+            ("condition", &condition),
+            ("trueShare", &percent(row.prob_given_true)),
+            ("falseShare", &percent(row.prob_given_false)),
+            ("target", &templates.target),
+        ],
+    ))
+}
+
+fn percent(share: f64) -> String {
+    format!("{:.0}%", share * 100.0)
+}
+
+/// Up to two decimals, without trailing zeros
+fn format_value(value: f64) -> String {
+    let text = format!("{:.2}", value);
+    text.trim_end_matches('0').trim_end_matches('.').to_string()
+}
@@ -0,0 +1,431 @@
+use crate::clock;
+use crate::sensor_analysis::{split_virtual_entity_id, DerivedFeature};
+use crate::threshold::OptimalThresholds;
+use crate::types::EntityProbability;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Sensor-level settings shared by all generated configurations
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SensorSettings {
+    pub name: String,
+    pub prior: f64,
+    pub probability_threshold: f64,
+    pub device_class: Option<String>,
+}
+
+impl Default for SensorSettings {
+    fn default() -> Self {
+        Self {
+            name: "Bayesian Sensor".to_string(),
+            prior: 0.5,
+            probability_threshold: 0.5,
+            device_class: None,
+        }
+    }
+}
+
+/// The HA condition a result row turns into
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObservationCondition {
+    State { to_state: String },
+    /// Any of several merged states; exported as a `template` observation
+    AnyState { states: Vec<String> },
+    /// None of the states while the entity is available; exported as a `template`
+    /// observation
+    NotState { states: Vec<String> },
+    /// Arbitrary Jinja expression, e.g. an observation that only applies at night
+    Template { value_template: String },
+    /// With `rate`, the thresholds apply to the per-minute rate of a cumulative counter,
+    /// so the condition needs a derivative helper's entity id in place of the counter's.
+    /// `above` greater than `below` wraps around the end of a cyclic entity's values
+    NumericState { above: Option<f64>, below: Option<f64>, rate: bool },
+}
+
+impl ObservationCondition {
+    /// A wrapping range, which `numeric_state` cannot express
+    fn wraps(&self) -> bool {
+        matches!(self, ObservationCondition::NumericState { above: Some(above), below: Some(below), .. } if above > below)
+    }
+}
+
+/// A result row reduced to what a generated configuration needs
+#[derive(Debug, Clone)]
+pub struct Observation {
+    pub entity_id: String,
+    pub condition: ObservationCondition,
+    pub prob_given_true: f64,
+    pub prob_given_false: f64,
+}
+
+impl Observation {
+    /// Numeric rows become `numeric_state` observations, merged states `template`, everything else `state`
+    ///
+    /// Virtual entities have no state in HA: clock and attribute rows become
+    /// `template` observations reading the time or the attribute. Derived features
+    /// such as `::derivative` need past readings that a template cannot see, so
+    /// their rows have no observation.
+    pub fn from_result(row: &EntityProbability) -> Option<Self> {
+        let states = row.grouped_states.as_deref().unwrap_or(std::slice::from_ref(&row.state));
+        let clock_templates: Option<Vec<String>> = states.iter().map(|state| clock::template(&row.entity_id, state)).collect();
+        let attribute = match split_virtual_entity_id(&row.entity_id) {
+            Some((_, suffix)) if DerivedFeature::from_suffix(suffix).is_some() => return None,
+            attribute => attribute,
+        };
+        let bounded = |t: &OptimalThresholds| t.above.is_some() || t.below.is_some();
+        let condition = match (&row.optimal_thresholds, clock_templates, attribute) {
+            (Some(t), _, Some((entity_id, attribute))) if bounded(t) => ObservationCondition::Template {
+                value_template: numeric_jinja(&attribute_value(entity_id, attribute), t.above, t.below),
+            },
+            (Some(t), _, None) if bounded(t) => ObservationCondition::NumericState {
+                above: t.above,
+                below: t.below,
+                rate: row.numeric_stats.as_ref().is_some_and(|stats| stats.cumulative),
+            },
+            (Some(_), _, _) => return None,
+            // Clock entities only exist here, so HA checks the time itself
+            (None, Some(templates), _) => ObservationCondition::Template {
+                value_template: templates.join(" or "),
+            },
+            (None, None, Some((entity_id, attribute))) => {
+                let value = attribute_value(entity_id, attribute);
+                let matches: Vec<String> = states
+                    .iter()
+                    .map(|state| match state.as_str() {
+                        "unknown" => format!("{} is none", value),
+                        _ => format!("({} | string) == {}", value, jinja_string(state)),
+                    })
+                    .collect();
+                ObservationCondition::Template {
+                    value_template: format!("({})", matches.join(" or ")),
+                }
+            }
+            (None, None, None) if row.absence.is_some_and(|absence| absence.preferred) => {
+                ObservationCondition::NotState { states: states.to_vec() }
+            }
+            // A single grouped state is a renamed one; HA still knows it by its own name
+            (None, None, None) => match states {
+                [state] => ObservationCondition::State {
+                    to_state: state.clone(),
+                },
+                states => ObservationCondition::AnyState { states: states.to_vec() },
+            },
+        };
+
+        let (prob_given_true, prob_given_false) = match (&condition, row.absence) {
+            (ObservationCondition::NotState { .. }, Some(absence)) => (absence.prob_given_true, absence.prob_given_false),
+            _ => (row.prob_given_true, row.prob_given_false),
+        };
+        Some(Self {
+            entity_id: row.entity_id.clone(),
+            condition,
+            prob_given_true,
+            prob_given_false,
+        })
+    }
+
+    /// Whether the observation holds for an entity state; mirrors `jinja_condition`
+    ///
+    /// `None` when HA would skip the observation (`unavailable` or `unknown` states
+    /// that it does not explicitly match) and for template conditions, which need HA
+    /// to evaluate.
+    pub fn is_met(&self, state: &str) -> Option<bool> {
+        let unavailable = state == "unavailable" || state == "unknown";
+        match &self.condition {
+            ObservationCondition::State { to_state } if state == to_state => Some(true),
+            ObservationCondition::AnyState { states } if states.iter().any(|s| s == state) => Some(true),
+            ObservationCondition::NotState { states } if states.iter().any(|s| s == state) => Some(false),
+            _ if unavailable => None,
+            ObservationCondition::State { .. } | ObservationCondition::AnyState { .. } => Some(false),
+            ObservationCondition::NotState { .. } => Some(true),
+            // A single reading says nothing about a counter's rate
+            ObservationCondition::Template { .. } | ObservationCondition::NumericState { rate: true, .. } => None,
+            ObservationCondition::NumericState { above, below, .. } => {
+                let value = state.parse::<f64>().ok()?;
+                let (over, under) = (above.is_none_or(|a| value > a), below.is_none_or(|b| value <= b));
+                Some(if self.condition.wraps() { over || under } else { over && under })
+            }
+        }
+    }
+
+    /// Jinja expression that is true when the observation holds
+    pub fn jinja_condition(&self) -> String {
+        match &self.condition {
+            ObservationCondition::State { to_state } => {
+                format!("is_state({}, {})", jinja_string(&self.entity_id), jinja_string(to_state))
+            }
+            ObservationCondition::AnyState { states } => {
+                let states: Vec<String> = states.iter().map(|state| jinja_string(state)).collect();
+                format!("is_state({}, [{}])", jinja_string(&self.entity_id), states.join(", "))
+            }
+            ObservationCondition::NotState { states } => {
+                let states: Vec<String> = states
+                    .iter()
+                    .map(String::as_str)
+                    .chain(["unavailable", "unknown"])
+                    .map(jinja_string)
+                    .collect();
+                format!("not is_state({}, [{}])", jinja_string(&self.entity_id), states.join(", "))
+            }
+            ObservationCondition::Template { value_template } => value_template.clone(),
+            ObservationCondition::NumericState { above, below, .. } => {
+                numeric_jinja(&format!("states({})", jinja_string(&self.entity_id)), *above, *below)
+            }
+        }
+    }
+}
+
+/// Jinja expression reading an entity's attribute, as analysed by `analyzed_attributes`
+fn attribute_value(entity_id: &str, attribute: &str) -> String {
+    format!("state_attr({}, {})", jinja_string(entity_id), jinja_string(attribute))
+}
+
+/// Jinja expression that is true when `value` is a number within the thresholds;
+/// `above` greater than `below` wraps around
+fn numeric_jinja(value: &str, above: Option<f64>, below: Option<f64>) -> String {
+    let number = format!("{} | float(none)", value);
+    let mut parts = Vec::new();
+    if let Some(above) = above {
+        parts.push(format!("({}) > {}", number, above));
+    }
+    if let Some(below) = below {
+        parts.push(format!("({}) <= {}", number, below));
+    }
+    let condition = match (above, below) {
+        (Some(above), Some(below)) if above > below => format!("({})", parts.join(" or ")),
+        _ => parts.join(" and "),
+    };
+    format!("(is_number({}) and {})", value, condition)
+}
+
+/// Highest-ranked row for each of the first `count` distinct entities
+pub fn best_row_per_entity(results: &[EntityProbability], count: usize) -> Vec<EntityProbability> {
+    let mut ranked: Vec<&EntityProbability> = results.iter().collect();
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    let mut seen = std::collections::HashSet::new();
+    ranked
+        .into_iter()
+        .filter(|row| seen.insert(row.entity_id.as_str()))
+        .take(count)
+        .cloned()
+        .collect()
+}
+
+/// Observations for the selected rows, skipping rows that have no usable condition
+pub fn observations_from_results(rows: &[EntityProbability]) -> Vec<Observation> {
+    rows.iter().filter_map(Observation::from_result).collect()
+}
+
+/// Build a `binary_sensor` bayesian platform configuration for the selected rows
+pub fn generate_bayesian_yaml(rows: &[EntityProbability], settings: &SensorSettings) -> String {
+    generate_bayesian_yaml_for_observations(&observations_from_results(rows), settings)
+}
+
+/// Build a `binary_sensor` bayesian platform configuration from prepared observations
+pub fn generate_bayesian_yaml_for_observations(observations: &[Observation], settings: &SensorSettings) -> String {
+    let mut yaml = String::new();
+
+    yaml.push_str("binary_sensor:\n");
+    yaml.push_str("  - platform: bayesian\n");
+    yaml.push_str(&format!("    name: {}\n", yaml_string(&settings.name)));
+    yaml.push_str(&format!("    prior: {}\n", format_probability(settings.prior)));
+    yaml.push_str(&format!("    probability_threshold: {}\n", format_probability(settings.probability_threshold)));
+    if let Some(device_class) = &settings.device_class {
+        yaml.push_str(&format!("    device_class: {}\n", yaml_string(device_class)));
+    }
+    yaml.push_str("    observations:\n");
+
+    for observation in observations {
+        match &observation.condition {
+            ObservationCondition::State { to_state } => {
+                yaml.push_str("      - platform: state\n");
+                yaml.push_str(&format!("        entity_id: {}\n", observation.entity_id));
+                yaml.push_str(&format!("        to_state: {}\n", yaml_string(to_state)));
+            }
+            ObservationCondition::NumericState { above, below, rate } if !observation.condition.wraps() => {
+                yaml.push_str("      - platform: numeric_state\n");
+                if *rate {
+                    yaml.push_str("        # per-minute rate of this counter; point entity_id at a derivative helper of it\n");
+                }
+                yaml.push_str(&format!("        entity_id: {}\n", observation.entity_id));
+                if let Some(above) = above {
+                    yaml.push_str(&format!("        above: {}\n", above));
+                }
+                if let Some(below) = below {
+                    yaml.push_str(&format!("        below: {}\n", below));
+                }
+            }
+            // Wrapping ranges have no `numeric_state` form
+            ObservationCondition::AnyState { .. }
+            | ObservationCondition::NotState { .. }
+            | ObservationCondition::Template { .. }
+            | ObservationCondition::NumericState { .. } => {
+                yaml.push_str("      - platform: template\n");
+                yaml.push_str("        value_template: >\n");
+                yaml.push_str(&format!("          {{{{ {} }}}}\n", observation.jinja_condition()));
+            }
+        }
+        yaml.push_str(&format!("        prob_given_true: {}\n", format_probability(observation.prob_given_true)));
+        yaml.push_str(&format!("        prob_given_false: {}\n", format_probability(observation.prob_given_false)));
+    }
+
+    yaml
+}
+
+/// Flat CSV of result rows (one line per row) for triage in a spreadsheet
+///
+/// Cells for values a row does not carry, such as cross-validation without
+/// `crossValidation`, are left empty.
+pub fn generate_results_csv(rows: &[EntityProbability]) -> String {
+    let mut csv = String::from(
+        "entity_id,domain,state,above,below,prob_given_true,prob_given_false,discrimination_power,\
+         true_occurrences,false_occurrences,total_true_periods,total_false_periods,\
+         coverage,low_coverage,prob_given_true_lower,prob_given_true_upper,prob_given_false_lower,\
+         prob_given_false_upper,cv_folds,cv_min_discrimination,cv_prob_given_true_variance,\
+         cv_prob_given_false_variance,cv_overfit,possible_leakage\n",
+    );
+
+    for row in rows {
+        let domain = row.entity_id.split('.').next().unwrap_or_default();
+        let (above, below) = row
+            .optimal_thresholds
+            .as_ref()
+            .map_or((None, None), |t| (t.above, t.below));
+        let optional = |value: Option<f64>| value.map(|v| v.to_string()).unwrap_or_default();
+        let (true_interval, false_interval) = (&row.prob_given_true_interval, &row.prob_given_false_interval);
+        let cv = row.cross_validation.as_ref();
+
+        let fields = [
+            csv_field(&row.entity_id),
+            csv_field(domain),
+            csv_field(&row.state),
+            optional(above),
+            optional(below),
+            row.prob_given_true.to_string(),
+            row.prob_given_false.to_string(),
+            row.discrimination_power.to_string(),
+            row.true_occurrences.to_string(),
+            row.false_occurrences.to_string(),
+            row.total_true_periods.to_string(),
+            row.total_false_periods.to_string(),
+            optional(row.coverage),
+            row.low_coverage.to_string(),
+            optional(true_interval.as_ref().map(|interval| interval.lower)),
+            optional(true_interval.as_ref().map(|interval| interval.upper)),
+            optional(false_interval.as_ref().map(|interval| interval.lower)),
+            optional(false_interval.as_ref().map(|interval| interval.upper)),
+            cv.map(|cv| cv.folds.to_string()).unwrap_or_default(),
+            optional(cv.map(|cv| cv.min_discrimination)),
+            optional(cv.map(|cv| cv.prob_given_true_variance)),
+            optional(cv.map(|cv| cv.prob_given_false_variance)),
+            cv.map(|cv| cv.overfit.to_string()).unwrap_or_default(),
+            row.possible_leakage.to_string(),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+/// Quote a CSV field when it contains a delimiter, quote or newline
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Probabilities rounded to four decimals, trailing zeros trimmed
+pub fn format_probability(value: f64) -> String {
+    let formatted = format!("{:.4}", value);
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    if trimmed.is_empty() { "0".to_string() } else { trimmed.to_string() }
+}
+
+/// Build an importable template blueprint that evaluates the Bayesian sensor in Jinja
+///
+/// HA's bayesian platform cannot be blueprinted, but template blueprints can be
+/// imported from the UI. The generated template reproduces the bayesian update
+/// (including the complement for observations that are not met) and exposes the
+/// prior and probability threshold as blueprint inputs.
+pub fn generate_blueprint(rows: &[EntityProbability], settings: &SensorSettings) -> String {
+    let observations = observations_from_results(rows);
+    let mut yaml = String::new();
+
+    yaml.push_str("blueprint:\n");
+    yaml.push_str(&format!("  name: {}\n", yaml_string(&settings.name)));
+    yaml.push_str("  description: Bayesian sensor generated by HA Bayesian Sensor Generator\n");
+    yaml.push_str("  domain: template\n");
+    yaml.push_str("  input:\n");
+    push_number_input(&mut yaml, "prior", "Prior probability", settings.prior);
+    push_number_input(&mut yaml, "probability_threshold", "Probability threshold", settings.probability_threshold);
+    yaml.push_str("variables:\n");
+    yaml.push_str("  prior: !input prior\n");
+    yaml.push_str("  probability_threshold: !input probability_threshold\n");
+    yaml.push_str("binary_sensor:\n");
+    if let Some(device_class) = &settings.device_class {
+        yaml.push_str(&format!("  device_class: {}\n", yaml_string(device_class)));
+    }
+    yaml.push_str("  state: >\n");
+    yaml.push_str("    {% set ns = namespace(p=prior | float) %}\n");
+    yaml.push_str("    {% set observations = [\n");
+    for (i, observation) in observations.iter().enumerate() {
+        let separator = if i + 1 < observations.len() { "," } else { "" };
+        yaml.push_str(&format!(
+            "      [{}, {}, {}]{}\n",
+            observation.jinja_condition(),
+            format_probability(observation.prob_given_true),
+            format_probability(observation.prob_given_false),
+            separator
+        ));
+    }
+    yaml.push_str("    ] %}\n");
+    yaml.push_str("    {% for met, p_true, p_false in observations %}\n");
+    yaml.push_str("      {% set t = p_true if met else 1 - p_true %}\n");
+    yaml.push_str("      {% set f = p_false if met else 1 - p_false %}\n");
+    yaml.push_str("      {% set ns.p = (t * ns.p) / (t * ns.p + f * (1 - ns.p)) %}\n");
+    yaml.push_str("    {% endfor %}\n");
+    yaml.push_str("    {{ ns.p >= probability_threshold | float }}\n");
+
+    yaml
+}
+
+fn push_number_input(yaml: &mut String, key: &str, name: &str, default: f64) {
+    yaml.push_str(&format!("    {}:\n", key));
+    yaml.push_str(&format!("      name: {}\n", name));
+    yaml.push_str(&format!("      default: {}\n", default));
+    yaml.push_str("      selector:\n");
+    yaml.push_str("        number:\n");
+    yaml.push_str("          min: 0.01\n");
+    yaml.push_str("          max: 0.99\n");
+    yaml.push_str("          step: 0.01\n");
+    yaml.push_str("          mode: box\n");
+}
+
+/// Object id HA derives from an entity name, e.g. "Office Occupied A" -> "office_occupied_a"
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.chars().flat_map(char::to_lowercase) {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.ends_with('_') {
+            slug.push('_');
+        }
+    }
+    slug.trim_matches('_').to_string()
+}
+
+/// Double-quoted YAML scalar
+pub fn yaml_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Single-quoted Jinja string literal
+pub fn jinja_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
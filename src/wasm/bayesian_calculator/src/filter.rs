@@ -0,0 +1,106 @@
+use crate::options::CalculatorOptions;
+use crate::types::EntityMetadata;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Least evidence a result row needs before its probabilities are trusted
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct MinimumEvidence {
+    /// Labeled time, TRUE and FALSE together, the row's state or range must have held for
+    pub min_duration_ms: i64,
+    /// Labeled periods the state, or a value in the row's range, must have been seen in
+    pub min_periods: usize,
+    /// Leave rows below either minimum out of the results instead of flagging them
+    pub drop_rows: bool,
+}
+
+impl Default for MinimumEvidence {
+    fn default() -> Self {
+        Self {
+            min_duration_ms: 300_000,
+            min_periods: 2,
+            drop_rows: false,
+        }
+    }
+}
+
+impl MinimumEvidence {
+    /// Whether a row seen for `duration_ms` in `periods` periods falls below the minimums
+    pub fn lacking(&self, duration_ms: i64, periods: Option<usize>) -> bool {
+        duration_ms < self.min_duration_ms || periods.is_some_and(|periods| periods < self.min_periods)
+    }
+}
+
+/// Decide whether an entity should be excluded from analysis
+///
+/// Returns a human-readable reason when the entity is filtered out, so the
+/// decision can be reported back to the caller as a warning
+pub fn exclusion_reason(
+    entity_id: &str,
+    metadata: Option<&EntityMetadata>,
+    options: &CalculatorOptions,
+) -> Option<String> {
+    if options.target_entity.as_deref() == Some(entity_id) {
+        return Some("entity is the target".to_string());
+    }
+
+    if !options.include_entities.is_empty() && !options.include_entities.iter().any(|p| matches_glob(p, entity_id)) {
+        return Some("entity matches none of the include patterns".to_string());
+    }
+
+    if let Some(pattern) = options.exclude_entities.iter().find(|p| matches_glob(p, entity_id)) {
+        return Some(format!("entity matches exclude pattern '{}'", pattern));
+    }
+
+    let metadata = metadata?;
+
+    if metadata.disabled && !options.include_disabled {
+        return Some("entity is disabled".to_string());
+    }
+
+    if metadata.hidden && !options.include_hidden {
+        return Some("entity is hidden".to_string());
+    }
+
+    if let Some(category) = &metadata.entity_category {
+        if options.excluded_entity_categories.iter().any(|c| c == category) {
+            return Some(format!("entity category '{}' is excluded", category));
+        }
+    }
+
+    None
+}
+
+/// Whether `text` matches a glob `pattern` where `*` stands for any run of
+/// characters and `?` for a single one
+pub fn matches_glob(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text position it was tried at, for backtracking
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
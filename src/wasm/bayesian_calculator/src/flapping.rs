@@ -0,0 +1,93 @@
+use crate::sensor_analysis::{NumericStateStats, ValueDuration};
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+const MS_PER_HOUR: f64 = 3_600_000.0;
+
+/// How the threshold search steers away from values a noisy sensor keeps crossing
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct HysteresisOptions {
+    /// A reading held for less than this before the value moves across a threshold
+    /// counts as flapping
+    pub flap_window_ms: i64,
+    /// Separation subtracted from a threshold per flapping crossing an hour
+    pub penalty: f64,
+}
+
+impl Default for HysteresisOptions {
+    fn default() -> Self {
+        Self {
+            flap_window_ms: 60_000,
+            penalty: 0.05,
+        }
+    }
+}
+
+/// Consecutive readings of each class where the first was short-lived, as (from, to) values
+///
+/// Chunks are kept in time order per class, so neighbours are successive readings.
+/// Aggregated statistics (`streaming_aggregation`) have lost that order and yield
+/// no pairs.
+fn rapid_moves<'a>(stats: &'a NumericStateStats, options: &'a HysteresisOptions) -> impl Iterator<Item = (f64, f64)> + 'a {
+    [&stats.true_chunks, &stats.false_chunks]
+        .into_iter()
+        .flat_map(|chunks| chunks.windows(2))
+        .filter(|pair| pair[0].duration < options.flap_window_ms)
+        .map(|pair| (pair[0].value, pair[1].value))
+}
+
+fn total_duration(chunks: &[ValueDuration]) -> i64 {
+    chunks.iter().map(|chunk| chunk.duration).sum()
+}
+
+/// Penalty of each cut (in increasing order) from its flapping crossings per hour
+///
+/// A difference array over the cuts counts every rapid move once for each cut it
+/// jumps over, so the cost is one binary search per move rather than one per cut.
+pub fn cut_penalties(stats: &NumericStateStats, cuts: &[f64], options: &HysteresisOptions) -> Vec<f64> {
+    let mut crossings = vec![0i64; cuts.len() + 1];
+    for (from, to) in rapid_moves(stats, options) {
+        let start = cuts.partition_point(|&cut| cut < from.min(to));
+        let end = cuts.partition_point(|&cut| cut < from.max(to));
+        crossings[start] += 1;
+        crossings[end] -= 1;
+    }
+
+    let hours = (total_duration(&stats.true_chunks) + total_duration(&stats.false_chunks)) as f64 / MS_PER_HOUR;
+    if hours <= 0.0 {
+        return vec![0.0; cuts.len()];
+    }
+
+    let mut running = 0;
+    crossings[..cuts.len()]
+        .iter()
+        .map(|&change| {
+            running += change;
+            options.penalty * running as f64 / hours
+        })
+        .collect()
+}
+
+/// Suggested hysteresis around the chosen thresholds: half the median jump of the
+/// flapping crossings over either of them
+///
+/// `None` when neither threshold is crossed rapidly.
+pub fn hysteresis(stats: &NumericStateStats, bounds: [Option<f64>; 2], options: &HysteresisOptions) -> Option<f64> {
+    let mut jumps: Vec<f64> = rapid_moves(stats, options)
+        .filter(|&(from, to)| {
+            bounds
+                .iter()
+                .flatten()
+                .any(|&bound| from.min(to) <= bound && bound < from.max(to))
+        })
+        .map(|(from, to)| (to - from).abs() / 2.0)
+        .collect();
+    if jumps.is_empty() {
+        return None;
+    }
+
+    jumps.sort_by(f64::total_cmp);
+    Some(jumps[jumps.len() / 2])
+}
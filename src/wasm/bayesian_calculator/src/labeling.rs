@@ -0,0 +1,329 @@
+use crate::export::yaml_string;
+use crate::sensor_analysis::is_unavailable;
+use crate::time;
+use crate::types::{HAHistoryEntry, TimePeriod, Timestamp};
+use chrono::{Datelike, Duration};
+use chrono_tz::Tz;
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct LabelingHelperSettings {
+    /// Object id of the generated `input_boolean` (without the domain)
+    pub helper_id: String,
+    /// What the helper means when on, e.g. "Someone is in the office"
+    pub name: String,
+}
+
+impl Default for LabelingHelperSettings {
+    fn default() -> Self {
+        Self {
+            helper_id: "bayesian_ground_truth".to_string(),
+            name: "Bayesian ground truth".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct LabelImportOptions {
+    /// Helper state that marks a TRUE period; every other known state is FALSE
+    pub true_state: String,
+    /// Close the final run at this time instead of dropping it
+    pub end: Option<Timestamp>,
+    /// Runs shorter than this are dropped (accidental toggles)
+    pub min_duration_ms: i64,
+    /// Prefix for generated period ids
+    pub id_prefix: String,
+}
+
+impl Default for LabelImportOptions {
+    fn default() -> Self {
+        Self {
+            true_state: "on".to_string(),
+            end: None,
+            min_duration_ms: 60_000,
+            id_prefix: "label".to_string(),
+        }
+    }
+}
+
+/// YAML for an `input_boolean` plus an automation that logs every toggle to the logbook
+///
+/// Users flip the helper whenever the target condition starts or stops; after a
+/// week its recorded history can be turned into periods with `import_periods`.
+pub fn generate_helper(settings: &LabelingHelperSettings) -> String {
+    let entity_id = format!("input_boolean.{}", settings.helper_id);
+    let mut yaml = String::new();
+
+    yaml.push_str("input_boolean:\n");
+    yaml.push_str(&format!("  {}:\n", settings.helper_id));
+    yaml.push_str(&format!("    name: {}\n", yaml_string(&settings.name)));
+    yaml.push_str("    icon: mdi:tag-check-outline\n");
+    yaml.push('\n');
+    yaml.push_str("automation:\n");
+    yaml.push_str(&format!("  - alias: {}\n", yaml_string(&format!("Log {}", settings.name))));
+    yaml.push_str("    mode: queued\n");
+    yaml.push_str("    trigger:\n");
+    yaml.push_str("      - platform: state\n");
+    yaml.push_str(&format!("        entity_id: {}\n", entity_id));
+    yaml.push_str("    action:\n");
+    yaml.push_str("      - service: logbook.log\n");
+    yaml.push_str("        data:\n");
+    yaml.push_str(&format!("          name: {}\n", yaml_string(&settings.name)));
+    yaml.push_str("          message: \"labeled {{ trigger.to_state.state }}\"\n");
+    yaml.push_str(&format!("          entity_id: {}\n", entity_id));
+
+    yaml
+}
+
+/// Turn a labeling helper's history into TRUE/FALSE periods
+///
+/// Each run of consecutive identical states becomes one period. Runs in
+/// `unavailable`/`unknown` are gaps, and the last run is only kept when `end` is set.
+pub fn import_periods(history: &[HAHistoryEntry], options: &LabelImportOptions) -> Vec<TimePeriod> {
+    let mut entries: Vec<&HAHistoryEntry> = history.iter().collect();
+    entries.sort_by_key(|e| e.last_changed.millis());
+
+    // Collapse repeated states so each run starts at its first entry
+    let mut runs: Vec<(&str, &Timestamp)> = Vec::new();
+    for entry in entries {
+        if runs.last().is_none_or(|&(state, _)| state != entry.state) {
+            runs.push((entry.state.as_str(), &entry.last_changed));
+        }
+    }
+
+    let mut periods = Vec::new();
+    for (i, &(state, start)) in runs.iter().enumerate() {
+        if state == "unavailable" || state == "unknown" {
+            continue;
+        }
+
+        let end = match (runs.get(i + 1), &options.end) {
+            (Some(&(_, next_start)), _) => next_start,
+            (None, Some(end)) => end,
+            (None, None) => continue,
+        };
+
+        if end.millis() - start.millis() < options.min_duration_ms {
+            continue;
+        }
+
+        let is_true_period = state == options.true_state;
+        periods.push(TimePeriod {
+            id: format!("{}-{}", options.id_prefix, periods.len()),
+            start: start.clone(),
+            end: end.clone(),
+            is_true_period,
+            label: Some(state.to_string()),
+            class: Some(state.to_string()),
+            confidence: None,
+        });
+    }
+
+    periods
+}
+
+/// Turn a `schedule.*` helper's on/off history into TRUE/FALSE periods
+///
+/// Schedules switch exactly at their block boundaries, so no minimum duration
+/// applies. `end` closes the block that is still active when the history ends.
+pub fn import_schedule_periods(history: &[HAHistoryEntry], end: Option<String>) -> Vec<TimePeriod> {
+    let options = LabelImportOptions {
+        true_state: "on".to_string(),
+        end: end.map(Timestamp::Iso),
+        min_duration_ms: 0,
+        id_prefix: "schedule".to_string(),
+    };
+
+    import_periods(history, &options)
+}
+
+/// Where an analysis gets its TRUE/FALSE periods from
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(tag = "type", rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum PeriodSource {
+    /// Explicitly labeled periods, as otherwise passed as a plain array
+    List { periods: Vec<TimePeriod> },
+    /// TRUE whenever an entity in the supplied history was in one of `states` (or,
+    /// with `invert`, in any other state), FALSE otherwise
+    ///
+    /// Only time between `start` and `end` counts; either defaults to the entity's
+    /// first or last recorded change. The entity itself is left out of the analysis,
+    /// since it would trivially predict its own periods.
+    #[serde(rename_all = "camelCase")]
+    Entity {
+        entity_id: String,
+        states: Vec<String>,
+        #[serde(default)]
+        invert: bool,
+        #[serde(default)]
+        start: Option<Timestamp>,
+        #[serde(default)]
+        end: Option<Timestamp>,
+    },
+    /// A weekly schedule: TRUE from `start_hour` to `end_hour` local time on each of
+    /// `weekdays` (ISO numbers, 1 = Monday; empty for every day), FALSE in between
+    ///
+    /// An `end_hour` at or before `start_hour` wraps past midnight, so 22 to 6 is a
+    /// night. Covers the `days` days up to `end`, which defaults to the latest change
+    /// in the history. Expanded in `timezone`, so windows keep their wall-clock times
+    /// across DST transitions.
+    #[serde(rename_all = "camelCase")]
+    Recurring {
+        timezone: String,
+        #[serde(default)]
+        weekdays: Vec<u32>,
+        start_hour: f64,
+        end_hour: f64,
+        days: u32,
+        #[serde(default)]
+        end: Option<Timestamp>,
+    },
+}
+
+impl PeriodSource {
+    /// The periods, taking a referenced entity out of `history`
+    pub fn materialize(self, history: &mut HashMap<String, Vec<HAHistoryEntry>>) -> Result<Vec<TimePeriod>, String> {
+        match self {
+            PeriodSource::List { periods } => Ok(periods),
+            PeriodSource::Entity {
+                entity_id,
+                states,
+                invert,
+                start,
+                end,
+            } => {
+                let entity_history = history
+                    .remove(&entity_id)
+                    .ok_or_else(|| format!("{} is not in the history", entity_id))?;
+                let range = (start.map(|t| t.millis()), end.map(|t| t.millis()));
+                Ok(entity_periods(&entity_history, &entity_id, |state| states.iter().any(|s| s == state) != invert, range))
+            }
+            PeriodSource::Recurring {
+                timezone,
+                weekdays,
+                start_hour,
+                end_hour,
+                days,
+                end,
+            } => {
+                let zone = time::parse_zone(&timezone)?;
+                let end = match end {
+                    Some(end) => end.millis(),
+                    None => history
+                        .values()
+                        .flatten()
+                        .map(|entry| entry.last_changed.millis())
+                        .max()
+                        .ok_or("a recurring schedule without an end needs history to end at")?,
+                };
+                let start = end - Duration::days(days.into()).num_milliseconds();
+                Ok(recurring_periods(zone, &weekdays, (start_hour, end_hour), (start, end)))
+            }
+        }
+    }
+}
+
+/// Alternating TRUE schedule windows and the FALSE gaps between them over `range`
+fn recurring_periods(zone: Tz, weekdays: &[u32], hours: (f64, f64), range: (i64, i64)) -> Vec<TimePeriod> {
+    let (start_hour, end_hour) = hours;
+    let end_hour = if end_hour <= start_hour { end_hour + 24.0 } else { end_hour };
+    let (range_start, range_end) = range;
+
+    // A window starting the day before the range may reach into it
+    let mut windows: Vec<(i64, i64)> = Vec::new();
+    let mut date = time::local_date(zone, range_start) - Duration::days(1);
+    while date <= time::local_date(zone, range_end) {
+        if weekdays.is_empty() || weekdays.contains(&date.weekday().number_from_monday()) {
+            let from = time::local_instant(zone, date, start_hour).max(range_start);
+            let to = time::local_instant(zone, date, end_hour).min(range_end);
+            match windows.last_mut() {
+                _ if to <= from => {}
+                Some(last) if from <= last.1 => last.1 = last.1.max(to),
+                _ => windows.push((from, to)),
+            }
+        }
+        date += Duration::days(1);
+    }
+
+    let mut periods = Vec::new();
+    let mut push = |start: i64, end: i64, is_true_period: bool| {
+        if end > start {
+            periods.push(TimePeriod {
+                id: format!("recurring-{}", periods.len()),
+                start: Timestamp::from_millis(start),
+                end: Timestamp::from_millis(end),
+                is_true_period,
+                label: None,
+                class: None,
+                confidence: None,
+            });
+        }
+    };
+    let mut cursor = range_start;
+    for (from, to) in windows {
+        push(cursor, from, false);
+        push(from, to, true);
+        cursor = to;
+    }
+    push(cursor, range_end, false);
+
+    periods
+}
+
+/// Periods from the runs of an entity's history in TRUE and in FALSE states
+///
+/// Runs in `unavailable`/`unknown` are gaps, and consecutive runs of the same class
+/// merge into one period. `range` bounds are optional and default to the first and
+/// last recorded change.
+fn entity_periods(
+    history: &[HAHistoryEntry],
+    entity_id: &str,
+    is_true: impl Fn(&str) -> bool,
+    range: (Option<i64>, Option<i64>),
+) -> Vec<TimePeriod> {
+    let mut entries: Vec<&HAHistoryEntry> = history.iter().collect();
+    entries.sort_by_key(|e| e.last_changed.millis());
+    let (Some(first), Some(last)) = (entries.first(), entries.last()) else {
+        return Vec::new();
+    };
+    let (range_start, range_end) = (
+        range.0.unwrap_or(first.last_changed.millis()),
+        range.1.unwrap_or(last.last_changed.millis()),
+    );
+
+    // (start, end, class) with `None` for gaps, clipped to the range
+    let mut runs: Vec<(i64, i64, Option<bool>)> = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let start = entry.last_changed.millis().max(range_start);
+        let end = entries.get(i + 1).map_or(range_end, |next| next.last_changed.millis()).min(range_end);
+        if end <= start {
+            continue;
+        }
+        let class = (!is_unavailable(&entry.state)).then(|| is_true(&entry.state));
+        match runs.last_mut() {
+            Some(run) if run.2 == class && run.1 == start => run.1 = end,
+            _ => runs.push((start, end, class)),
+        }
+    }
+
+    runs.into_iter()
+        .filter_map(|(start, end, class)| Some((start, end, class?)))
+        .enumerate()
+        .map(|(i, (start, end, is_true_period))| TimePeriod {
+            id: format!("{}-{}", entity_id, i),
+            start: Timestamp::from_millis(start),
+            end: Timestamp::from_millis(end),
+            is_true_period,
+            label: None,
+            class: None,
+            confidence: None,
+        })
+        .collect()
+}
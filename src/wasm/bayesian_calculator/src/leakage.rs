@@ -0,0 +1,148 @@
+use crate::clock;
+use crate::sensor_analysis;
+use crate::types::{EntityProbability, HAHistoryEntry, TimePeriod};
+use rustc_hash::{FxHashMap, FxHashSet};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// How close an entity change must be to a class change to count as lining up with it
+const BOUNDARY_TOLERANCE_MS: i64 = 60_000;
+/// Share of class changes an entity must follow before it looks derived from the labels
+const MIN_ALIGNMENT: f64 = 0.8;
+/// Fewest class changes the alignment is judged on
+const MIN_BOUNDARIES: usize = 4;
+/// Highest share of random instants near an entity change for the alignment to mean
+/// anything; a sensor reporting every minute lines up with every boundary
+const MAX_CHANCE_ALIGNMENT: f64 = 0.2;
+/// Discrimination above which a row is suspiciously good
+const MIN_DISCRIMINATION: f64 = 0.9;
+
+/// Signs that an entity was derived from the target it is meant to predict
+///
+/// Additive, so the evidence from separately analysed periods can be combined.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct LeakageEvidence {
+    /// Changes between a TRUE and a FALSE period
+    pub boundaries: usize,
+    /// Those with a state change of the entity within `BOUNDARY_TOLERANCE_MS`
+    pub aligned: usize,
+    /// Entity changes and length of the labeled span they were counted over, for the
+    /// alignment expected by chance
+    pub changes: usize,
+    pub span_ms: i64,
+    /// The entity carries the attributes of an HA `bayesian` binary sensor
+    pub bayesian: bool,
+}
+
+impl LeakageEvidence {
+    pub fn collect(entity_history: &[HAHistoryEntry], periods: &[TimePeriod]) -> Self {
+        let mut changes: Vec<i64> = entity_history.iter().map(|entry| entry.last_changed.millis()).collect();
+        changes.sort_unstable();
+        let boundaries = class_changes(periods);
+        let aligned = boundaries
+            .iter()
+            .filter(|&&boundary| {
+                let first = changes.partition_point(|&change| change < boundary - BOUNDARY_TOLERANCE_MS);
+                changes.get(first).is_some_and(|&change| change <= boundary + BOUNDARY_TOLERANCE_MS)
+            })
+            .count();
+        let (start, end) = (
+            periods.iter().map(|period| period.start.millis()).min().unwrap_or(0),
+            periods.iter().map(|period| period.end.millis()).max().unwrap_or(0),
+        );
+        let bayesian = entity_history
+            .iter()
+            .find_map(|entry| entry.attributes.as_ref())
+            .is_some_and(|attributes| attributes.get("probability_threshold").is_some() && attributes.get("observations").is_some());
+
+        Self {
+            boundaries: boundaries.len(),
+            aligned,
+            changes: changes.iter().filter(|&&change| start <= change && change < end).count(),
+            span_ms: (end - start).max(0),
+            bayesian,
+        }
+    }
+
+    pub fn add(&mut self, other: LeakageEvidence) {
+        self.boundaries += other.boundaries;
+        self.aligned += other.aligned;
+        self.changes += other.changes;
+        self.span_ms += other.span_ms;
+        self.bayesian |= other.bayesian;
+    }
+
+    fn follows_labels(&self) -> bool {
+        let chance = if self.span_ms > 0 {
+            (self.changes as f64 * 2.0 * BOUNDARY_TOLERANCE_MS as f64 / self.span_ms as f64).min(1.0)
+        } else {
+            1.0
+        };
+        self.boundaries >= MIN_BOUNDARIES
+            && chance <= MAX_CHANCE_ALIGNMENT
+            && self.aligned as f64 >= MIN_ALIGNMENT * self.boundaries as f64
+    }
+}
+
+/// Period starts and ends where the class changes; a period directly following one
+/// of the same class adds no boundary
+fn class_changes(periods: &[TimePeriod]) -> Vec<i64> {
+    let edges: Vec<(i64, bool)> = periods
+        .iter()
+        .flat_map(|period| {
+            let is_true = sensor_analysis::true_weight(period) >= 0.5;
+            [(period.start.millis(), is_true), (period.end.millis(), is_true)]
+        })
+        .collect();
+    let mut counts: FxHashMap<(i64, bool), usize> = FxHashMap::default();
+    for &edge in &edges {
+        *counts.entry(edge).or_default() += 1;
+    }
+
+    // An edge shared by two periods of the same class is where one ends and the next starts
+    let mut boundaries: Vec<i64> = counts
+        .into_iter()
+        .filter(|&(_, count)| count == 1)
+        .map(|((time, _), _)| time)
+        .collect();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+    boundaries
+}
+
+/// Set `possible_leakage` on the rows of entities that look derived from the target
+///
+/// An existing `bayesian` sensor is always flagged. Other entities are flagged when
+/// their best row discriminates almost perfectly and their state changes line up
+/// with the period boundaries, as a template over the labeling helper would.
+/// Virtual entities are judged by their own history where they have one (attributes)
+/// and by their source entity's otherwise; clock entities are left alone, since
+/// schedule-based labels line up with them by design.
+pub fn mark_possible_leakage(rows: &mut [EntityProbability], evidence: &HashMap<String, LeakageEvidence>) {
+    let evidence_for = |entity_id: &str| {
+        evidence.get(entity_id).or_else(|| {
+            let (source, _) = sensor_analysis::split_virtual_entity_id(entity_id)?;
+            evidence.get(source)
+        })
+    };
+
+    let mut best: FxHashMap<&str, f64> = FxHashMap::default();
+    for row in rows.iter() {
+        let entry = best.entry(row.entity_id.as_str()).or_insert(0.0);
+        *entry = entry.max(row.discrimination_power);
+    }
+    let flagged: FxHashSet<String> = best
+        .into_iter()
+        .filter(|&(entity_id, _)| !clock::is_clock_entity(entity_id))
+        .filter(|&(entity_id, discrimination)| {
+            evidence_for(entity_id).is_some_and(|evidence| {
+                evidence.bayesian || (discrimination >= MIN_DISCRIMINATION && evidence.follows_labels())
+            })
+        })
+        .map(|(entity_id, _)| entity_id.to_string())
+        .collect();
+
+    for row in rows.iter_mut() {
+        row.possible_leakage = flagged.contains(&row.entity_id);
+    }
+}
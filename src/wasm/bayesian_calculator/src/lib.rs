@@ -1,21 +1,197 @@
 mod types;
+mod error;
+mod cancellation;
+mod persistence;
+mod entity_analysis;
+mod options;
+mod filter;
+mod day_night;
 mod sensor_analysis;
+mod timeline;
 mod threshold;
+mod flapping;
+mod scheduling;
+mod segmentation;
+mod validation;
+mod self_test;
+mod shrinkage;
+mod smoothing;
+mod scoring;
+mod simulation;
+mod evaluation;
+mod selection;
+mod correlation;
+mod clock;
+mod breakdown;
+mod coverage;
+mod bootstrap;
+mod cross_validation;
+mod density;
+mod distribution;
+mod state_grouping;
+mod rng;
+mod synthetic;
+mod time;
+mod trace;
+mod diff;
+mod export;
+mod explanation;
+mod labeling;
+mod leakage;
+mod multiclass;
+mod report;
+mod posterior;
+mod posterior_range;
+mod operating_points;
+mod pooling;
+mod prior;
+mod preprocessing;
+mod ranking;
+mod ensemble;
+mod trigger_template;
+#[cfg(feature = "parallel")]
+mod thread_pool;
 
 use wasm_bindgen::prelude::*;
-use types::{EntityProbability, TimePeriod, HAHistoryEntry};
+use types::{AnalysisWarning, EntityMetadata, EntityProbability, TimePeriod, HAHistoryEntry, RefinementEvent, WarningKind};
+use options::CalculatorOptions;
+use error::CalculatorError;
+use cancellation::{CancellationFlag, CancellationHandle};
+use timeline::TimelineEngine;
+use rustc_hash::FxHashMap;
+use std::borrow::Cow;
+
+/// History entries by entity id
+type History = std::collections::HashMap<String, Vec<HAHistoryEntry>>;
 
 #[cfg(feature = "parallel")]
 pub use wasm_bindgen_rayon::init_thread_pool;
+#[cfg(feature = "parallel")]
+pub use thread_pool::{init_thread_pool_with_spawner, run_pool_worker};
 
 #[wasm_bindgen(start)]
 pub fn main() {
     console_error_panic_hook::set_once();
 }
 
+/// Verify this build computes correctly on the current platform using an embedded golden dataset
+#[wasm_bindgen]
+pub fn self_test() -> Result<JsValue, JsValue> {
+    serde_wasm_bindgen::to_value(&self_test::run())
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize self-test report: {}", e)))
+}
+
+/// Generate synthetic entity histories and labeled periods for demos, benchmarks and UI testing
+///
+/// Missing config fields fall back to a week-long living-room example.
+#[wasm_bindgen]
+pub fn generate_synthetic_dataset(config: JsValue) -> Result<JsValue, JsValue> {
+    let config: synthetic::SyntheticConfig = parse_optional(config, "synthetic config")?;
+
+    let dataset = synthetic::generate(&config).map_err(|e| JsValue::from_str(&e))?;
+
+    serde_wasm_bindgen::to_value(&dataset)
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize synthetic dataset: {}", e)))
+}
+
+/// YAML for an `input_boolean` and logging automation used to collect ground-truth labels
+#[wasm_bindgen]
+pub fn generate_labeling_helper(settings: JsValue) -> Result<String, JsValue> {
+    let settings: labeling::LabelingHelperSettings = parse_optional(settings, "labeling helper settings")?;
+    Ok(labeling::generate_helper(&settings))
+}
+
+/// Convert a labeling helper's recorded history into TRUE/FALSE periods for the next analysis
+#[wasm_bindgen]
+pub fn import_labeling_periods(history: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+    let history: Vec<HAHistoryEntry> = serde_wasm_bindgen::from_value(history)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse helper history: {}", e)))?;
+    let options: labeling::LabelImportOptions = parse_optional(options, "label import options")?;
+
+    serde_wasm_bindgen::to_value(&labeling::import_periods(&history, &options))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize periods: {}", e)))
+}
+
+/// Convert a `schedule.*` helper's on/off history into TRUE/FALSE periods
+///
+/// `end` (ISO 8601) closes the schedule block still active at the end of the history.
+#[wasm_bindgen]
+pub fn import_schedule_periods(history: JsValue, end: Option<String>) -> Result<JsValue, JsValue> {
+    let history: Vec<HAHistoryEntry> = serde_wasm_bindgen::from_value(history)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse schedule history: {}", e)))?;
+
+    serde_wasm_bindgen::to_value(&labeling::import_schedule_periods(&history, end))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize periods: {}", e)))
+}
+
+/// Suggested prior from the labeled periods, by TRUE share of time or of period count
+///
+/// Returns `null` when the periods cover no time.
+#[wasm_bindgen]
+pub fn estimate_prior(periods: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+    let periods: Vec<TimePeriod> = serde_wasm_bindgen::from_value(periods)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse periods: {}", e)))?;
+    let options: prior::PeriodPriorOptions = parse_optional(options, "prior options")?;
+
+    serde_wasm_bindgen::to_value(&prior::estimate_from_periods(&periods, &options))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize prior estimate: {}", e)))
+}
+
+/// Propose a prior from the history or long-term statistics of an existing related sensor
+///
+/// `periods` is optional; labeled periods overlapping the archive let the estimate
+/// correct for the related sensor's misses and false alarms. Returns `null` when the
+/// archive has no usable on/off time.
+#[wasm_bindgen]
+pub fn estimate_prior_from_related_sensor(data: JsValue, periods: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+    let data: prior::RelatedSensorData = serde_wasm_bindgen::from_value(data)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse related sensor data: {}", e)))?;
+    let periods: Vec<TimePeriod> = parse_optional(periods, "periods")?;
+    let options: prior::RelatedSensorOptions = parse_optional(options, "related sensor options")?;
+
+    serde_wasm_bindgen::to_value(&prior::estimate_from_related(&data, &periods, &options))
+        .map_err(|e| JsValue::from_str(&format!("Failed to serialize prior estimate: {}", e)))
+}
+
 #[wasm_bindgen]
 pub struct BayesianCalculator {
-    threshold_cache: std::collections::HashMap<String, threshold::ThresholdCache>,
+    /// Shared with the background refinement of `calculate_progressive`, which adds
+    /// the exact thresholds it finds
+    threshold_cache: std::rc::Rc<std::cell::RefCell<std::collections::HashMap<String, threshold::ThresholdCache>>>,
+    options: CalculatorOptions,
+    entity_metadata: std::collections::HashMap<String, EntityMetadata>,
+    warnings: Vec<AnalysisWarning>,
+    numeric_stats: std::collections::HashMap<String, sensor_analysis::NumericStateStats>,
+    state_stats: std::collections::HashMap<String, FxHashMap<String, types::StateDurationStats>>,
+    /// TRUE and FALSE periods behind the stored statistics
+    period_counts: (usize, usize),
+    /// Per-period profiles for correlation detection, covering `profiled_periods` periods
+    profiles: std::collections::HashMap<String, correlation::EntityProfile>,
+    profiled_periods: usize,
+    /// Recorded-data coverage of each analysed period per entity
+    coverage: std::collections::HashMap<String, Vec<coverage::PeriodCoverage>>,
+    /// Where each entity spent each analysed period, with `period_breakdown` or `include_chunks`
+    period_durations: std::collections::HashMap<String, Vec<breakdown::PeriodDurations>>,
+    /// Unit and device class each entity reported
+    sensor_attributes: std::collections::HashMap<String, types::SensorAttributes>,
+    /// Evidence per entity that it was derived from the target
+    leakage: std::collections::HashMap<String, leakage::LeakageEvidence>,
+    /// History and prepared periods behind the stored statistics, for `append_history`
+    retained_history: std::collections::HashMap<String, Vec<HAHistoryEntry>>,
+    analysed_periods: Vec<TimePeriod>,
+    pending_history: std::collections::HashMap<String, Vec<HAHistoryEntry>>,
+    refinement_generation: std::rc::Rc<std::cell::Cell<u64>>,
+    /// Callback and entity count of the calculation in progress, if the caller asked for progress
+    progress: Option<(js_sys::Function, usize)>,
+    cancelled: CancellationFlag,
+    /// Rows from `analyze_single_entity` since the last `finalize`; `None` outside a stream
+    streamed_results: Option<Vec<EntityProbability>>,
+}
+
+impl Default for BayesianCalculator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[wasm_bindgen]
@@ -23,201 +199,2017 @@ impl BayesianCalculator {
     #[wasm_bindgen(constructor)]
     pub fn new() -> Self {
         Self {
-            threshold_cache: std::collections::HashMap::new(),
+            threshold_cache: std::rc::Rc::default(),
+            options: CalculatorOptions::default(),
+            entity_metadata: std::collections::HashMap::new(),
+            warnings: Vec::new(),
+            numeric_stats: std::collections::HashMap::new(),
+            state_stats: std::collections::HashMap::new(),
+            period_counts: (0, 0),
+            profiles: std::collections::HashMap::new(),
+            profiled_periods: 0,
+            coverage: std::collections::HashMap::new(),
+            period_durations: std::collections::HashMap::new(),
+            sensor_attributes: std::collections::HashMap::new(),
+            leakage: std::collections::HashMap::new(),
+            retained_history: std::collections::HashMap::new(),
+            analysed_periods: Vec::new(),
+            pending_history: std::collections::HashMap::new(),
+            refinement_generation: std::rc::Rc::new(std::cell::Cell::new(0)),
+            progress: None,
+            cancelled: CancellationFlag::default(),
+            streamed_results: None,
         }
     }
 
+    #[wasm_bindgen]
+    pub fn set_options(&mut self, options: JsValue) -> Result<(), JsValue> {
+        self.options = serde_wasm_bindgen::from_value(options)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse options: {}", e)))?;
+        Ok(())
+    }
+
+    /// Provide entity registry metadata (category, hidden, disabled) keyed by entity_id
+    #[wasm_bindgen]
+    pub fn set_entity_metadata(&mut self, metadata: JsValue) -> Result<(), JsValue> {
+        self.entity_metadata = serde_wasm_bindgen::from_value(metadata)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse entity metadata: {}", e)))?;
+        Ok(())
+    }
+
+    /// Warnings collected during the most recent calculation
+    #[wasm_bindgen]
+    pub fn get_warnings(&self) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.warnings)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize warnings: {}", e)))
+    }
+
+    /// Per entity, how much of each analysed period has recorded data, by entity id
+    #[wasm_bindgen]
+    pub fn get_coverage_report(&self) -> Result<JsValue, JsValue> {
+        let mut report: Vec<coverage::EntityCoverage> = self
+            .coverage
+            .iter()
+            .map(|(entity_id, periods)| {
+                let fraction = coverage::overall(periods);
+                coverage::EntityCoverage {
+                    entity_id: entity_id.clone(),
+                    periods: periods.clone(),
+                    fraction,
+                    low: fraction < self.options.min_coverage,
+                }
+            })
+            .collect();
+        report.sort_by(|a, b| a.entity_id.cmp(&b.entity_id));
+
+        serde_wasm_bindgen::to_value(&report)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize coverage report: {}", e)))
+    }
+
+    /// Full chunk-level numeric statistics for one entity from the most recent calculation
+    #[wasm_bindgen]
+    pub fn get_numeric_stats(&self, entity_id: &str) -> Result<JsValue, JsValue> {
+        serde_wasm_bindgen::to_value(&self.numeric_stats.get(entity_id))
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize numeric stats: {}", e)))
+    }
+
+    /// ROC curve of one numeric entity from the most recent calculation, for plotting
+    ///
+    /// Returns `null` for entities without numeric statistics; `max_points` limits the
+    /// points between the two ends of the curve (200 when not given).
+    #[wasm_bindgen]
+    pub fn get_roc_curve(&self, entity_id: &str, max_points: Option<usize>) -> Result<JsValue, JsValue> {
+        let curve = self
+            .numeric_stats
+            .get(entity_id)
+            .and_then(|stats| threshold::roc_curve(stats, max_points.unwrap_or(200)));
+        serde_wasm_bindgen::to_value(&curve)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize ROC curve: {}", e)))
+    }
+
+    /// Stop the running analysis at the next entity or threshold candidate
+    ///
+    /// The analysis then fails with a `Cancelled` error, and any background
+    /// refinement from `calculate_progressive` stops without a `Complete` event. The
+    /// next calculation starts uncancelled; run a full one before `update_with_period`,
+    /// since the statistics kept from a cancelled run are incomplete.
+    #[wasm_bindgen]
+    pub fn cancel(&self) {
+        self.cancelled.cancel();
+    }
+
+    /// Handle that cancels this calculator's analysis from inside its own callbacks
+    #[wasm_bindgen]
+    pub fn cancellation_handle(&self) -> CancellationHandle {
+        CancellationHandle::new(self.cancelled.clone())
+    }
+
+    /// Problems with the history and periods, checked without running the analysis
+    ///
+    /// Returns a list of `InputIssue`s, each an error the analysis would fail on or a
+    /// warning about data it would skip; empty when the input looks fine. Periods are
+    /// read with the current options (`periodTimezone`, `periodOverlap`).
+    #[wasm_bindgen]
+    pub fn validate_input(&self, history: JsValue, periods: JsValue) -> Result<JsValue, JsValue> {
+        let mut history = parse_history(history, "history")?;
+        let periods = parse_periods(periods, &mut history)?;
+        let periods = self.localize_periods(periods)?;
+
+        let issues = validation::check_input(&history, &periods, self.options.period_overlap);
+        serde_wasm_bindgen::to_value(&issues)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize input issues: {}", e)))
+    }
+
+    /// Analyse every entity in the history against the labeled periods
+    ///
+    /// `on_progress`, if given, is called with `(entities_done, entities_total,
+    /// current_entity_id)` as entities are processed and once more with a null entity
+    /// when all are done.
     #[wasm_bindgen]
     pub fn calculate_entity_probabilities(
         &mut self,
         history: JsValue,
         periods: JsValue,
+        on_progress: Option<js_sys::Function>,
     ) -> Result<JsValue, JsValue> {
         // Parse history as it's a HashMap
-        let history: std::collections::HashMap<String, Vec<HAHistoryEntry>> = 
-            serde_wasm_bindgen::from_value(history)
-                .map_err(|e| JsValue::from_str(&format!("Failed to parse history: {}", e)))?;
-        
-        // Parse periods using Tsify's from_wasm_abi
-        let periods: Vec<TimePeriod> = 
-            serde_wasm_bindgen::from_value(periods)
-                .map_err(|e| JsValue::from_str(&format!("Failed to parse periods: {}", e)))?;
+        let mut history = parse_history(history, "history")?;
+        let periods = parse_periods(periods, &mut history)?;
 
-        let results = self.process_entities(history, periods)?;
+        self.progress = on_progress.map(|callback| (callback, history.len()));
+        let results = self.process_entities(history, periods);
+        self.report_progress(self.progress.as_ref().map_or(0, |(_, total)| *total), None);
+        self.progress = None;
+        let results = results?;
         
         // Convert results back using Tsify's into_wasm_abi
         serde_wasm_bindgen::to_value(&results)
             .map_err(|e| JsValue::from_str(&format!("Failed to serialize results: {}", e)))
     }
 
+    /// Analyse the entities once per period `class`, each class against all others
+    ///
+    /// For targets with more than two outcomes ("home", "away", "asleep"): returns a
+    /// list of `ClassResults`, one per distinct class in sorted order, whose rows can
+    /// be exported as a sensor per class. Periods without a class count as FALSE for
+    /// every class and `is_true_period` is ignored. The statistics kept for
+    /// `get_numeric_stats`, `get_warnings` and the incremental methods stay those of
+    /// the last binary calculation; each class reports its own warnings.
+    #[wasm_bindgen]
+    pub fn calculate_per_class(&mut self, history: JsValue, periods: JsValue) -> Result<JsValue, JsValue> {
+        let mut history = parse_history(history, "history")?;
+        let periods = parse_periods(periods, &mut history)?;
+
+        let results = self.process_classes(history, periods)?;
+        serde_wasm_bindgen::to_value(&results)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize class results: {}", e)))
+    }
+
+    /// `calculate_entity_probabilities` as a Promise that yields to the event loop between entities
+    ///
+    /// For hosts without Web Workers, so a multi-second analysis does not freeze the
+    /// page. The analysis runs on a copy of this calculator's options, metadata and
+    /// threshold cache, and its statistics stay with that copy: `get_numeric_stats`
+    /// and `get_warnings` keep describing the last synchronous calculation. `cancel()`
+    /// stops it like any other analysis.
+    #[wasm_bindgen]
+    pub fn calculate_entity_probabilities_async(
+        &mut self,
+        history: JsValue,
+        periods: JsValue,
+    ) -> Result<js_sys::Promise, JsValue> {
+        let mut history = parse_history(history, "history")?;
+        let periods = parse_periods(periods, &mut history)?;
+
+        self.cancelled.reset();
+        let mut calculator = BayesianCalculator {
+            options: self.options.clone(),
+            entity_metadata: self.entity_metadata.clone(),
+            threshold_cache: std::rc::Rc::new(std::cell::RefCell::new(self.threshold_cache.borrow().clone())),
+            cancelled: self.cancelled.clone(),
+            ..BayesianCalculator::new()
+        };
+
+        Ok(wasm_bindgen_futures::future_to_promise(async move {
+            let results = calculator.process_entities_yielding(history, periods).await?;
+            serde_wasm_bindgen::to_value(&results)
+                .map_err(|e| JsValue::from_str(&format!("Failed to serialize results: {}", e)))
+        }))
+    }
+
+    /// Return fast preview results, then keep refining them in the background
+    ///
+    /// Numeric thresholds are first searched on a coarse grid and returned
+    /// immediately. A background task then runs the full threshold search entity by
+    /// entity, yielding to the event loop in between, and calls `on_update` with a
+    /// `RefinementEvent` carrying each numeric entity's refined rows, built exactly as
+    /// a full calculation builds them, and once more when finished. The exact
+    /// thresholds go into this calculator's threshold cache, so a later full
+    /// calculation reuses them. Only thresholds are refined; `bootstrap` stability
+    /// comes with a full calculation. Starting another progressive calculation
+    /// abandons any refinement still in flight.
+    #[wasm_bindgen]
+    pub fn calculate_progressive(
+        &mut self,
+        history: JsValue,
+        periods: JsValue,
+        on_update: js_sys::Function,
+    ) -> Result<JsValue, JsValue> {
+        let mut history = parse_history(history, "history")?;
+        let periods = parse_periods(periods, &mut history)?;
+
+        self.cancelled.reset();
+        let preview = self.process_entities_with_search(history, periods, threshold::ThresholdSearch::Preview)?;
+        let preview_js = serde_wasm_bindgen::to_value(&preview)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize results: {}", e)))?;
+
+        let generation = self.refinement_generation.get() + 1;
+        self.refinement_generation.set(generation);
+        let current_generation = self.refinement_generation.clone();
+
+        // The refinement works on a copy of the statistics behind the preview, with the
+        // period counts they were prepared with, and rows of every entity (no paging)
+        let correlating = self.options.correlation_threshold.is_some();
+        let mut refiner = BayesianCalculator {
+            options: CalculatorOptions {
+                result_offset: 0,
+                max_results: None,
+                ..self.options.clone()
+            },
+            entity_metadata: self.entity_metadata.clone(),
+            threshold_cache: self.threshold_cache.clone(),
+            cancelled: self.cancelled.clone(),
+            numeric_stats: self.numeric_stats.clone(),
+            state_stats: if correlating { self.state_stats.clone() } else { Default::default() },
+            period_counts: self.period_counts,
+            profiles: if correlating { self.profiles.clone() } else { Default::default() },
+            profiled_periods: self.profiled_periods,
+            coverage: self.coverage.clone(),
+            period_durations: self.period_durations.clone(),
+            sensor_attributes: self.sensor_attributes.clone(),
+            leakage: self.leakage.clone(),
+            ..BayesianCalculator::new()
+        };
+
+        // Refine the entities that ranked highest in the preview first
+        let rank: std::collections::HashMap<&str, usize> = preview
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, row)| (row.entity_id.as_str(), i))
+            .collect();
+        let mut pending: Vec<String> = self.numeric_stats.keys().cloned().collect();
+        pending.sort_by_key(|entity_id| (rank.get(entity_id.as_str()).copied().unwrap_or(usize::MAX), entity_id.clone()));
+
+        wasm_bindgen_futures::spawn_local(async move {
+            // Correlations are marked against the latest rows of every other entity
+            let mut current = if correlating {
+                refiner.build_results(threshold::ThresholdSearch::Preview)
+            } else {
+                Vec::new()
+            };
+            refiner.warnings.clear();
+
+            for entity_id in pending {
+                scheduling::yield_to_event_loop().await;
+                if current_generation.get() != generation {
+                    return;
+                }
+
+                let Some(stats) = refiner.numeric_stats.remove(&entity_id) else {
+                    continue;
+                };
+                let mut rows = refiner.numeric_rows(&entity_id, &stats, threshold::ThresholdSearch::Full);
+                if refiner.cancelled.is_cancelled() {
+                    return;
+                }
+                refiner.numeric_stats.insert(entity_id.clone(), stats);
+                refiner.annotate_rows(&mut rows);
+
+                if let Some(threshold) = refiner.options.correlation_threshold {
+                    current.retain(|row| row.entity_id != entity_id);
+                    current.extend(rows);
+                    correlation::mark_correlated(&mut current, &refiner.profiles, refiner.profiled_periods, threshold);
+                    rows = current.iter().filter(|row| row.entity_id == entity_id).cloned().collect();
+                }
+                ranking::sort(&mut rows, &refiner.options.sort_keys);
+
+                let event = RefinementEvent::Updated {
+                    entity_id,
+                    rows,
+                    warnings: std::mem::take(&mut refiner.warnings),
+                };
+                if let Ok(event) = serde_wasm_bindgen::to_value(&event) {
+                    let _ = on_update.call1(&JsValue::NULL, &event);
+                }
+            }
+
+            if let Ok(event) = serde_wasm_bindgen::to_value(&RefinementEvent::Complete) {
+                let _ = on_update.call1(&JsValue::NULL, &event);
+            }
+        });
+
+        Ok(preview_js)
+    }
+
+    /// Complete `binary_sensor` bayesian configuration for the selected result rows
+    ///
+    /// Numeric rows become `numeric_state` observations from their thresholds and
+    /// categorical rows `state` observations. `prior` and `probability_threshold`
+    /// must lie strictly between 0 and 1; `settings` supplies the name and device class.
+    #[wasm_bindgen]
+    pub fn export_bayesian_yaml(
+        &self,
+        selected: JsValue,
+        prior: f64,
+        probability_threshold: f64,
+        settings: JsValue,
+    ) -> Result<String, JsValue> {
+        let selected: Vec<EntityProbability> = serde_wasm_bindgen::from_value(selected)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse selected results: {}", e)))?;
+        let settings: export::SensorSettings = parse_optional(settings, "sensor settings")?;
+
+        for (name, value) in [("prior", prior), ("probability_threshold", probability_threshold)] {
+            if !(value > 0.0 && value < 1.0) {
+                return Err(JsValue::from_str(&format!("{} must be between 0 and 1, got {}", name, value)));
+            }
+        }
+
+        let settings = export::SensorSettings {
+            prior,
+            probability_threshold,
+            ..settings
+        };
+        Ok(export::generate_bayesian_yaml(&selected, &settings))
+    }
+
+    /// Replay the history through the selected rows and return the posterior over time
+    ///
+    /// Each point holds the posterior and predicted on/off state from that time until
+    /// the next point, across the span of the labeled periods, so the generated
+    /// sensor's behaviour can be compared to the labels.
+    #[wasm_bindgen]
+    pub fn simulate_posterior(
+        &self,
+        history: JsValue,
+        periods: JsValue,
+        selected: JsValue,
+        prior: f64,
+        probability_threshold: f64,
+    ) -> Result<JsValue, JsValue> {
+        let (history, periods) = self.parse_replay_input(history, periods)?;
+        let selected: Vec<EntityProbability> = serde_wasm_bindgen::from_value(selected)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse selected results: {}", e)))?;
+
+        let simulation = simulation::simulate(&history, &periods, &selected, prior, probability_threshold);
+        serde_wasm_bindgen::to_value(&simulation)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize posterior simulation: {}", e)))
+    }
+
+    /// An entity's history as chart points across the span of the labeled periods
+    ///
+    /// Each point carries whether a TRUE or FALSE period covers it, so the trace can
+    /// be drawn over the period shading. With `resolution_ms`, the span is cut into
+    /// buckets of that length (numeric values averaged over time, categorical
+    /// entities at their longest state) instead of returning every change.
+    #[wasm_bindgen]
+    pub fn get_entity_timeline(
+        &self,
+        entity_id: &str,
+        history: JsValue,
+        periods: JsValue,
+        resolution_ms: Option<f64>,
+    ) -> Result<JsValue, JsValue> {
+        let (history, periods) = self.parse_replay_input(history, periods)?;
+        let entity_history = history.get(entity_id).map(Vec::as_slice).unwrap_or(&[]);
+
+        let points = trace::entity_trace(entity_history, &TimelineEngine::new(&periods), resolution_ms.map(|ms| ms as i64));
+        serde_wasm_bindgen::to_value(&points)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize entity timeline: {}", e)))
+    }
+
+    /// Confusion matrix, precision, recall, F1 and accuracy of the selected rows
+    ///
+    /// The history is replayed as in `simulate_posterior` and every labeled
+    /// millisecond is scored against the sensor state at `probability_threshold`.
+    #[wasm_bindgen]
+    pub fn evaluate_configuration(
+        &self,
+        history: JsValue,
+        periods: JsValue,
+        selected: JsValue,
+        prior: f64,
+        probability_threshold: f64,
+    ) -> Result<JsValue, JsValue> {
+        let (history, periods) = self.parse_replay_input(history, periods)?;
+        let selected: Vec<EntityProbability> = serde_wasm_bindgen::from_value(selected)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse selected results: {}", e)))?;
+
+        let segments = simulation::replay(&history, &periods, &selected, prior);
+        serde_wasm_bindgen::to_value(&evaluation::evaluate(&segments, probability_threshold))
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize configuration evaluation: {}", e)))
+    }
+
+    /// Evaluate the selected rows at every `step` of the probability threshold
+    ///
+    /// The history is replayed once and each threshold between 0 and 1 is scored as
+    /// in `evaluate_configuration`, giving the accuracy/precision/recall curve to pick
+    /// a cutoff from. A `step` of 0.05 gives thresholds 0.05 to 0.95; it must be
+    /// between 0.001 and 0.5.
+    #[wasm_bindgen]
+    pub fn sweep_probability_threshold(
+        &self,
+        history: JsValue,
+        periods: JsValue,
+        selected: JsValue,
+        prior: f64,
+        step: f64,
+    ) -> Result<JsValue, JsValue> {
+        let (history, periods) = self.parse_replay_input(history, periods)?;
+        let selected: Vec<EntityProbability> = serde_wasm_bindgen::from_value(selected)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse selected results: {}", e)))?;
+
+        if !(evaluation::MIN_STEP..=0.5).contains(&step) {
+            return Err(JsValue::from_str(&format!(
+                "step must be between {} and 0.5, got {}",
+                evaluation::MIN_STEP,
+                step
+            )));
+        }
+
+        let segments = simulation::replay(&history, &periods, &selected, prior);
+        serde_wasm_bindgen::to_value(&evaluation::sweep(&segments, step))
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize threshold sweep: {}", e)))
+    }
+
+    /// Greedily pick the observations that best separate TRUE from FALSE time
+    ///
+    /// Candidates are the best row of each entity in `results`; the separation of each
+    /// combination is measured by replaying the history, so redundant entities that
+    /// would rank high by `discrimination_power` alone are passed over.
+    #[wasm_bindgen]
+    pub fn select_observations(
+        &self,
+        history: JsValue,
+        periods: JsValue,
+        results: JsValue,
+        options: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let (history, periods) = self.parse_replay_input(history, periods)?;
+        let results: Vec<EntityProbability> = serde_wasm_bindgen::from_value(results)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse results: {}", e)))?;
+        let options: selection::SelectionOptions = parse_optional(options, "selection options")?;
+
+        serde_wasm_bindgen::to_value(&selection::select(&history, &periods, &results, &options))
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize observation selection: {}", e)))
+    }
+
+    /// Generate an importable template blueprint for the selected result rows
+    ///
+    /// The blueprint exposes the prior and probability threshold as inputs so they can
+    /// be adjusted from the HA UI when the blueprint is used.
+    #[wasm_bindgen]
+    pub fn export_blueprint(&self, selected: JsValue, settings: JsValue) -> Result<String, JsValue> {
+        let selected: Vec<EntityProbability> = serde_wasm_bindgen::from_value(selected)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse selected results: {}", e)))?;
+        let settings: export::SensorSettings = parse_optional(settings, "sensor settings")?;
+
+        Ok(export::generate_blueprint(&selected, &settings))
+    }
+
+    /// Complete configs for the selected rows at conservative, balanced and sensitive thresholds
+    ///
+    /// Each comes with predicted precision and recall, so users can pick the trade-off
+    /// without re-running the analysis. The prior comes from `settings`.
+    #[wasm_bindgen]
+    pub fn generate_operating_points(&self, selected: JsValue, settings: JsValue) -> Result<JsValue, JsValue> {
+        let selected: Vec<EntityProbability> = serde_wasm_bindgen::from_value(selected)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse selected results: {}", e)))?;
+        let settings: export::SensorSettings = parse_optional(settings, "sensor settings")?;
+
+        serde_wasm_bindgen::to_value(&operating_points::generate(&selected, &settings))
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize operating points: {}", e)))
+    }
+
+    /// Lowest and highest posterior the selected rows can produce, and each row's swing
+    ///
+    /// Warns when the configuration can never cross the probability threshold from
+    /// `settings` or can never drop back under it.
+    #[wasm_bindgen]
+    pub fn analyze_posterior_range(&self, selected: JsValue, settings: JsValue) -> Result<JsValue, JsValue> {
+        let selected: Vec<EntityProbability> = serde_wasm_bindgen::from_value(selected)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse selected results: {}", e)))?;
+        let settings: export::SensorSettings = parse_optional(settings, "sensor settings")?;
+
+        serde_wasm_bindgen::to_value(&posterior_range::analyze(&selected, &settings))
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize posterior range: {}", e)))
+    }
+
+    /// Check whether a single sensor reaches the target F1, otherwise propose a two-sensor ensemble
+    #[wasm_bindgen]
+    pub fn suggest_ensemble(&self, results: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+        let results: Vec<EntityProbability> = serde_wasm_bindgen::from_value(results)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse results: {}", e)))?;
+        let options: ensemble::EnsembleOptions = parse_optional(options, "ensemble options")?;
+
+        serde_wasm_bindgen::to_value(&ensemble::suggest(&results, &options))
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize ensemble suggestion: {}", e)))
+    }
+
+    /// Suggest a trigger-based template binary sensor when one or two conditions dominate
+    ///
+    /// Returns `null` when no single condition or pair reaches `minF1`. The
+    /// `delay_on`/`delay_off` values come from how long the condition flickers
+    /// against the labels in `history` over `periods`.
+    #[wasm_bindgen]
+    pub fn suggest_trigger_template(
+        &self,
+        results: JsValue,
+        history: JsValue,
+        periods: JsValue,
+        options: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let results: Vec<EntityProbability> = serde_wasm_bindgen::from_value(results)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse results: {}", e)))?;
+        let (history, periods) = self.parse_replay_input(history, periods)?;
+        let options: trigger_template::TriggerTemplateOptions = parse_optional(options, "trigger template options")?;
+
+        serde_wasm_bindgen::to_value(&trigger_template::suggest(&results, &history, &periods, &options))
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize trigger template suggestion: {}", e)))
+    }
+
+    /// Fit separate probability sets per day group (e.g. weekdays vs weekends)
+    ///
+    /// Periods are split at local midnight in the given time zone and each group is
+    /// analysed with this calculator's options. Rows whose probabilities differ by at
+    /// least `materialDifference` between groups are reported, and with
+    /// `generateConfig` one sensor per group plus a schedule-based switch-over is built.
+    #[wasm_bindgen]
+    pub fn segment_by_day_groups(&self, history: JsValue, periods: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+        let mut history = parse_history(history, "history")?;
+        let periods = parse_periods(periods, &mut history)?;
+        let options: segmentation::DaySegmentationOptions = parse_optional(options, "day segmentation options")?;
+        let zone = time::parse_zone(&options.timezone).map_err(|e| JsValue::from_str(&e))?;
+
+        let mut groups = Vec::with_capacity(options.groups.len());
+        for group in &options.groups {
+            let group_periods = segmentation::periods_for_group(&periods, group, zone);
+            let mut calculator = BayesianCalculator {
+                options: self.options.clone(),
+                entity_metadata: self.entity_metadata.clone(),
+                ..BayesianCalculator::new()
+            };
+            let results = calculator
+                .process_entities(history.clone(), group_periods)
+                .map_err(|e| JsValue::from_str(&format!("Day group '{}': {}", group.name, e)))?;
+            groups.push(segmentation::DayGroupResults {
+                name: group.name.clone(),
+                results,
+            });
+        }
+
+        serde_wasm_bindgen::to_value(&segmentation::summarize(groups, &options))
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize day segmentation: {}", e)))
+    }
+
+    /// Analyse day and night separately and merge the result into one configuration
+    ///
+    /// Entities that only discriminate in one part of the day (e.g. lights at night)
+    /// become template observations conditioned on that part, using a fixed local
+    /// boundary or `sun.sun`.
+    #[wasm_bindgen]
+    pub fn split_day_night(&self, history: JsValue, periods: JsValue, options: JsValue) -> Result<JsValue, JsValue> {
+        let mut history = parse_history(history, "history")?;
+        let periods = parse_periods(periods, &mut history)?;
+        let options: day_night::DayNightOptions = parse_optional(options, "day/night options")?;
+        let zone = time::parse_zone(&options.timezone).map_err(|e| JsValue::from_str(&e))?;
+
+        let nights = day_night::night_intervals(&periods, &options.boundary, zone);
+        let (night_periods, day_periods) = preprocessing::split_periods(&periods, &nights);
+
+        let analyse = |what: &str, periods: Vec<TimePeriod>| {
+            let mut calculator = BayesianCalculator {
+                options: self.options.clone(),
+                entity_metadata: self.entity_metadata.clone(),
+                ..BayesianCalculator::new()
+            };
+            calculator
+                .process_entities(history.clone(), periods)
+                .map_err(|e| JsValue::from_str(&format!("{}: {}", what, e)))
+        };
+        let full = analyse("All periods", periods.clone())?;
+        let day = analyse("Day", day_periods.clone())?;
+        let night = analyse("Night", night_periods.clone())?;
+
+        let analysis = day_night::analyze(&full, day, night, &day_periods, &night_periods, &options);
+        serde_wasm_bindgen::to_value(&analysis)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize day/night analysis: {}", e)))
+    }
+
+    /// Flat CSV of result rows for triaging candidates in a spreadsheet
+    #[wasm_bindgen]
+    pub fn export_results_csv(&self, results: JsValue) -> Result<String, JsValue> {
+        let results: Vec<EntityProbability> = serde_wasm_bindgen::from_value(results)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse results: {}", e)))?;
+
+        Ok(export::generate_results_csv(&results))
+    }
+
+    /// Compare the results of two runs, e.g. before and after adding labeled periods
+    #[wasm_bindgen]
+    pub fn diff_results(&self, previous: JsValue, current: JsValue) -> Result<JsValue, JsValue> {
+        let previous: Vec<EntityProbability> = serde_wasm_bindgen::from_value(previous)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse previous results: {}", e)))?;
+        let current: Vec<EntityProbability> = serde_wasm_bindgen::from_value(current)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse current results: {}", e)))?;
+
+        serde_wasm_bindgen::to_value(&diff::diff(&previous, &current))
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize results diff: {}", e)))
+    }
+
+    /// Produce a self-contained Markdown or HTML report for archiving or sharing
+    ///
+    /// Includes the ranked results, warnings from the most recent calculation, and a
+    /// proposed bayesian configuration built from the best row of the top entities.
+    #[wasm_bindgen]
+    pub fn generate_report(&self, results: JsValue, options: JsValue) -> Result<String, JsValue> {
+        let results: Vec<EntityProbability> = serde_wasm_bindgen::from_value(results)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse results: {}", e)))?;
+        let options: report::ReportOptions = parse_optional(options, "report options")?;
+
+        Ok(report::generate(&results, &self.warnings, &options))
+    }
+
+    /// Stage part of the history for a later `commit`
+    ///
+    /// Large histories can be split into several chunks (by entity or by time range)
+    /// so no single serde_wasm_bindgen conversion has to handle the whole payload.
+    /// Entries for the same entity across chunks are concatenated.
+    #[wasm_bindgen]
+    pub fn append_history_chunk(&mut self, history_chunk: JsValue) -> Result<(), JsValue> {
+        let chunk = parse_history(history_chunk, "history chunk")?;
+
+        for (entity_id, entries) in chunk {
+            self.pending_history.entry(entity_id).or_default().extend(entries);
+        }
+
+        Ok(())
+    }
+
+    /// Run the calculation over all history staged with `append_history_chunk`
+    ///
+    /// The staged history is consumed, so the next batch starts empty.
+    #[wasm_bindgen]
+    pub fn commit(&mut self, periods: JsValue) -> Result<JsValue, JsValue> {
+        let mut history = std::mem::take(&mut self.pending_history);
+        let periods = parse_periods(periods, &mut history)?;
+        let results = self.process_entities(history, periods)?;
+
+        serde_wasm_bindgen::to_value(&results)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize results: {}", e)))
+    }
+
+    /// Drop any history staged with `append_history_chunk` without running a calculation
+    #[wasm_bindgen]
+    pub fn clear_pending_history(&mut self) {
+        self.pending_history.clear();
+    }
+
+    /// Analyse one entity as its history arrives, keeping its rows for `finalize`
+    ///
+    /// Lets the caller stream entities instead of collecting every history into one
+    /// `calculate_entity_probabilities` call. The first call after a `finalize` starts
+    /// a new stream. Each entity is analysed on its own, so pooling groups and global
+    /// shrinkage only see that entity; correlations are marked in `finalize`. Returns
+    /// the entity's rows, or none if its history is empty.
+    #[wasm_bindgen]
+    pub fn analyze_single_entity(
+        &mut self,
+        entity_id: String,
+        history: JsValue,
+        periods: JsValue,
+    ) -> Result<JsValue, JsValue> {
+        let entries: Vec<types::HistoryEntryInput> = serde_wasm_bindgen::from_value(history)
+            .map_err(|e| CalculatorError::ParseError {
+                field: "entity history".to_string(),
+                message: e.to_string(),
+            })?;
+        let entries: Vec<HAHistoryEntry> = entries.into_iter().map(Into::into).collect();
+        let mut history = std::collections::HashMap::from([(entity_id, entries)]);
+        let periods = parse_periods(periods, &mut history)?;
+
+        if self.streamed_results.is_none() {
+            self.warnings.clear();
+            self.numeric_stats.clear();
+            self.state_stats.clear();
+            self.profiles.clear();
+            self.profiled_periods = 0;
+            self.coverage.clear();
+            self.period_durations.clear();
+            self.sensor_attributes.clear();
+            self.leakage.clear();
+            self.retained_history.clear();
+        }
+        let streamed = self.streamed_results.get_or_insert_with(Vec::new);
+
+        let rows = if history.values().all(Vec::is_empty) {
+            Vec::new()
+        } else {
+            let mut calculator = BayesianCalculator {
+                // Paging applies to the ranking across all entities in `finalize`
+                options: CalculatorOptions {
+                    result_offset: 0,
+                    max_results: None,
+                    ..self.options.clone()
+                },
+                entity_metadata: self.entity_metadata.clone(),
+                threshold_cache: self.threshold_cache.clone(),
+                cancelled: self.cancelled.clone(),
+                ..BayesianCalculator::new()
+            };
+            let rows = calculator.process_entities(history, periods)?;
+
+            streamed.extend(rows.iter().cloned());
+            // Warnings about the periods rather than an entity come with every entity
+            for warning in calculator.warnings {
+                let repeated = warning.entity_id.is_none()
+                    && self.warnings.iter().any(|seen| seen.entity_id.is_none() && seen.message == warning.message);
+                if !repeated {
+                    self.warnings.push(warning);
+                }
+            }
+            self.numeric_stats.extend(calculator.numeric_stats);
+            self.state_stats.extend(calculator.state_stats);
+            self.profiles.extend(calculator.profiles);
+            self.profiled_periods = calculator.profiled_periods;
+            self.coverage.extend(calculator.coverage);
+            self.period_durations.extend(calculator.period_durations);
+            self.sensor_attributes.extend(calculator.sensor_attributes);
+            self.leakage.extend(calculator.leakage);
+            self.period_counts = calculator.period_counts;
+            self.retained_history.extend(calculator.retained_history);
+            self.analysed_periods = calculator.analysed_periods;
+            rows
+        };
+
+        serde_wasm_bindgen::to_value(&rows)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize results: {}", e)))
+    }
+
+    /// All rows streamed through `analyze_single_entity` since the last call, ranked
+    #[wasm_bindgen]
+    pub fn finalize(&mut self) -> Result<JsValue, JsValue> {
+        let mut results = self.streamed_results.take().unwrap_or_default();
+
+        if let Some(threshold) = self.options.correlation_threshold {
+            correlation::mark_correlated(&mut results, &self.profiles, self.profiled_periods, threshold);
+        }
+        self.sort_results(&mut results);
+
+        serde_wasm_bindgen::to_value(&results)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize results: {}", e)))
+    }
+
+    /// Fold one newly labeled period into the statistics of the last calculation
+    ///
+    /// `history_slice` only needs the entries covering the period, including the one
+    /// in effect at its start. The period is prepared as in a full calculation, except
+    /// that the periods already analysed are counted as they are: where the new period
+    /// overlaps one of the other class, `period_overlap` can only cut the new one.
+    /// Durations and period counts are added to what the previous calculation
+    /// accumulated, so the whole history is not reprocessed; numeric thresholds are
+    /// searched again over the combined chunks. Rolling detrending only sees the
+    /// slice. Returns the updated results.
+    #[wasm_bindgen]
+    pub fn update_with_period(&mut self, history_slice: JsValue, period: JsValue) -> Result<JsValue, JsValue> {
+        let mut history = parse_history(history_slice, "history slice")?;
+        let period: TimePeriod = serde_wasm_bindgen::from_value(period)
+            .map_err(|e| JsValue::from_str(&format!("Failed to parse period: {}", e)))?;
+
+        self.cancelled.reset();
+        let mut warnings = error::drop_invalid_timestamps(&mut history);
+        let periods = self.localize_periods(vec![period])?;
+        error::check_timestamps(&periods)?;
+        let periods = self.resolve_new_overlaps(periods, &mut warnings)?;
+
+        let periods = self.prepare_periods(periods);
+        for period in &periods {
+            let weight = sensor_analysis::true_weight(period);
+            self.period_counts.0 += usize::from(weight > 0.0);
+            self.period_counts.1 += usize::from(weight < 1.0);
+        }
+        if self.period_counts.0 == 0 {
+            return Err(CalculatorError::NoTruePeriods.into());
+        }
+        if self.period_counts.1 == 0 {
+            return Err(CalculatorError::NoFalsePeriods.into());
+        }
+
+        self.warnings = warnings;
+        self.accumulate_entities(&history, &periods);
+        let results = self.build_results(threshold::ThresholdSearch::Full);
+
+        for (entity_id, entries) in history {
+            merge_entries(self.retained_history.entry(entity_id).or_default(), entries);
+        }
+        self.analysed_periods.extend(periods);
+
+        serde_wasm_bindgen::to_value(&results)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize results: {}", e)))
+    }
+
+    /// Snapshot the per-entity analysis and threshold cache as compact binary
+    ///
+    /// The bytes can be stored (e.g. in IndexedDB) and passed to `restore_state` in a
+    /// later session to get the results back without recomputing them.
+    #[wasm_bindgen]
+    pub fn serialize_state(&self) -> Result<Vec<u8>, JsValue> {
+        persistence::PersistedState::new(self)
+            .to_bytes()
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize state: {}", e)))
+    }
+
+    /// Load a snapshot from `serialize_state` and return the results it represents
+    ///
+    /// Results are rebuilt under the current options; numeric thresholds come from
+    /// the restored cache unless those options change them.
+    #[wasm_bindgen]
+    pub fn restore_state(&mut self, bytes: &[u8]) -> Result<JsValue, JsValue> {
+        let state = persistence::PersistedState::from_bytes(bytes)
+            .map_err(|e| JsValue::from_str(&format!("Failed to restore state: {}", e)))?;
+
+        *self.threshold_cache.borrow_mut() = state.threshold_cache.into_owned();
+        self.numeric_stats = state.numeric_stats.into_owned();
+        self.state_stats = state.state_stats.into_owned();
+        self.period_counts = state.period_counts;
+        self.profiles = state.profiles.into_owned();
+        self.profiled_periods = state.profiled_periods;
+        self.coverage = state.coverage.into_owned();
+        self.period_durations = state.period_durations.into_owned();
+        self.sensor_attributes = state.sensor_attributes.into_owned();
+        self.leakage = state.leakage.into_owned();
+        self.retained_history.clear();
+        self.analysed_periods.clear();
+        self.warnings.clear();
+        self.cancelled.reset();
+
+        let results = self.build_results(threshold::ThresholdSearch::Full);
+        serde_wasm_bindgen::to_value(&results)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize results: {}", e)))
+    }
+
+    /// Add newly arrived history for one entity and recompute only that entity
+    ///
+    /// The entries are merged into the history kept from the last calculation and the
+    /// entity's statistics are rebuilt over the same periods; every other entity keeps
+    /// its statistics and cached thresholds. Cross-validation is not rerun. Returns
+    /// the updated results.
+    #[wasm_bindgen]
+    pub fn append_history(&mut self, entity_id: String, new_entries: JsValue) -> Result<JsValue, JsValue> {
+        let entries: Vec<types::HistoryEntryInput> = serde_wasm_bindgen::from_value(new_entries)
+            .map_err(|e| CalculatorError::ParseError {
+                field: "new entries".to_string(),
+                message: e.to_string(),
+            })?;
+        if self.analysed_periods.is_empty() {
+            return Err(JsValue::from_str("No calculation to update; run calculate_entity_probabilities first"));
+        }
+
+        let results = self.append_entity_history(entity_id, entries.into_iter().map(Into::into).collect())?;
+        serde_wasm_bindgen::to_value(&results)
+            .map_err(|e| JsValue::from_str(&format!("Failed to serialize results: {}", e)))
+    }
+
+    fn append_entity_history(
+        &mut self,
+        entity_id: String,
+        entries: Vec<HAHistoryEntry>,
+    ) -> Result<Vec<EntityProbability>, CalculatorError> {
+        let entity_history = self.retained_history.entry(entity_id.clone()).or_default();
+        merge_entries(entity_history, entries);
+        let mut history = std::collections::HashMap::from([(entity_id.clone(), entity_history.clone())]);
+        let timestamp_warnings = error::drop_invalid_timestamps(&mut history);
+
+        let derived_ids = self.options.derived_features.iter().map(|feature| feature.entity_id(&entity_id));
+        let attribute_ids = self
+            .options
+            .analyzed_attributes
+            .get(&entity_id)
+            .into_iter()
+            .flatten()
+            .map(|attribute| sensor_analysis::virtual_entity_id(&entity_id, attribute));
+        let virtual_ids: Vec<String> = derived_ids.chain(attribute_ids).collect();
+        for id in std::iter::once(entity_id.clone()).chain(virtual_ids) {
+            self.numeric_stats.remove(&id);
+            self.state_stats.remove(&id);
+            self.profiles.remove(&id);
+            self.coverage.remove(&id);
+            self.period_durations.remove(&id);
+            self.leakage.remove(&id);
+        }
+        self.warnings = timestamp_warnings;
+
+        // The entity's profile is rebuilt over all analysed periods from the start
+        let profiled_periods = self.profiled_periods;
+        self.profiled_periods = 0;
+        let periods = std::mem::take(&mut self.analysed_periods);
+        self.accumulate_entities(&history, &periods);
+        self.analysed_periods = periods;
+        self.profiled_periods = profiled_periods;
+
+        Ok(self.build_results(threshold::ThresholdSearch::Full))
+    }
+
     fn process_entities(
         &mut self,
         history: std::collections::HashMap<String, Vec<HAHistoryEntry>>,
         periods: Vec<TimePeriod>,
-    ) -> Result<Vec<EntityProbability>, String> {
-        let true_periods: Vec<_> = periods.iter().filter(|p| p.is_true_period).collect();
-        let false_periods: Vec<_> = periods.iter().filter(|p| !p.is_true_period).collect();
+    ) -> Result<Vec<EntityProbability>, CalculatorError> {
+        self.cancelled.reset();
+        self.process_entities_with_search(history, periods, threshold::ThresholdSearch::Full)
+    }
+
+    fn process_classes(
+        &mut self,
+        history: std::collections::HashMap<String, Vec<HAHistoryEntry>>,
+        periods: Vec<TimePeriod>,
+    ) -> Result<Vec<multiclass::ClassResults>, CalculatorError> {
+        let classes = multiclass::classes(&periods);
+        if classes.is_empty() {
+            return Err(CalculatorError::NoClasses);
+        }
 
-        if true_periods.is_empty() || false_periods.is_empty() {
-            return Err("Need at least one TRUE and one FALSE period".to_string());
+        self.cancelled.reset();
+        let mut results = Vec::with_capacity(classes.len());
+        for class in classes {
+            let class_periods = multiclass::one_vs_rest(&periods, &class);
+            let count = class_periods.iter().filter(|period| period.is_true_period).count();
+            // Each class runs on its own calculator, so this one keeps the statistics
+            // of its last binary calculation
+            let mut calculator = BayesianCalculator {
+                options: self.options.clone(),
+                entity_metadata: self.entity_metadata.clone(),
+                threshold_cache: self.threshold_cache.clone(),
+                cancelled: self.cancelled.clone(),
+                ..BayesianCalculator::new()
+            };
+            let rows = calculator.process_entities_with_search(history.clone(), class_periods, threshold::ThresholdSearch::Full)?;
+            results.push(multiclass::ClassResults {
+                class,
+                periods: count,
+                results: rows,
+                warnings: calculator.warnings,
+            });
         }
+        Ok(results)
+    }
 
-        let mut results = Vec::new();
+    fn process_entities_with_search(
+        &mut self,
+        history: std::collections::HashMap<String, Vec<HAHistoryEntry>>,
+        periods: Vec<TimePeriod>,
+        search: threshold::ThresholdSearch,
+    ) -> Result<Vec<EntityProbability>, CalculatorError> {
+        let mut history = history;
+        let (periods, labeled) = self.start_analysis(&mut history, periods, search)?;
+        let history = self.with_clock_entities(history, &periods)?;
+        self.accumulate_entities(&history, &periods);
+        self.finish_analysis(history, periods, &labeled, search)
+    }
+
+    /// `process_entities` that gives the event loop a turn between entity batches
+    /// and between numeric threshold searches
+    async fn process_entities_yielding(
+        &mut self,
+        history: std::collections::HashMap<String, Vec<HAHistoryEntry>>,
+        periods: Vec<TimePeriod>,
+    ) -> Result<Vec<EntityProbability>, CalculatorError> {
+        let search = threshold::ThresholdSearch::Full;
+        let mut history = history;
+        let (periods, labeled) = self.start_analysis(&mut history, periods, search)?;
+        let history = self.with_clock_entities(history, &periods)?;
+
+        let batch_size = entity_analysis::batch_size(&self.options);
+        let entities: Vec<(&String, &Vec<HAHistoryEntry>)> = history.iter().collect();
+        let timeline = TimelineEngine::new(&periods);
+        for (batch_index, batch) in entities.chunks(batch_size).enumerate() {
+            scheduling::yield_to_event_loop().await;
+            if self.cancelled.is_cancelled() {
+                break;
+            }
+            self.accumulate_batch(batch_index * batch_size, batch, &timeline);
+        }
+        self.profiled_periods += periods.len();
 
-        for (entity_id, entity_history) in history.iter() {
-            if entity_history.is_empty() {
-                continue;
+        // Fill the threshold cache one entity at a time so `build_results` only reads it
+        let numeric_stats = std::mem::take(&mut self.numeric_stats);
+        for (entity_id, stats) in &numeric_stats {
+            scheduling::yield_to_event_loop().await;
+            if self.cancelled.is_cancelled() {
+                break;
             }
+            self.get_or_calculate_thresholds(entity_id, stats, search);
+        }
+        self.numeric_stats = numeric_stats;
+
+        self.finish_analysis(history, periods, &labeled, search)
+    }
+
+    /// Validate the input, reset the stored statistics and prepare the periods
+    ///
+    /// Returns the prepared periods and, with cross-validation on, the original ones
+    /// to hold out in turn. History entries with unreadable timestamps are dropped and
+    /// reported in the warnings.
+    fn start_analysis(
+        &mut self,
+        history: &mut std::collections::HashMap<String, Vec<HAHistoryEntry>>,
+        periods: Vec<TimePeriod>,
+        search: threshold::ThresholdSearch,
+    ) -> Result<(Vec<TimePeriod>, Vec<TimePeriod>), CalculatorError> {
+        let mut warnings = error::drop_invalid_timestamps(history);
+        if history.values().all(Vec::is_empty) {
+            return Err(CalculatorError::EmptyHistory);
+        }
+        let periods = self.localize_periods(periods)?;
+        error::check_timestamps(&periods)?;
+        let periods = self.resolve_overlaps(periods, &mut warnings)?;
+
+        // Cross-validation and the bootstrap re-run the analysis on the periods as given
+        let resampling = self.options.cross_validation || self.options.bootstrap.is_some();
+        let labeled = if resampling && search == threshold::ThresholdSearch::Full {
+            periods.clone()
+        } else {
+            Vec::new()
+        };
+        let periods = self.prepare_periods(periods);
+
+        // A soft-labeled period counts towards each class it has weight in
+        let true_periods = periods.iter().filter(|p| sensor_analysis::true_weight(p) > 0.0).count();
+        let false_periods = periods.iter().filter(|p| sensor_analysis::true_weight(p) < 1.0).count();
+
+        if true_periods == 0 {
+            return Err(CalculatorError::NoTruePeriods);
+        }
+        if false_periods == 0 {
+            return Err(CalculatorError::NoFalsePeriods);
+        }
+
+        self.warnings = warnings;
+        self.numeric_stats.clear();
+        self.state_stats.clear();
+        self.profiles.clear();
+        self.profiled_periods = 0;
+        self.coverage.clear();
+        self.period_durations.clear();
+        self.sensor_attributes.clear();
+        self.leakage.clear();
+        self.period_counts = (true_periods, false_periods);
+
+        Ok((periods, labeled))
+    }
+
+    /// Apply `period_overlap` to TRUE and FALSE periods that cover the same time
+    fn resolve_overlaps(
+        &self,
+        periods: Vec<TimePeriod>,
+        warnings: &mut Vec<AnalysisWarning>,
+    ) -> Result<Vec<TimePeriod>, CalculatorError> {
+        let overlaps = preprocessing::period_overlaps(&periods);
+        let Some(&(first, _)) = overlaps.first() else {
+            return Ok(periods);
+        };
+
+        match self.options.period_overlap {
+            Some(preprocessing::OverlapPolicy::Reject) => {
+                let (true_period, false_period) = preprocessing::periods_at(&periods, first).unwrap_or_default();
+                Err(CalculatorError::OverlappingPeriods {
+                    true_period,
+                    false_period,
+                })
+            }
+            Some(policy) => Ok(preprocessing::resolve_overlaps(periods, &overlaps, policy)),
+            None => {
+                warnings.push(overlap_warning(&overlaps, "set periodOverlap to resolve them"));
+                Ok(periods)
+            }
+        }
+    }
+
+    /// `resolve_overlaps` for periods added to an analysis whose periods are already counted
+    ///
+    /// Only the new periods can give way: they lose the overlap when `period_overlap`
+    /// says their class does. Other overlaps stay counted towards both classes until
+    /// the next full calculation, with a warning.
+    fn resolve_new_overlaps(
+        &self,
+        periods: Vec<TimePeriod>,
+        warnings: &mut Vec<AnalysisWarning>,
+    ) -> Result<Vec<TimePeriod>, CalculatorError> {
+        let mut all = self.analysed_periods.clone();
+        all.extend(periods.iter().cloned());
+        let overlaps: Vec<(i64, i64)> = preprocessing::period_overlaps(&all)
+            .into_iter()
+            .filter(|&(start, end)| {
+                periods
+                    .iter()
+                    .any(|period| period.start.millis() < end && start < period.end.millis())
+            })
+            .collect();
+        let Some(&(first, _)) = overlaps.first() else {
+            return Ok(periods);
+        };
+
+        let losing = match self.options.period_overlap {
+            Some(preprocessing::OverlapPolicy::Reject) => {
+                let (true_period, false_period) = preprocessing::periods_at(&all, first).unwrap_or_default();
+                return Err(CalculatorError::OverlappingPeriods {
+                    true_period,
+                    false_period,
+                });
+            }
+            Some(preprocessing::OverlapPolicy::TrueWins) => Some(false),
+            Some(preprocessing::OverlapPolicy::FalseWins) => Some(true),
+            Some(preprocessing::OverlapPolicy::Split) | None => None,
+        };
+
+        let (cut, mut kept): (Vec<TimePeriod>, Vec<TimePeriod>) = periods
+            .into_iter()
+            .partition(|period| period.confidence.is_none() && Some(period.is_true_period) == losing);
+        if cut.is_empty() {
+            let advice = match self.options.period_overlap {
+                Some(_) => "recalculate to resolve them with periodOverlap",
+                None => "set periodOverlap and recalculate to resolve them",
+            };
+            warnings.push(overlap_warning(&overlaps, advice));
+        }
+        kept.extend(preprocessing::split_periods(&cut, &overlaps).1);
+        Ok(kept)
+    }
+
+    /// Add the clock-time virtual entities of `clock_features` to the history
+    fn with_clock_entities(
+        &self,
+        mut history: std::collections::HashMap<String, Vec<HAHistoryEntry>>,
+        periods: &[TimePeriod],
+    ) -> Result<std::collections::HashMap<String, Vec<HAHistoryEntry>>, CalculatorError> {
+        if let Some(features) = &self.options.clock_features {
+            let clock = clock::histories(periods, features).map_err(|message| CalculatorError::ParseError {
+                field: "clock features".to_string(),
+                message,
+            })?;
+            history.extend(clock);
+        }
+        Ok(history)
+    }
+
+    /// Build the results from the accumulated statistics and keep the input for `append_history`
+    fn finish_analysis(
+        &mut self,
+        history: std::collections::HashMap<String, Vec<HAHistoryEntry>>,
+        periods: Vec<TimePeriod>,
+        labeled: &[TimePeriod],
+        search: threshold::ThresholdSearch,
+    ) -> Result<Vec<EntityProbability>, CalculatorError> {
+        let mut results = self.build_results(search);
+        if self.options.cross_validation && !labeled.is_empty() {
+            self.cross_validate(&history, labeled, &mut results);
+        }
+        if let Some(options) = self.options.bootstrap.clone().filter(|_| !labeled.is_empty()) {
+            self.bootstrap(&history, labeled, &options, &mut results);
+        }
+        if self.cancelled.is_cancelled() {
+            return Err(CalculatorError::Cancelled);
+        }
+        self.retained_history = history;
+        self.analysed_periods = periods;
+        Ok(results)
+    }
+
+    /// Analyse once per labeled period with that period left out and attach the spread
+    fn cross_validate(
+        &mut self,
+        history: &std::collections::HashMap<String, Vec<HAHistoryEntry>>,
+        periods: &[TimePeriod],
+        results: &mut [EntityProbability],
+    ) {
+        let options = self.resampling_options();
+
+        let folds: Vec<Vec<EntityProbability>> = (0..periods.len())
+            .filter_map(|held_out| {
+                let rest = periods
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| i != held_out)
+                    .map(|(_, period)| period.clone())
+                    .collect();
+                let mut calculator = BayesianCalculator {
+                    options: options.clone(),
+                    entity_metadata: self.entity_metadata.clone(),
+                    cancelled: self.cancelled.clone(),
+                    ..BayesianCalculator::new()
+                };
+                // Folds left without a TRUE or FALSE period are skipped
+                calculator
+                    .process_entities_with_search(history.clone(), rest, threshold::ThresholdSearch::Full)
+                    .ok()
+            })
+            .collect();
+
+        self.warnings.extend(cross_validation::apply(results, &folds));
+    }
+
+    /// Analyse periods drawn with replacement and attach how much each row moves
+    fn bootstrap(
+        &mut self,
+        history: &std::collections::HashMap<String, Vec<HAHistoryEntry>>,
+        periods: &[TimePeriod],
+        options: &bootstrap::BootstrapOptions,
+        results: &mut [EntityProbability],
+    ) {
+        let calculator_options = self.resampling_options();
+
+        let resamples: Vec<Vec<EntityProbability>> = bootstrap::resample(periods, options)
+            .into_iter()
+            .filter_map(|periods| {
+                let mut calculator = BayesianCalculator {
+                    options: calculator_options.clone(),
+                    entity_metadata: self.entity_metadata.clone(),
+                    cancelled: self.cancelled.clone(),
+                    ..BayesianCalculator::new()
+                };
+                // Resamples without a TRUE or FALSE period are skipped
+                calculator
+                    .process_entities_with_search(history.clone(), periods, threshold::ThresholdSearch::Full)
+                    .ok()
+            })
+            .collect();
+
+        bootstrap::apply(results, &resamples);
+    }
+
+    /// Options of the analyses behind cross-validation and the bootstrap: every row,
+    /// without nesting either again
+    fn resampling_options(&self) -> CalculatorOptions {
+        CalculatorOptions {
+            cross_validation: false,
+            bootstrap: None,
+            correlation_threshold: None,
+            result_offset: 0,
+            max_results: None,
+            ..self.options.clone()
+        }
+    }
+
+    /// Tell the caller of the running calculation how far it has got
+    fn report_progress(&self, entities_done: usize, current_entity_id: Option<&str>) {
+        if let Some((callback, total)) = &self.progress {
+            let _ = callback.call3(
+                &JsValue::NULL,
+                &JsValue::from(entities_done as u32),
+                &JsValue::from(*total as u32),
+                &current_entity_id.map_or(JsValue::NULL, JsValue::from_str),
+            );
+        }
+    }
+
+    /// Read period times without an offset in `period_timezone`, turning them into instants
+    fn localize_periods(&self, mut periods: Vec<TimePeriod>) -> Result<Vec<TimePeriod>, CalculatorError> {
+        let Some(timezone) = &self.options.period_timezone else {
+            return Ok(periods);
+        };
+        let zone = time::parse_zone(timezone).map_err(|message| CalculatorError::ParseError {
+            field: "period timezone".to_string(),
+            message,
+        })?;
+
+        for period in &mut periods {
+            period.start = time::localize(&period.start, zone);
+            period.end = time::localize(&period.end, zone);
+        }
+        Ok(periods)
+    }
+
+    /// History and periods for replaying selected rows, with the periods read as the
+    /// analysis reads them
+    ///
+    /// History entries with unreadable timestamps are dropped as in an analysis; there
+    /// is no analysis to report them in, so no warning is kept.
+    fn parse_replay_input(
+        &self,
+        history: JsValue,
+        periods: JsValue,
+    ) -> Result<(History, Vec<TimePeriod>), JsValue> {
+        let mut history = parse_history(history, "history")?;
+        error::drop_invalid_timestamps(&mut history);
+        let periods = parse_periods(periods, &mut history)?;
+        let periods = self.localize_periods(periods)?;
+        error::check_timestamps(&periods)?;
+        Ok((history, periods))
+    }
+
+    /// Quantize the periods and cut out the exclusion windows
+    fn prepare_periods(&self, periods: Vec<TimePeriod>) -> Vec<TimePeriod> {
+        let resolution = self.options.time_resolution_ms;
+        let periods = if resolution > 0 {
+            preprocessing::quantize_periods(&periods, resolution)
+        } else {
+            periods
+        };
+
+        if self.options.exclusion_windows.is_empty() {
+            periods
+        } else {
+            preprocessing::exclude_windows(&periods, &self.options.exclusion_windows)
+        }
+    }
+
+    /// Fold each entity's history over the periods into the stored per-entity statistics
+    fn accumulate_entities(
+        &mut self,
+        history: &std::collections::HashMap<String, Vec<HAHistoryEntry>>,
+        periods: &[TimePeriod],
+    ) {
+        let batch_size = entity_analysis::batch_size(&self.options);
+        let entities: Vec<(&String, &Vec<HAHistoryEntry>)> = history.iter().collect();
+        let timeline = TimelineEngine::new(periods);
+
+        for (batch_index, batch) in entities.chunks(batch_size).enumerate() {
+            if self.cancelled.is_cancelled() {
+                break;
+            }
+            self.accumulate_batch(batch_index * batch_size, batch, &timeline);
+        }
 
-            let is_numeric = sensor_analysis::is_numeric_entity(entity_history);
-            
-            if is_numeric {
-                let numeric_stats = sensor_analysis::analyze_numeric_states(entity_history, &periods);
-                let optimal_thresholds = if let Some(stats) = &numeric_stats {
-                    self.get_or_calculate_thresholds(entity_id, stats)
+        self.profiled_periods += periods.len();
+    }
+
+    /// Analyse a batch of entities and merge them in; `first_position` is how many came before
+    fn accumulate_batch(
+        &mut self,
+        first_position: usize,
+        batch: &[(&String, &Vec<HAHistoryEntry>)],
+        timeline: &TimelineEngine,
+    ) {
+        let jobs: Vec<entity_analysis::EntityJob> = batch
+            .iter()
+            .map(|(entity_id, entity_history)| {
+                let known_numeric = if self.numeric_stats.contains_key(*entity_id) {
+                    Some(true)
+                } else if self.state_stats.contains_key(*entity_id) {
+                    Some(false)
                 } else {
                     None
                 };
+                (entity_id.as_str(), entity_history.as_slice(), known_numeric)
+            })
+            .collect();
+        let analyses = entity_analysis::analyze_batch(&jobs, timeline, &self.options, &self.entity_metadata);
 
-                // Calculate time-based probabilities for numeric entities
-                if let (Some(stats), Some(thresholds)) = (&numeric_stats, &optimal_thresholds) {
-                    let mut true_matching_duration = 0.0;
-                    let mut true_total_duration = 0.0;
-                    let mut false_matching_duration = 0.0;
-                    let mut false_total_duration = 0.0;
-                    
-                    // Calculate probabilities based on time duration, not occurrences
-                    for chunk in &stats.true_chunks {
-                        true_total_duration += chunk.duration as f64;
-                        if threshold::value_matches_thresholds(chunk.value, thresholds) {
-                            true_matching_duration += chunk.duration as f64;
-                        }
-                    }
-                    
-                    for chunk in &stats.false_chunks {
-                        false_total_duration += chunk.duration as f64;
-                        if threshold::value_matches_thresholds(chunk.value, thresholds) {
-                            false_matching_duration += chunk.duration as f64;
-                        }
-                    }
-                    
-                    let prob_given_true = if true_total_duration > 0.0 {
-                        true_matching_duration / true_total_duration
-                    } else {
-                        0.0
-                    };
-                    
-                    let prob_given_false = if false_total_duration > 0.0 {
-                        false_matching_duration / false_total_duration
-                    } else {
-                        0.0
-                    };
-                    
-                    // Preserve discrimination by scaling both probabilities proportionally
-                    let (clamped_true, clamped_false) = clamp_preserve_discrimination(prob_given_true, prob_given_false);
-                    let discrimination_power = (clamped_true - clamped_false).abs();
-                    
-                    // Create a descriptive state string for numeric thresholds
-                    let state_desc = threshold::format_threshold_description(thresholds);
-
-                    results.push(EntityProbability {
-                        entity_id: entity_id.clone(),
-                        state: state_desc,
-                        prob_given_true: clamped_true,
-                        prob_given_false: clamped_false,
-                        discrimination_power,
-                        true_occurrences: true_periods.len(),  // For numeric, we use period count
-                        false_occurrences: false_periods.len(),
-                        total_true_periods: true_periods.len(),
-                        total_false_periods: false_periods.len(),
-                        numeric_stats: numeric_stats.clone(),
-                        optimal_thresholds: optimal_thresholds.clone(),
-                    });
+        for (offset, ((entity_id, _), analysis)) in batch.iter().zip(analyses).enumerate() {
+            // Numeric entities are only done once their thresholds are found in `build_results`
+            let position = first_position + offset;
+            self.report_progress(position.saturating_sub(self.numeric_stats.len()), Some(entity_id));
+            self.merge_entity_analysis(entity_id, analysis);
+        }
+    }
+
+    /// Add one entity's analysis to the stored statistics
+    fn merge_entity_analysis(&mut self, entity_id: &str, analysis: entity_analysis::EntityAnalysis) {
+        self.warnings.extend(analysis.warnings);
+        self.coverage.entry(entity_id.to_string()).or_default().extend(analysis.coverage);
+        if !analysis.period_durations.is_empty() {
+            self.period_durations
+                .entry(entity_id.to_string())
+                .or_default()
+                .extend(analysis.period_durations);
+        }
+        if let Some(sensor) = analysis.sensor {
+            // Appended history need not repeat the attributes, so the first report sticks
+            self.sensor_attributes.entry(entity_id.to_string()).or_insert(sensor);
+        }
+        if let Some(evidence) = analysis.leakage {
+            self.leakage.entry(entity_id.to_string()).or_default().add(evidence);
+        }
+        for (derived_id, derived) in analysis.derived {
+            self.merge_entity_analysis(&derived_id, derived);
+        }
+
+        if let Some(profile) = analysis.profile {
+            let is_numeric = matches!(profile, correlation::EntityProfile::Numeric(_));
+            self.profiles
+                .entry(entity_id.to_string())
+                .or_insert_with(|| correlation::EntityProfile::empty(is_numeric))
+                .extend(profile, self.profiled_periods);
+        }
+
+        match analysis.stats {
+            // Keep the full chunk data on the calculator instead of copying it into every row
+            Some(entity_analysis::EntityStats::Numeric(numeric_stats)) => match self.numeric_stats.get_mut(entity_id) {
+                Some(existing) => existing.merge(numeric_stats),
+                None => {
+                    self.numeric_stats.insert(entity_id.to_string(), numeric_stats);
+                }
+            },
+            Some(entity_analysis::EntityStats::States(state_stats)) => {
+                let existing = self.state_stats.entry(entity_id.to_string()).or_default();
+                for (state, stats) in state_stats {
+                    let entry = existing.entry(state).or_default();
+                    entry.true_duration += stats.true_duration;
+                    entry.false_duration += stats.false_duration;
+                    entry.true_periods += stats.true_periods;
+                    entry.false_periods += stats.false_periods;
+                }
+            }
+            None => {}
+        }
+    }
+
+    /// Result rows from the stored per-entity statistics
+    fn build_results(&mut self, search: threshold::ThresholdSearch) -> Vec<EntityProbability> {
+        let (true_periods, false_periods) = self.period_counts;
+        let mut results = Vec::new();
+
+        let numeric_stats = std::mem::take(&mut self.numeric_stats);
+        #[cfg(feature = "parallel")]
+        if search == threshold::ThresholdSearch::Full {
+            self.search_thresholds_in_parallel(&numeric_stats);
+        }
+        let entities_done = self.progress.as_ref().map_or(0, |(_, total)| total.saturating_sub(numeric_stats.len()));
+        for (position, (entity_id, stats)) in numeric_stats.iter().enumerate() {
+            self.report_progress(entities_done + position, Some(entity_id));
+            results.extend(self.numeric_rows(entity_id, stats, search));
+        }
+        self.numeric_stats = numeric_stats;
+
+        let categorical: Vec<_> = self
+            .state_stats
+            .iter()
+            .map(|(entity_id, state_stats)| {
+                let mut buckets = state_grouping::user_buckets(entity_id, state_stats, &self.options.state_groups);
+                if self.options.mdl_state_grouping {
+                    // MDL merges whole user buckets, so their members stay together
+                    let bucket_stats = state_grouping::merge_stats(state_stats, &buckets);
+                    let members: FxHashMap<String, Vec<String>> = buckets.into_iter().collect();
+                    buckets = state_grouping::mdl_groups(&bucket_stats, true_periods, false_periods)
+                        .into_iter()
+                        .map(|labels| {
+                            let states = labels.iter().flat_map(|label| members[label].iter().cloned()).collect();
+                            let label = match labels.as_slice() {
+                                [label] => label.clone(),
+                                labels => state_grouping::group_label(labels),
+                            };
+                            (label, states)
+                        })
+                        .collect();
+                }
+
+                let ungrouped = |(label, members): &(String, Vec<String>)| members.as_slice() == std::slice::from_ref(label);
+                if buckets.iter().all(ungrouped) {
+                    return (entity_id, Cow::Borrowed(state_stats), FxHashMap::default());
                 }
+                let merged = state_grouping::merge_stats(state_stats, &buckets);
+                let state_groups = buckets.into_iter().filter(|bucket| !ungrouped(bucket)).collect();
+                (entity_id, Cow::Owned(merged), state_groups)
+            })
+            .collect();
+
+        // Global shrinkage and pooling groups span several entities, so rows are built last
+        let global_base_rates = match &self.options.shrinkage {
+            Some(shrinkage) if shrinkage.target == shrinkage::ShrinkageTarget::Global => {
+                let base_rates = shrinkage::base_rates(categorical.iter().map(|(_, stats, _)| stats.as_ref()));
+                let strength = match shrinkage.method {
+                    shrinkage::ShrinkageMethod::EmpiricalBayes => shrinkage::fitted_strength(
+                        categorical.iter().map(|(_, stats, _)| stats.as_ref()),
+                        &base_rates,
+                        (true_periods, false_periods),
+                        shrinkage.strength,
+                    ),
+                    _ => shrinkage.strength,
+                };
+                Some((base_rates, strength))
+            }
+            _ => None,
+        };
+        let group_rates: FxHashMap<&str, FxHashMap<String, (f64, f64)>> = self
+            .options
+            .pooling_groups
+            .iter()
+            .map(|group| {
+                let members = categorical
+                    .iter()
+                    .filter(|(entity_id, _, _)| group.entity_ids.contains(entity_id))
+                    .map(|(_, stats, _)| stats.as_ref());
+                (group.name.as_str(), pooling::class_rates(members))
+            })
+            .collect();
+
+        for (entity_id, state_stats, state_groups) in &categorical {
+            let entity_base_rates;
+            let adjustment = if let Some(group) = pooling::group_for(&self.options.pooling_groups, entity_id) {
+                StateAdjustment::Pool(group, &group_rates[group.name.as_str()])
+            } else if let Some(options) = &self.options.shrinkage {
+                let (base_rates, strength) = match &global_base_rates {
+                    Some((base_rates, strength)) => (base_rates, *strength),
+                    None => {
+                        entity_base_rates = shrinkage::entity_base_rates(state_stats);
+                        let strength = match options.method {
+                            shrinkage::ShrinkageMethod::EmpiricalBayes => shrinkage::fitted_strength(
+                                std::iter::once(state_stats.as_ref()),
+                                &entity_base_rates,
+                                (true_periods, false_periods),
+                                options.strength,
+                            ),
+                            _ => options.strength,
+                        };
+                        (&entity_base_rates, strength)
+                    }
+                };
+                StateAdjustment::Shrink(options, base_rates, strength)
             } else {
-                // Use duration-based approach for state sensors (same as numeric sensors)
-                let state_stats = sensor_analysis::analyze_state_chunks(entity_history, &periods);
-
-                // Calculate total durations for true and false periods
-                let mut total_true_duration = 0i64;
-                let mut total_false_duration = 0i64;
-                
-                for stats in state_stats.values() {
-                    total_true_duration += stats.true_duration;
-                    total_false_duration += stats.false_duration;
+                StateAdjustment::None
+            };
+
+            results.extend(build_state_results(
+                entity_id,
+                state_stats,
+                state_groups,
+                adjustment,
+                true_periods,
+                false_periods,
+                &self.options,
+            ));
+        }
+
+        self.annotate_rows(&mut results);
+        if let Some(threshold) = self.options.correlation_threshold {
+            correlation::mark_correlated(&mut results, &self.profiles, self.profiled_periods, threshold);
+        }
+
+        self.sort_results(&mut results);
+        results
+    }
+
+    /// Rows of one numeric entity: one per `numeric_bins` bin, or one for its thresholds
+    fn numeric_rows(
+        &mut self,
+        entity_id: &str,
+        stats: &sensor_analysis::NumericStateStats,
+        search: threshold::ThresholdSearch,
+    ) -> Vec<EntityProbability> {
+        let (true_periods, false_periods) = self.period_counts;
+        let thresholds = self.get_or_calculate_thresholds(entity_id, stats, search);
+
+        if let Some(&direction) = self.options.monotonic_constraints.get(entity_id) {
+            if let Some((consistent, opposite)) = threshold::direction_violation(stats, direction) {
+                self.warnings.push(AnalysisWarning {
+                    entity_id: Some(entity_id.to_string()),
+                    kind: WarningKind::MonotonicityViolation,
+                    message: format!(
+                        "{} was declared {:?} but separates better the opposite way ({:.2} vs {:.2})",
+                        entity_id, direction, opposite, consistent
+                    ),
+                });
+            }
+        }
+
+        // Calculate time-based probabilities for numeric entities
+        let bins = match self.options.numeric_bins {
+            Some(bins) if bins > 1 && !self.options.monotonic_constraints.contains_key(entity_id) => {
+                threshold::find_numeric_bins(stats, search, bins, &self.cancelled).unwrap_or_default()
+            }
+            _ => Vec::new(),
+        };
+        let rows = if bins.len() > 1 { bins } else { thresholds.into_iter().collect() };
+        rows.into_iter()
+            .map(|thresholds| {
+                let periods = self.period_durations.get(entity_id).map(Vec::as_slice);
+                let mut result =
+                    build_numeric_result(entity_id, stats, thresholds, true_periods, false_periods, periods, &self.options);
+                // Thresholds apply to the deviation from the trend, not the raw value
+                if self.options.detrend.contains_key(entity_id) {
+                    result.state = format!("detrended {}", result.state);
                 }
+                result
+            })
+            .collect()
+    }
 
-                for (state, stats) in state_stats.iter() {
-                    let prob_given_true = if total_true_duration > 0 {
-                        (stats.true_duration as f64) / (total_true_duration as f64)
-                    } else {
-                        0.0
-                    };
-                    
-                    let prob_given_false = if total_false_duration > 0 {
-                        (stats.false_duration as f64) / (total_false_duration as f64)
-                    } else {
-                        0.0
-                    };
-                    
-                    // Preserve discrimination by scaling both probabilities proportionally
-                    let (clamped_true, clamped_false) = clamp_preserve_discrimination(prob_given_true, prob_given_false);
-                    let discrimination_power = (clamped_true - clamped_false).abs();
-
-                    results.push(EntityProbability {
-                        entity_id: entity_id.clone(),
-                        state: state.clone(),
-                        prob_given_true: clamped_true,
-                        prob_given_false: clamped_false,
-                        discrimination_power,
-                        true_occurrences: if stats.true_duration > 0 { 1 } else { 0 }, // Convert duration to presence
-                        false_occurrences: if stats.false_duration > 0 { 1 } else { 0 }, // Convert duration to presence
-                        total_true_periods: true_periods.len(),
-                        total_false_periods: false_periods.len(),
-                        numeric_stats: None,
-                        optimal_thresholds: None,
-                    });
+    /// Attach what the stored statistics say about each row besides its
+    /// probabilities, and drop the rows `min_evidence` rejects
+    ///
+    /// Everything here looks at one entity at a time; correlations between entities
+    /// are marked separately.
+    fn annotate_rows(&mut self, results: &mut Vec<EntityProbability>) {
+        for row in results.iter_mut() {
+            row.coverage = self.coverage.get(&row.entity_id).map(|periods| coverage::overall(periods));
+            row.low_coverage = row.coverage.is_some_and(|fraction| fraction < self.options.min_coverage);
+            // Before the unit is appended to the state, which the breakdown matches on
+            if let Some(periods) = self.period_durations.get(&row.entity_id) {
+                if self.options.period_breakdown {
+                    row.period_breakdown = Some(breakdown::contributions(row, periods));
+                }
+                if self.options.include_chunks {
+                    row.chunks = Some(breakdown::chunk_details(row, periods));
                 }
             }
+            if let Some(sensor) = self.sensor_attributes.get(&row.entity_id) {
+                sensor.apply(row);
+            }
+            row.explanation = explanation::explain(row, &self.options.explanations);
+        }
+        if self.options.leakage_detection {
+            leakage::mark_possible_leakage(results, &self.leakage);
+        }
+        if self.options.min_evidence.as_ref().is_some_and(|minimum| minimum.drop_rows) {
+            results.retain(|row| !row.low_confidence);
         }
 
-        results.sort_by(|a, b| b.discrimination_power.partial_cmp(&a.discrimination_power).unwrap());
-        Ok(results)
+        if self.options.debug_validate {
+            self.warnings.extend(results.iter().flat_map(validation::check_result));
+        }
+    }
+
+    /// Order the rows by `sort_keys` and keep the requested page of them
+    fn sort_results(&self, results: &mut Vec<EntityProbability>) {
+        ranking::sort(results, &self.options.sort_keys);
+        ranking::page(results, self.options.result_offset, self.options.max_results);
+    }
+
+    /// Fill the threshold cache for every uncached entity at once across the rayon pool
+    #[cfg(feature = "parallel")]
+    fn search_thresholds_in_parallel(
+        &mut self,
+        numeric_stats: &std::collections::HashMap<String, sensor_analysis::NumericStateStats>,
+    ) {
+        use rayon::prelude::*;
+
+        let uncached: Vec<(&String, &sensor_analysis::NumericStateStats, Option<threshold::Monotonicity>, u64)> = numeric_stats
+            .iter()
+            .filter_map(|(entity_id, stats)| {
+                let direction = self.options.monotonic_constraints.get(entity_id).copied();
+                let cache_key = threshold::cache_key(stats, direction, self.options.hysteresis.as_ref(), self.options.scoring_metric);
+                let cached = self
+                    .threshold_cache
+                    .borrow()
+                    .get(entity_id)
+                    .is_some_and(|cache| cache.contains_key(&cache_key));
+                (!cached).then_some((entity_id, stats, direction, cache_key))
+            })
+            .collect();
+
+        let (cancelled, hysteresis, metric) = (&self.cancelled, self.options.hysteresis.as_ref(), self.options.scoring_metric);
+        let found: Vec<(&String, u64, Option<threshold::OptimalThresholds>)> = uncached
+            .into_par_iter()
+            .map(|(entity_id, stats, direction, cache_key)| {
+                (entity_id, cache_key, threshold::find_optimal_numeric_thresholds(stats, direction, hysteresis, metric, cancelled))
+            })
+            .collect();
+
+        // A cancelled search is incomplete and must not be cached
+        for (entity_id, cache_key, thresholds) in found {
+            if let Some(thresholds) = thresholds {
+                self.threshold_cache.borrow_mut().entry(entity_id.clone()).or_default().insert(cache_key, thresholds);
+            }
+        }
     }
 
     fn get_or_calculate_thresholds(
         &mut self,
         entity_id: &str,
         stats: &sensor_analysis::NumericStateStats,
+        search: threshold::ThresholdSearch,
     ) -> Option<threshold::OptimalThresholds> {
-        let cache_key = threshold::get_cache_key(stats);
+        let direction = self.options.monotonic_constraints.get(entity_id).copied();
+        let hysteresis = self.options.hysteresis.as_ref();
+        let metric = self.options.scoring_metric;
+
+        // Preview thresholds are deliberately coarse, so keep them out of the cache
+        if search == threshold::ThresholdSearch::Preview {
+            return threshold::find_numeric_thresholds(stats, search, direction, hysteresis, metric, &self.cancelled);
+        }
+
+        let cache_key = threshold::cache_key(stats, direction, hysteresis, metric);
         
-        if let Some(cache) = self.threshold_cache.get_mut(entity_id) {
-            if let Some(cached) = cache.get(&cache_key) {
-                return Some(cached.clone());
-            }
+        if let Some(cached) = self.threshold_cache.borrow().get(entity_id).and_then(|cache| cache.get(&cache_key)) {
+            return Some(cached.clone());
         }
 
-        let thresholds = threshold::find_optimal_numeric_thresholds(stats);
+        // A cancelled search is incomplete and must not be cached
+        let thresholds = threshold::find_optimal_numeric_thresholds(stats, direction, hysteresis, metric, &self.cancelled)?;
         
         self.threshold_cache
+            .borrow_mut()
             .entry(entity_id.to_string())
-            .or_insert_with(threshold::ThresholdCache::new)
+            .or_default()
             .insert(cache_key, thresholds.clone());
 
         Some(thresholds)
     }
 }
 
-/// Clamp probabilities while preserving discrimination power
-/// 
-/// This ensures that Bayesian calculations remain meaningful by avoiding
-/// identical probability values that would make observations non-discriminative
-fn clamp_preserve_discrimination(prob_true: f64, prob_false: f64) -> (f64, f64) {
-    // Simple clamping without artificial discrimination preservation
-    // The actual probabilities should be preserved, not forced to extreme values
-    let clamped_true = prob_true.max(0.01).min(0.99);
-    let clamped_false = prob_false.max(0.01).min(0.99);
+/// Warning for time counted towards both classes, ending with how to resolve it
+fn overlap_warning(overlaps: &[(i64, i64)], advice: &str) -> AnalysisWarning {
+    let minutes = overlaps.iter().map(|(start, end)| end - start).sum::<i64>() / 60_000;
+    AnalysisWarning {
+        entity_id: None,
+        kind: WarningKind::OverlappingPeriods,
+        message: format!(
+            "{} minutes are covered by both TRUE and FALSE periods and count towards both; {}",
+            minutes, advice
+        ),
+    }
+}
+
+/// Merge entries into an entity's history, keeping it in time order without repeats
+fn merge_entries(history: &mut Vec<HAHistoryEntry>, entries: Vec<HAHistoryEntry>) {
+    history.extend(entries);
+    history.sort_by_key(|entry| entry.last_changed.millis());
+    history.dedup_by(|a, b| a.last_changed.millis() == b.last_changed.millis() && a.state == b.state);
+}
+
+/// Parse a history map in HA's full or compressed (websocket `minimal_response`) format
+fn parse_history(
+    value: JsValue,
+    what: &str,
+) -> Result<std::collections::HashMap<String, Vec<HAHistoryEntry>>, JsValue> {
+    let history: std::collections::HashMap<String, Vec<types::HistoryEntryInput>> = serde_wasm_bindgen::from_value(value)
+        .map_err(|e| CalculatorError::ParseError {
+            field: what.to_string(),
+            message: e.to_string(),
+        })?;
+
+    Ok(history
+        .into_iter()
+        .map(|(entity_id, entries)| (entity_id, entries.into_iter().map(HAHistoryEntry::from).collect()))
+        .collect())
+}
+
+/// Parse the periods argument: an array of periods or a `PeriodSource`
+///
+/// A source referencing an entity builds the periods from, and takes that entity
+/// out of, `history`.
+fn parse_periods(
+    value: JsValue,
+    history: &mut std::collections::HashMap<String, Vec<HAHistoryEntry>>,
+) -> Result<Vec<TimePeriod>, JsValue> {
+    let parse_error = |message: String| CalculatorError::ParseError {
+        field: "periods".to_string(),
+        message,
+    };
+    let source = if js_sys::Array::is_array(&value) {
+        labeling::PeriodSource::List {
+            periods: serde_wasm_bindgen::from_value(value).map_err(|e| parse_error(e.to_string()))?,
+        }
+    } else {
+        serde_wasm_bindgen::from_value(value).map_err(|e| parse_error(e.to_string()))?
+    };
+    Ok(source.materialize(history).map_err(parse_error)?)
+}
+
+/// Parse an optional JS argument, using the type's default when it is `undefined` or `null`
+fn parse_optional<T: serde::de::DeserializeOwned + Default>(value: JsValue, what: &str) -> Result<T, JsValue> {
+    if value.is_undefined() || value.is_null() {
+        return Ok(T::default());
+    }
+
+    serde_wasm_bindgen::from_value(value)
+        .map_err(|e| JsValue::from_str(&format!("Failed to parse {}: {}", what, e)))
+}
+
+/// Build the result row for a numeric entity from its chunks and chosen thresholds
+///
+/// Probabilities are the fraction of TRUE/FALSE time the value matched the thresholds
+fn build_numeric_result(
+    entity_id: &str,
+    stats: &sensor_analysis::NumericStateStats,
+    thresholds: threshold::OptimalThresholds,
+    total_true_periods: usize,
+    total_false_periods: usize,
+    periods: Option<&[breakdown::PeriodDurations]>,
+    options: &CalculatorOptions,
+) -> EntityProbability {
+    let mut true_matching_duration = 0.0;
+    let mut true_total_duration = 0.0;
+    let mut false_matching_duration = 0.0;
+    let mut false_total_duration = 0.0;
     
-    (clamped_true, clamped_false)
-}
\ No newline at end of file
+    // Calculate probabilities based on time duration, not occurrences
+    for chunk in &stats.true_chunks {
+        true_total_duration += chunk.duration as f64;
+        if threshold::value_matches_thresholds(chunk.value, &thresholds) {
+            true_matching_duration += chunk.duration as f64;
+        }
+    }
+    
+    for chunk in &stats.false_chunks {
+        false_total_duration += chunk.duration as f64;
+        if threshold::value_matches_thresholds(chunk.value, &thresholds) {
+            false_matching_duration += chunk.duration as f64;
+        }
+    }
+    
+    let prob_given_true = if true_total_duration > 0.0 {
+        true_matching_duration / true_total_duration
+    } else {
+        0.0
+    };
+    
+    let prob_given_false = if false_total_duration > 0.0 {
+        false_matching_duration / false_total_duration
+    } else {
+        0.0
+    };
+    
+    let smoothed_true = options.smoothing.apply(prob_given_true, total_true_periods);
+    let smoothed_false = options.smoothing.apply(prob_given_false, total_false_periods);
+    let discrimination_power = (smoothed_true - smoothed_false).abs();
+    let score = options
+        .scoring_metric
+        .row_score((prob_given_true, prob_given_false), (smoothed_true, smoothed_false));
+
+    let low_confidence = options.min_evidence.as_ref().is_some_and(|minimum| {
+        let periods_seen = periods.map(|periods| breakdown::periods_in_range(&thresholds, periods));
+        minimum.lacking((true_matching_duration + false_matching_duration) as i64, periods_seen)
+    });
+
+    // Create a descriptive state string for numeric thresholds
+    let mut state_desc = threshold::format_threshold_description(&thresholds);
+    if stats.cumulative {
+        state_desc = format!("rate {}", state_desc);
+    }
+
+    EntityProbability {
+        entity_id: entity_id.to_string(),
+        state: state_desc,
+        prob_given_true: smoothed_true,
+        prob_given_false: smoothed_false,
+        discrimination_power,
+        score,
+        true_occurrences: total_true_periods,  // For numeric, we use period count
+        false_occurrences: total_false_periods,
+        total_true_periods,
+        total_false_periods,
+        numeric_stats: Some(stats.summary(options)),
+        threshold_sensitivity: options
+            .threshold_sensitivity
+            .then(|| threshold::sensitivity(stats, &thresholds, options.scoring_metric))
+            .flatten(),
+        optimal_thresholds: Some(thresholds),
+        pooled: None,
+        grouped_states: None,
+        correlated_with: Vec::new(),
+        cross_validation: None,
+        stability: None,
+        prob_given_true_interval: Some(smoothing::wilson_interval(prob_given_true, total_true_periods, options.interval_z)),
+        prob_given_false_interval: Some(smoothing::wilson_interval(prob_given_false, total_false_periods, options.interval_z)),
+        duration_shares: None,
+        occurrence_shares: None,
+        absence: None,
+        period_breakdown: None,
+        chunks: None,
+        explanation: None,
+        low_coverage: false,
+        coverage: None,
+        low_confidence,
+        possible_leakage: false,
+        unit_of_measurement: None,
+        device_class: None,
+    }
+}
+
+/// How a categorical entity's raw state probabilities are stabilized
+enum StateAdjustment<'a> {
+    None,
+    /// Toward a class-independent base rate per state, with the prior strength to use
+    Shrink(&'a shrinkage::ShrinkageOptions, &'a FxHashMap<String, f64>, f64),
+    /// Toward the group's P(state | TRUE) and P(state | FALSE) per state (hierarchical pooling)
+    Pool(&'a pooling::PoolingGroup, &'a FxHashMap<String, (f64, f64)>),
+}
+
+/// Build one result row per state of a categorical entity
+///
+/// Probabilities are the state's share of TRUE/FALSE time; merged states are labeled
+/// rows whose members are listed in `state_groups`. An adjustment pulls each
+/// toward its target according to how many TRUE (or FALSE) periods the state was
+/// seen in, so sparsely observed states move the most.
+fn build_state_results(
+    entity_id: &str,
+    state_stats: &FxHashMap<String, types::StateDurationStats>,
+    state_groups: &FxHashMap<String, Vec<String>>,
+    adjustment: StateAdjustment,
+    total_true_periods: usize,
+    total_false_periods: usize,
+    options: &CalculatorOptions,
+) -> Vec<EntityProbability> {
+    // Calculate total durations for true and false periods
+    let mut total_true_duration = 0i64;
+    let mut total_false_duration = 0i64;
+
+    for stats in state_stats.values() {
+        total_true_duration += stats.true_duration;
+        total_false_duration += stats.false_duration;
+    }
+
+    let share = |part: f64, total: f64| if total > 0.0 { part / total } else { 0.0 };
+
+    state_stats
+        .iter()
+        .map(|(state, stats)| {
+            let duration_shares = types::ClassShares {
+                given_true: share(stats.true_duration as f64, total_true_duration as f64),
+                given_false: share(stats.false_duration as f64, total_false_duration as f64),
+            };
+            let occurrence_shares = types::ClassShares {
+                given_true: share(stats.true_periods as f64, total_true_periods as f64).min(1.0),
+                given_false: share(stats.false_periods as f64, total_false_periods as f64).min(1.0),
+            };
+            let shares = match options.state_probability {
+                sensor_analysis::StateProbability::Duration => duration_shares,
+                sensor_analysis::StateProbability::Occurrence => occurrence_shares,
+            };
+            let (mut prob_given_true, mut prob_given_false) = (shares.given_true, shares.given_false);
+
+            let mut pooled = None;
+            match adjustment {
+                StateAdjustment::None => {}
+                StateAdjustment::Shrink(options, base_rates, strength) => {
+                    let base_rate = base_rates.get(state).copied().unwrap_or(0.0);
+                    if options.method == shrinkage::ShrinkageMethod::PeriodsSeen {
+                        prob_given_true = shrinkage::shrink(prob_given_true, base_rate, stats.true_periods, strength);
+                        prob_given_false = shrinkage::shrink(prob_given_false, base_rate, stats.false_periods, strength);
+                    } else {
+                        prob_given_true = shrinkage::beta_binomial(prob_given_true, base_rate, total_true_periods, strength);
+                        prob_given_false = shrinkage::beta_binomial(prob_given_false, base_rate, total_false_periods, strength);
+                    }
+                }
+                StateAdjustment::Pool(group, class_rates) => {
+                    let (group_true, group_false) = class_rates.get(state).copied().unwrap_or((0.0, 0.0));
+                    pooled = Some(pooling::PooledEstimate {
+                        group: group.name.clone(),
+                        prob_given_true: group_true,
+                        prob_given_false: group_false,
+                        deviation_true: prob_given_true - group_true,
+                        deviation_false: prob_given_false - group_false,
+                    });
+                    prob_given_true = shrinkage::shrink(prob_given_true, group_true, stats.true_periods, group.strength);
+                    prob_given_false = shrinkage::shrink(prob_given_false, group_false, stats.false_periods, group.strength);
+                }
+            }
+
+            let smoothed_true = options.smoothing.apply(prob_given_true, total_true_periods);
+            let smoothed_false = options.smoothing.apply(prob_given_false, total_false_periods);
+            let discrimination_power = (smoothed_true - smoothed_false).abs();
+            let score = options
+                .scoring_metric
+                .row_score((prob_given_true, prob_given_false), (smoothed_true, smoothed_false));
+            let low_confidence = options.min_evidence.as_ref().is_some_and(|minimum| {
+                minimum.lacking(stats.true_duration + stats.false_duration, Some(stats.true_periods + stats.false_periods))
+            });
+            // Every smoothing is symmetric, so the complement of the smoothed share is the
+            // smoothed complement
+            let (absent_true, absent_false) = (1.0 - smoothed_true, 1.0 - smoothed_false);
+            let strength = |p, q| scoring::ScoringMetric::LogLikelihoodRatio.score(p, q);
+            let absence = types::Absence {
+                prob_given_true: absent_true,
+                prob_given_false: absent_false,
+                preferred: options.absence_observations
+                    && strength(absent_true, absent_false) > strength(smoothed_true, smoothed_false),
+            };
+
+            EntityProbability {
+                entity_id: entity_id.to_string(),
+                state: state.clone(),
+                prob_given_true: smoothed_true,
+                prob_given_false: smoothed_false,
+                discrimination_power,
+                score,
+                true_occurrences: stats.true_periods,
+                false_occurrences: stats.false_periods,
+                total_true_periods,
+                total_false_periods,
+                numeric_stats: None,
+                threshold_sensitivity: None,
+                optimal_thresholds: None,
+                pooled,
+                grouped_states: state_groups.get(state).cloned(),
+                correlated_with: Vec::new(),
+                cross_validation: None,
+                stability: None,
+                prob_given_true_interval: Some(smoothing::wilson_interval(prob_given_true, total_true_periods, options.interval_z)),
+                prob_given_false_interval: Some(smoothing::wilson_interval(prob_given_false, total_false_periods, options.interval_z)),
+                duration_shares: Some(duration_shares),
+                occurrence_shares: Some(occurrence_shares),
+                absence: Some(absence),
+                period_breakdown: None,
+                chunks: None,
+                explanation: None,
+                low_coverage: false,
+                coverage: None,
+                low_confidence,
+                possible_leakage: false,
+                unit_of_measurement: None,
+                device_class: None,
+            }
+        })
+        .collect()
+}
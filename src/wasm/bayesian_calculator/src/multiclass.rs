@@ -0,0 +1,40 @@
+use crate::types::{AnalysisWarning, EntityProbability, TimePeriod};
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Ranked observations for one class of a multi-class target
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ClassResults {
+    /// The period `class` analysed against the rest
+    pub class: String,
+    /// Periods labeled with the class, which count as TRUE against all others
+    pub periods: usize,
+    pub results: Vec<EntityProbability>,
+    /// Warnings raised while analysing this class
+    pub warnings: Vec<AnalysisWarning>,
+}
+
+/// The distinct period classes, sorted
+pub fn classes(periods: &[TimePeriod]) -> Vec<String> {
+    let mut classes: Vec<String> = periods.iter().filter_map(|period| period.class.clone()).collect();
+    classes.sort_unstable();
+    classes.dedup();
+    classes
+}
+
+/// The periods relabeled as `class` against the rest
+///
+/// Periods without a class count as FALSE for every class. `confidence` belongs to
+/// the binary labeling and is dropped, since class membership is taken from the class.
+pub fn one_vs_rest(periods: &[TimePeriod], class: &str) -> Vec<TimePeriod> {
+    periods
+        .iter()
+        .map(|period| TimePeriod {
+            is_true_period: period.class.as_deref() == Some(class),
+            confidence: None,
+            ..period.clone()
+        })
+        .collect()
+}
@@ -0,0 +1,49 @@
+use crate::export::{self, observations_from_results, SensorSettings};
+use crate::posterior;
+use crate::types::EntityProbability;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct OperatingPoint {
+    /// `conservative`, `balanced` or `sensitive`
+    pub name: String,
+    pub probability_threshold: f64,
+    pub predicted_precision: f64,
+    pub predicted_recall: f64,
+    pub predicted_false_positive_rate: f64,
+    pub yaml: String,
+}
+
+/// Complete configs at three operating points along the precision/recall trade-off
+///
+/// Candidate thresholds from 0.05 to 0.95 are scored on the predicted posterior
+/// distribution. Conservative maximizes F0.5 (favours precision), balanced maximizes
+/// F1 and sensitive maximizes F2 (favours recall).
+pub fn generate(rows: &[EntityProbability], settings: &SensorSettings) -> Vec<OperatingPoint> {
+    let observations = observations_from_results(rows);
+    let distribution = posterior::predicted_distribution(&observations, settings.prior);
+
+    [("conservative", 0.5), ("balanced", 1.0), ("sensitive", 2.0)]
+        .into_iter()
+        .map(|(name, beta)| {
+            let (threshold, metrics) = posterior::best_threshold(&distribution, settings.prior, beta);
+
+            let point_settings = SensorSettings {
+                probability_threshold: threshold,
+                ..settings.clone()
+            };
+
+            OperatingPoint {
+                name: name.to_string(),
+                probability_threshold: threshold,
+                predicted_precision: metrics.precision,
+                predicted_recall: metrics.recall,
+                predicted_false_positive_rate: metrics.false_positive_rate,
+                yaml: export::generate_bayesian_yaml(rows, &point_settings),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,227 @@
+use crate::bootstrap::BootstrapOptions;
+use crate::clock::ClockFeatures;
+use crate::density::LikelihoodModel;
+use crate::filter::MinimumEvidence;
+use crate::flapping::HysteresisOptions;
+use crate::explanation::ExplanationTemplates;
+use crate::pooling::PoolingGroup;
+use crate::state_grouping::StateGroup;
+use crate::preprocessing::{Detrend, ExclusionWindow, OverlapPolicy};
+use crate::ranking::SortKey;
+use crate::sensor_analysis::{DerivedFeature, OutlierFilter, StateProbability, UnavailableMode};
+use crate::shrinkage::ShrinkageOptions;
+use crate::scoring::ScoringMetric;
+use crate::smoothing::Smoothing;
+use crate::threshold::Monotonicity;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tsify::Tsify;
+
+/// Tunable analysis settings, configured once per calculator via `set_options`
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct CalculatorOptions {
+    /// Entity categories to skip. Defaults to `config` and `diagnostic`, which HA uses
+    /// for entities besides a device's primary function, so those are left out of the
+    /// results unless this is set to an empty list
+    pub excluded_entity_categories: Vec<String>,
+    /// Analyse entities hidden in HA; they are skipped by default
+    pub include_hidden: bool,
+    /// Analyse entities disabled in HA; they are skipped by default
+    pub include_disabled: bool,
+    /// Entity id patterns, with `*` and `?` wildcards (`light.*`), of which an entity
+    /// must match one to be analysed; every entity is when empty
+    pub include_entities: Vec<String>,
+    /// Entity id patterns to skip, such as `sensor.*_battery` or `update.*`
+    pub exclude_entities: Vec<String>,
+    /// The entity the Bayesian sensor is meant to replace or predict; it is skipped,
+    /// since it would explain its own labels
+    pub target_entity: Option<String>,
+    /// Fold numeric chunks into per-value duration totals during the sweep instead of
+    /// keeping every chunk, bounding memory for long, high-resolution histories
+    pub streaming_aggregation: bool,
+    /// Check internal invariants during analysis and report violations as warnings
+    pub debug_validate: bool,
+    /// Shrink categorical state probabilities toward a base rate in proportion to how
+    /// few periods the state was seen in; off when unset
+    pub shrinkage: Option<ShrinkageOptions>,
+    /// Groups of similar entities whose categorical statistics are partially pooled;
+    /// grouped entities are drawn toward the group estimate instead of `shrinkage`
+    pub pooling_groups: Vec<PoolingGroup>,
+    /// Merge categorical states with indistinguishable TRUE/FALSE splits into one row
+    pub mdl_state_grouping: bool,
+    /// Buckets of equivalent categorical states analysed as one row each, applied
+    /// before `mdl_state_grouping`
+    pub state_groups: Vec<StateGroup>,
+    /// Expected direction per numeric entity; its thresholds are restricted to that
+    /// direction and contradicting data is reported as a warning
+    pub monotonic_constraints: HashMap<String, Monotonicity>,
+    /// Numeric entities whose level drifts over weeks, analysed as deviations from
+    /// their trend; using the resulting thresholds in HA needs a matching derived sensor
+    pub detrend: HashMap<String, Detrend>,
+    /// Round all history and period timestamps to this many milliseconds (e.g. 10000
+    /// or 60000) before analysis; 0 keeps them as recorded
+    pub time_resolution_ms: i64,
+    /// Atypical date ranges cut out of every period before analysis
+    pub exclusion_windows: Vec<ExclusionWindow>,
+    /// Keys rows are ordered by, each breaking the ties of the ones before; rows still
+    /// tied are ordered by entity and state, so repeated runs return the same order
+    pub sort_keys: Vec<SortKey>,
+    /// Rows skipped from the start of the ordered results, for paging through them
+    pub result_offset: usize,
+    /// Most rows returned after `result_offset`; all of them when unset
+    pub max_results: Option<usize>,
+    /// Mark entity pairs whose per-period behaviour correlates at least this strongly
+    /// (absolute Pearson r) in `correlated_with`; off when unset
+    pub correlation_threshold: Option<f64>,
+    /// Re-run the analysis once per labeled period with that period held out and report
+    /// how much each row's probabilities move; costs one full analysis per period
+    pub cross_validation: bool,
+    /// Re-run the analysis on labeled periods drawn with replacement and report how
+    /// much each row's discrimination and thresholds move (`stability`); off when unset
+    pub bootstrap: Option<BootstrapOptions>,
+    /// How probabilities are kept inside (0, 1); a fixed 0.01/0.99 clamp by default
+    pub smoothing: Smoothing,
+    /// Standard deviations covered by each row's probability intervals (1.96 for 95%)
+    pub interval_z: f64,
+    /// With the `parallel` feature, entities analysed together across the thread pool;
+    /// progress and cancellation are checked between batches
+    pub entity_chunk_size: usize,
+    /// Split each numeric entity into up to this many value bins, one row per bin, so
+    /// several `numeric_state` observations can describe one sensor; entities with a
+    /// declared direction keep their single threshold. Off when unset
+    pub numeric_bins: Option<usize>,
+    /// Bins of the TRUE/FALSE value histograms in each numeric row's stats; 0 leaves
+    /// the distribution out
+    pub histogram_bins: usize,
+    /// Score each numeric row's thresholds shifted by ±1%, ±5% and ±10% of the value
+    /// range and flag those that collapse under a 1% shift as `razorEdge`
+    pub threshold_sensitivity: bool,
+    /// Fit a continuous model to each class of every numeric entity and report how
+    /// well its likelihood ratio separates them (`numericStats.density`), for sensors
+    /// that discriminate through the shape of their values. Off when unset
+    pub likelihood_model: Option<LikelihoodModel>,
+    /// Penalize numeric thresholds in bands the sensor flaps across and suggest a
+    /// hysteresis margin for the chosen ones; off when unset. Needs chunks in time
+    /// order, so it has no effect with `streaming_aggregation`
+    pub hysteresis: Option<HysteresisOptions>,
+    /// Series derived from every numeric entity and analysed as virtual entities such
+    /// as `sensor.living_room_temp::derivative`; using their thresholds in HA needs a
+    /// matching helper sensor (e.g. a `derivative` helper)
+    pub derived_features: Vec<DerivedFeature>,
+    /// Replace numeric histories with their trailing duration-weighted average over
+    /// this many seconds before analysis, so single-reading spikes cannot dominate the
+    /// thresholds; using them in HA needs a matching `statistics` helper
+    /// (`average_step` over the same `max_age`). Off when unset
+    pub rolling_average_seconds: Option<f64>,
+    /// Drop or clip numeric chunks with glitch values before the value range and
+    /// thresholds are computed; off when unset
+    pub outlier_filter: Option<OutlierFilter>,
+    /// How time spent `unavailable` or `unknown` counts; left out entirely by default
+    pub unavailable: UnavailableMode,
+    /// Rows of entities with recorded data for less than this share of the labeled
+    /// time are marked `low_coverage`
+    pub min_coverage: f64,
+    /// Attributes analysed as virtual entities such as `light.kitchen::brightness`,
+    /// by entity id; using their rows in HA needs a template sensor for the attribute
+    pub analyzed_attributes: HashMap<String, Vec<String>>,
+    /// Analyse the per-minute rate of numeric entities that only ever count up (energy
+    /// or rain totals) instead of their reading; using the rows in HA needs a
+    /// `derivative` helper over the counter
+    pub cumulative_detection: bool,
+    /// Numeric entities whose values wrap around, by entity id and the value they wrap
+    /// at (e.g. 24 for an hour of day), so a range like "> 330 or <= 30" can be found.
+    /// Angles in degrees are detected from their unit or `wind_direction` device class
+    pub cyclic_entities: HashMap<String, f64>,
+    /// Virtual entities for the hour of day and weekday/weekend, built from the period
+    /// timestamps and ranked with the rest; exported as `now()` templates. Off when unset
+    pub clock_features: Option<ClockFeatures>,
+    /// IANA time zone that period times without an offset (`2024-03-10T18:00:00`) are
+    /// read in, so labels like "every evening at 18:00" stay on the wall clock across
+    /// DST changes; they are read as UTC when unset
+    pub period_timezone: Option<String>,
+    /// What happens to time covered by both a TRUE and a FALSE period; when unset it
+    /// counts towards both classes with an `overlappingPeriods` warning
+    pub period_overlap: Option<OverlapPolicy>,
+    /// Rows backed by less labeled time or fewer periods are marked `low_confidence`,
+    /// so a state seen for seconds in one period cannot rank first. Off when unset
+    pub min_evidence: Option<MinimumEvidence>,
+    /// Mark rows of entities that look derived from the target (an existing
+    /// `bayesian` sensor, or a near-perfect entity whose changes follow the period
+    /// boundaries) as `possible_leakage`
+    pub leakage_detection: bool,
+    /// Export categorical rows as `state != X` observations where the state's
+    /// absence is stronger evidence than its presence
+    pub absence_observations: bool,
+    /// Break each row down by labeled period (`periodBreakdown`), to show which
+    /// periods drive its probabilities
+    pub period_breakdown: bool,
+    /// Attach each row's time slices per period (`chunks`), marked by whether they
+    /// match the row; kept for every analysed period, so memory grows with the history
+    pub include_chunks: bool,
+    /// Sentences each row's `explanation` is built from
+    pub explanations: ExplanationTemplates,
+    /// How rows are ranked and numeric thresholds chosen; `|P(T) - P(F)|` over-ranks
+    /// states that are on most of the time in both classes
+    pub scoring_metric: ScoringMetric,
+    /// Whether categorical probabilities are shares of time or of periods; both are
+    /// reported on each row either way
+    pub state_probability: StateProbability,
+}
+
+impl Default for CalculatorOptions {
+    fn default() -> Self {
+        Self {
+            excluded_entity_categories: vec!["config".to_string(), "diagnostic".to_string()],
+            include_hidden: false,
+            include_disabled: false,
+            include_entities: Vec::new(),
+            exclude_entities: Vec::new(),
+            target_entity: None,
+            streaming_aggregation: false,
+            debug_validate: false,
+            shrinkage: None,
+            pooling_groups: Vec::new(),
+            mdl_state_grouping: false,
+            state_groups: Vec::new(),
+            monotonic_constraints: HashMap::new(),
+            detrend: HashMap::new(),
+            time_resolution_ms: 0,
+            exclusion_windows: Vec::new(),
+            sort_keys: vec![SortKey::Score],
+            result_offset: 0,
+            max_results: None,
+            correlation_threshold: None,
+            cross_validation: false,
+            bootstrap: None,
+            smoothing: Smoothing::default(),
+            interval_z: 1.96,
+            entity_chunk_size: 32,
+            numeric_bins: None,
+            histogram_bins: 20,
+            threshold_sensitivity: false,
+            likelihood_model: None,
+            hysteresis: None,
+            derived_features: Vec::new(),
+            rolling_average_seconds: None,
+            outlier_filter: None,
+            unavailable: UnavailableMode::default(),
+            min_coverage: 0.1,
+            analyzed_attributes: HashMap::new(),
+            cumulative_detection: true,
+            cyclic_entities: HashMap::new(),
+            clock_features: None,
+            period_timezone: None,
+            period_overlap: None,
+            min_evidence: None,
+            leakage_detection: true,
+            absence_observations: false,
+            period_breakdown: false,
+            include_chunks: false,
+            explanations: ExplanationTemplates::default(),
+            scoring_metric: ScoringMetric::default(),
+            state_probability: StateProbability::default(),
+        }
+    }
+}
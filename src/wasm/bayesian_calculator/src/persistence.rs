@@ -0,0 +1,69 @@
+use crate::breakdown::PeriodDurations;
+use crate::correlation::EntityProfile;
+use crate::coverage::PeriodCoverage;
+use crate::leakage::LeakageEvidence;
+use crate::sensor_analysis::NumericStateStats;
+use crate::threshold::ThresholdCache;
+use crate::types::{SensorAttributes, StateDurationStats};
+use crate::BayesianCalculator;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Bumped whenever the layout below changes, so stale snapshots are rejected
+/// instead of being misread
+const STATE_VERSION: u32 = 11;
+
+/// Snapshot of a calculator's per-entity analysis
+///
+/// Borrowed when saving and owned when restoring. The raw history is not kept, so a
+/// restored calculator supports `update_with_period` but not `append_history`.
+#[derive(Serialize, Deserialize)]
+pub struct PersistedState<'a> {
+    version: u32,
+    pub threshold_cache: Cow<'a, HashMap<String, ThresholdCache>>,
+    pub numeric_stats: Cow<'a, HashMap<String, NumericStateStats>>,
+    pub state_stats: Cow<'a, HashMap<String, FxHashMap<String, StateDurationStats>>>,
+    pub period_counts: (usize, usize),
+    pub profiles: Cow<'a, HashMap<String, EntityProfile>>,
+    pub profiled_periods: usize,
+    pub coverage: Cow<'a, HashMap<String, Vec<PeriodCoverage>>>,
+    pub period_durations: Cow<'a, HashMap<String, Vec<PeriodDurations>>>,
+    pub sensor_attributes: Cow<'a, HashMap<String, SensorAttributes>>,
+    pub leakage: Cow<'a, HashMap<String, LeakageEvidence>>,
+}
+
+impl<'a> PersistedState<'a> {
+    pub fn new(calculator: &'a BayesianCalculator) -> Self {
+        Self {
+            version: STATE_VERSION,
+            // Shared with any refinement still running, so copied out
+            threshold_cache: Cow::Owned(calculator.threshold_cache.borrow().clone()),
+            numeric_stats: Cow::Borrowed(&calculator.numeric_stats),
+            state_stats: Cow::Borrowed(&calculator.state_stats),
+            period_counts: calculator.period_counts,
+            profiles: Cow::Borrowed(&calculator.profiles),
+            profiled_periods: calculator.profiled_periods,
+            coverage: Cow::Borrowed(&calculator.coverage),
+            period_durations: Cow::Borrowed(&calculator.period_durations),
+            sensor_attributes: Cow::Borrowed(&calculator.sensor_attributes),
+            leakage: Cow::Borrowed(&calculator.leakage),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        bincode::serialize(self).map_err(|e| e.to_string())
+    }
+}
+
+impl PersistedState<'static> {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        // The version leads the encoding, so it can be checked before the rest is decoded
+        let version: u32 = bincode::deserialize(bytes).map_err(|e| e.to_string())?;
+        if version != STATE_VERSION {
+            return Err(format!("unsupported state version {} (expected {})", version, STATE_VERSION));
+        }
+        bincode::deserialize(bytes).map_err(|e| e.to_string())
+    }
+}
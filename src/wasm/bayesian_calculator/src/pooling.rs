@@ -0,0 +1,64 @@
+use crate::types::StateDurationStats;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Entities declared similar (e.g. all door sensors) whose statistics are pooled
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PoolingGroup {
+    pub name: String,
+    pub entity_ids: Vec<String>,
+    /// Pseudo-periods of evidence the group estimate is worth for each member
+    #[serde(default = "default_strength")]
+    pub strength: f64,
+}
+
+fn default_strength() -> f64 {
+    4.0
+}
+
+/// The group estimate a pooled row was drawn toward, and how far the entity's own data deviates from it
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PooledEstimate {
+    pub group: String,
+    pub prob_given_true: f64,
+    pub prob_given_false: f64,
+    /// Entity's unpooled estimate minus the group estimate
+    pub deviation_true: f64,
+    pub deviation_false: f64,
+}
+
+/// Group whose members include `entity_id`; the first match wins when groups overlap
+pub fn group_for<'a>(groups: &'a [PoolingGroup], entity_id: &str) -> Option<&'a PoolingGroup> {
+    groups.iter().find(|group| group.entity_ids.iter().any(|id| id == entity_id))
+}
+
+/// P(state | TRUE) and P(state | FALSE) for each state value over all members' time combined
+pub fn class_rates<'a>(
+    members: impl IntoIterator<Item = &'a FxHashMap<String, StateDurationStats>>,
+) -> FxHashMap<String, (f64, f64)> {
+    let mut durations: FxHashMap<String, (i64, i64)> = FxHashMap::default();
+    let (mut total_true, mut total_false) = (0i64, 0i64);
+
+    for state_stats in members {
+        for (state, stats) in state_stats {
+            let entry = durations.entry(state.clone()).or_default();
+            entry.0 += stats.true_duration;
+            entry.1 += stats.false_duration;
+            total_true += stats.true_duration;
+            total_false += stats.false_duration;
+        }
+    }
+
+    let rate = |duration: i64, total: i64| if total > 0 { duration as f64 / total as f64 } else { 0.0 };
+    durations
+        .into_iter()
+        .map(|(state, (true_duration, false_duration))| {
+            (state, (rate(true_duration, total_true), rate(false_duration, total_false)))
+        })
+        .collect()
+}
@@ -0,0 +1,231 @@
+use crate::export::Observation;
+
+/// Log-odds resolution used to merge nearly identical posteriors during enumeration
+const LOG_ODDS_RESOLUTION: f64 = 1e-4;
+
+/// Probabilities are kept this far from 0 and 1 so every log-likelihood ratio is finite
+const PROBABILITY_EPSILON: f64 = 1e-9;
+
+/// Probability mass of one posterior value under each class
+#[derive(Debug, Clone, Copy)]
+pub struct PosteriorMass {
+    pub posterior: f64,
+    /// The posterior as log-odds, which keeps its precision when it saturates near 0 or 1
+    pub log_odds: f64,
+    /// Fraction of TRUE time the sensor would report this posterior
+    pub weight_true: f64,
+    /// Fraction of FALSE time the sensor would report this posterior
+    pub weight_false: f64,
+}
+
+/// One way an entity's observations can turn out, with its likelihoods and class frequencies
+struct GroupOutcome {
+    log_likelihood_ratio: f64,
+    freq_true: f64,
+    freq_false: f64,
+}
+
+/// Predicted distribution of the bayesian sensor's posterior during TRUE and FALSE time
+///
+/// Assumes entities are independent given the class (the same assumption HA's
+/// bayesian sensor makes). Observations of the same entity are treated as mutually
+/// exclusive, since an entity is in one state at a time. Every observation contributes
+/// its likelihood when met and the complement when not, matching HA's update.
+///
+/// Posteriors are accumulated as log-odds and class weights as log-probabilities, so
+/// long configurations and extreme probabilities neither underflow nor saturate
+/// before the final conversion.
+pub fn predicted_distribution(observations: &[Observation], prior: f64) -> Vec<PosteriorMass> {
+    let mut distribution: Vec<(f64, f64, f64)> = vec![(prior_log_odds(prior), 0.0, 0.0)];
+
+    for outcomes in entity_outcomes(observations) {
+        // Ordered by log-odds so the weights are always summed in the same order
+        let mut next: std::collections::BTreeMap<i64, (f64, f64, f64)> = std::collections::BTreeMap::new();
+
+        for &(log_odds, log_weight_true, log_weight_false) in &distribution {
+            for outcome in &outcomes {
+                let combined = log_odds + outcome.log_likelihood_ratio;
+                let key = (combined / LOG_ODDS_RESOLUTION).round() as i64;
+                let entry = next.entry(key).or_insert((combined, f64::NEG_INFINITY, f64::NEG_INFINITY));
+                entry.1 = log_add(entry.1, log_weight_true + libm::log(outcome.freq_true));
+                entry.2 = log_add(entry.2, log_weight_false + libm::log(outcome.freq_false));
+            }
+        }
+
+        distribution = next.into_values().collect();
+    }
+
+    let mut masses: Vec<PosteriorMass> = distribution
+        .into_iter()
+        .map(|(log_odds, log_weight_true, log_weight_false)| PosteriorMass {
+            posterior: posterior_from_log_odds(log_odds),
+            log_odds,
+            weight_true: libm::exp(log_weight_true),
+            weight_false: libm::exp(log_weight_false),
+        })
+        .collect();
+    masses.sort_by(|a, b| a.posterior.total_cmp(&b.posterior));
+    masses
+}
+
+/// `ln(e^a + e^b)` without leaving log space
+fn log_add(a: f64, b: f64) -> f64 {
+    let (high, low) = if a >= b { (a, b) } else { (b, a) };
+    if low == f64::NEG_INFINITY {
+        return high;
+    }
+    high + libm::log1p(libm::exp(low - high))
+}
+
+/// Log-odds of a probability, kept finite for 0 and 1
+pub fn logit(probability: f64) -> f64 {
+    let p = probability.clamp(PROBABILITY_EPSILON, 1.0 - PROBABILITY_EPSILON);
+    libm::log(p / (1.0 - p))
+}
+
+pub fn prior_log_odds(prior: f64) -> f64 {
+    logit(prior.clamp(1e-6, 1.0 - 1e-6))
+}
+
+/// Log-likelihood ratios an observation adds when (met, not met)
+pub fn log_likelihood_ratios(observation: &Observation) -> (f64, f64) {
+    let clamp = |p: f64| p.clamp(PROBABILITY_EPSILON, 1.0 - PROBABILITY_EPSILON);
+    let (p_true, p_false) = (clamp(observation.prob_given_true), clamp(observation.prob_given_false));
+    (libm::log(p_true / p_false), libm::log((1.0 - p_true) / (1.0 - p_false)))
+}
+
+/// Lowest and highest log-odds the sensor can reach, starting from the prior
+///
+/// Each entity contributes its most extreme outcome in either direction, so
+/// observations of the same entity are never counted as met together.
+pub fn log_odds_range(observations: &[Observation], prior: f64) -> (f64, f64) {
+    let prior_log_odds = prior_log_odds(prior);
+
+    entity_outcomes(observations)
+        .iter()
+        .fold((prior_log_odds, prior_log_odds), |(low, high), outcomes| {
+            let ratios = outcomes.iter().map(|o| o.log_likelihood_ratio);
+            (low + ratios.clone().fold(f64::INFINITY, f64::min), high + ratios.fold(f64::NEG_INFINITY, f64::max))
+        })
+}
+
+/// Log-likelihood ratio an observation adds when met minus the one it adds when not met
+pub fn log_odds_swing(observation: &Observation) -> f64 {
+    let (met, unmet) = log_likelihood_ratios(observation);
+    met - unmet
+}
+
+/// Logistic function, written so neither branch overflows
+pub fn posterior_from_log_odds(log_odds: f64) -> f64 {
+    if log_odds >= 0.0 {
+        1.0 / (1.0 + libm::exp(-log_odds))
+    } else {
+        let odds = libm::exp(log_odds);
+        odds / (1.0 + odds)
+    }
+}
+
+/// Observations grouped per entity, expanded into their mutually exclusive outcomes
+fn entity_outcomes(observations: &[Observation]) -> Vec<Vec<GroupOutcome>> {
+    let mut groups: Vec<(&str, Vec<&Observation>)> = Vec::new();
+    for observation in observations {
+        match groups.iter_mut().find(|(id, _)| *id == observation.entity_id) {
+            Some((_, members)) => members.push(observation),
+            None => groups.push((&observation.entity_id, vec![observation])),
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|(_, members)| {
+            // Clamped per-state probabilities can sum past 1; rescale so frequencies stay valid
+            let sum_true: f64 = members.iter().map(|o| o.prob_given_true).sum();
+            let sum_false: f64 = members.iter().map(|o| o.prob_given_false).sum();
+            let scale_true = sum_true.max(1.0);
+            let scale_false = sum_false.max(1.0);
+
+            // Log likelihood ratio when none of the members is met
+            let none_met: f64 = members.iter().map(|o| log_likelihood_ratios(o).1).sum();
+
+            let mut outcomes: Vec<GroupOutcome> = members
+                .iter()
+                .map(|o| GroupOutcome {
+                    log_likelihood_ratio: {
+                        let (met, unmet) = log_likelihood_ratios(o);
+                        none_met - unmet + met
+                    },
+                    freq_true: o.prob_given_true / scale_true,
+                    freq_false: o.prob_given_false / scale_false,
+                })
+                .collect();
+
+            outcomes.push(GroupOutcome {
+                log_likelihood_ratio: none_met,
+                freq_true: (1.0 - sum_true / scale_true).max(0.0),
+                freq_false: (1.0 - sum_false / scale_false).max(0.0),
+            });
+
+            outcomes
+        })
+        .collect()
+}
+
+/// Predicted classification quality when the sensor turns on at `posterior >= threshold`
+#[derive(Debug, Clone, Copy)]
+pub struct PredictedMetrics {
+    pub precision: f64,
+    pub recall: f64,
+    pub false_positive_rate: f64,
+}
+
+impl PredictedMetrics {
+    /// F-beta score; beta below 1 favours precision, above 1 favours recall
+    pub fn f_beta(&self, beta: f64) -> f64 {
+        let beta_squared = beta * beta;
+        let denominator = beta_squared * self.precision + self.recall;
+        if denominator > 0.0 {
+            (1.0 + beta_squared) * self.precision * self.recall / denominator
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Candidate probability thresholds from 0.05 to 0.95 with their predicted metrics
+pub fn threshold_candidates(distribution: &[PosteriorMass], prior: f64) -> Vec<(f64, PredictedMetrics)> {
+    (5..=95)
+        .map(|step| {
+            let threshold = step as f64 / 100.0;
+            (threshold, predicted_metrics(distribution, prior, threshold))
+        })
+        .collect()
+}
+
+/// Candidate threshold with the highest F-beta score
+pub fn best_threshold(distribution: &[PosteriorMass], prior: f64, beta: f64) -> (f64, PredictedMetrics) {
+    threshold_candidates(distribution, prior)
+        .into_iter()
+        .max_by(|a, b| a.1.f_beta(beta).total_cmp(&b.1.f_beta(beta)))
+        .expect("candidate list is never empty")
+}
+
+pub fn predicted_metrics(distribution: &[PosteriorMass], prior: f64, threshold: f64) -> PredictedMetrics {
+    let (recall, false_positive_rate) = distribution
+        .iter()
+        .filter(|m| m.posterior >= threshold)
+        .fold((0.0, 0.0), |(t, f), m| (t + m.weight_true, f + m.weight_false));
+
+    let true_positive = prior * recall;
+    let false_positive = (1.0 - prior) * false_positive_rate;
+    let precision = if true_positive + false_positive > 0.0 {
+        true_positive / (true_positive + false_positive)
+    } else {
+        0.0
+    };
+
+    PredictedMetrics {
+        precision,
+        recall,
+        false_positive_rate,
+    }
+}
@@ -0,0 +1,133 @@
+use crate::export::{Observation, SensorSettings};
+use crate::posterior;
+use crate::types::{AnalysisWarning, EntityProbability, WarningKind};
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Posteriors this close to 0 or 1 count as saturated
+const SATURATION_MARGIN: f64 = 0.01;
+/// Share of predicted time saturated beyond reach of any single observation that triggers a warning
+const SATURATION_WARNING_SHARE: f64 = 0.5;
+
+/// How much one observation can move the posterior
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ObservationSwing {
+    pub entity_id: String,
+    pub state: String,
+    /// Log-odds when the observation is met minus when it is not
+    pub log_odds_swing: f64,
+    /// Posterior when met minus when not met, starting from the prior with no other evidence
+    pub posterior_swing: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PosteriorRange {
+    /// Posterior with every entity in its most FALSE-like outcome
+    pub min_posterior: f64,
+    /// Posterior with every entity in its most TRUE-like outcome
+    pub max_posterior: f64,
+    pub can_trigger: bool,
+    pub can_release: bool,
+    /// Predicted share of time the posterior is saturated near 0 or 1 and no single
+    /// observation changing could move it across the probability threshold
+    pub insensitive_share: f64,
+    /// Largest swing first
+    pub observations: Vec<ObservationSwing>,
+    pub warnings: Vec<AnalysisWarning>,
+}
+
+/// Range of posteriors the selected rows can produce and each row's marginal swing
+///
+/// Warns when even the most favourable evidence stays under the probability
+/// threshold (the sensor never turns on) or the least favourable stays at or above
+/// it (the sensor never turns off). Also warns when many weak observations pile up
+/// so the posterior saturates and no single input can change the sensor's state.
+pub fn analyze(rows: &[EntityProbability], settings: &SensorSettings) -> PosteriorRange {
+    let observed: Vec<(&EntityProbability, Observation)> = rows
+        .iter()
+        .filter_map(|row| Observation::from_result(row).map(|observation| (row, observation)))
+        .collect();
+    let observations: Vec<Observation> = observed.iter().map(|(_, observation)| observation.clone()).collect();
+
+    let (low, high) = posterior::log_odds_range(&observations, settings.prior);
+    let min_posterior = posterior::posterior_from_log_odds(low);
+    let max_posterior = posterior::posterior_from_log_odds(high);
+    let can_trigger = max_posterior >= settings.probability_threshold;
+    let can_release = min_posterior < settings.probability_threshold;
+
+    let prior_log_odds = posterior::prior_log_odds(settings.prior);
+    let mut swings: Vec<ObservationSwing> = observed
+        .iter()
+        .map(|(row, observation)| {
+            let (met, unmet) = posterior::log_likelihood_ratios(observation);
+            ObservationSwing {
+                entity_id: row.entity_id.clone(),
+                state: row.state.clone(),
+                log_odds_swing: posterior::log_odds_swing(observation),
+                posterior_swing: posterior::posterior_from_log_odds(prior_log_odds + met)
+                    - posterior::posterior_from_log_odds(prior_log_odds + unmet),
+            }
+        })
+        .collect();
+    swings.sort_by(|a, b| b.log_odds_swing.abs().total_cmp(&a.log_odds_swing.abs()));
+    let insensitive_share = insensitive_share(&observations, settings, swings.first().map_or(0.0, |s| s.log_odds_swing.abs()));
+
+    let mut warnings = Vec::new();
+    if !can_trigger {
+        warnings.push(AnalysisWarning {
+            entity_id: None,
+            kind: WarningKind::NeverTriggers,
+            message: format!(
+                "The posterior peaks at {:.3}, below the probability threshold {:.3}, so the sensor can never turn on",
+                max_posterior, settings.probability_threshold
+            ),
+        });
+    }
+    if !can_release {
+        warnings.push(AnalysisWarning {
+            entity_id: None,
+            kind: WarningKind::NeverReleases,
+            message: format!(
+                "The posterior never drops below {:.3}, at or above the probability threshold {:.3}, so the sensor can never turn off",
+                min_posterior, settings.probability_threshold
+            ),
+        });
+    }
+
+    if insensitive_share >= SATURATION_WARNING_SHARE {
+        warnings.push(AnalysisWarning {
+            entity_id: None,
+            kind: WarningKind::PosteriorSaturation,
+            message: format!(
+                "The posterior is saturated near 0 or 1 for {:.0}% of the time and no single observation can flip the sensor; prune weak observations or move their probabilities towards 0.5",
+                insensitive_share * 100.0
+            ),
+        });
+    }
+
+    PosteriorRange {
+        min_posterior,
+        max_posterior,
+        can_trigger,
+        can_release,
+        insensitive_share,
+        observations: swings,
+        warnings,
+    }
+}
+
+/// Predicted share of time the posterior is saturated and further from the threshold than `max_swing`
+fn insensitive_share(observations: &[Observation], settings: &SensorSettings, max_swing: f64) -> f64 {
+    let threshold = posterior::logit(settings.probability_threshold);
+    let prior = settings.prior.clamp(0.0, 1.0);
+
+    posterior::predicted_distribution(observations, settings.prior)
+        .iter()
+        .filter(|m| m.posterior <= SATURATION_MARGIN || m.posterior >= 1.0 - SATURATION_MARGIN)
+        .filter(|m| (m.log_odds - threshold).abs() > max_swing)
+        .fold(0.0, |share, m| share + prior * m.weight_true + (1.0 - prior) * m.weight_false)
+}
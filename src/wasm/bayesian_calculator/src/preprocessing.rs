@@ -0,0 +1,420 @@
+use crate::types::{HAHistoryEntry, TimePeriod, Timestamp};
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Date range whose data counts towards neither class (vacation, party, guests)
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ExclusionWindow {
+    pub start: Timestamp,
+    pub end: Timestamp,
+    pub label: Option<String>,
+}
+
+/// How a TRUE period and a FALSE period that cover the same time are reconciled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum OverlapPolicy {
+    /// Fail the analysis with `OverlappingPeriods`
+    Reject,
+    /// Cut the overlap out of the FALSE periods
+    TrueWins,
+    /// Cut the overlap out of the TRUE periods
+    FalseWins,
+    /// Cut the overlap out of both and add it back as a period with a confidence of
+    /// 0.5, so it counts half towards each class
+    Split,
+}
+
+/// How the drifting level of a numeric entity is removed before analysis
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Tsify)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum Detrend {
+    /// Subtract a least-squares line fitted to the whole history
+    Linear,
+    /// Subtract the mean of the samples in the trailing window
+    #[serde(rename_all = "camelCase")]
+    RollingBaseline { window_hours: f64 },
+}
+
+/// Replace each numeric state with its deviation from the trend
+///
+/// Non-numeric states (`unavailable`, `unknown`, ...) are kept as they are.
+pub fn detrend(entity_history: &[HAHistoryEntry], mode: Detrend) -> Vec<HAHistoryEntry> {
+    let mut samples: Vec<(usize, i64, f64)> = entity_history
+        .iter()
+        .enumerate()
+        .filter_map(|(index, entry)| {
+            let value = entry.state.parse::<f64>().ok()?;
+            Some((index, entry.last_changed.millis(), value))
+        })
+        .collect();
+    samples.sort_by_key(|&(_, time, _)| time);
+
+    let baselines = match mode {
+        Detrend::Linear => linear_baseline(&samples),
+        Detrend::RollingBaseline { window_hours } => rolling_baseline(&samples, (window_hours * 3_600_000.0) as i64),
+    };
+
+    let mut detrended = entity_history.to_vec();
+    for (&(index, _, value), baseline) in samples.iter().zip(baselines) {
+        detrended[index].state = (value - baseline).to_string();
+    }
+    detrended
+}
+
+fn linear_baseline(samples: &[(usize, i64, f64)]) -> Vec<f64> {
+    if samples.is_empty() {
+        return Vec::new();
+    }
+
+    // Fit against hours since the first sample to keep the sums well conditioned
+    let origin = samples[0].1;
+    let hours = |time: i64| (time - origin) as f64 / 3_600_000.0;
+    let n = samples.len() as f64;
+    let mean_x = samples.iter().map(|&(_, t, _)| hours(t)).sum::<f64>() / n;
+    let mean_y = samples.iter().map(|&(_, _, v)| v).sum::<f64>() / n;
+
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for &(_, time, value) in samples {
+        let dx = hours(time) - mean_x;
+        covariance += dx * (value - mean_y);
+        variance += dx * dx;
+    }
+    let slope = if variance > 0.0 { covariance / variance } else { 0.0 };
+
+    samples
+        .iter()
+        .map(|&(_, time, _)| mean_y + slope * (hours(time) - mean_x))
+        .collect()
+}
+
+fn rolling_baseline(samples: &[(usize, i64, f64)], window: i64) -> Vec<f64> {
+    let mut baselines = Vec::with_capacity(samples.len());
+    let mut window_start = 0;
+    let mut window_sum = 0.0;
+
+    for (i, &(_, time, value)) in samples.iter().enumerate() {
+        window_sum += value;
+        while samples[window_start].1 < time - window {
+            window_sum -= samples[window_start].2;
+            window_start += 1;
+        }
+        baselines.push(window_sum / (i + 1 - window_start) as f64);
+    }
+
+    baselines
+}
+
+/// Replace a numeric history with its trailing duration-weighted average over `window_ms`
+///
+/// The average of a stepwise history only bends where a reading starts or leaves
+/// the window, so one entry is written at each of those points: a spike then shows
+/// as its share of the window, spread over the window's length, instead of as its
+/// full height. Non-numeric states are kept as they are and add no time to the
+/// average.
+pub fn rolling_average(entity_history: &[HAHistoryEntry], window_ms: i64) -> Vec<HAHistoryEntry> {
+    let mut entries: Vec<(i64, &HAHistoryEntry, Option<f64>)> = entity_history
+        .iter()
+        .map(|entry| (entry.last_changed.millis(), entry, entry.state.parse::<f64>().ok()))
+        .collect();
+    entries.sort_by_key(|&(time, _, _)| time);
+
+    // Value-weighted and covered time before each entry
+    let mut before = Vec::with_capacity(entries.len());
+    let (mut weighted, mut covered) = (0.0, 0i64);
+    for (i, &(time, _, value)) in entries.iter().enumerate() {
+        before.push((weighted, covered));
+        if let (Some(value), Some(&(next, _, _))) = (value, entries.get(i + 1)) {
+            weighted += value * (next - time) as f64;
+            covered += next - time;
+        }
+    }
+    let accumulated = |time: i64| {
+        let Some(index) = entries.partition_point(|&(start, _, _)| start <= time).checked_sub(1) else {
+            return (0.0, 0);
+        };
+        let (start, _, value) = entries[index];
+        let (weighted, covered) = before[index];
+        match value {
+            Some(value) => (weighted + value * (time - start) as f64, covered + time - start),
+            None => (weighted, covered),
+        }
+    };
+
+    let mut points: Vec<(i64, usize)> = Vec::with_capacity(entries.len() * 2);
+    for (i, &(time, _, value)) in entries.iter().enumerate() {
+        points.push((time, i));
+        // Where this reading has left the window, if the entity is still numeric then
+        if value.is_some() && window_ms > 0 {
+            let left = time + window_ms;
+            let index = entries.partition_point(|&(start, _, _)| start <= left) - 1;
+            if entries[index].2.is_some() && entries[index].0 < left {
+                points.push((left, index));
+            }
+        }
+    }
+    // Of several entries at the same time the last one is in effect
+    points.sort_unstable();
+    points.dedup_by(|later, kept| {
+        let same_time = later.0 == kept.0;
+        if same_time {
+            *kept = *later;
+        }
+        same_time
+    });
+
+    points
+        .into_iter()
+        .map(|(time, index)| {
+            let (_, entry, value) = entries[index];
+            let Some(value) = value else {
+                return entry.clone();
+            };
+            let (weighted_end, covered_end) = accumulated(time);
+            let (weighted_start, covered_start) = accumulated(time - window_ms);
+            let covered = covered_end - covered_start;
+            let average = if covered > 0 {
+                (weighted_end - weighted_start) / covered as f64
+            } else {
+                value
+            };
+            HAHistoryEntry {
+                state: average.to_string(),
+                last_changed: Timestamp::from_millis(time),
+                last_updated: Timestamp::from_millis(time),
+                attributes: entry.attributes.clone(),
+            }
+        })
+        .collect()
+}
+
+/// History of one attribute as if it were the entity's state
+///
+/// Timed by `last_updated`, since HA only moves `last_changed` when the state itself
+/// changes. Numbers and booleans become their text form and a missing or null
+/// attribute (e.g. the brightness of a light that is off) becomes `unknown`.
+/// Returns an empty history when no entry carries the attribute, as with HA's
+/// compressed history, which has no attributes.
+pub fn attribute_history(entity_history: &[HAHistoryEntry], attribute: &str) -> Vec<HAHistoryEntry> {
+    fn value_of<'a>(entry: &'a HAHistoryEntry, attribute: &str) -> Option<&'a serde_json::Value> {
+        entry.attributes.as_ref()?.get(attribute).filter(|value| !value.is_null())
+    }
+    if !entity_history.iter().any(|entry| value_of(entry, attribute).is_some()) {
+        return Vec::new();
+    }
+
+    let mut history: Vec<HAHistoryEntry> = Vec::with_capacity(entity_history.len());
+    for entry in entity_history {
+        let state = match value_of(entry, attribute) {
+            Some(serde_json::Value::String(value)) => value.clone(),
+            Some(value) => value.to_string(),
+            None => "unknown".to_string(),
+        };
+        if history.last().is_some_and(|last| last.state == state) {
+            continue;
+        }
+        history.push(HAHistoryEntry {
+            state,
+            last_changed: entry.last_updated.clone(),
+            last_updated: entry.last_updated.clone(),
+            attributes: None,
+        });
+    }
+    history
+}
+
+/// Round a timestamp to the nearest multiple of `resolution_ms`
+fn quantize_millis(millis: i64, resolution_ms: i64) -> i64 {
+    (millis + resolution_ms / 2).div_euclid(resolution_ms) * resolution_ms
+}
+
+/// Round every change to the resolution, keeping one entry per slot
+///
+/// When several changes land in the same slot the last one wins, since it is the
+/// state in effect once the slot ends; changes that repeat the previous state are
+/// dropped. Sub-resolution flickers collapse instead of becoming micro-chunks, and
+/// histories from recorders with different timestamp precision give the same result.
+pub fn quantize_history(entity_history: &[HAHistoryEntry], resolution_ms: i64) -> Vec<HAHistoryEntry> {
+    let mut entries: Vec<(i64, &HAHistoryEntry)> = entity_history
+        .iter()
+        .map(|entry| (quantize_millis(entry.last_changed.millis(), resolution_ms), entry))
+        .collect();
+    entries.sort_by_key(|&(time, _)| time);
+
+    let mut quantized: Vec<HAHistoryEntry> = Vec::with_capacity(entries.len());
+    // Slot of the last kept entry; entries kept from earlier slots are never replaced
+    let mut last_slot = None;
+    for (time, entry) in entries {
+        if last_slot == Some(time) {
+            quantized.pop();
+            last_slot = None;
+        }
+        if quantized.last().is_some_and(|last| last.state == entry.state) {
+            continue;
+        }
+
+        quantized.push(HAHistoryEntry {
+            state: entry.state.clone(),
+            last_changed: Timestamp::from_millis(time),
+            last_updated: Timestamp::from_millis(time),
+            attributes: entry.attributes.clone(),
+        });
+        last_slot = Some(time);
+    }
+
+    quantized
+}
+
+/// Round period boundaries to the resolution so they line up with quantized history
+pub fn quantize_periods(periods: &[TimePeriod], resolution_ms: i64) -> Vec<TimePeriod> {
+    periods
+        .iter()
+        .map(|period| TimePeriod {
+            start: Timestamp::from_millis(quantize_millis(period.start.millis(), resolution_ms)),
+            end: Timestamp::from_millis(quantize_millis(period.end.millis(), resolution_ms)),
+            ..period.clone()
+        })
+        .collect()
+}
+
+/// Split periods into the pieces inside and outside the intervals
+///
+/// `intervals` must be sorted and non-overlapping.
+pub fn split_periods(periods: &[TimePeriod], intervals: &[(i64, i64)]) -> (Vec<TimePeriod>, Vec<TimePeriod>) {
+    let (mut inside, mut outside) = (Vec::new(), Vec::new());
+
+    for period in periods {
+        let (start, end) = (period.start.millis(), period.end.millis());
+        let mut cursor = start;
+        let piece = |from: i64, to: i64, index: usize| TimePeriod {
+            id: format!("{}-{}", period.id, index),
+            start: Timestamp::from_millis(from),
+            end: Timestamp::from_millis(to),
+            ..period.clone()
+        };
+
+        for &(interval_start, interval_end) in intervals {
+            let (from, to) = (interval_start.max(start), interval_end.min(end));
+            if to <= from {
+                continue;
+            }
+            if from > cursor {
+                outside.push(piece(cursor, from, outside.len()));
+            }
+            inside.push(piece(from, to, inside.len()));
+            cursor = to;
+        }
+        if end > cursor {
+            outside.push(piece(cursor, end, outside.len()));
+        }
+    }
+
+    (inside, outside)
+}
+
+/// Periods with every exclusion window cut out
+pub fn exclude_windows(periods: &[TimePeriod], windows: &[ExclusionWindow]) -> Vec<TimePeriod> {
+    let intervals = windows
+        .iter()
+        .map(|window| (window.start.millis(), window.end.millis()))
+        .collect();
+
+    split_periods(periods, &merge_intervals(intervals)).1
+}
+
+/// Sorted intervals with overlapping and touching ones joined
+fn merge_intervals(mut intervals: Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+    intervals.retain(|(start, end)| end > start);
+    intervals.sort_unstable();
+
+    let mut merged: Vec<(i64, i64)> = Vec::with_capacity(intervals.len());
+    for (start, end) in intervals {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Merged time covered by the hard-labeled periods of one class
+fn class_intervals(periods: &[TimePeriod], is_true: bool) -> Vec<(i64, i64)> {
+    merge_intervals(
+        periods
+            .iter()
+            .filter(|period| period.confidence.is_none() && period.is_true_period == is_true)
+            .map(|period| (period.start.millis(), period.end.millis()))
+            .collect(),
+    )
+}
+
+/// Time covered by both a TRUE and a FALSE period, sorted and non-overlapping
+///
+/// Soft-labeled periods (`confidence` set) already split their time between the
+/// classes and are left out.
+pub fn period_overlaps(periods: &[TimePeriod]) -> Vec<(i64, i64)> {
+    let (trues, falses) = (class_intervals(periods, true), class_intervals(periods, false));
+    let mut overlaps = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < trues.len() && j < falses.len() {
+        let (start, end) = (trues[i].0.max(falses[j].0), trues[i].1.min(falses[j].1));
+        if end > start {
+            overlaps.push((start, end));
+        }
+        if trues[i].1 < falses[j].1 {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    overlaps
+}
+
+/// Ids of a TRUE and a FALSE period that both cover `time`
+pub fn periods_at(periods: &[TimePeriod], time: i64) -> Option<(String, String)> {
+    let covering = |is_true: bool| {
+        periods
+            .iter()
+            .find(|period| {
+                period.confidence.is_none()
+                    && period.is_true_period == is_true
+                    && period.start.millis() <= time
+                    && time < period.end.millis()
+            })
+            .map(|period| period.id.clone())
+    };
+    covering(true).zip(covering(false))
+}
+
+/// Periods with the `overlaps` resolved by `policy`; `Reject` is for the caller to
+/// handle and leaves the periods as they are
+pub fn resolve_overlaps(periods: Vec<TimePeriod>, overlaps: &[(i64, i64)], policy: OverlapPolicy) -> Vec<TimePeriod> {
+    let losing = match policy {
+        OverlapPolicy::Reject => return periods,
+        OverlapPolicy::TrueWins => Some(false),
+        OverlapPolicy::FalseWins => Some(true),
+        OverlapPolicy::Split => None,
+    };
+    let (cut, kept): (Vec<TimePeriod>, Vec<TimePeriod>) = periods
+        .into_iter()
+        .partition(|period| period.confidence.is_none() && losing.is_none_or(|class| period.is_true_period == class));
+
+    let mut resolved = kept;
+    resolved.extend(split_periods(&cut, overlaps).1);
+    if losing.is_none() {
+        resolved.extend(overlaps.iter().enumerate().map(|(index, &(start, end))| TimePeriod {
+            id: format!("overlap-{}", index),
+            start: Timestamp::from_millis(start),
+            end: Timestamp::from_millis(end),
+            is_true_period: true,
+            label: None,
+            class: None,
+            confidence: Some(0.5),
+        }));
+    }
+    resolved
+}
@@ -0,0 +1,278 @@
+use crate::sensor_analysis::{period_length, split_duration, true_weight};
+use crate::types::{HAHistoryEntry, TimePeriod, Timestamp};
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+const DAY_MS: f64 = 86_400_000.0;
+
+/// What the prior estimated from labeled periods counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum PriorWeighting {
+    /// Share of labeled wall-clock time that is TRUE
+    Duration,
+    /// Share of labeled periods that are TRUE, regardless of their length
+    PeriodCount,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PeriodPriorOptions {
+    pub weighting: PriorWeighting,
+}
+
+impl Default for PeriodPriorOptions {
+    fn default() -> Self {
+        Self {
+            weighting: PriorWeighting::Duration,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PeriodPrior {
+    /// Suggested prior, kept within [0.01, 0.99] like HA-usable probabilities
+    pub prior: f64,
+    pub true_duration_ms: i64,
+    pub false_duration_ms: i64,
+    pub true_periods: usize,
+    pub false_periods: usize,
+}
+
+/// Suggested prior from the labeled periods
+///
+/// Soft-labeled periods count towards both classes by their confidence. `None`
+/// when the periods cover no time (or, counting periods, when there are none).
+pub fn estimate_from_periods(periods: &[TimePeriod], options: &PeriodPriorOptions) -> Option<PeriodPrior> {
+    let (mut true_duration_ms, mut false_duration_ms) = (0, 0);
+    let (mut true_count, mut false_count) = (0.0, 0.0);
+    let (mut true_periods, mut false_periods) = (0, 0);
+
+    for period in periods {
+        let weight = true_weight(period);
+        let (true_part, false_part) = split_duration(period_length(period).max(0), weight);
+        true_duration_ms += true_part;
+        false_duration_ms += false_part;
+        true_count += weight;
+        false_count += 1.0 - weight;
+        true_periods += usize::from(weight > 0.0);
+        false_periods += usize::from(weight < 1.0);
+    }
+
+    let share = match options.weighting {
+        PriorWeighting::Duration if true_duration_ms + false_duration_ms > 0 => {
+            true_duration_ms as f64 / (true_duration_ms + false_duration_ms) as f64
+        }
+        PriorWeighting::PeriodCount if !periods.is_empty() => true_count / (true_count + false_count),
+        _ => return None,
+    };
+
+    Some(PeriodPrior {
+        prior: share.clamp(0.01, 0.99),
+        true_duration_ms,
+        false_duration_ms,
+        true_periods,
+        false_periods,
+    })
+}
+
+/// One row of HA long-term statistics (hourly or 5-minute)
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct StatisticsRow {
+    pub start: Timestamp,
+    pub mean: f64,
+}
+
+/// Archived data of an existing sensor related to the target (e.g. an old presence detector)
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(tag = "type", rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum RelatedSensorData {
+    History { entries: Vec<HAHistoryEntry> },
+    /// Each row lasts until the next one starts; the last lasts as long as the one before it
+    Statistics { rows: Vec<StatisticsRow> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct RelatedSensorOptions {
+    /// History states that mean the target is on
+    pub on_states: Vec<String>,
+    /// Numeric states or statistics means above this count as on
+    pub above: Option<f64>,
+    /// Statistics mean that stands for "on all of the time" (1 for a 0/1 sensor, 100 for a percentage)
+    pub mean_scale: f64,
+}
+
+impl Default for RelatedSensorOptions {
+    fn default() -> Self {
+        Self {
+            on_states: vec!["on".to_string(), "home".to_string()],
+            above: None,
+            mean_scale: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PriorEstimate {
+    /// Proposed prior for the bayesian sensor
+    pub prior: f64,
+    /// Share of the archive the related sensor was on
+    pub on_fraction: f64,
+    pub covered_days: f64,
+    /// Share of labeled TRUE time the related sensor was on, when periods overlap the archive
+    pub sensitivity: Option<f64>,
+    /// Share of labeled FALSE time the related sensor was on
+    pub false_alarm_rate: Option<f64>,
+    /// `high`, `medium` or `low`
+    pub confidence: String,
+    pub note: String,
+}
+
+/// Estimate the target's base rate from a related sensor's archive
+///
+/// The related sensor's share of on-time is the base rate if it tracks the target
+/// exactly. When labeled periods overlap the archive, its sensitivity `s` and false
+/// alarm rate `f` against the labels are measured and the on-fraction `r` is
+/// corrected for them: `prior = (r - f) / (s - f)`. Returns `None` without any
+/// usable on/off time in the archive.
+pub fn estimate_from_related(
+    data: &RelatedSensorData,
+    periods: &[TimePeriod],
+    options: &RelatedSensorOptions,
+) -> Option<PriorEstimate> {
+    let segments = on_segments(data, options);
+    let covered: f64 = segments.iter().map(|&(start, end, _)| (end - start) as f64).sum();
+    if covered <= 0.0 {
+        return None;
+    }
+    let on_fraction = segments.iter().map(|&(start, end, on)| (end - start) as f64 * on).sum::<f64>() / covered;
+    let covered_days = covered / DAY_MS;
+
+    // On-time of the related sensor during labeled TRUE and FALSE time
+    let (mut on_true, mut time_true, mut on_false, mut time_false) = (0.0, 0.0, 0.0, 0.0);
+    for period in periods {
+        let (period_start, period_end) = (period.start.millis(), period.end.millis());
+        let weight = true_weight(period);
+        for &(start, end, on) in &segments {
+            let overlap = (end.min(period_end) - start.max(period_start)).max(0) as f64;
+            on_true += overlap * on * weight;
+            time_true += overlap * weight;
+            on_false += overlap * on * (1.0 - weight);
+            time_false += overlap * (1.0 - weight);
+        }
+    }
+    let sensitivity = (time_true > 0.0).then(|| on_true / time_true);
+    let false_alarm_rate = (time_false > 0.0).then(|| on_false / time_false);
+
+    let (prior, confidence, note) = match (sensitivity, false_alarm_rate) {
+        (Some(s), Some(f)) if s - f >= 0.1 => {
+            let prior = ((on_fraction - f) / (s - f)).clamp(0.01, 0.99);
+            let confidence = if covered_days >= 30.0 && s - f >= 0.5 {
+                "high"
+            } else if covered_days >= 7.0 && s - f >= 0.3 {
+                "medium"
+            } else {
+                "low"
+            };
+            let note = format!(
+                "{:.0}% on-time over {:.1} days, corrected for the sensor being on {:.0}% of labeled TRUE and {:.0}% of labeled FALSE time",
+                on_fraction * 100.0,
+                covered_days,
+                s * 100.0,
+                f * 100.0
+            );
+            (prior, confidence, note)
+        }
+        (Some(s), Some(f)) => {
+            let note = format!(
+                "The sensor barely tracks the labels ({:.0}% on in TRUE vs {:.0}% in FALSE time), so its {:.0}% on-time over {:.1} days is only a rough guess",
+                s * 100.0,
+                f * 100.0,
+                on_fraction * 100.0,
+                covered_days
+            );
+            (on_fraction.clamp(0.01, 0.99), "low", note)
+        }
+        _ => {
+            let confidence = if covered_days >= 7.0 { "medium" } else { "low" };
+            let note = format!(
+                "{:.0}% on-time over {:.1} days, assuming the sensor tracks the target exactly; label periods inside the archive to correct for its errors",
+                on_fraction * 100.0,
+                covered_days
+            );
+            (on_fraction.clamp(0.01, 0.99), confidence, note)
+        }
+    };
+
+    Some(PriorEstimate {
+        prior,
+        on_fraction,
+        covered_days,
+        sensitivity,
+        false_alarm_rate,
+        confidence: confidence.to_string(),
+        note,
+    })
+}
+
+/// Time segments (start, end, share of the segment the sensor was on)
+///
+/// History entries last until the next change; the final entry and states that are
+/// neither on nor off (`unavailable`, `unknown`) are left out.
+fn on_segments(data: &RelatedSensorData, options: &RelatedSensorOptions) -> Vec<(i64, i64, f64)> {
+    match data {
+        RelatedSensorData::History { entries } => {
+            let mut changes: Vec<(i64, Option<f64>)> = entries
+                .iter()
+                .map(|entry| (entry.last_changed.millis(), state_on(&entry.state, options)))
+                .collect();
+            changes.sort_by_key(|&(time, _)| time);
+
+            changes
+                .windows(2)
+                .filter_map(|pair| pair[0].1.map(|on| (pair[0].0, pair[1].0, on)))
+                .collect()
+        }
+        RelatedSensorData::Statistics { rows } => {
+            let mut rows: Vec<(i64, f64)> = rows
+                .iter()
+                .filter(|row| row.mean.is_finite())
+                .map(|row| (row.start.millis(), row.mean))
+                .collect();
+            rows.sort_by_key(|&(time, _)| time);
+
+            let on = |mean: f64| match options.above {
+                Some(above) => f64::from(u8::from(mean > above)),
+                None if options.mean_scale > 0.0 => (mean / options.mean_scale).clamp(0.0, 1.0),
+                None => 0.0,
+            };
+            let mut segments: Vec<(i64, i64, f64)> =
+                rows.windows(2).map(|pair| (pair[0].0, pair[1].0, on(pair[0].1))).collect();
+            if let (Some(&(start, mean)), Some(&(previous_start, previous_end, _))) = (rows.last(), segments.last()) {
+                segments.push((start, start + (previous_end - previous_start), on(mean)));
+            }
+            segments
+        }
+    }
+}
+
+fn state_on(state: &str, options: &RelatedSensorOptions) -> Option<f64> {
+    if let (Some(above), Ok(value)) = (options.above, state.parse::<f64>()) {
+        return Some(f64::from(u8::from(value > above)));
+    }
+    match state {
+        "unavailable" | "unknown" | "" => None,
+        _ => Some(f64::from(u8::from(options.on_states.iter().any(|s| s == state)))),
+    }
+}
@@ -0,0 +1,63 @@
+use crate::types::EntityProbability;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use tsify::Tsify;
+
+/// A key result rows can be ordered by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum SortKey {
+    /// Highest `score` first
+    Score,
+    /// Highest discrimination power first
+    DiscriminationPower,
+    /// Alphabetical by entity id
+    EntityId,
+    /// Alphabetical by the domain part of the entity id (`sensor`, `light`, ...)
+    Domain,
+    /// Alphabetical by state
+    State,
+}
+
+impl SortKey {
+    fn compare(self, a: &EntityProbability, b: &EntityProbability) -> Ordering {
+        match self {
+            SortKey::Score => descending(a.score, b.score),
+            SortKey::DiscriminationPower => descending(a.discrimination_power, b.discrimination_power),
+            SortKey::EntityId => a.entity_id.cmp(&b.entity_id),
+            SortKey::Domain => domain(&a.entity_id).cmp(domain(&b.entity_id)),
+            SortKey::State => a.state.cmp(&b.state),
+        }
+    }
+}
+
+/// Larger values first, with NaN after every number
+fn descending(a: f64, b: f64) -> Ordering {
+    let rank = |value: f64| if value.is_nan() { f64::NEG_INFINITY } else { value };
+    rank(b).total_cmp(&rank(a))
+}
+
+fn domain(entity_id: &str) -> &str {
+    entity_id.split_once('.').map_or(entity_id, |(domain, _)| domain)
+}
+
+/// Order rows by `keys` in turn, then by entity and state so ties never fall back
+/// to hash order
+///
+/// NaN scores sort last instead of panicking the comparison.
+pub fn sort(results: &mut [EntityProbability], keys: &[SortKey]) {
+    results.sort_by(|a, b| {
+        keys.iter()
+            .chain(&[SortKey::EntityId, SortKey::State])
+            .fold(Ordering::Equal, |order, key| order.then_with(|| key.compare(a, b)))
+    });
+}
+
+/// Keep at most `limit` rows after skipping the first `offset`
+pub fn page(results: &mut Vec<EntityProbability>, offset: usize, limit: Option<usize>) {
+    results.drain(..offset.min(results.len()));
+    if let Some(limit) = limit {
+        results.truncate(limit);
+    }
+}
@@ -0,0 +1,149 @@
+use crate::export::{self, format_probability, SensorSettings};
+use crate::types::{AnalysisWarning, EntityProbability};
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ReportOptions {
+    pub title: String,
+    pub format: ReportFormat,
+    /// Number of ranked rows listed in the table
+    pub max_rows: usize,
+    /// Number of entities (best row each) included in the proposed YAML
+    pub observation_count: usize,
+    pub sensor: SensorSettings,
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        Self {
+            title: "Bayesian sensor analysis".to_string(),
+            format: ReportFormat::Markdown,
+            max_rows: 25,
+            observation_count: 5,
+            sensor: SensorSettings::default(),
+        }
+    }
+}
+
+/// Build a self-contained report of ranked results, warnings and the proposed YAML
+pub fn generate(results: &[EntityProbability], warnings: &[AnalysisWarning], options: &ReportOptions) -> String {
+    let mut ranked: Vec<&EntityProbability> = results.iter().collect();
+    ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    let proposed = export::best_row_per_entity(results, options.observation_count);
+    let yaml = export::generate_bayesian_yaml(&proposed, &options.sensor);
+
+    let rows: Vec<[String; 6]> = ranked
+        .iter()
+        .take(options.max_rows)
+        .enumerate()
+        .map(|(i, row)| {
+            [
+                (i + 1).to_string(),
+                row.entity_id.clone(),
+                row.state.clone(),
+                format_probability(row.prob_given_true),
+                format_probability(row.prob_given_false),
+                format_probability(row.discrimination_power),
+            ]
+        })
+        .collect();
+
+    let entity_count = {
+        let mut ids: Vec<&str> = results.iter().map(|r| r.entity_id.as_str()).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids.len()
+    };
+    let summary = format!(
+        "{} result rows across {} entities; {} TRUE and {} FALSE labeled periods.",
+        results.len(),
+        entity_count,
+        results.first().map_or(0, |r| r.total_true_periods),
+        results.first().map_or(0, |r| r.total_false_periods),
+    );
+
+    match options.format {
+        ReportFormat::Markdown => markdown(&options.title, &summary, &rows, warnings, &yaml),
+        ReportFormat::Html => html(&options.title, &summary, &rows, warnings, &yaml),
+    }
+}
+
+const HEADERS: [&str; 6] = ["#", "Entity", "State / threshold", "Prob given TRUE", "Prob given FALSE", "Discrimination"];
+
+fn markdown(title: &str, summary: &str, rows: &[[String; 6]], warnings: &[AnalysisWarning], yaml: &str) -> String {
+    let cell = |value: &str| value.replace('|', "\\|");
+    let mut out = String::new();
+
+    out.push_str(&format!("# {}\n\n{}\n\n## Ranked entities\n\n", title, summary));
+    out.push_str(&format!("| {} |\n", HEADERS.join(" | ")));
+    out.push_str(&format!("|{}\n", "---|".repeat(HEADERS.len())));
+    for row in rows {
+        let cells: Vec<String> = row.iter().map(|v| cell(v)).collect();
+        out.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+
+    if !warnings.is_empty() {
+        out.push_str("\n## Warnings\n\n");
+        for warning in warnings {
+            out.push_str(&format!("- {}\n", warning.message));
+        }
+    }
+
+    out.push_str(&format!("\n## Proposed configuration\n\n```yaml\n{}```\n", yaml));
+    out
+}
+
+fn html(title: &str, summary: &str, rows: &[[String; 6]], warnings: &[AnalysisWarning], yaml: &str) -> String {
+    let mut out = String::new();
+
+    out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str(&format!("<title>{}</title>\n", escape_html(title)));
+    out.push_str("<style>body{font-family:sans-serif;margin:2em}table{border-collapse:collapse}td,th{border:1px solid #ccc;padding:4px 8px;text-align:left}pre{background:#f4f4f4;padding:1em}</style>\n");
+    out.push_str("</head>\n<body>\n");
+    out.push_str(&format!("<h1>{}</h1>\n<p>{}</p>\n", escape_html(title), escape_html(summary)));
+    out.push_str("<h2>Ranked entities</h2>\n<table>\n<tr>");
+    for header in HEADERS {
+        out.push_str(&format!("<th>{}</th>", escape_html(header)));
+    }
+    out.push_str("</tr>\n");
+    for row in rows {
+        out.push_str("<tr>");
+        for value in row {
+            out.push_str(&format!("<td>{}</td>", escape_html(value)));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n");
+
+    if !warnings.is_empty() {
+        out.push_str("<h2>Warnings</h2>\n<ul>\n");
+        for warning in warnings {
+            out.push_str(&format!("<li>{}</li>\n", escape_html(&warning.message)));
+        }
+        out.push_str("</ul>\n");
+    }
+
+    out.push_str(&format!("<h2>Proposed configuration</h2>\n<pre>{}</pre>\n", escape_html(yaml)));
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,27 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+
+/// Give control back to the JS event loop before continuing
+///
+/// Uses the global `setTimeout` so it behaves the same on the main thread,
+/// in Web Workers and under Node, letting rendering and input run in between.
+pub async fn yield_to_event_loop() {
+    let global = js_sys::global();
+    let set_timeout = js_sys::Reflect::get(&global, &JsValue::from_str("setTimeout"))
+        .ok()
+        .and_then(|f| f.dyn_into::<js_sys::Function>().ok());
+
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        let scheduled = set_timeout
+            .as_ref()
+            .map(|f| f.call2(&global, &resolve, &JsValue::from(0)).is_ok())
+            .unwrap_or(false);
+
+        // Fall back to a microtask when no timer is available
+        if !scheduled {
+            let _ = resolve.call0(&JsValue::NULL);
+        }
+    });
+
+    let _ = JsFuture::from(promise).await;
+}
@@ -0,0 +1,74 @@
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Floor and ceiling on the shares in a log-likelihood ratio, so an observation
+/// never seen in one class does not score infinitely
+const LLR_CLAMP: f64 = 0.01;
+
+/// How result rows and numeric threshold candidates are ranked
+///
+/// Every metric scores a yes/no observation from `p`, its share of TRUE time, and
+/// `q`, its share of FALSE time, with both classes weighted equally so the label
+/// balance does not decide the ranking. Rows are scored on their smoothed
+/// probabilities, threshold candidates on the raw shares.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum ScoringMetric {
+    /// `|p - q|`, the discrimination power
+    #[default]
+    AbsoluteDifference,
+    /// Bits of information the observation carries about the target, which rewards
+    /// observations that are informative whether they occur or not
+    MutualInformation,
+    /// Chi-square statistic of the observation/target table per unit of time (phi
+    /// squared), between 0 and 1
+    ChiSquare,
+    /// `|ln(p / q)|`, how far the observation alone moves the posterior log-odds
+    LogLikelihoodRatio,
+    /// Sensitivity plus specificity minus one, `|p - q|` on the unsmoothed shares
+    YoudensJ,
+}
+
+impl ScoringMetric {
+    pub fn score(self, p: f64, q: f64) -> f64 {
+        let (p, q) = (p.clamp(0.0, 1.0), q.clamp(0.0, 1.0));
+        match self {
+            ScoringMetric::AbsoluteDifference | ScoringMetric::YoudensJ => (p - q).abs(),
+            ScoringMetric::MutualInformation => {
+                let mean = (p + q) / 2.0;
+                (binary_entropy(mean) - (binary_entropy(p) + binary_entropy(q)) / 2.0).max(0.0)
+            }
+            ScoringMetric::ChiSquare => {
+                let mean = (p + q) / 2.0;
+                let spread = mean * (1.0 - mean);
+                if spread > 0.0 {
+                    (p - q) * (p - q) / (4.0 * spread)
+                } else {
+                    0.0
+                }
+            }
+            ScoringMetric::LogLikelihoodRatio => {
+                let clamp = |share: f64| share.clamp(LLR_CLAMP, 1.0 - LLR_CLAMP);
+                libm::log(clamp(p) / clamp(q)).abs()
+            }
+        }
+    }
+
+    /// Score of a result row from its raw and smoothed (P(T), P(F))
+    pub fn row_score(self, raw: (f64, f64), smoothed: (f64, f64)) -> f64 {
+        match self {
+            ScoringMetric::YoudensJ => self.score(raw.0, raw.1),
+            _ => self.score(smoothed.0, smoothed.1),
+        }
+    }
+}
+
+/// Entropy in bits of a yes/no outcome with probability `p`
+fn binary_entropy(p: f64) -> f64 {
+    [p, 1.0 - p]
+        .into_iter()
+        .filter(|&share| share > 0.0)
+        .map(|share| -share * libm::log2(share))
+        .sum()
+}
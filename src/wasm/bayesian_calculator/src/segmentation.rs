@@ -0,0 +1,233 @@
+use crate::export::{self, best_row_per_entity, slugify, SensorSettings};
+use crate::time;
+use crate::types::{EntityProbability, TimePeriod, Timestamp};
+use chrono::{Datelike, Duration};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Days analysed together; weekdays are ISO numbers (1 = Monday ... 7 = Sunday)
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DayGroup {
+    pub name: String,
+    pub weekdays: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DaySegmentationOptions {
+    /// IANA time zone the day boundaries are taken in
+    pub timezone: String,
+    pub groups: Vec<DayGroup>,
+    /// Probability change between groups that counts as a material difference
+    pub material_difference: f64,
+    /// Generate one sensor per group plus a schedule-based switch-over
+    pub generate_config: bool,
+    /// Entities (best row each) per generated sensor
+    pub observation_count: usize,
+    pub sensor: SensorSettings,
+}
+
+impl Default for DaySegmentationOptions {
+    fn default() -> Self {
+        Self {
+            timezone: "UTC".to_string(),
+            groups: vec![
+                DayGroup {
+                    name: "Weekdays".to_string(),
+                    weekdays: vec![1, 2, 3, 4, 5],
+                },
+                DayGroup {
+                    name: "Weekends".to_string(),
+                    weekdays: vec![6, 7],
+                },
+            ],
+            material_difference: 0.2,
+            generate_config: false,
+            observation_count: 5,
+            sensor: SensorSettings::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DayGroupResults {
+    pub name: String,
+    pub results: Vec<EntityProbability>,
+}
+
+/// One group's estimate for a row compared across groups
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct GroupEstimate {
+    pub group: String,
+    pub state: String,
+    pub prob_given_true: f64,
+    pub prob_given_false: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SegmentDifference {
+    pub entity_id: String,
+    /// Categorical state, or `None` for numeric entities (each group has its own thresholds)
+    pub state: Option<String>,
+    /// Largest change in either probability between any two groups
+    pub max_change: f64,
+    pub estimates: Vec<GroupEstimate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct DaySegmentation {
+    pub groups: Vec<DayGroupResults>,
+    /// Rows whose probabilities differ by at least `materialDifference`, largest first
+    pub differences: Vec<SegmentDifference>,
+    pub differs_materially: bool,
+    pub yaml: Option<String>,
+}
+
+/// Pieces of each period that fall on the group's days
+///
+/// Periods are cut at local midnight, so a TRUE period running from Friday evening
+/// into Saturday contributes to both weekdays and weekends.
+pub fn periods_for_group(periods: &[TimePeriod], group: &DayGroup, zone: Tz) -> Vec<TimePeriod> {
+    let mut pieces = Vec::new();
+
+    for period in periods {
+        let (start, end) = (period.start.millis(), period.end.millis());
+        let mut date = time::local_date(zone, start);
+        let mut piece_start = start;
+
+        while piece_start < end {
+            let next_midnight = time::local_instant(zone, date + Duration::days(1), 0.0);
+            let piece_end = next_midnight.min(end);
+
+            if group.weekdays.contains(&date.weekday().number_from_monday()) && piece_end > piece_start {
+                pieces.push(TimePeriod {
+                    id: format!("{}-{}", period.id, date),
+                    start: Timestamp::from_millis(piece_start),
+                    end: Timestamp::from_millis(piece_end),
+                    ..period.clone()
+                });
+            }
+
+            piece_start = piece_end;
+            date += Duration::days(1);
+        }
+    }
+
+    pieces
+}
+
+/// Compare the groups' rows and optionally build per-group sensors with a switch-over
+pub fn summarize(groups: Vec<DayGroupResults>, options: &DaySegmentationOptions) -> DaySegmentation {
+    let mut keys: Vec<(String, Option<String>)> = Vec::new();
+    for group in &groups {
+        for row in &group.results {
+            let key = row_key(row);
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+    }
+
+    let mut differences: Vec<SegmentDifference> = keys
+        .into_iter()
+        .filter_map(|(entity_id, state)| {
+            let estimates: Vec<GroupEstimate> = groups
+                .iter()
+                .filter_map(|group| {
+                    group
+                        .results
+                        .iter()
+                        .find(|row| row_key(row) == (entity_id.clone(), state.clone()))
+                        .map(|row| GroupEstimate {
+                            group: group.name.clone(),
+                            state: row.state.clone(),
+                            prob_given_true: row.prob_given_true,
+                            prob_given_false: row.prob_given_false,
+                        })
+                })
+                .collect();
+
+            let spread = |value: fn(&GroupEstimate) -> f64| {
+                let values = estimates.iter().map(value);
+                values.clone().fold(f64::MIN, f64::max) - values.fold(f64::MAX, f64::min)
+            };
+            let max_change = spread(|e| e.prob_given_true).max(spread(|e| e.prob_given_false));
+
+            (estimates.len() > 1 && max_change >= options.material_difference).then_some(SegmentDifference {
+                entity_id,
+                state,
+                max_change,
+                estimates,
+            })
+        })
+        .collect();
+    differences.sort_by(|a, b| b.max_change.total_cmp(&a.max_change));
+
+    let differs_materially = !differences.is_empty();
+    let yaml = options.generate_config.then(|| switch_over_yaml(&groups, options));
+
+    DaySegmentation {
+        groups,
+        differences,
+        differs_materially,
+        yaml,
+    }
+}
+
+/// Numeric rows are matched by entity because each group picks its own thresholds
+fn row_key(row: &EntityProbability) -> (String, Option<String>) {
+    let state = row.optimal_thresholds.is_none().then(|| row.state.clone());
+    (row.entity_id.clone(), state)
+}
+
+/// One bayesian sensor per group, and a template sensor following the one for today's group
+fn switch_over_yaml(groups: &[DayGroupResults], options: &DaySegmentationOptions) -> String {
+    let mut sensors = String::new();
+    let mut branches = Vec::new();
+
+    for (i, group) in groups.iter().enumerate() {
+        let settings = SensorSettings {
+            name: format!("{} ({})", options.sensor.name, group.name),
+            ..options.sensor.clone()
+        };
+        let rows = best_row_per_entity(&group.results, options.observation_count);
+        let yaml = export::generate_bayesian_yaml(&rows, &settings);
+        sensors.push_str(if i == 0 { &yaml } else { yaml.trim_start_matches("binary_sensor:\n") });
+
+        let weekdays: Vec<String> = options.groups[i].weekdays.iter().map(|d| d.to_string()).collect();
+        branches.push(format!(
+            "{{% {} now().isoweekday() in [{}] %}}{{{{ is_state('binary_sensor.{}', 'on') }}}}",
+            if i == 0 { "if" } else { "elif" },
+            weekdays.join(", "),
+            slugify(&settings.name)
+        ));
+    }
+
+    let mut yaml = sensors;
+    yaml.push('\n');
+    yaml.push_str("template:\n");
+    yaml.push_str("  - binary_sensor:\n");
+    yaml.push_str(&format!("      - name: {}\n", export::yaml_string(&options.sensor.name)));
+    if let Some(device_class) = &options.sensor.device_class {
+        yaml.push_str(&format!("        device_class: {}\n", export::yaml_string(device_class)));
+    }
+    yaml.push_str("        state: >\n");
+    for branch in branches {
+        yaml.push_str(&format!("          {}\n", branch));
+    }
+    yaml.push_str("          {% else %}false{% endif %}\n");
+
+    yaml
+}
@@ -0,0 +1,146 @@
+use crate::export::{self, best_row_per_entity, Observation, SensorSettings};
+use crate::posterior;
+use crate::simulation::{self, ObservationGrid};
+use crate::types::{EntityProbability, HAHistoryEntry, TimePeriod};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tsify::Tsify;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SelectionOptions {
+    /// Most observations in the selected configuration
+    pub max_observations: usize,
+    /// Stop once the posterior separation reaches this
+    pub target_separation: f64,
+    /// Stop when the best remaining observation adds less separation than this
+    pub min_gain: f64,
+    /// Entities (best row each) drawn from the ranked results as candidates
+    pub candidates: usize,
+    pub sensor: SensorSettings,
+}
+
+impl Default for SelectionOptions {
+    fn default() -> Self {
+        Self {
+            max_observations: 6,
+            target_separation: 0.8,
+            min_gain: 0.01,
+            candidates: 20,
+            sensor: SensorSettings::default(),
+        }
+    }
+}
+
+/// One greedy step
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SelectionStep {
+    pub entity_id: String,
+    pub state: String,
+    /// Separation with this observation and every one picked before it
+    pub separation: f64,
+    pub gain: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ObservationSelection {
+    pub selected: Vec<EntityProbability>,
+    pub steps: Vec<SelectionStep>,
+    /// Mean posterior over labeled TRUE time minus mean posterior over labeled FALSE time
+    pub separation: f64,
+    pub target_reached: bool,
+    pub yaml: String,
+}
+
+/// Greedily pick the observations that separate TRUE from FALSE time the most
+///
+/// Each step replays the history with every remaining candidate added to the ones
+/// already picked and keeps the candidate that raises the separation most. Because
+/// the separation is measured on the actual history rather than on the rows'
+/// probabilities, an entity that mostly repeats one already picked (three motion
+/// sensors in the same room) adds little and loses to one carrying new information.
+pub fn select(
+    history: &HashMap<String, Vec<HAHistoryEntry>>,
+    periods: &[TimePeriod],
+    rows: &[EntityProbability],
+    options: &SelectionOptions,
+) -> ObservationSelection {
+    let (candidates, observations): (Vec<EntityProbability>, Vec<Observation>) =
+        best_row_per_entity(rows, options.candidates)
+            .into_iter()
+            .filter_map(|row| Observation::from_result(&row).map(|observation| (row, observation)))
+            .unzip();
+    let grid = simulation::observation_grid(history, periods, &observations);
+
+    let mut log_odds = vec![posterior::prior_log_odds(options.sensor.prior); grid.slots.len()];
+    let mut separation = separation_of(&grid, &log_odds);
+    let mut remaining: Vec<usize> = (0..candidates.len()).collect();
+    let mut picked = Vec::new();
+    let mut steps = Vec::new();
+
+    while picked.len() < options.max_observations && separation < options.target_separation {
+        let best = remaining
+            .iter()
+            .enumerate()
+            .map(|(position, &candidate)| {
+                let combined: Vec<f64> =
+                    log_odds.iter().zip(&grid.log_ratios[candidate]).map(|(a, b)| a + b).collect();
+                (position, separation_of(&grid, &combined), combined)
+            })
+            // Ties go to the higher-ranked candidate
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(&a.0)));
+        let Some((position, candidate_separation, combined)) = best else {
+            break;
+        };
+        let gain = candidate_separation - separation;
+        if gain < options.min_gain {
+            break;
+        }
+
+        let candidate = remaining.remove(position);
+        steps.push(SelectionStep {
+            entity_id: candidates[candidate].entity_id.clone(),
+            state: candidates[candidate].state.clone(),
+            separation: candidate_separation,
+            gain,
+        });
+        picked.push(candidate);
+        separation = candidate_separation;
+        log_odds = combined;
+    }
+
+    let selected: Vec<EntityProbability> = picked.iter().map(|&i| candidates[i].clone()).collect();
+    ObservationSelection {
+        yaml: export::generate_bayesian_yaml(&selected, &options.sensor),
+        selected,
+        steps,
+        separation,
+        target_reached: separation >= options.target_separation,
+    }
+}
+
+/// Time-weighted mean posterior over TRUE time minus the one over FALSE time
+fn separation_of(grid: &ObservationGrid, log_odds: &[f64]) -> f64 {
+    let (mut posterior_true, mut time_true, mut posterior_false, mut time_false) = (0.0, 0.0, 0.0, 0.0);
+    for (&(_, length, label), &log_odds) in grid.slots.iter().zip(log_odds) {
+        let Some(weight) = label else {
+            continue;
+        };
+        let (length, value) = (length as f64, posterior::posterior_from_log_odds(log_odds));
+        posterior_true += value * length * weight;
+        time_true += length * weight;
+        posterior_false += value * length * (1.0 - weight);
+        time_false += length * (1.0 - weight);
+    }
+
+    if time_true > 0.0 && time_false > 0.0 {
+        posterior_true / time_true - posterior_false / time_false
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,166 @@
+use crate::types::{EntityProbability, HAHistoryEntry, TimePeriod, Timestamp};
+use crate::BayesianCalculator;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Allowed absolute difference between expected and computed probabilities
+const TOLERANCE: f64 = 1e-9;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SelfTestCheck {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub checks: Vec<SelfTestCheck>,
+}
+
+/// Run the embedded golden dataset through a fresh calculator and compare to known outputs
+///
+/// The dataset has two TRUE periods (08:00-10:00, 14:00-16:00) and one FALSE period
+/// (10:00-14:00). The motion sensor is `on` for 2.5 of the 4 TRUE hours and never
+/// during FALSE time; the CO2 sensor is above 800 ppm throughout TRUE time and at
+/// 450 ppm throughout FALSE time.
+pub fn run() -> SelfTestReport {
+    let mut checks = Vec::new();
+    let mut calculator = BayesianCalculator::new();
+
+    match calculator.process_entities(golden_history(), golden_periods()) {
+        Ok(results) => {
+            check_row(&mut checks, &results, "binary_sensor.motion", Some("on"), 0.625, 0.01);
+            check_row(&mut checks, &results, "binary_sensor.motion", Some("off"), 0.375, 0.99);
+            check_row(&mut checks, &results, "sensor.co2", None, 0.99, 0.01);
+
+            let co2 = results.iter().find(|r| r.entity_id == "sensor.co2");
+            let thresholds = co2.and_then(|r| r.optimal_thresholds.as_ref());
+            let separates = thresholds
+                .map(|t| t.below.is_none() && t.above.is_some_and(|above| (450.0..800.0).contains(&above)))
+                .unwrap_or(false);
+            checks.push(SelfTestCheck {
+                name: "sensor.co2 threshold".to_string(),
+                passed: separates,
+                detail: format!("expected an above-only threshold in [450, 800), got {:?}", thresholds),
+            });
+
+            let sorted = results
+                .windows(2)
+                .all(|pair| pair[0].score >= pair[1].score);
+            checks.push(SelfTestCheck {
+                name: "results sorted by score".to_string(),
+                passed: sorted,
+                detail: format!("{} rows", results.len()),
+            });
+        }
+        Err(e) => checks.push(SelfTestCheck {
+            name: "calculation".to_string(),
+            passed: false,
+            detail: e.to_string(),
+        }),
+    }
+
+    SelfTestReport {
+        passed: checks.iter().all(|c| c.passed),
+        checks,
+    }
+}
+
+fn check_row(
+    checks: &mut Vec<SelfTestCheck>,
+    results: &[EntityProbability],
+    entity_id: &str,
+    state: Option<&str>,
+    expected_true: f64,
+    expected_false: f64,
+) {
+    let name = match state {
+        Some(state) => format!("{} = {}", entity_id, state),
+        None => entity_id.to_string(),
+    };
+
+    let row = results
+        .iter()
+        .find(|r| r.entity_id == entity_id && state.is_none_or(|s| r.state == s));
+
+    let check = match row {
+        Some(row) => SelfTestCheck {
+            passed: (row.prob_given_true - expected_true).abs() <= TOLERANCE
+                && (row.prob_given_false - expected_false).abs() <= TOLERANCE,
+            detail: format!(
+                "expected P(T)={} P(F)={}, got P(T)={} P(F)={}",
+                expected_true, expected_false, row.prob_given_true, row.prob_given_false
+            ),
+            name,
+        },
+        None => SelfTestCheck {
+            passed: false,
+            detail: "row missing from results".to_string(),
+            name,
+        },
+    };
+
+    checks.push(check);
+}
+
+fn entry(state: &str, time: &str) -> HAHistoryEntry {
+    HAHistoryEntry {
+        state: state.to_string(),
+        last_changed: Timestamp::Iso(time.to_string()),
+        last_updated: Timestamp::Iso(time.to_string()),
+        attributes: None,
+    }
+}
+
+fn period(id: &str, start: &str, end: &str, is_true_period: bool) -> TimePeriod {
+    TimePeriod {
+        id: id.to_string(),
+        start: Timestamp::Iso(start.to_string()),
+        end: Timestamp::Iso(end.to_string()),
+        is_true_period,
+        label: None,
+        class: None,
+        confidence: None,
+    }
+}
+
+fn golden_periods() -> Vec<TimePeriod> {
+    vec![
+        period("morning", "2024-01-01T08:00:00Z", "2024-01-01T10:00:00Z", true),
+        period("midday", "2024-01-01T10:00:00Z", "2024-01-01T14:00:00Z", false),
+        period("afternoon", "2024-01-01T14:00:00Z", "2024-01-01T16:00:00Z", true),
+    ]
+}
+
+fn golden_history() -> std::collections::HashMap<String, Vec<HAHistoryEntry>> {
+    let mut history = std::collections::HashMap::new();
+
+    history.insert(
+        "binary_sensor.motion".to_string(),
+        vec![
+            entry("on", "2024-01-01T07:00:00Z"),
+            entry("off", "2024-01-01T09:00:00Z"),
+            entry("on", "2024-01-01T14:30:00Z"),
+            entry("off", "2024-01-01T20:00:00Z"),
+        ],
+    );
+
+    history.insert(
+        "sensor.co2".to_string(),
+        vec![
+            entry("900", "2024-01-01T07:00:00Z"),
+            entry("850", "2024-01-01T09:30:00Z"),
+            entry("450", "2024-01-01T10:00:00Z"),
+            entry("800", "2024-01-01T14:00:00Z"),
+            entry("500", "2024-01-01T16:00:00Z"),
+        ],
+    );
+
+    history
+}
@@ -1,3 +1,9 @@
+use crate::breakdown::{ChunkReading, Durations, PeriodDurations, TimeChunk};
+use crate::density::{self, DensityFit};
+use crate::distribution::{self, Distribution};
+use crate::options::CalculatorOptions;
+use crate::threshold;
+use crate::timeline::{Reading, TimelineEngine};
 use crate::types::{HAHistoryEntry, TimePeriod, SensorChunk, StateChunk, StateDurationStats};
 use rustc_hash::FxHashMap;
 use serde::{Deserialize, Serialize};
@@ -12,6 +18,71 @@ pub struct NumericStateStats {
     pub max: Option<f64>,
     pub true_chunks: Vec<ValueDuration>,
     pub false_chunks: Vec<ValueDuration>,
+    /// Chunks dropped or clipped by the outlier filter
+    pub outlier_chunks: usize,
+    /// Values are the rate of a cumulative counter rather than its reading
+    pub cumulative: bool,
+    /// Values wrap around at this value (360 for angles), so thresholds may too
+    pub cycle: Option<f64>,
+}
+
+/// Lightweight view of `NumericStateStats` attached to each result row
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct NumericStatsSummary {
+    pub is_numeric: bool,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub true_chunk_count: usize,
+    pub false_chunk_count: usize,
+    pub outlier_chunks: usize,
+    pub cumulative: bool,
+    pub cycle: Option<f64>,
+    /// Area under the entity's ROC curve; below 0.5 when lower values indicate TRUE
+    #[serde(default)]
+    pub auc: Option<f64>,
+    /// Histograms and mean/median/spread of the values per class, with `histogram_bins`
+    #[serde(default)]
+    pub distribution: Option<Distribution>,
+    /// Continuous per-class fit of the values, with `likelihood_model`
+    #[serde(default)]
+    pub density: Option<DensityFit>,
+}
+
+impl NumericStateStats {
+    pub fn summary(&self, options: &CalculatorOptions) -> NumericStatsSummary {
+        NumericStatsSummary {
+            is_numeric: self.is_numeric,
+            min: self.min,
+            max: self.max,
+            true_chunk_count: self.true_chunks.len(),
+            false_chunk_count: self.false_chunks.len(),
+            outlier_chunks: self.outlier_chunks,
+            cumulative: self.cumulative,
+            cycle: self.cycle,
+            auc: threshold::roc_curve(self, 0).map(|curve| curve.auc),
+            distribution: distribution::distribution(self, options.histogram_bins),
+            density: options.likelihood_model.and_then(|model| density::fit(self, model)),
+        }
+    }
+
+    /// Fold in the chunks of another slice of the same entity's history
+    pub fn merge(&mut self, other: NumericStateStats) {
+        self.min = match (self.min, other.min) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.max = match (self.max, other.max) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+        self.true_chunks.extend(other.true_chunks);
+        self.false_chunks.extend(other.false_chunks);
+        self.outlier_chunks += other.outlier_chunks;
+        self.cumulative |= other.cumulative;
+        self.cycle = self.cycle.or(other.cycle);
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
@@ -22,31 +93,38 @@ pub struct ValueDuration {
     pub duration: i64,
 }
 
+/// Whether most of the first ten available states parse as numbers
+///
+/// `unavailable` and `unknown` say nothing about the entity's kind, which matters for
+/// attributes that are null whenever their entity is off.
 pub fn is_numeric_entity(entity_history: &[HAHistoryEntry]) -> bool {
-    if entity_history.is_empty() {
+    let sample: Vec<&str> = entity_history
+        .iter()
+        .map(|entry| entry.state.as_str())
+        .filter(|state| !is_unavailable(state))
+        .take(10)
+        .collect();
+    if sample.is_empty() {
         return false;
     }
 
-    let sample_size = entity_history.len().min(10);
-    let mut numeric_count = 0;
-
-    for i in 0..sample_size {
-        let state = &entity_history[i].state;
-        if state != "unavailable" && state != "unknown" {
-            if state.parse::<f64>().is_ok() {
-                numeric_count += 1;
-            }
-        }
-    }
+    let sample_size = sample.len();
+    let numeric_count = sample.iter().filter(|state| state.parse::<f64>().is_ok()).count();
 
     numeric_count >= (sample_size as f64 * 0.7) as usize
 }
 
 pub fn analyze_numeric_states(
     entity_history: &[HAHistoryEntry],
-    periods: &[TimePeriod],
+    timeline: &TimelineEngine,
+    outliers: Option<&OutlierFilter>,
+    unavailable: UnavailableMode,
 ) -> Option<NumericStateStats> {
-    let all_chunks = create_sensor_period_chunks(entity_history, periods);
+    let mut all_chunks = create_sensor_period_chunks(entity_history, timeline, unavailable);
+    let outlier_chunks = match outliers {
+        Some(filter) => filter.apply(&mut all_chunks, |chunk| (&mut chunk.sensor_value, chunk.duration)),
+        None => 0,
+    };
     
     if all_chunks.is_empty() {
         return None;
@@ -80,208 +158,660 @@ pub fn analyze_numeric_states(
         max: Some(max),
         true_chunks,
         false_chunks,
+        outlier_chunks,
+        cumulative: false,
+        cycle: None,
     })
 }
 
-fn create_sensor_period_chunks(
+/// Fold numeric history into per-value duration totals without materializing chunks
+///
+/// Produces the same statistics shape as `analyze_numeric_states`, but with one
+/// `ValueDuration` per distinct value and class, so memory is bounded by the number
+/// of distinct values rather than the number of state changes
+pub fn aggregate_numeric_states(
     entity_history: &[HAHistoryEntry],
-    periods: &[TimePeriod],
-) -> Vec<SensorChunk> {
-    if entity_history.is_empty() || periods.is_empty() {
-        return Vec::new();
+    timeline: &TimelineEngine,
+    outliers: Option<&OutlierFilter>,
+    unavailable: UnavailableMode,
+) -> Option<NumericStateStats> {
+    if entity_history.is_empty() || timeline.is_empty() {
+        return None;
     }
 
-    let mut chunks = Vec::new();
+    let history_cache = numeric_history_cache(entity_history, unavailable);
 
-    // Cache timestamps and values
-    let mut history_cache: Vec<(i64, Option<f64>)> = Vec::with_capacity(entity_history.len());
-    for entry in entity_history {
-        let timestamp = parse_timestamp(&entry.last_changed);
-        let value = entry.state.parse::<f64>().ok();
-        history_cache.push((timestamp, value));
+    // Keyed by the value's bit pattern; (true duration, false duration)
+    let mut totals: FxHashMap<u64, (i64, i64)> = FxHashMap::default();
+
+    timeline.sweep(&history_cache, |value, duration, is_true, _| {
+        let entry = totals.entry(value.to_bits()).or_default();
+        if is_true {
+            entry.0 += duration;
+        } else {
+            entry.1 += duration;
+        }
+    });
+
+    if totals.is_empty() {
+        return None;
     }
 
-    // Sort by timestamp
-    history_cache.sort_by_key(|&(time, _)| time);
+    let mut values: Vec<(f64, i64, i64)> = totals
+        .into_iter()
+        .map(|(bits, (true_duration, false_duration))| (f64::from_bits(bits), true_duration, false_duration))
+        .collect();
+    // Each value stands for all its chunks here, so the count is of distinct values
+    let outlier_chunks = match outliers {
+        Some(filter) => filter.apply(&mut values, |(value, true_duration, false_duration)| (value, *true_duration + *false_duration)),
+        None => 0,
+    };
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.0.total_cmp(&b.0));
 
-    // Process each period
-    for period in periods {
-        let period_start = parse_timestamp(&period.start);
-        let period_end = parse_timestamp(&period.end);
+    let true_chunks = values
+        .iter()
+        .filter(|&&(_, true_duration, _)| true_duration > 0)
+        .map(|&(value, duration, _)| ValueDuration { value, duration })
+        .collect();
 
-        // Find relevant changes within the period
-        let mut relevant_timestamps: Vec<i64> = Vec::new();
+    let false_chunks = values
+        .iter()
+        .filter(|&&(_, _, false_duration)| false_duration > 0)
+        .map(|&(value, _, duration)| ValueDuration { value, duration })
+        .collect();
 
-        for &(timestamp, _) in &history_cache {
-            if timestamp > period_start && timestamp < period_end {
-                relevant_timestamps.push(timestamp);
-            }
-        }
+    Some(NumericStateStats {
+        is_numeric: true,
+        min: values.first().map(|v| v.0),
+        max: values.last().map(|v| v.0),
+        true_chunks,
+        false_chunks,
+        outlier_chunks,
+        cumulative: false,
+        cycle: None,
+    })
+}
 
-        // Build time points
-        let mut time_points = vec![period_start];
-        time_points.extend(&relevant_timestamps);
-        time_points.push(period_end);
-
-        // Find initial value
-        let mut current_value: Option<f64> = None;
-        for i in (0..history_cache.len()).rev() {
-            if history_cache[i].0 <= period_start {
-                current_value = history_cache[i].1;
-                break;
-            }
-        }
+/// Which numeric values count as outliers
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Tsify)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum OutlierBounds {
+    /// Outside the duration-weighted `lower` and `upper` percentiles (0-100)
+    Percentile { lower: f64, upper: f64 },
+    /// More than `factor` interquartile ranges below the first or above the third quartile
+    Iqr { factor: f64 },
+}
 
-        // Create chunks for this period
-        for i in 0..time_points.len() - 1 {
-            let chunk_start = time_points[i];
-            let chunk_end = time_points[i + 1];
-            let duration = chunk_end - chunk_start;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum OutlierAction {
+    /// Leave the chunk out of the analysis
+    Drop,
+    /// Keep the chunk at the nearest bound
+    Clip,
+}
 
-            if duration < 1000 {
-                continue; // Skip chunks smaller than 1 second
-            }
+/// Glitch readings (e.g. a power sensor reporting 65535 W) to remove before the
+/// value range and thresholds are computed
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct OutlierFilter {
+    pub bounds: OutlierBounds,
+    pub action: OutlierAction,
+}
 
-            // Update current value if there was a change
-            if i > 0 {
-                // Find the change at this timestamp
-                for &(timestamp, value) in &history_cache {
-                    if timestamp == time_points[i] {
-                        if let Some(v) = value {
-                            current_value = Some(v);
-                        }
-                        break;
-                    }
+impl OutlierFilter {
+    /// Drop or clip the items outside the bounds and return how many were affected
+    ///
+    /// Quantiles are weighted by duration over both classes, so a brief glitch
+    /// barely moves them however extreme it is.
+    fn apply<T>(&self, items: &mut Vec<T>, parts: impl Fn(&mut T) -> (&mut f64, i64)) -> usize {
+        let mut weighted: Vec<(f64, i64)> = items
+            .iter_mut()
+            .map(|item| {
+                let (value, duration) = parts(item);
+                (*value, duration)
+            })
+            .collect();
+        weighted.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let total = weighted.iter().fold(0i64, |sum, &(_, duration)| sum + duration);
+        if total <= 0 {
+            return 0;
+        }
+
+        let quantile = |q: f64| {
+            let target = q.clamp(0.0, 1.0) * total as f64;
+            let mut cumulative = 0i64;
+            for &(value, duration) in &weighted {
+                cumulative += duration;
+                if cumulative as f64 >= target {
+                    return value;
                 }
             }
-
-            if let Some(value) = current_value {
-                chunks.push(SensorChunk {
-                    sensor_value: value,
-                    duration,
-                    desired_output: period.is_true_period,
+            weighted[weighted.len() - 1].0
+        };
+        let (low, high) = match self.bounds {
+            OutlierBounds::Percentile { lower, upper } => (quantile(lower / 100.0), quantile(upper / 100.0)),
+            OutlierBounds::Iqr { factor } => {
+                let (q1, q3) = (quantile(0.25), quantile(0.75));
+                (q1 - factor * (q3 - q1), q3 + factor * (q3 - q1))
+            }
+        };
+
+        match self.action {
+            OutlierAction::Drop => {
+                let before = items.len();
+                items.retain_mut(|item| {
+                    let value = *parts(item).0;
+                    value >= low && value <= high
                 });
+                before - items.len()
+            }
+            OutlierAction::Clip => {
+                let mut clipped = 0;
+                for item in items.iter_mut() {
+                    let (value, _) = parts(item);
+                    if *value < low || *value > high {
+                        // max/min instead of clamp so inverted percentiles cannot panic
+                        *value = value.max(low).min(high);
+                        clipped += 1;
+                    }
+                }
+                clipped
             }
         }
     }
+}
+
+fn create_sensor_period_chunks(
+    entity_history: &[HAHistoryEntry],
+    timeline: &TimelineEngine,
+    unavailable: UnavailableMode,
+) -> Vec<SensorChunk> {
+    if entity_history.is_empty() || timeline.is_empty() {
+        return Vec::new();
+    }
+
+    let history_cache = numeric_history_cache(entity_history, unavailable);
+    let mut chunks = Vec::with_capacity(timeline.chunk_capacity(&history_cache));
+
+    timeline.sweep(&history_cache, |sensor_value, duration, desired_output, _| {
+        chunks.push(SensorChunk {
+            sensor_value,
+            duration,
+            desired_output,
+        });
+    });
 
     chunks
 }
 
 /// Create state-based chunks using the same duration approach as numeric sensors
-pub fn create_state_period_chunks(
-    entity_history: &[HAHistoryEntry],
-    periods: &[TimePeriod],
-) -> Vec<StateChunk> {
-    if entity_history.is_empty() || periods.is_empty() {
+pub fn create_state_period_chunks<'a>(
+    entity_history: &'a [HAHistoryEntry],
+    timeline: &TimelineEngine,
+    unavailable: UnavailableMode,
+) -> Vec<StateChunk<'a>> {
+    if entity_history.is_empty() || timeline.is_empty() {
         return Vec::new();
     }
 
-    let mut chunks = Vec::new();
+    let history_cache = state_history_cache(entity_history, unavailable);
+    let mut chunks = Vec::with_capacity(timeline.chunk_capacity(&history_cache));
 
-    // Cache timestamps and states
-    let mut history_cache: Vec<(i64, String)> = Vec::with_capacity(entity_history.len());
-    for entry in entity_history {
-        let timestamp = parse_timestamp(&entry.last_changed);
-        history_cache.push((timestamp, entry.state.clone()));
-    }
+    timeline.sweep(&history_cache, |state, duration, desired_output, period_index| {
+        chunks.push(StateChunk {
+            state,
+            duration,
+            desired_output,
+            period_index,
+        });
+    });
 
-    // Sort by timestamp
-    history_cache.sort_by_key(|&(time, _)| time);
+    chunks
+}
 
-    // Process each period
-    for period in periods {
-        let period_start = parse_timestamp(&period.start);
-        let period_end = parse_timestamp(&period.end);
+/// Length of a period in milliseconds
+pub fn period_length(period: &TimePeriod) -> i64 {
+    period.end.millis() - period.start.millis()
+}
 
-        // Find relevant changes within the period
-        let mut relevant_timestamps: Vec<i64> = Vec::new();
+/// Share of a period's time that counts towards TRUE
+///
+/// Hard labels give 1 or 0; a soft label's confidence is clamped to [0, 1].
+pub fn true_weight(period: &TimePeriod) -> f64 {
+    match period.confidence {
+        Some(confidence) if confidence.is_finite() => confidence.clamp(0.0, 1.0),
+        _ if period.is_true_period => 1.0,
+        _ => 0.0,
+    }
+}
 
-        for &(timestamp, _) in &history_cache {
-            if timestamp > period_start && timestamp < period_end {
-                relevant_timestamps.push(timestamp);
-            }
-        }
+/// Split a duration into its (TRUE, FALSE) parts by the TRUE weight
+///
+/// Rounded so the parts always add back up to the whole duration.
+pub fn split_duration(duration: i64, weight: f64) -> (i64, i64) {
+    let true_part = (duration as f64 * weight).round() as i64;
+    (true_part, duration - true_part)
+}
 
-        // Build time points
-        let mut time_points = vec![period_start];
-        time_points.extend(&relevant_timestamps);
-        time_points.push(period_end);
-
-        // Find initial state
-        let mut current_state: Option<String> = None;
-        for i in (0..history_cache.len()).rev() {
-            if history_cache[i].0 <= period_start {
-                current_state = Some(history_cache[i].1.clone());
-                break;
-            }
-        }
+/// How `unavailable` and `unknown` states count towards an entity's statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum UnavailableMode {
+    /// Leave their time out entirely, as if the entity had no history then
+    #[default]
+    Exclude,
+    /// Count their time towards the state the entity had before
+    LastKnown,
+    /// Count them as states of their own; numeric entities cannot have them as a
+    /// value, so their time is left out there as with `Exclude`
+    OwnState,
+}
 
-        // Create chunks for this period
-        for i in 0..time_points.len() - 1 {
-            let chunk_start = time_points[i];
-            let chunk_end = time_points[i + 1];
-            let duration = chunk_end - chunk_start;
+/// What a categorical state's P(state | TRUE) and P(state | FALSE) measure
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum StateProbability {
+    /// Share of the class's time spent in the state, as for numeric entities
+    #[default]
+    Duration,
+    /// Share of the class's periods the state was seen in at all, however briefly
+    Occurrence,
+}
 
-            if duration < 1000 {
-                continue; // Skip chunks smaller than 1 second
-            }
+pub fn is_unavailable(state: &str) -> bool {
+    state == "unavailable" || state == "unknown"
+}
 
-            // Update current state if there was a change
-            if i > 0 {
-                // Find the change at this timestamp
-                for &(timestamp, ref state) in &history_cache {
-                    if timestamp == time_points[i] {
-                        current_state = Some(state.clone());
-                        break;
-                    }
-                }
-            }
+/// Reading of an `unavailable`/`unknown` state under `mode`
+fn unavailable_reading<T>(mode: UnavailableMode, own_state: impl FnOnce() -> Reading<T>) -> Reading<T> {
+    match mode {
+        UnavailableMode::Exclude => Reading::Gap,
+        UnavailableMode::LastKnown => Reading::Keep,
+        UnavailableMode::OwnState => own_state(),
+    }
+}
 
-            if let Some(ref state) = current_state {
-                chunks.push(StateChunk {
-                    state: state.clone(),
-                    duration,
-                    desired_output: period.is_true_period,
-                });
-            }
-        }
+/// Timestamps and parsed values sorted by time; other non-numeric states keep the
+/// value before them
+fn numeric_history_cache(entity_history: &[HAHistoryEntry], unavailable: UnavailableMode) -> Vec<(i64, Reading<f64>)> {
+    let mut history_cache: Vec<(i64, Reading<f64>)> = Vec::with_capacity(entity_history.len());
+    for entry in entity_history {
+        let timestamp = entry.last_changed.millis();
+        let reading = if is_unavailable(&entry.state) {
+            unavailable_reading(unavailable, || Reading::Gap)
+        } else {
+            entry.state.parse::<f64>().map_or(Reading::Keep, Reading::Value)
+        };
+        history_cache.push((timestamp, reading));
     }
 
-    chunks
+    history_cache.sort_by_key(|&(time, _)| time);
+    history_cache
+}
+
+/// Timestamps and borrowed states sorted by time
+fn state_history_cache(entity_history: &[HAHistoryEntry], unavailable: UnavailableMode) -> Vec<(i64, Reading<&str>)> {
+    let mut history_cache: Vec<(i64, Reading<&str>)> = Vec::with_capacity(entity_history.len());
+    for entry in entity_history {
+        let timestamp = entry.last_changed.millis();
+        let state = entry.state.as_str();
+        let reading = if is_unavailable(state) {
+            unavailable_reading(unavailable, || Reading::Value(state))
+        } else {
+            Reading::Value(state)
+        };
+        history_cache.push((timestamp, reading));
+    }
+
+    history_cache.sort_by_key(|&(time, _)| time);
+    history_cache
 }
 
 /// Analyze state chunks to get duration-based statistics for each state
 pub fn analyze_state_chunks(
     entity_history: &[HAHistoryEntry],
-    periods: &[TimePeriod],
+    timeline: &TimelineEngine,
+    unavailable: UnavailableMode,
 ) -> FxHashMap<String, StateDurationStats> {
-    let chunks = create_state_period_chunks(entity_history, periods);
-    let mut stats: FxHashMap<String, StateDurationStats> = FxHashMap::default();
+    let chunks = create_state_period_chunks(entity_history, timeline, unavailable);
+    let mut stats: FxHashMap<&str, StateDurationStats> = FxHashMap::default();
+    // Chunks arrive in period order, so a state is in a new period when the index changes;
+    // tracked per class because a soft-labeled period reports its chunks to both
+    let mut last_period: FxHashMap<(&str, bool), usize> = FxHashMap::default();
 
     for chunk in chunks {
-        let entry = stats.entry(chunk.state.clone()).or_insert(StateDurationStats {
-            true_duration: 0,
-            false_duration: 0,
-        });
+        let entry = stats.entry(chunk.state).or_default();
+        let new_period = last_period.insert((chunk.state, chunk.desired_output), chunk.period_index)
+            != Some(chunk.period_index);
 
         if chunk.desired_output {
             entry.true_duration += chunk.duration;
+            entry.true_periods += usize::from(new_period);
         } else {
             entry.false_duration += chunk.duration;
+            entry.false_periods += usize::from(new_period);
         }
     }
 
     stats
+        .into_iter()
+        .map(|(state, durations)| (state.to_string(), durations))
+        .collect()
+}
+
+/// Share of each period's covered time spent in each state
+///
+/// Returns the periods the history covers at all, and per state the share of each
+/// period (0 where the period is not covered).
+pub fn state_period_shares(
+    entity_history: &[HAHistoryEntry],
+    timeline: &TimelineEngine,
+    unavailable: UnavailableMode,
+) -> (Vec<bool>, FxHashMap<String, Vec<f64>>) {
+    let chunks = create_state_period_chunks(entity_history, timeline, unavailable);
+    let mut covered = vec![0i64; timeline.periods().len()];
+    let mut durations: FxHashMap<&str, Vec<i64>> = FxHashMap::default();
+
+    // A soft-labeled period reports its chunks once per class; both halves add up to the whole
+    for chunk in &chunks {
+        covered[chunk.period_index] += chunk.duration;
+        durations.entry(chunk.state).or_insert_with(|| vec![0; timeline.periods().len()])[chunk.period_index] += chunk.duration;
+    }
+
+    let shares = durations
+        .into_iter()
+        .map(|(state, durations)| {
+            let shares = durations
+                .iter()
+                .zip(&covered)
+                .map(|(&duration, &total)| if total > 0 { duration as f64 / total as f64 } else { 0.0 })
+                .collect();
+            (state.to_string(), shares)
+        })
+        .collect();
+
+    (covered.iter().map(|&total| total > 0).collect(), shares)
+}
+
+/// Time-weighted mean value of a numeric entity in each period, `None` where it has no data
+pub fn numeric_period_means(
+    entity_history: &[HAHistoryEntry],
+    timeline: &TimelineEngine,
+    unavailable: UnavailableMode,
+) -> Vec<Option<f64>> {
+    let history_cache = numeric_history_cache(entity_history, unavailable);
+    let mut sums = vec![(0.0, 0i64); timeline.periods().len()];
+
+    timeline.sweep(&history_cache, |value, duration, _, period_index| {
+        sums[period_index].0 += value * duration as f64;
+        sums[period_index].1 += duration;
+    });
+
+    sums.into_iter()
+        .map(|(sum, duration)| (duration > 0).then(|| sum / duration as f64))
+        .collect()
+}
+
+/// (TRUE, FALSE) time with a value in effect in each period, in milliseconds
+///
+/// Follows the same sweep as the statistics, so time they leave out (before the
+/// first recorded state, `unavailable` gaps under `Exclude`, chunks under a second)
+/// is uncovered here too. A soft-labeled period splits its time between both.
+pub fn class_durations(
+    entity_history: &[HAHistoryEntry],
+    timeline: &TimelineEngine,
+    is_numeric: bool,
+    unavailable: UnavailableMode,
+) -> Vec<(i64, i64)> {
+    let mut durations = vec![(0i64, 0i64); timeline.periods().len()];
+    let mut visit = |duration: i64, is_true: bool, period_index: usize| {
+        let (true_duration, false_duration) = &mut durations[period_index];
+        *if is_true { true_duration } else { false_duration } += duration;
+    };
+    if is_numeric {
+        let history_cache = numeric_history_cache(entity_history, unavailable);
+        timeline.sweep(&history_cache, |_, duration, is_true, period_index| visit(duration, is_true, period_index));
+    } else {
+        let history_cache = state_history_cache(entity_history, unavailable);
+        timeline.sweep(&history_cache, |_, duration, is_true, period_index| visit(duration, is_true, period_index));
+    }
+    durations
+}
+
+/// Time at each value or in each state in every period, as `class_durations`
+/// sweeps it; both halves of a soft-labeled period are added back together
+pub fn period_durations(
+    entity_history: &[HAHistoryEntry],
+    timeline: &TimelineEngine,
+    is_numeric: bool,
+    unavailable: UnavailableMode,
+) -> Vec<PeriodDurations> {
+    let periods = timeline.periods();
+    if is_numeric {
+        let history_cache = numeric_history_cache(entity_history, unavailable);
+        // Keyed by the value's bit pattern
+        let mut totals: Vec<FxHashMap<u64, i64>> = vec![FxHashMap::default(); periods.len()];
+        timeline.sweep(&history_cache, |value, duration, _, period_index| {
+            *totals[period_index].entry(value.to_bits()).or_default() += duration;
+        });
+        periods
+            .iter()
+            .zip(totals)
+            .map(|(period, totals)| {
+                let mut values: Vec<ValueDuration> = totals
+                    .into_iter()
+                    .map(|(bits, duration)| ValueDuration { value: f64::from_bits(bits), duration })
+                    .collect();
+                values.sort_by(|a, b| a.value.total_cmp(&b.value));
+                PeriodDurations::new(period, Durations::Numeric(values))
+            })
+            .collect()
+    } else {
+        let history_cache = state_history_cache(entity_history, unavailable);
+        let mut totals: Vec<FxHashMap<String, i64>> = vec![FxHashMap::default(); periods.len()];
+        timeline.sweep(&history_cache, |state, duration, _, period_index| {
+            *totals[period_index].entry(state.to_string()).or_default() += duration;
+        });
+        periods
+            .iter()
+            .zip(totals)
+            .map(|(period, totals)| PeriodDurations::new(period, Durations::States(totals)))
+            .collect()
+    }
+}
+
+/// Every time slice the statistics are built from, per period in period order
+///
+/// Slices of a soft-labeled period are reported whole rather than split by class.
+pub fn period_chunks(
+    entity_history: &[HAHistoryEntry],
+    timeline: &TimelineEngine,
+    is_numeric: bool,
+    unavailable: UnavailableMode,
+) -> Vec<Vec<TimeChunk>> {
+    let mut chunks: Vec<Vec<TimeChunk>> = vec![Vec::new(); timeline.periods().len()];
+    if is_numeric {
+        let history_cache = numeric_history_cache(entity_history, unavailable);
+        timeline.slices(&history_cache, |value, start, duration, period_index| {
+            chunks[period_index].push(TimeChunk {
+                start,
+                duration,
+                reading: ChunkReading::Value(value),
+            });
+        });
+    } else {
+        let history_cache = state_history_cache(entity_history, unavailable);
+        timeline.slices(&history_cache, |state, start, duration, period_index| {
+            chunks[period_index].push(TimeChunk {
+                start,
+                duration,
+                reading: ChunkReading::State(state.to_string()),
+            });
+        });
+    }
+    chunks
+}
+
+/// Id of a series analysed alongside `entity_id`, e.g. `light.kitchen::brightness`
+pub fn virtual_entity_id(entity_id: &str, suffix: &str) -> String {
+    format!("{}::{}", entity_id, suffix)
+}
+
+/// Source and outermost suffix of a virtual entity id; `None` for real entities
+///
+/// The source is itself virtual for a feature of a virtual entity, such as
+/// `light.kitchen::brightness::derivative`.
+pub fn split_virtual_entity_id(entity_id: &str) -> Option<(&str, &str)> {
+    entity_id.rsplit_once("::")
+}
+
+/// Series computed from a numeric entity's history and analysed as a virtual entity
+/// of its own, `<entity_id>::<suffix>`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum DerivedFeature {
+    /// Change per minute between successive readings (e.g. °C/min, W/min), matching
+    /// HA's `derivative` helper with a one-minute time unit
+    Derivative,
 }
 
-fn parse_timestamp(iso_string: &str) -> i64 {
-    // Simple ISO 8601 parser for timestamps
-    // Format: "2024-01-01T12:00:00.000Z" or similar
+impl DerivedFeature {
+    pub fn from_suffix(suffix: &str) -> Option<Self> {
+        [DerivedFeature::Derivative].into_iter().find(|feature| feature.suffix() == suffix)
+    }
+
+    pub fn suffix(self) -> &'static str {
+        match self {
+            DerivedFeature::Derivative => "derivative",
+        }
+    }
+
+    /// Id of the virtual entity carrying this feature of `entity_id`
+    pub fn entity_id(self, entity_id: &str) -> String {
+        virtual_entity_id(entity_id, self.suffix())
+    }
+
+    /// Unit of the feature of an entity measured in `unit`
+    pub fn unit(self, unit: &str) -> String {
+        match self {
+            DerivedFeature::Derivative => format!("{}/min", unit),
+        }
+    }
+
+    pub fn derive(self, entity_history: &[HAHistoryEntry]) -> Vec<HAHistoryEntry> {
+        match self {
+            DerivedFeature::Derivative => rate_history(entity_history, false),
+        }
+    }
+}
+
+/// Fewest increases before a numeric history can count as a cumulative counter
+const MIN_CUMULATIVE_STEPS: usize = 10;
+
+/// Increases a counter needs per decrease, so occasional resets are tolerated
+const STEPS_PER_RESET: usize = 20;
+
+/// Whether a numeric entity is a cumulative counter (an energy or rain total) whose
+/// level says nothing and whose rate does
+///
+/// An HA `state_class` of `total_increasing` settles it. Otherwise the readings must
+/// rise at least `MIN_CUMULATIVE_STEPS` times and drop (a reset) at most once per
+/// `STEPS_PER_RESET` rises.
+pub fn is_cumulative(entity_history: &[HAHistoryEntry], state_class: Option<&str>) -> bool {
+    if state_class == Some("total_increasing") {
+        return true;
+    }
+
+    let mut ordered: Vec<(i64, f64)> = entity_history
+        .iter()
+        .filter_map(|entry| Some((entry.last_changed.millis(), entry.state.parse::<f64>().ok()?)))
+        .collect();
+    ordered.sort_by_key(|&(time, _)| time);
+
+    let (mut increases, mut decreases) = (0, 0);
+    for pair in ordered.windows(2) {
+        if pair[1].1 > pair[0].1 {
+            increases += 1;
+        } else if pair[1].1 < pair[0].1 {
+            decreases += 1;
+        }
+    }
+    increases >= MIN_CUMULATIVE_STEPS && decreases * STEPS_PER_RESET <= increases
+}
+
+/// Per-minute rate of a cumulative counter; a drop is read as a reset to zero, so
+/// the new reading is the amount counted since
+pub fn counter_rate_history(entity_history: &[HAHistoryEntry]) -> Vec<HAHistoryEntry> {
+    rate_history(entity_history, true)
+}
+
+/// Rate of change per minute, set at each reading from the one before it
+///
+/// Non-numeric states are kept as they are and break the series, so no rate is
+/// computed across an `unavailable` gap. Readings without time passed since the
+/// previous one are dropped. With `resets`, a drop counts from zero instead.
+fn rate_history(entity_history: &[HAHistoryEntry], resets: bool) -> Vec<HAHistoryEntry> {
+    let mut ordered: Vec<&HAHistoryEntry> = entity_history.iter().collect();
+    ordered.sort_by_key(|entry| entry.last_changed.millis());
+
+    let mut derived = Vec::with_capacity(ordered.len());
+    let mut previous: Option<(i64, f64)> = None;
+    for entry in ordered {
+        let time = entry.last_changed.millis();
+        let Ok(value) = entry.state.parse::<f64>() else {
+            previous = None;
+            derived.push(entry.clone());
+            continue;
+        };
+
+        match previous {
+            Some((previous_time, previous_value)) if time > previous_time => {
+                let minutes = (time - previous_time) as f64 / 60_000.0;
+                let change = if resets && value < previous_value { value } else { value - previous_value };
+                derived.push(HAHistoryEntry {
+                    state: (change / minutes).to_string(),
+                    ..entry.clone()
+                });
+            }
+            Some(_) => continue,
+            None => {}
+        }
+        previous = Some((time, value));
+    }
+
+    derived
+}
+
+/// Epoch milliseconds of an ISO 8601 timestamp; `None` when it cannot be read
+///
+/// Besides RFC 3339 (`2024-01-01T12:00:00.000Z`, or with a space as HA writes it,
+/// `2024-01-01 12:00:00+00:00`) this accepts offsets without a colon (`+0000`) and
+/// times without any offset, which are taken as UTC like HA's recorder stores them.
+/// Fractional seconds may have any precision.
+pub fn parse_timestamp(iso_string: &str) -> Option<i64> {
+    const WITH_OFFSET: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f%z", "%Y-%m-%d %H:%M:%S%.f%z"];
+    const WITHOUT_OFFSET: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"];
+
+    let iso_string = iso_string.trim();
     chrono::DateTime::parse_from_rfc3339(iso_string)
+        .ok()
+        .or_else(|| WITH_OFFSET.iter().find_map(|format| chrono::DateTime::parse_from_str(iso_string, format).ok()))
         .map(|dt| dt.timestamp_millis())
-        .unwrap_or(0)
+        .or_else(|| {
+            WITHOUT_OFFSET
+                .iter()
+                .find_map(|format| chrono::NaiveDateTime::parse_from_str(iso_string, format).ok())
+                .map(|dt| dt.and_utc().timestamp_millis())
+        })
 }
-
-// Add chrono to dependencies for timestamp parsing
-use chrono;
\ No newline at end of file
@@ -0,0 +1,150 @@
+use crate::types::StateDurationStats;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Rate a sparsely observed state is pulled toward
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum ShrinkageTarget {
+    /// Share of all labeled time the entity spent in the state, regardless of class
+    Entity,
+    /// Share of labeled time all categorical entities spent in a state with the same value
+    Global,
+}
+
+/// How much evidence a state's own estimate is credited with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum ShrinkageMethod {
+    /// The periods the state was seen in
+    #[default]
+    PeriodsSeen,
+    /// Beta prior centred on the base rate, updated with the state's share over all
+    /// of the class's periods; keeps a state seen once in a few periods well away
+    /// from 0 and 1 without relying on the smoothing clamp
+    BetaBinomial,
+    /// `betaBinomial`, with the prior's strength fitted to how far the states' shares
+    /// spread around their base rates beyond sampling noise (method of moments);
+    /// `strength` is used where there is too little data to fit
+    EmpiricalBayes,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ShrinkageOptions {
+    pub target: ShrinkageTarget,
+    /// Pseudo-periods of evidence the base rate is worth; a state seen in this many
+    /// periods ends up halfway between its own estimate and the base rate
+    pub strength: f64,
+    pub method: ShrinkageMethod,
+}
+
+impl Default for ShrinkageOptions {
+    fn default() -> Self {
+        Self {
+            target: ShrinkageTarget::Entity,
+            strength: 2.0,
+            method: ShrinkageMethod::default(),
+        }
+    }
+}
+
+/// Class-independent time share of each state of one entity
+pub fn entity_base_rates(state_stats: &FxHashMap<String, StateDurationStats>) -> FxHashMap<String, f64> {
+    base_rates(std::iter::once(state_stats))
+}
+
+/// Class-independent time share of each state value pooled over several entities
+pub fn base_rates<'a>(
+    entities: impl IntoIterator<Item = &'a FxHashMap<String, StateDurationStats>>,
+) -> FxHashMap<String, f64> {
+    let mut durations: FxHashMap<String, i64> = FxHashMap::default();
+    let mut total = 0i64;
+
+    for state_stats in entities {
+        for (state, stats) in state_stats {
+            let duration = stats.true_duration + stats.false_duration;
+            *durations.entry(state.clone()).or_default() += duration;
+            total += duration;
+        }
+    }
+
+    durations
+        .into_iter()
+        .map(|(state, duration)| {
+            let rate = if total > 0 { duration as f64 / total as f64 } else { 0.0 };
+            (state, rate)
+        })
+        .collect()
+}
+
+/// Move `probability` toward `base_rate` by `strength / (periods_seen + strength)`
+pub fn shrink(probability: f64, base_rate: f64, periods_seen: usize, strength: f64) -> f64 {
+    let weight = periods_seen as f64 / (periods_seen as f64 + strength.max(0.0));
+    if weight.is_nan() {
+        return probability;
+    }
+    weight * probability + (1.0 - weight) * base_rate
+}
+
+/// Pseudo-periods of a beta prior on the base rate that best explains how far the
+/// states' class shares spread around it; `fallback` without any state to fit on
+///
+/// A share measured over `n` periods from a Beta prior of strength `m` has variance
+/// `b(1 - b)(1/(m + 1) + (m/(m + 1))/n)` around base rate `b`, which is solved for
+/// `m` from the shares of every state in both classes.
+pub fn fitted_strength<'a>(
+    entities: impl IntoIterator<Item = &'a FxHashMap<String, StateDurationStats>>,
+    base_rates: &FxHashMap<String, f64>,
+    (true_periods, false_periods): (usize, usize),
+    fallback: f64,
+) -> f64 {
+    let mut correlation = 0.0;
+    let mut pairs = 0usize;
+
+    for state_stats in entities {
+        let total_true: i64 = state_stats.values().map(|stats| stats.true_duration).sum();
+        let total_false: i64 = state_stats.values().map(|stats| stats.false_duration).sum();
+
+        for (state, stats) in state_stats {
+            let base_rate = base_rates.get(state).copied().unwrap_or(0.0);
+            if base_rate <= 0.0 || base_rate >= 1.0 {
+                continue;
+            }
+            for (duration, total, periods) in [
+                (stats.true_duration, total_true, true_periods),
+                (stats.false_duration, total_false, false_periods),
+            ] {
+                if total <= 0 || periods < 2 {
+                    continue;
+                }
+                let n = periods as f64;
+                let share = duration as f64 / total as f64;
+                let scaled = (share - base_rate).powi(2) / (base_rate * (1.0 - base_rate));
+                correlation += (scaled - 1.0 / n) / (1.0 - 1.0 / n);
+                pairs += 1;
+            }
+        }
+    }
+
+    if pairs == 0 {
+        return fallback.max(0.0);
+    }
+    // A spread no wider than sampling noise alone would mean an infinitely strong prior
+    let correlation = (correlation / pairs as f64).clamp(1e-3, 1.0);
+    1.0 / correlation - 1.0
+}
+
+/// Posterior mean of a Beta prior of `strength` pseudo-periods on `base_rate`, after
+/// `probability` measured over `periods` periods
+pub fn beta_binomial(probability: f64, base_rate: f64, periods: usize, strength: f64) -> f64 {
+    let (n, m) = (periods as f64, strength.max(0.0));
+    if n + m <= 0.0 {
+        return probability;
+    }
+    (probability * n + base_rate * m) / (n + m)
+}
@@ -0,0 +1,194 @@
+use crate::export::{observations_from_results, Observation};
+use crate::posterior;
+use crate::sensor_analysis::true_weight;
+use crate::time;
+use crate::timeline::{value_at, TimelineEngine};
+use crate::types::{EntityProbability, HAHistoryEntry, TimePeriod};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tsify::Tsify;
+
+/// Change times of an observation with whether it is met (`None` when HA skips it)
+type ObservationTimeline = Vec<(i64, Option<bool>)>;
+
+/// Posterior from one change until the next
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PosteriorPoint {
+    pub time: String, // ISO 8601 string
+    pub posterior: f64,
+    /// Sensor state at `posterior >= probability_threshold`
+    pub on: bool,
+    /// TRUE weight of the labeled period covering this time, if any
+    pub label: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct PosteriorSimulation {
+    pub points: Vec<PosteriorPoint>,
+    /// Share of labeled TRUE time the sensor would have been on
+    pub true_on_fraction: f64,
+    /// Share of labeled FALSE time the sensor would have been on
+    pub false_on_fraction: f64,
+}
+
+/// Stretch of time with a constant posterior
+#[derive(Debug, Clone, Copy)]
+pub struct Segment {
+    pub start: i64,
+    pub length: i64,
+    pub posterior: f64,
+    /// TRUE weight of the labeled period covering the segment, if any
+    pub label: Option<f64>,
+}
+
+/// Replay the history through the selected observations as HA's bayesian sensor would
+///
+/// Every entity's timeline is walked together from the first period start to the
+/// last period end; a point is emitted at the start and at every change of any
+/// selected entity or period boundary. Observations whose entity has no state yet,
+/// is `unavailable`/`unknown`, or needs a template are skipped, like HA does for
+/// observations it cannot evaluate.
+pub fn simulate(
+    history: &HashMap<String, Vec<HAHistoryEntry>>,
+    periods: &[TimePeriod],
+    rows: &[EntityProbability],
+    prior: f64,
+    probability_threshold: f64,
+) -> PosteriorSimulation {
+    let segments = replay(history, periods, rows, prior);
+    let (mut on_true, mut time_true, mut on_false, mut time_false) = (0.0, 0.0, 0.0, 0.0);
+
+    let points = segments
+        .iter()
+        .map(|segment| {
+            let on = segment.posterior >= probability_threshold;
+            if let Some(weight) = segment.label {
+                let length = segment.length as f64;
+                let on_time = if on { length } else { 0.0 };
+                on_true += on_time * weight;
+                time_true += length * weight;
+                on_false += on_time * (1.0 - weight);
+                time_false += length * (1.0 - weight);
+            }
+            PosteriorPoint {
+                time: time::format_millis(segment.start),
+                posterior: segment.posterior,
+                on,
+                label: segment.label,
+            }
+        })
+        .collect();
+
+    PosteriorSimulation {
+        points,
+        true_on_fraction: if time_true > 0.0 { on_true / time_true } else { 0.0 },
+        false_on_fraction: if time_false > 0.0 { on_false / time_false } else { 0.0 },
+    }
+}
+
+/// Posterior segments from the first period start to the last period end
+pub fn replay(
+    history: &HashMap<String, Vec<HAHistoryEntry>>,
+    periods: &[TimePeriod],
+    rows: &[EntityProbability],
+    prior: f64,
+) -> Vec<Segment> {
+    let grid = observation_grid(history, periods, &observations_from_results(rows));
+    let prior_log_odds = posterior::prior_log_odds(prior);
+
+    (0..grid.slots.len())
+        .map(|i| {
+            let log_odds = grid.log_ratios.iter().fold(prior_log_odds, |log_odds, ratios| log_odds + ratios[i]);
+            grid.segment(i, posterior::posterior_from_log_odds(log_odds))
+        })
+        .collect()
+}
+
+/// Time slots shared by all observations, between consecutive changes and period boundaries
+pub struct ObservationGrid {
+    /// Start, length and TRUE weight of the labeled period covering each slot
+    pub slots: Vec<(i64, i64, Option<f64>)>,
+    /// Log-likelihood ratio each observation adds in each slot (0 when HA skips it)
+    pub log_ratios: Vec<Vec<f64>>,
+}
+
+impl ObservationGrid {
+    pub fn segment(&self, index: usize, posterior: f64) -> Segment {
+        let (start, length, label) = self.slots[index];
+        Segment {
+            start,
+            length,
+            posterior,
+            label,
+        }
+    }
+}
+
+/// Lay the observations out on slots from the first period start to the last period end
+pub fn observation_grid(
+    history: &HashMap<String, Vec<HAHistoryEntry>>,
+    periods: &[TimePeriod],
+    observations: &[Observation],
+) -> ObservationGrid {
+    let timelines: Vec<(&Observation, ObservationTimeline)> = observations
+        .iter()
+        .map(|observation| {
+            let mut timeline: ObservationTimeline = history
+                .get(&observation.entity_id)
+                .map(Vec::as_slice)
+                .unwrap_or(&[])
+                .iter()
+                .map(|entry| (entry.last_changed.millis(), observation.is_met(&entry.state)))
+                .collect();
+            timeline.sort_by_key(|&(time, _)| time);
+            (observation, timeline)
+        })
+        .collect();
+
+    let engine = TimelineEngine::new(periods);
+    let Some((range_start, range_end)) = engine.span() else {
+        return ObservationGrid {
+            slots: Vec::new(),
+            log_ratios: vec![Vec::new(); observations.len()],
+        };
+    };
+
+    let mut times: Vec<i64> = timelines
+        .iter()
+        .flat_map(|(_, timeline)| timeline.iter().map(|&(time, _)| time))
+        .chain(engine.boundaries())
+        .filter(|&time| time > range_start && time < range_end)
+        .chain(std::iter::once(range_start))
+        .collect();
+    times.sort_unstable();
+    times.dedup();
+
+    let slots = times
+        .iter()
+        .enumerate()
+        .map(|(i, &time)| {
+            let length = times.get(i + 1).copied().unwrap_or(range_end) - time;
+            (time, length, engine.period_at(time).map(true_weight))
+        })
+        .collect();
+    let log_ratios = timelines
+        .iter()
+        .map(|(observation, timeline)| {
+            let (if_met, if_unmet) = posterior::log_likelihood_ratios(observation);
+            times
+                .iter()
+                .map(|&time| match value_at(timeline, time).copied().flatten() {
+                    Some(true) => if_met,
+                    Some(false) => if_unmet,
+                    None => 0.0,
+                })
+                .collect()
+        })
+        .collect();
+
+    ObservationGrid { slots, log_ratios }
+}
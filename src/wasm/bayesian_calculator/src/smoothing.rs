@@ -0,0 +1,77 @@
+use crate::types::ProbabilityInterval;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// How raw time-share probabilities are kept away from 0 and 1
+///
+/// HA rejects observations with a probability of exactly 0 or 1, and shares measured
+/// over only a couple of periods are far less certain than they look.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Tsify)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum Smoothing {
+    /// Cut off at fixed bounds regardless of how much data backs the estimate
+    Clamp { min: f64, max: f64 },
+    /// Add `k` pseudo-periods to each outcome, treating the share as measured over the
+    /// class's labeled periods (`k = 1` is Laplace smoothing)
+    AddK { k: f64 },
+    /// Use the centre of the Wilson score interval at `z` standard deviations, which
+    /// moves estimates from few periods toward 0.5
+    Wilson { z: f64 },
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Smoothing::Clamp { min: 0.01, max: 0.99 }
+    }
+}
+
+impl Smoothing {
+    /// Smooth a probability measured over `periods` labeled periods of its class
+    ///
+    /// Without any periods there is no evidence either way and the data-dependent
+    /// strategies return 0.5.
+    pub fn apply(&self, probability: f64, periods: usize) -> f64 {
+        let n = periods as f64;
+        match *self {
+            // max/min instead of clamp so inverted bounds cannot panic
+            Smoothing::Clamp { min, max } => probability.max(min).min(max),
+            Smoothing::AddK { k } => {
+                let k = k.max(0.0);
+                if n + 2.0 * k > 0.0 {
+                    (probability * n + k) / (n + 2.0 * k)
+                } else {
+                    0.5
+                }
+            }
+            Smoothing::Wilson { z } => {
+                if n > 0.0 {
+                    let z2 = z * z;
+                    (probability + z2 / (2.0 * n)) / (1.0 + z2 / n)
+                } else {
+                    0.5
+                }
+            }
+        }
+    }
+}
+
+/// Wilson score interval of a probability measured over `periods` labeled periods
+///
+/// The interval stays inside [0, 1] and is widest with few periods; without any it
+/// is the whole range.
+pub fn wilson_interval(probability: f64, periods: usize, z: f64) -> ProbabilityInterval {
+    if periods == 0 {
+        return ProbabilityInterval { lower: 0.0, upper: 1.0 };
+    }
+
+    let (n, z2) = (periods as f64, z * z);
+    let denominator = 1.0 + z2 / n;
+    let centre = (probability + z2 / (2.0 * n)) / denominator;
+    let half_width = z * (probability * (1.0 - probability) / n + z2 / (4.0 * n * n)).max(0.0).sqrt() / denominator;
+
+    ProbabilityInterval {
+        lower: (centre - half_width).max(0.0),
+        upper: (centre + half_width).min(1.0),
+    }
+}
@@ -0,0 +1,167 @@
+use crate::filter::matches_glob;
+use crate::types::StateDurationStats;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// User-defined bucket of states that mean the same thing, e.g. `playing` and
+/// `paused` for a media player
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct StateGroup {
+    /// Entity ids or glob patterns (`media_player.*`) the bucket applies to
+    pub entities: Vec<String>,
+    /// States or glob patterns in the bucket; `*` takes every state no earlier
+    /// bucket claimed
+    pub states: Vec<String>,
+    /// Row label; the member states joined by `-or-` when unset
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Label used for a row covering several merged states
+pub fn group_label(states: &[String]) -> String {
+    states.join("-or-")
+}
+
+/// Sort an entity's states into the user's buckets as (label, members)
+///
+/// Buckets are tried in order and a state joins the first that matches it; states
+/// no bucket matches stay on their own under their own name. Members are ordered
+/// by total duration, largest first.
+pub fn user_buckets(
+    entity_id: &str,
+    state_stats: &FxHashMap<String, StateDurationStats>,
+    groups: &[StateGroup],
+) -> Vec<(String, Vec<String>)> {
+    let groups: Vec<&StateGroup> = groups
+        .iter()
+        .filter(|group| group.entities.iter().any(|pattern| matches_glob(pattern, entity_id)))
+        .collect();
+
+    let mut states: Vec<(&String, &StateDurationStats)> = state_stats.iter().collect();
+    states.sort_by(|a, b| {
+        let total = |s: &StateDurationStats| s.true_duration + s.false_duration;
+        total(b.1).cmp(&total(a.1)).then_with(|| a.0.cmp(b.0))
+    });
+
+    let mut members: Vec<Vec<String>> = vec![Vec::new(); groups.len()];
+    let mut buckets = Vec::new();
+    for (state, _) in states {
+        let bucket = groups
+            .iter()
+            .position(|group| group.states.iter().any(|pattern| matches_glob(pattern, state)));
+        match bucket {
+            Some(index) => members[index].push(state.clone()),
+            None => buckets.push((state.clone(), vec![state.clone()])),
+        }
+    }
+
+    for (group, members) in groups.iter().zip(members) {
+        if !members.is_empty() {
+            let label = group.name.clone().unwrap_or_else(|| group_label(&members));
+            buckets.push((label, members));
+        }
+    }
+    buckets
+}
+
+/// Merge states whose TRUE/FALSE split is indistinguishable under a minimum description length criterion
+///
+/// Each state's evidence is measured in periods' worth of time: its share of TRUE
+/// time times the number of TRUE periods, and likewise for FALSE. Describing the
+/// class labels given the state costs the binary entropy of the state's TRUE/FALSE
+/// split per unit of evidence, plus half a log of the total evidence per state for
+/// its parameter. Pairs are merged greedily, best saving first, while merging does
+/// not lengthen the description. Returns the groups with members ordered by total
+/// duration, largest first.
+pub fn mdl_groups(
+    state_stats: &FxHashMap<String, StateDurationStats>,
+    total_true_periods: usize,
+    total_false_periods: usize,
+) -> Vec<Vec<String>> {
+    let total_true: i64 = state_stats.values().map(|s| s.true_duration).sum();
+    let total_false: i64 = state_stats.values().map(|s| s.false_duration).sum();
+
+    let mut states: Vec<(&String, &StateDurationStats)> = state_stats.iter().collect();
+    states.sort_by(|a, b| {
+        let total = |s: &StateDurationStats| s.true_duration + s.false_duration;
+        total(b.1).cmp(&total(a.1)).then_with(|| a.0.cmp(b.0))
+    });
+
+    let evidence = |duration: i64, total: i64, periods: usize| {
+        if total > 0 { duration as f64 / total as f64 * periods as f64 } else { 0.0 }
+    };
+    let mut groups: Vec<(Vec<String>, f64, f64)> = states
+        .into_iter()
+        .map(|(state, stats)| {
+            (
+                vec![state.clone()],
+                evidence(stats.true_duration, total_true, total_true_periods),
+                evidence(stats.false_duration, total_false, total_false_periods),
+            )
+        })
+        .collect();
+
+    let parameter_cost = 0.5 * libm::log2((total_true_periods + total_false_periods).max(2) as f64);
+
+    loop {
+        let mut best: Option<(usize, usize, f64)> = None;
+        for i in 0..groups.len() {
+            for j in (i + 1)..groups.len() {
+                let (_, true_i, false_i) = &groups[i];
+                let (_, true_j, false_j) = &groups[j];
+                let separate = data_cost(*true_i, *false_i) + data_cost(*true_j, *false_j) + 2.0 * parameter_cost;
+                let merged = data_cost(true_i + true_j, false_i + false_j) + parameter_cost;
+                let saving = separate - merged;
+                if saving >= 0.0 && best.is_none_or(|(_, _, s)| saving > s) {
+                    best = Some((i, j, saving));
+                }
+            }
+        }
+
+        let Some((i, j, _)) = best else { break };
+        let (members, true_j, false_j) = groups.remove(j);
+        groups[i].0.extend(members);
+        groups[i].1 += true_j;
+        groups[i].2 += false_j;
+    }
+
+    groups.into_iter().map(|(members, _, _)| members).collect()
+}
+
+/// Bits to encode the class of `true_evidence + false_evidence` units given their split
+fn data_cost(true_evidence: f64, false_evidence: f64) -> f64 {
+    let total = true_evidence + false_evidence;
+    if total <= 0.0 {
+        return 0.0;
+    }
+    [true_evidence, false_evidence]
+        .iter()
+        .filter(|&&n| n > 0.0)
+        .map(|&n| -n * libm::log2(n / total))
+        .sum()
+}
+
+/// Combine the statistics of each (label, members) group under its label
+///
+/// Period counts take the largest member's, since the members' periods may overlap.
+pub fn merge_stats(
+    state_stats: &FxHashMap<String, StateDurationStats>,
+    groups: &[(String, Vec<String>)],
+) -> FxHashMap<String, StateDurationStats> {
+    groups
+        .iter()
+        .map(|(label, members)| {
+            let mut merged = StateDurationStats::default();
+            for stats in members.iter().filter_map(|state| state_stats.get(state)) {
+                merged.true_duration += stats.true_duration;
+                merged.false_duration += stats.false_duration;
+                merged.true_periods = merged.true_periods.max(stats.true_periods);
+                merged.false_periods = merged.false_periods.max(stats.false_periods);
+            }
+            (label.clone(), merged)
+        })
+        .collect()
+}
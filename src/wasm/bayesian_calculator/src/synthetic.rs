@@ -0,0 +1,304 @@
+use crate::rng::SplitMix64;
+use crate::types::{HAHistoryEntry, TimePeriod, Timestamp};
+use crate::time;
+use chrono::{DateTime, Datelike, Duration, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+const HOUR_MS: i64 = 3_600_000;
+const DAY_MS: i64 = 24 * HOUR_MS;
+
+/// Daily window during which the synthetic target is TRUE
+///
+/// Hours are local wall-clock time in the config's `timezone`. `end_hour` at or
+/// before `start_hour` wraps past midnight (e.g. 22 to 6).
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ScheduleWindow {
+    pub start_hour: f64,
+    pub end_hour: f64,
+    #[serde(default)]
+    pub weekdays_only: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(tag = "type", rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum SyntheticSensorKind {
+    /// `on`/`off` sensor resampled every step with a class-dependent `on` probability
+    #[serde(rename_all = "camelCase")]
+    Binary {
+        on_probability_true: f64,
+        on_probability_false: f64,
+    },
+    /// Class-dependent mean plus Gaussian noise, reported every step
+    #[serde(rename_all = "camelCase")]
+    Numeric {
+        mean_true: f64,
+        mean_false: f64,
+        noise: f64,
+        decimals: u32,
+    },
+    /// Picks from the class's state list, or from the other class's list with probability `noise`
+    #[serde(rename_all = "camelCase")]
+    Categorical {
+        states_true: Vec<String>,
+        states_false: Vec<String>,
+        noise: f64,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SyntheticSensor {
+    pub entity_id: String,
+    pub kind: SyntheticSensorKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SyntheticConfig {
+    pub seed: u64,
+    /// ISO 8601 start of the generated range
+    pub start: String,
+    pub days: u32,
+    pub step_minutes: u32,
+    /// IANA time zone the schedule windows are expanded in
+    pub timezone: String,
+    pub schedule: Vec<ScheduleWindow>,
+    /// Standard deviation applied to each window's start and end
+    pub schedule_jitter_minutes: f64,
+    /// Chance a scheduled window is skipped entirely (e.g. away that evening)
+    pub skip_probability: f64,
+    pub sensors: Vec<SyntheticSensor>,
+}
+
+impl Default for SyntheticConfig {
+    fn default() -> Self {
+        Self {
+            seed: 42,
+            start: "2024-01-01T00:00:00Z".to_string(),
+            days: 7,
+            step_minutes: 5,
+            timezone: "UTC".to_string(),
+            schedule: vec![ScheduleWindow {
+                start_hour: 18.0,
+                end_hour: 23.0,
+                weekdays_only: false,
+            }],
+            schedule_jitter_minutes: 20.0,
+            skip_probability: 0.1,
+            sensors: vec![
+                SyntheticSensor {
+                    entity_id: "binary_sensor.living_room_motion".to_string(),
+                    kind: SyntheticSensorKind::Binary {
+                        on_probability_true: 0.6,
+                        on_probability_false: 0.05,
+                    },
+                },
+                SyntheticSensor {
+                    entity_id: "sensor.living_room_co2".to_string(),
+                    kind: SyntheticSensorKind::Numeric {
+                        mean_true: 900.0,
+                        mean_false: 480.0,
+                        noise: 120.0,
+                        decimals: 0,
+                    },
+                },
+                SyntheticSensor {
+                    entity_id: "media_player.living_room_tv".to_string(),
+                    kind: SyntheticSensorKind::Categorical {
+                        states_true: vec!["playing".to_string(), "paused".to_string()],
+                        states_false: vec!["off".to_string(), "idle".to_string()],
+                        noise: 0.1,
+                    },
+                },
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SyntheticDataset {
+    pub history: std::collections::HashMap<String, Vec<HAHistoryEntry>>,
+    pub periods: Vec<TimePeriod>,
+}
+
+/// Generate labeled periods and matching sensor histories from a config
+pub fn generate(config: &SyntheticConfig) -> Result<SyntheticDataset, String> {
+    let start = DateTime::parse_from_rfc3339(&config.start)
+        .map_err(|e| format!("Invalid start '{}': {}", config.start, e))?
+        .with_timezone(&Utc);
+
+    if config.days == 0 || config.step_minutes == 0 {
+        return Err("days and stepMinutes must be greater than zero".to_string());
+    }
+
+    let zone = time::parse_zone(&config.timezone)?;
+    let mut rng = SplitMix64::new(config.seed);
+    let range_start = start.timestamp_millis();
+    let range_end = range_start + config.days as i64 * DAY_MS;
+
+    let true_intervals = schedule_intervals(config, zone, range_start, range_end, &mut rng);
+    let periods = label_periods(&true_intervals, range_start, range_end);
+
+    let step = config.step_minutes as i64 * 60_000;
+    let mut history = std::collections::HashMap::new();
+
+    for sensor in &config.sensors {
+        let mut entries: Vec<HAHistoryEntry> = Vec::new();
+        let mut time = range_start;
+
+        while time < range_end {
+            let is_true = is_within(&true_intervals, time);
+            let state = sample_state(&sensor.kind, is_true, &mut rng);
+
+            if entries.last().is_none_or(|last| last.state != state) {
+                let timestamp = Timestamp::Iso(time::format_millis(time));
+                entries.push(HAHistoryEntry {
+                    state,
+                    last_changed: timestamp.clone(),
+                    last_updated: timestamp,
+                    attributes: None,
+                });
+            }
+
+            time += step;
+        }
+
+        history.insert(sensor.entity_id.clone(), entries);
+    }
+
+    Ok(SyntheticDataset { history, periods })
+}
+
+/// Jittered, merged TRUE intervals for every scheduled window in the range
+///
+/// Windows are placed on local calendar days, so they keep their wall-clock hours
+/// across DST transitions instead of drifting by an hour on 23- and 25-hour days.
+fn schedule_intervals(
+    config: &SyntheticConfig,
+    zone: Tz,
+    range_start: i64,
+    range_end: i64,
+    rng: &mut SplitMix64,
+) -> Vec<(i64, i64)> {
+    let jitter = config.schedule_jitter_minutes * 60_000.0;
+    let first_date = time::local_date(zone, range_start);
+    let mut intervals = Vec::new();
+
+    for day in 0..config.days as i64 {
+        let date = first_date + Duration::days(day);
+        let is_weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+
+        for window in &config.schedule {
+            if window.weekdays_only && is_weekend {
+                continue;
+            }
+            if rng.next_f64() < config.skip_probability {
+                continue;
+            }
+
+            let mut end_hour = window.end_hour;
+            if end_hour <= window.start_hour {
+                end_hour += 24.0;
+            }
+
+            let window_start = time::local_instant(zone, date, window.start_hour) + (rng.normal() * jitter) as i64;
+            let window_end = time::local_instant(zone, date, end_hour) + (rng.normal() * jitter) as i64;
+            let (window_start, window_end) = (window_start.max(range_start), window_end.min(range_end));
+
+            if window_end > window_start {
+                intervals.push((window_start, window_end));
+            }
+        }
+    }
+
+    intervals.sort_unstable();
+
+    let mut merged: Vec<(i64, i64)> = Vec::with_capacity(intervals.len());
+    for (start, end) in intervals {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    merged
+}
+
+/// TRUE periods for each interval and FALSE periods for the gaps between them
+fn label_periods(true_intervals: &[(i64, i64)], range_start: i64, range_end: i64) -> Vec<TimePeriod> {
+    let mut periods = Vec::new();
+    let mut cursor = range_start;
+
+    for &(start, end) in true_intervals {
+        if start > cursor {
+            periods.push(make_period(periods.len(), cursor, start, false));
+        }
+        periods.push(make_period(periods.len(), start, end, true));
+        cursor = end;
+    }
+
+    if range_end > cursor {
+        periods.push(make_period(periods.len(), cursor, range_end, false));
+    }
+
+    periods
+}
+
+fn make_period(index: usize, start: i64, end: i64, is_true_period: bool) -> TimePeriod {
+    TimePeriod {
+        id: format!("synthetic-{}", index),
+        start: Timestamp::Iso(time::format_millis(start)),
+        end: Timestamp::Iso(time::format_millis(end)),
+        is_true_period,
+        label: Some(if is_true_period { "synthetic TRUE" } else { "synthetic FALSE" }.to_string()),
+        class: None,
+        confidence: None,
+    }
+}
+
+fn sample_state(kind: &SyntheticSensorKind, is_true: bool, rng: &mut SplitMix64) -> String {
+    match kind {
+        SyntheticSensorKind::Binary {
+            on_probability_true,
+            on_probability_false,
+        } => {
+            let on_probability = if is_true { *on_probability_true } else { *on_probability_false };
+            if rng.next_f64() < on_probability { "on" } else { "off" }.to_string()
+        }
+        SyntheticSensorKind::Numeric {
+            mean_true,
+            mean_false,
+            noise,
+            decimals,
+        } => {
+            let mean = if is_true { *mean_true } else { *mean_false };
+            format!("{:.*}", *decimals as usize, mean + rng.normal() * noise)
+        }
+        SyntheticSensorKind::Categorical {
+            states_true,
+            states_false,
+            noise,
+        } => {
+            let flip = rng.next_f64() < *noise;
+            let states = if is_true != flip { states_true } else { states_false };
+            states
+                .get(rng.index(states.len()))
+                .cloned()
+                .unwrap_or_else(|| "unknown".to_string())
+        }
+    }
+}
+
+fn is_within(intervals: &[(i64, i64)], time: i64) -> bool {
+    let index = intervals.partition_point(|&(start, _)| start <= time);
+    index > 0 && time < intervals[index - 1].1
+}
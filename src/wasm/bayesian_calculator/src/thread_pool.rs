@@ -0,0 +1,66 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use wasm_bindgen::prelude::*;
+
+/// Pool threads handed to `spawn_worker` but not yet claimed by `run_pool_worker`,
+/// by token
+static PENDING_THREADS: Mutex<Vec<(usize, rayon::ThreadBuilder)>> = Mutex::new(Vec::new());
+static NEXT_TOKEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Start the global rayon pool using a caller-provided worker spawner
+///
+/// `init_thread_pool` from wasm-bindgen-rayon only knows how to start browser Web
+/// Workers. This path lets any host provide its own threads, e.g. Node's
+/// `worker_threads`: `spawn_worker` is called once per pool thread with an opaque
+/// token, and the spawned worker must instantiate this module with the same
+/// `WebAssembly.Module` and shared `WebAssembly.Memory` and then call
+/// `run_pool_worker(token)`. The thread count is always explicit (e.g.
+/// `os.availableParallelism()` under Node) so nothing depends on
+/// `navigator.hardwareConcurrency`.
+#[wasm_bindgen]
+pub fn init_thread_pool_with_spawner(
+    num_threads: usize,
+    spawn_worker: js_sys::Function,
+) -> Result<(), JsValue> {
+    if num_threads == 0 {
+        return Err(JsValue::from_str("Thread pool needs at least one thread"));
+    }
+
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .spawn_handler(move |thread| {
+            let token = NEXT_TOKEN.fetch_add(1, Ordering::Relaxed);
+            pending_threads().push((token, thread));
+            spawn_worker
+                .call1(&JsValue::NULL, &JsValue::from(token))
+                .map(|_| ())
+                .map_err(|e| {
+                    // Nobody will claim the thread, so drop it here
+                    take_thread(token);
+                    std::io::Error::other(format!("Failed to spawn worker: {:?}", e))
+                })
+        })
+        .build_global()
+        .map_err(|e| JsValue::from_str(&format!("Failed to start thread pool: {}", e)))
+}
+
+/// Entry point for a worker spawned by `init_thread_pool_with_spawner`
+///
+/// Blocks for the lifetime of the pool, running rayon jobs on this thread. Fails for
+/// a token `spawn_worker` was not given or that another worker already claimed.
+#[wasm_bindgen]
+pub fn run_pool_worker(token: usize) -> Result<(), JsValue> {
+    let thread = take_thread(token).ok_or_else(|| JsValue::from_str(&format!("No pool thread for token {}", token)))?;
+    thread.run();
+    Ok(())
+}
+
+fn pending_threads() -> std::sync::MutexGuard<'static, Vec<(usize, rayon::ThreadBuilder)>> {
+    PENDING_THREADS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn take_thread(token: usize) -> Option<rayon::ThreadBuilder> {
+    let mut pending = pending_threads();
+    let index = pending.iter().position(|&(pending_token, _)| pending_token == token)?;
+    Some(pending.swap_remove(index).1)
+}
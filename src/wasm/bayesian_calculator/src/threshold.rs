@@ -1,13 +1,37 @@
+use crate::cancellation::CancellationFlag;
+use crate::flapping::{self, HysteresisOptions};
+use crate::scoring::ScoringMetric;
 use crate::sensor_analysis::{NumericStateStats, ValueDuration};
+use rustc_hash::FxHasher;
 use serde::{Deserialize, Serialize};
 use tsify::Tsify;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 
-pub type ThresholdCache = HashMap<String, OptimalThresholds>;
+/// Most cuts whose every pairing is scored as a range under a metric other than the
+/// separation, which has no single-pass range search
+const MAX_METRIC_RANGE_CUTS: usize = 128;
+
+/// Shifts of the chosen thresholds tried by `sensitivity`, as shares of the value range
+const SENSITIVITY_SHIFTS: [f64; 7] = [-0.10, -0.05, -0.01, 0.0, 0.01, 0.05, 0.10];
+
+/// Share of its discrimination a threshold must keep when shifted by 1% of the
+/// value range not to count as razor-edge
+const RAZOR_EDGE_RATIO: f64 = 0.5;
+
+/// Thresholds this weak are noise either way and are not flagged
+const MIN_FLAGGED_DISCRIMINATION: f64 = 0.1;
+
+/// Thresholds found per `cache_key`
+pub type ThresholdCache = HashMap<u64, OptimalThresholds>;
 
 /// Check if a value matches the given thresholds
+///
+/// `above` greater than `below` is a range wrapping around the end of a cyclic
+/// entity's values, matching either side.
 pub fn value_matches_thresholds(value: f64, thresholds: &OptimalThresholds) -> bool {
     match (thresholds.above, thresholds.below) {
+        (Some(above), Some(below)) if above > below => value > above || value <= below,
         (Some(above), Some(below)) => value > above && value <= below,
         (Some(above), None) => value > above,
         (None, Some(below)) => value <= below,
@@ -18,6 +42,7 @@ pub fn value_matches_thresholds(value: f64, thresholds: &OptimalThresholds) -> b
 /// Format threshold description for display
 pub fn format_threshold_description(thresholds: &OptimalThresholds) -> String {
     match (thresholds.above, thresholds.below) {
+        (Some(above), Some(below)) if above > below => format!("> {:.2} or <= {:.2}", above, below),
         (Some(above), Some(below)) => format!("{:.2} < value <= {:.2}", above, below),
         (Some(above), None) => format!("> {:.2}", above),
         (None, Some(below)) => format!("<= {:.2}", below),
@@ -31,245 +56,859 @@ pub fn format_threshold_description(thresholds: &OptimalThresholds) -> String {
 pub struct OptimalThresholds {
     pub above: Option<f64>,
     pub below: Option<f64>,
+    /// Margin to keep on either side of the thresholds (e.g. as the `hysteresis` of an
+    /// HA threshold helper) so readings flapping around them do not toggle the
+    /// observation; only set when hysteresis options are given and the sensor flaps there
+    #[serde(default)]
+    pub hysteresis: Option<f64>,
+}
+
+/// One threshold of a ROC curve, reading values above it as TRUE
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct RocPoint {
+    /// `None` at the ends of the curve, where every or no value is above
+    pub threshold: Option<f64>,
+    /// Share of TRUE time above the threshold
+    pub true_positive_rate: f64,
+    /// Share of FALSE time above the threshold
+    pub false_positive_rate: f64,
+}
+
+/// Duration-weighted ROC curve of a numeric entity as its threshold sweeps the values
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct RocCurve {
+    /// From the lowest threshold (everything TRUE) to the highest, thinned to at most
+    /// `max_points` besides the two ends
+    pub points: Vec<RocPoint>,
+    /// Area under the full curve: 1 when higher values always mean TRUE, 0 when they
+    /// always mean FALSE, 0.5 when the value says nothing
+    pub auc: f64,
+}
+
+/// The ROC curve over every distinct split of the values; `None` without TRUE and FALSE time
+pub fn roc_curve(stats: &NumericStateStats, max_points: usize) -> Option<RocCurve> {
+    if !stats.is_numeric || stats.true_chunks.is_empty() || stats.false_chunks.is_empty() {
+        return None;
+    }
+
+    let sorted_true_chunks = SortedDurations::new(&stats.true_chunks);
+    let sorted_false_chunks = SortedDurations::new(&stats.false_chunks);
+    if sorted_true_chunks.total() <= 0 || sorted_false_chunks.total() <= 0 {
+        return None;
+    }
+    let (cuts, class_below) = exact_cuts(&sorted_true_chunks, &sorted_false_chunks);
+
+    let end = |rate: f64| RocPoint {
+        threshold: None,
+        true_positive_rate: rate,
+        false_positive_rate: rate,
+    };
+    let mut curve = vec![end(1.0)];
+    curve.extend(cuts.iter().zip(&class_below).map(|(&cut, &(true_below, false_below))| RocPoint {
+        threshold: Some(cut),
+        true_positive_rate: 1.0 - true_below,
+        false_positive_rate: 1.0 - false_below,
+    }));
+    curve.push(end(0.0));
+
+    let auc = curve
+        .windows(2)
+        .map(|pair| {
+            let width = pair[0].false_positive_rate - pair[1].false_positive_rate;
+            width * (pair[0].true_positive_rate + pair[1].true_positive_rate) / 2.0
+        })
+        .sum();
+
+    let inner = curve.len() - 2;
+    let points = if inner > max_points {
+        let step = inner.div_ceil(max_points.max(1));
+        let mut thinned: Vec<RocPoint> = curve[1..=inner].iter().step_by(step).take(max_points).copied().collect();
+        thinned.insert(0, curve[0]);
+        thinned.push(curve[inner + 1]);
+        thinned
+    } else {
+        curve
+    };
+
+    Some(RocCurve { points, auc })
+}
+
+/// The chosen thresholds shifted together by one share of the value range
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SensitivityPoint {
+    /// Signed share of the value range the thresholds moved by
+    pub shift: f64,
+    /// Unsmoothed share of TRUE and FALSE time matching the shifted thresholds
+    pub prob_given_true: f64,
+    pub prob_given_false: f64,
+    pub discrimination: f64,
+    /// The shifted thresholds under the scoring metric
+    pub score: f64,
+}
+
+/// How the chosen thresholds of a numeric row hold up when moved a little
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ThresholdSensitivity {
+    /// From the largest shift down to the largest shift up, the unshifted thresholds
+    /// in the middle
+    pub points: Vec<SensitivityPoint>,
+    /// A 1% shift either way loses more than half of the discrimination, so the
+    /// threshold sits on a knife edge of the labeled data
+    pub razor_edge: bool,
+}
+
+/// Score the thresholds shifted by ±1%, ±5% and ±10% of the value range; `None`
+/// without TRUE and FALSE time or a value range to shift across
+///
+/// Each shift is two lookups in the running duration totals of each class, like
+/// the threshold search.
+pub fn sensitivity(stats: &NumericStateStats, thresholds: &OptimalThresholds, metric: ScoringMetric) -> Option<ThresholdSensitivity> {
+    if !stats.is_numeric || (thresholds.above.is_none() && thresholds.below.is_none()) {
+        return None;
+    }
+    let sorted_true_chunks = SortedDurations::new(&stats.true_chunks);
+    let sorted_false_chunks = SortedDurations::new(&stats.false_chunks);
+    if sorted_true_chunks.total() <= 0 || sorted_false_chunks.total() <= 0 {
+        return None;
+    }
+
+    let values = |sorted: &SortedDurations| {
+        let values = sorted.values();
+        (values[0], values[values.len() - 1])
+    };
+    let ((true_low, true_high), (false_low, false_high)) = (values(&sorted_true_chunks), values(&sorted_false_chunks));
+    let range = true_high.max(false_high) - true_low.min(false_low);
+    if range <= 0.0 {
+        return None;
+    }
+
+    let points: Vec<SensitivityPoint> = SENSITIVITY_SHIFTS
+        .iter()
+        .map(|&shift| {
+            let shifted = OptimalThresholds {
+                above: thresholds.above.map(|above| above + shift * range),
+                below: thresholds.below.map(|below| below + shift * range),
+                hysteresis: None,
+            };
+            let (p, q) = (
+                sorted_true_chunks.fraction_matching(&shifted),
+                sorted_false_chunks.fraction_matching(&shifted),
+            );
+            SensitivityPoint {
+                shift,
+                prob_given_true: p,
+                prob_given_false: q,
+                discrimination: (p - q).abs(),
+                score: metric.score(p, q),
+            }
+        })
+        .collect();
+
+    let discrimination_at = |shift: f64| {
+        points
+            .iter()
+            .find(|point| point.shift == shift)
+            .map_or(0.0, |point| point.discrimination)
+    };
+    let base = discrimination_at(0.0);
+    let nudged = discrimination_at(-0.01).min(discrimination_at(0.01));
+    let razor_edge = base >= MIN_FLAGGED_DISCRIMINATION && nudged < base * RAZOR_EDGE_RATIO;
+
+    Some(ThresholdSensitivity { points, razor_edge })
+}
+
+/// How thoroughly the threshold search explores candidate cut points
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThresholdSearch {
+    /// Only the evenly spaced grid across the value range, for fast previews
+    Preview,
+    /// Exact: every distinct split of the observed values, cut halfway between
+    /// neighbouring values
+    Full,
+}
+
+/// Declared direction of a numeric entity's relationship with the target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum Monotonicity {
+    /// Higher values make TRUE more likely (e.g. CO2 and occupancy)
+    Increasing,
+    /// Higher values make TRUE less likely
+    Decreasing,
+}
+
+impl Monotonicity {
+    /// +1 when TRUE time should dominate above the threshold, -1 when below
+    fn sign(self) -> f64 {
+        match self {
+            Monotonicity::Increasing => 1.0,
+            Monotonicity::Decreasing => -1.0,
+        }
+    }
 }
 
-pub fn find_optimal_numeric_thresholds(stats: &NumericStateStats) -> OptimalThresholds {
+pub fn find_optimal_numeric_thresholds(
+    stats: &NumericStateStats,
+    direction: Option<Monotonicity>,
+    hysteresis: Option<&HysteresisOptions>,
+    metric: ScoringMetric,
+    cancel: &CancellationFlag,
+) -> Option<OptimalThresholds> {
+    find_numeric_thresholds(stats, ThresholdSearch::Full, direction, hysteresis, metric, cancel)
+}
+
+/// Search for the (above, below) pair that best separates TRUE from FALSE time
+///
+/// The full search is exact: one sweep over the observed values scores every
+/// distinct split of the data, cutting halfway between neighbouring values, and one
+/// more pass finds the best range over all pairs of those cuts. Previews only try an
+/// even grid across the value range.
+///
+/// With a declared `direction` only one-sided thresholds are considered, scored by
+/// signed rather than absolute separation, so the chosen observation always points
+/// the declared way. With `hysteresis` options each cut loses separation for every
+/// time an hour the sensor flaps across it, steering thresholds out of noisy bands,
+/// and the chosen thresholds carry a suggested hysteresis margin.
+///
+/// Under a `metric` other than the separation (`|p - q|`) each one-sided threshold
+/// is scored by that metric, and ranges are the best separating range or any pair
+/// of up to `MAX_METRIC_RANGE_CUTS` evenly spread cuts, whichever scores highest.
+///
+/// For cyclic entities (`stats.cycle`) a range leaning towards FALSE is turned into
+/// its complement, which wraps around the end of the values and separates equally,
+/// so the observation indicates TRUE. Every arc of the circle is either a range or
+/// the complement of one, so this search stays exact.
+///
+/// Returns `None` if `cancel` is raised during the search.
+pub fn find_numeric_thresholds(
+    stats: &NumericStateStats,
+    search: ThresholdSearch,
+    direction: Option<Monotonicity>,
+    hysteresis: Option<&HysteresisOptions>,
+    metric: ScoringMetric,
+    cancel: &CancellationFlag,
+) -> Option<OptimalThresholds> {
     if !stats.is_numeric || stats.true_chunks.is_empty() || stats.false_chunks.is_empty() {
-        return OptimalThresholds {
+        return Some(OptimalThresholds {
             above: None,
             below: None,
+            hysteresis: None,
+        });
+    }
+
+    // Sort once and keep running totals; quantized sensors repeat values heavily
+    let sorted_true_chunks = SortedDurations::new(&stats.true_chunks);
+    let sorted_false_chunks = SortedDurations::new(&stats.false_chunks);
+    let (cuts, class_below) = match search {
+        ThresholdSearch::Full => exact_cuts(&sorted_true_chunks, &sorted_false_chunks),
+        ThresholdSearch::Preview => grid_cuts(stats, &sorted_true_chunks, &sorted_false_chunks),
+    };
+    let below_share: Vec<f64> = class_below.iter().map(|&(true_below, false_below)| true_below - false_below).collect();
+    let penalty = match hysteresis {
+        Some(options) => flapping::cut_penalties(stats, &cuts, options),
+        None => vec![0.0; cuts.len()],
+    };
+    if cancel.is_cancelled() {
+        return None;
+    }
+
+    let separation = matches!(metric, ScoringMetric::AbsoluteDifference | ScoringMetric::YoudensJ);
+    let (mut above, mut below) = match direction {
+        Some(direction) if separation => best_one_sided(&cuts, &below_share, &penalty, direction.sign()),
+        Some(direction) => best_one_sided_by_metric(&cuts, &class_below, &penalty, direction.sign(), metric),
+        None if separation => best_separation(&cuts, &below_share, &penalty, cancel)?,
+        None => {
+            let seed = best_separation(&cuts, &below_share, &penalty, cancel)?;
+            best_by_metric(&cuts, &class_below, &penalty, seed, metric, cancel)?
+        }
+    };
+    if let (Some(_), Some(lower), Some(upper)) = (stats.cycle, above, below) {
+        let lean = sorted_true_chunks.fraction_in(above, below) - sorted_false_chunks.fraction_in(above, below);
+        if lean < 0.0 {
+            (above, below) = (Some(upper), Some(lower));
+        }
+    }
+    Some(OptimalThresholds {
+        above,
+        below,
+        hysteresis: hysteresis.and_then(|options| flapping::hysteresis(stats, [above, below], options)),
+    })
+}
+
+/// Every distinct split of the observed values, with the shares of TRUE and of
+/// FALSE time below each cut
+///
+/// A single merged sweep over both classes' sorted values with running duration
+/// totals, like a CART split on duration-weighted samples. Each cut lies halfway
+/// between two neighbouring values, so no observed value sits on a boundary.
+fn exact_cuts(sorted_true_chunks: &SortedDurations, sorted_false_chunks: &SortedDurations) -> (Vec<f64>, Vec<(f64, f64)>) {
+    let (true_values, false_values) = (sorted_true_chunks.values(), sorted_false_chunks.values());
+    let next_value = |i: usize, j: usize| match (true_values.get(i), false_values.get(j)) {
+        (Some(&a), Some(&b)) => Some(if a.total_cmp(&b).is_le() { a } else { b }),
+        (a, b) => a.or(b).copied(),
+    };
+
+    let mut cuts = Vec::new();
+    let mut below = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while let Some(value) = next_value(i, j) {
+        // total_cmp so a NaN reading still advances the sweep
+        while true_values.get(i).is_some_and(|v| v.total_cmp(&value).is_eq()) {
+            i += 1;
+        }
+        while false_values.get(j).is_some_and(|v| v.total_cmp(&value).is_eq()) {
+            j += 1;
+        }
+        let Some(next) = next_value(i, j) else {
+            break;
         };
+
+        let cut = value + (next - value) / 2.0;
+        if cut.is_finite() {
+            cuts.push(cut);
+            below.push((sorted_true_chunks.fraction_below(i), sorted_false_chunks.fraction_below(j)));
+        }
     }
 
+    (cuts, below)
+}
+
+/// An even 20-step grid across the value range, for fast previews
+fn grid_cuts(
+    stats: &NumericStateStats,
+    sorted_true_chunks: &SortedDurations,
+    sorted_false_chunks: &SortedDurations,
+) -> (Vec<f64>, Vec<(f64, f64)>) {
     let min = stats.min.unwrap_or(0.0);
     let max = stats.max.unwrap_or(100.0);
+    let step = (max - min) / 20.0;
 
-    // Sort chunks once for efficiency
-    let mut sorted_true_chunks = stats.true_chunks.clone();
-    let mut sorted_false_chunks = stats.false_chunks.clone();
-    sorted_true_chunks.sort_by(|a, b| a.value.partial_cmp(&b.value).unwrap());
-    sorted_false_chunks.sort_by(|a, b| a.value.partial_cmp(&b.value).unwrap());
+    let cuts: Vec<f64> = (0..=20).map(|i| min + step * i as f64).collect();
+    let below = cuts
+        .iter()
+        .map(|&cut| (sorted_true_chunks.fraction_in(None, Some(cut)), sorted_false_chunks.fraction_in(None, Some(cut))))
+        .collect();
+    (cuts, below)
+}
 
-    // Generate threshold candidates
-    let mut candidates = Vec::new();
-    
-    // Add all unique values
-    for chunk in &stats.true_chunks {
-        candidates.push(chunk.value);
+/// One-sided threshold at a cut, on the side where TRUE time dominates
+///
+/// Above and below a cut separate equally (above it both shares are 1 minus the
+/// share below), so the side is chosen to make the observation indicate TRUE.
+fn one_sided(cut: f64, below_share: f64) -> (Option<f64>, Option<f64>) {
+    if below_share > 0.0 {
+        (None, Some(cut))
+    } else {
+        (Some(cut), None)
     }
-    for chunk in &stats.false_chunks {
-        candidates.push(chunk.value);
+}
+
+/// Best one-sided threshold or range over every pair of cuts, less each used cut's penalty
+///
+/// One-sided thresholds win ties over ranges, and earlier cuts over later ones.
+/// Ranges are found in a single pass by keeping the lowest `share + penalty` and
+/// highest `share - penalty` seen below each upper bound, which covers all pairs
+/// without scoring each one.
+fn best_separation(
+    cuts: &[f64],
+    below_share: &[f64],
+    penalty: &[f64],
+    cancel: &CancellationFlag,
+) -> Option<(Option<f64>, Option<f64>)> {
+    let mut best_score = f64::NEG_INFINITY;
+    let mut best_pair = (None, None);
+
+    for ((&cut, &share), &penalty) in cuts.iter().zip(below_share).zip(penalty) {
+        if share.abs() - penalty > best_score {
+            best_score = share.abs() - penalty;
+            best_pair = one_sided(cut, share);
+        }
     }
 
-    // Add midpoints between consecutive values
-    let mut all_values: Vec<f64> = candidates.clone();
-    all_values.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    all_values.dedup();
+    let mut lowest: Option<(f64, usize)> = None;
+    let mut highest: Option<(f64, usize)> = None;
+    for (upper, (&share, &penalty)) in below_share.iter().zip(penalty).enumerate() {
+        if cancel.is_cancelled() {
+            return None;
+        }
 
-    for i in 0..all_values.len().saturating_sub(1) {
-        let midpoint = (all_values[i] + all_values[i + 1]) / 2.0;
-        candidates.push(midpoint);
+        let candidates = [
+            lowest.map(|(lowest, lower)| (share - penalty - lowest, lower)),
+            highest.map(|(highest, lower)| (highest - share - penalty, lower)),
+        ];
+        for (score, lower) in candidates.into_iter().flatten() {
+            if score > best_score {
+                best_score = score;
+                best_pair = (Some(cuts[lower]), Some(cuts[upper]));
+            }
+        }
+
+        if lowest.is_none_or(|(lowest, _)| share + penalty < lowest) {
+            lowest = Some((share + penalty, upper));
+        }
+        if highest.is_none_or(|(highest, _)| share - penalty > highest) {
+            highest = Some((share - penalty, upper));
+        }
     }
 
-    // Add evenly spaced values across the range
-    let range = max - min;
-    let step = range / 20.0;
-    for i in 0..=20 {
-        candidates.push(min + (step * i as f64));
+    Some(best_pair)
+}
+
+/// Best one-sided threshold agreeing with `sign`: above for +1, below for -1
+///
+/// TRUE time dominating above a cut is the same as FALSE time dominating below it,
+/// so both forms score `-sign * below_share` and the one pointing the declared way
+/// is reported, less the cut's penalty. Earlier cuts win ties.
+fn best_one_sided(cuts: &[f64], below_share: &[f64], penalty: &[f64], sign: f64) -> (Option<f64>, Option<f64>) {
+    let best = cuts
+        .iter()
+        .zip(below_share)
+        .zip(penalty)
+        .fold(None, |best: Option<(f64, f64)>, ((&cut, &share), &penalty)| {
+            let score = -sign * share - penalty;
+            match best {
+                Some((_, best_score)) if best_score >= score => best,
+                _ => Some((cut, score)),
+            }
+        });
+
+    match best {
+        Some((cut, _)) if sign > 0.0 => (Some(cut), None),
+        Some((cut, _)) => (None, Some(cut)),
+        None => (None, None),
     }
+}
 
-    candidates.sort_by(|a, b| a.partial_cmp(b).unwrap());
-    candidates.dedup();
+/// `best_one_sided` scoring the side each cut points to by `metric`
+///
+/// Cuts where the declared side leans towards FALSE score the metric negated, so
+/// any cut agreeing with `sign` beats them.
+fn best_one_sided_by_metric(
+    cuts: &[f64],
+    below: &[(f64, f64)],
+    penalty: &[f64],
+    sign: f64,
+    metric: ScoringMetric,
+) -> (Option<f64>, Option<f64>) {
+    let best = cuts
+        .iter()
+        .zip(below)
+        .zip(penalty)
+        .fold(None, |best: Option<(f64, f64)>, ((&cut, &(true_below, false_below)), &penalty)| {
+            let (p, q) = if sign > 0.0 {
+                (1.0 - true_below, 1.0 - false_below)
+            } else {
+                (true_below, false_below)
+            };
+            let agrees = if p > q { 1.0 } else { -1.0 };
+            let score = agrees * metric.score(p, q) - penalty;
+            match best {
+                Some((_, best_score)) if best_score >= score => best,
+                _ => Some((cut, score)),
+            }
+        });
 
-    let mut best_score = -1.0;
-    let mut best_thresholds = OptimalThresholds {
-        above: None,
-        below: None,
-    };
+    match best {
+        Some((cut, _)) if sign > 0.0 => (Some(cut), None),
+        Some((cut, _)) => (None, Some(cut)),
+        None => (None, None),
+    }
+}
 
-    // Test above-only thresholds
-    for &threshold in &candidates {
-        let score = calculate_threshold_score(
-            &sorted_true_chunks,
-            &sorted_false_chunks,
-            Some(threshold),
-            None,
-        );
+/// Best one-sided threshold or range by `metric`, less each used cut's penalty
+///
+/// Every one-sided threshold is scored, ranges only between `seed` (the best
+/// separating pair) and every pair of up to `MAX_METRIC_RANGE_CUTS` evenly spread
+/// cuts. One-sided thresholds win ties over ranges.
+fn best_by_metric(
+    cuts: &[f64],
+    below: &[(f64, f64)],
+    penalty: &[f64],
+    seed: (Option<f64>, Option<f64>),
+    metric: ScoringMetric,
+    cancel: &CancellationFlag,
+) -> Option<(Option<f64>, Option<f64>)> {
+    let mut best_score = f64::NEG_INFINITY;
+    let mut best_pair = (None, None);
+
+    for ((&cut, &(true_below, false_below)), &penalty) in cuts.iter().zip(below).zip(penalty) {
+        // The observation below a cut and its absence score the same; report the TRUE side
+        let (p, q) = if true_below > false_below {
+            (true_below, false_below)
+        } else {
+            (1.0 - true_below, 1.0 - false_below)
+        };
+        let score = metric.score(p, q) - penalty;
         if score > best_score {
             best_score = score;
-            best_thresholds = OptimalThresholds {
-                above: Some(threshold),
-                below: None,
-            };
+            best_pair = one_sided(cut, true_below - false_below);
         }
     }
 
-    // Test below-only thresholds
-    for &threshold in &candidates {
-        let score = calculate_threshold_score(
-            &sorted_true_chunks,
-            &sorted_false_chunks,
-            None,
-            Some(threshold),
-        );
+    let index = |cut: Option<f64>| cut.and_then(|cut| cuts.iter().position(|&c| c == cut));
+    let mut ranges: Vec<(usize, usize)> = index(seed.0).zip(index(seed.1)).into_iter().collect();
+    let step = cuts.len().div_ceil(MAX_METRIC_RANGE_CUTS).max(1);
+    let spread: Vec<usize> = (0..cuts.len()).step_by(step).collect();
+    for (i, &lower) in spread.iter().enumerate() {
+        ranges.extend(spread[i + 1..].iter().map(|&upper| (lower, upper)));
+    }
+    if cancel.is_cancelled() {
+        return None;
+    }
+
+    for (lower, upper) in ranges {
+        let (p, q) = (below[upper].0 - below[lower].0, below[upper].1 - below[lower].1);
+        let score = metric.score(p, q) - penalty[lower] - penalty[upper];
         if score > best_score {
             best_score = score;
-            best_thresholds = OptimalThresholds {
-                above: None,
-                below: Some(threshold),
-            };
+            best_pair = (Some(cuts[lower]), Some(cuts[upper]));
         }
     }
 
-    // Test range thresholds (above and below)
-    // Limit to reasonable number of combinations for performance
-    let max_range_tests = 100;
-    let step = ((candidates.len() * candidates.len()) / max_range_tests).max(1);
-    let mut test_count = 0;
+    Some(best_pair)
+}
+
+/// Split the value range into at most `bins` intervals that together separate TRUE
+/// from FALSE time best, in increasing order of value
+///
+/// Scores a binning by the summed |share of TRUE - share of FALSE time| of its bins,
+/// the most any set of `numeric_state` observations on the entity can carry. Bins
+/// are found exactly by dynamic programming over the intervals between neighbouring
+/// cuts: each bin leans towards TRUE or FALSE, and a new bin only pays off where the
+/// lean flips. Fewer bins win ties. Returns `None` if `cancel` is raised.
+pub fn find_numeric_bins(
+    stats: &NumericStateStats,
+    search: ThresholdSearch,
+    bins: usize,
+    cancel: &CancellationFlag,
+) -> Option<Vec<OptimalThresholds>> {
+    if !stats.is_numeric || stats.true_chunks.is_empty() || stats.false_chunks.is_empty() || bins == 0 {
+        return Some(Vec::new());
+    }
+
+    let sorted_true_chunks = SortedDurations::new(&stats.true_chunks);
+    let sorted_false_chunks = SortedDurations::new(&stats.false_chunks);
+    let (cuts, below) = match search {
+        ThresholdSearch::Full => exact_cuts(&sorted_true_chunks, &sorted_false_chunks),
+        ThresholdSearch::Preview => grid_cuts(stats, &sorted_true_chunks, &sorted_false_chunks),
+    };
+    let below_share: Vec<f64> = below.iter().map(|&(true_below, false_below)| true_below - false_below).collect();
+    if cuts.is_empty() {
+        return Some(Vec::new());
+    }
 
-    for i in 0..candidates.len() - 1 {
-        if test_count >= max_range_tests {
-            break;
+    // Share of TRUE minus share of FALSE time in each interval between neighbouring cuts
+    let lean: Vec<f64> = (0..=cuts.len())
+        .map(|i| below_share.get(i).copied().unwrap_or(0.0) - if i > 0 { below_share[i - 1] } else { 0.0 })
+        .collect();
+
+    // State `2 * (bins used - 1) + side`, side 0 leaning TRUE and 1 leaning FALSE
+    let states = 2 * bins;
+    let sign = |state: usize| if state.is_multiple_of(2) { 1.0 } else { -1.0 };
+    let mut score: Vec<f64> = (0..states)
+        .map(|state| if state < 2 { sign(state) * lean[0] } else { f64::NEG_INFINITY })
+        .collect();
+    // For every interval after the first, the state each state was reached from
+    let mut came_from: Vec<Vec<usize>> = Vec::with_capacity(lean.len() - 1);
+
+    for &share in &lean[1..] {
+        if cancel.is_cancelled() {
+            return None;
         }
-        for j in (i + 1..candidates.len()).step_by(step) {
-            let above = candidates[i];
-            let below = candidates[j];
-            let score = calculate_threshold_score(
-                &sorted_true_chunks,
-                &sorted_false_chunks,
-                Some(above),
-                Some(below),
-            );
-            if score > best_score {
-                best_score = score;
-                best_thresholds = OptimalThresholds {
-                    above: Some(above),
-                    below: Some(below),
-                };
-            }
-            test_count += 1;
-            if test_count >= max_range_tests {
-                break;
+
+        let mut next = vec![f64::NEG_INFINITY; states];
+        let mut from = vec![0; states];
+        for state in 0..states {
+            let (mut best, mut best_from) = (score[state], state);
+            // Opening a bin means flipping the lean of the one before it
+            if state >= 2 && score[state - 1 - 2 * (state % 2)] > best {
+                best_from = state - 1 - 2 * (state % 2);
+                best = score[best_from];
             }
+            next[state] = best + sign(state) * share;
+            from[state] = best_from;
         }
+        score = next;
+        came_from.push(from);
     }
 
-    best_thresholds
+    let mut state = (0..states).fold(0, |best, state| if score[state] > score[best] { state } else { best });
+    let mut boundaries = Vec::new();
+    for (interval, from) in came_from.iter().enumerate().rev() {
+        if from[state] != state {
+            boundaries.push(cuts[interval]);
+            state = from[state];
+        }
+    }
+    boundaries.reverse();
+
+    let bounds: Vec<Option<f64>> = std::iter::once(None)
+        .chain(boundaries.into_iter().map(Some))
+        .chain(std::iter::once(None))
+        .collect();
+    Some(
+        bounds
+            .windows(2)
+            .map(|pair| OptimalThresholds {
+                above: pair[0],
+                below: pair[1],
+                hysteresis: None,
+            })
+            .collect(),
+    )
 }
 
-fn calculate_threshold_score(
-    sorted_true_chunks: &[ValueDuration],
-    sorted_false_chunks: &[ValueDuration],
-    above: Option<f64>,
-    below: Option<f64>,
-) -> f64 {
-    let true_stats = calculate_chunks_in_range(sorted_true_chunks, above, below);
-    let false_stats = calculate_chunks_in_range(sorted_false_chunks, above, below);
-
-    let true_pct = if true_stats.total_duration > 0 {
-        true_stats.matching_duration as f64 / true_stats.total_duration as f64
-    } else {
-        0.0
-    };
+/// Best one-sided separation in the declared direction and in the opposite one
+///
+/// Returns `Some((consistent, opposite))` when the data separates better the
+/// opposite way, i.e. the entity violates the declared direction.
+pub fn direction_violation(stats: &NumericStateStats, direction: Monotonicity) -> Option<(f64, f64)> {
+    if !stats.is_numeric || stats.true_chunks.is_empty() || stats.false_chunks.is_empty() {
+        return None;
+    }
 
-    let false_pct = if false_stats.total_duration > 0 {
-        false_stats.matching_duration as f64 / false_stats.total_duration as f64
-    } else {
-        0.0
-    };
+    let sorted_true_chunks = SortedDurations::new(&stats.true_chunks);
+    let sorted_false_chunks = SortedDurations::new(&stats.false_chunks);
+    let (_, below) = exact_cuts(&sorted_true_chunks, &sorted_false_chunks);
+    let below_share: Vec<f64> = below.iter().map(|&(true_below, false_below)| true_below - false_below).collect();
 
-    (true_pct - false_pct).abs()
-}
+    let best = |sign: f64| below_share.iter().map(|&share| -sign * share).fold(0.0, f64::max);
+    let consistent = best(direction.sign());
+    let opposite = best(-direction.sign());
 
-struct ChunkStats {
-    matching_duration: i64,
-    total_duration: i64,
+    (opposite > consistent).then_some((consistent, opposite))
 }
 
-fn calculate_chunks_in_range(
-    sorted_chunks: &[ValueDuration],
-    above: Option<f64>,
-    below: Option<f64>,
-) -> ChunkStats {
-    let mut total_duration = 0i64;
-    let mut matching_duration = 0i64;
-
-    // Use binary search to find range boundaries
-    let start_idx = if let Some(threshold) = above {
-        binary_search_first_above(sorted_chunks, threshold)
-    } else {
-        0
-    };
+/// Entities with at least this many chunks are sorted across the rayon pool
+#[cfg(feature = "parallel")]
+const PARALLEL_CHUNK_THRESHOLD: usize = 10_000;
+
+/// Chunk indices in increasing order of value
+#[cfg(feature = "parallel")]
+fn value_order(chunks: &[ValueDuration]) -> Vec<usize> {
+    use rayon::prelude::*;
 
-    let end_idx = if let Some(threshold) = below {
-        binary_search_last_below(sorted_chunks, threshold)
+    let mut order: Vec<usize> = (0..chunks.len()).collect();
+    if chunks.len() >= PARALLEL_CHUNK_THRESHOLD {
+        order.par_sort_unstable_by(|&a, &b| chunks[a].value.total_cmp(&chunks[b].value));
     } else {
-        sorted_chunks.len()
-    };
+        order.sort_unstable_by(|&a, &b| chunks[a].value.total_cmp(&chunks[b].value));
+    }
+    order
+}
+
+#[cfg(not(feature = "parallel"))]
+fn value_order(chunks: &[ValueDuration]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..chunks.len()).collect();
+    order.sort_unstable_by(|&a, &b| chunks[a].value.total_cmp(&chunks[b].value));
+    order
+}
+
+/// Chunk durations merged per distinct value and sorted by it, with running totals
+///
+/// Built once per search; the duration inside any value range is then two binary
+/// searches and a subtraction instead of a pass over every chunk.
+pub struct SortedDurations {
+    values: Vec<f64>,
+    /// `cumulative[i]` is the duration of the first `i` values
+    cumulative: Vec<i64>,
+}
 
-    for (i, chunk) in sorted_chunks.iter().enumerate() {
-        total_duration += chunk.duration;
-        
-        if i >= start_idx && i < end_idx {
-            matching_duration += chunk.duration;
+impl SortedDurations {
+    pub fn new(chunks: &[ValueDuration]) -> Self {
+        let order = value_order(chunks);
+
+        let mut values: Vec<f64> = Vec::new();
+        let mut cumulative = vec![0];
+        for index in order {
+            let chunk = &chunks[index];
+            let total = cumulative.last().copied().unwrap_or(0) + chunk.duration;
+            if values.last().is_some_and(|last: &f64| last.total_cmp(&chunk.value).is_eq()) {
+                *cumulative.last_mut().unwrap() = total;
+            } else {
+                values.push(chunk.value);
+                cumulative.push(total);
+            }
         }
+
+        Self { values, cumulative }
     }
 
-    ChunkStats {
-        matching_duration,
-        total_duration,
+    /// Distinct values in increasing order
+    pub fn values(&self) -> &[f64] {
+        &self.values
     }
-}
 
-fn binary_search_first_above(chunks: &[ValueDuration], threshold: f64) -> usize {
-    let mut left = 0;
-    let mut right = chunks.len();
+    fn total(&self) -> i64 {
+        self.cumulative[self.values.len()]
+    }
 
-    while left < right {
-        let mid = (left + right) / 2;
-        if chunks[mid].value <= threshold {
-            left = mid + 1;
+    /// Share of the total duration spent at the first `count` values; 0 without any duration
+    fn fraction_below(&self, count: usize) -> f64 {
+        let total = self.total();
+        if total > 0 {
+            self.cumulative[count] as f64 / total as f64
         } else {
-            right = mid;
+            0.0
         }
     }
 
-    left
-}
+    /// Duration spent at values in `(above, below]`, either bound being optional
+    fn duration_in(&self, above: Option<f64>, below: Option<f64>) -> i64 {
+        let start = above.map_or(0, |threshold| self.values.partition_point(|&value| value <= threshold));
+        let end = below.map_or(self.values.len(), |threshold| self.values.partition_point(|&value| value <= threshold));
+        self.cumulative[end.max(start)] - self.cumulative[start]
+    }
 
-fn binary_search_last_below(chunks: &[ValueDuration], threshold: f64) -> usize {
-    let mut left = 0;
-    let mut right = chunks.len();
+    /// Share of the total duration spent at values matching `thresholds`, including
+    /// ranges wrapping around the end of a cyclic entity's values
+    fn fraction_matching(&self, thresholds: &OptimalThresholds) -> f64 {
+        match (thresholds.above, thresholds.below) {
+            (Some(above), Some(below)) if above > below => 1.0 - self.fraction_in(Some(below), Some(above)),
+            (None, None) => 0.0,
+            (above, below) => self.fraction_in(above, below),
+        }
+    }
 
-    while left < right {
-        let mid = (left + right) / 2;
-        if chunks[mid].value <= threshold {
-            left = mid + 1;
+    /// Share of the total duration spent at values in `(above, below]`; 0 without any duration
+    fn fraction_in(&self, above: Option<f64>, below: Option<f64>) -> f64 {
+        let total = self.total();
+        if total > 0 {
+            self.duration_in(above, below) as f64 / total as f64
         } else {
-            right = mid;
+            0.0
         }
     }
+}
 
-    left
+/// Hash of every chunk, the declared direction, the hysteresis options and the
+/// scoring metric, keying the threshold cache
+///
+/// The same chunks give different thresholds under a declared direction or flapping
+/// penalty, and chunk data changes when new periods are folded into existing stats.
+pub fn cache_key(
+    stats: &NumericStateStats,
+    direction: Option<Monotonicity>,
+    hysteresis: Option<&HysteresisOptions>,
+    metric: ScoringMetric,
+) -> u64 {
+    let mut hasher = FxHasher::default();
+    for chunks in [&stats.true_chunks, &stats.false_chunks] {
+        chunks.len().hash(&mut hasher);
+        for chunk in chunks {
+            chunk.value.to_bits().hash(&mut hasher);
+            chunk.duration.hash(&mut hasher);
+        }
+    }
+    direction.hash(&mut hasher);
+    stats.cycle.map(f64::to_bits).hash(&mut hasher);
+    hysteresis
+        .map(|options| (options.flap_window_ms, options.penalty.to_bits()))
+        .hash(&mut hasher);
+    metric.hash(&mut hasher);
+    hasher.finish()
 }
 
-pub fn get_cache_key(stats: &NumericStateStats) -> String {
-    // Create a cache key from the first few chunks
-    let true_key: String = stats
-        .true_chunks
-        .iter()
-        .take(5)
-        .map(|c| format!("{:.2}-{}", c.value, c.duration))
-        .collect::<Vec<_>>()
-        .join(",");
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let false_key: String = stats
-        .false_chunks
-        .iter()
-        .take(5)
-        .map(|c| format!("{:.2}-{}", c.value, c.duration))
-        .collect::<Vec<_>>()
-        .join(",");
+    fn stats(true_values: &[(f64, i64)], false_values: &[(f64, i64)], cycle: Option<f64>) -> NumericStateStats {
+        let chunks = |values: &[(f64, i64)]| -> Vec<ValueDuration> {
+            values.iter().map(|&(value, duration)| ValueDuration { value, duration }).collect()
+        };
+        let finite = true_values.iter().chain(false_values).map(|&(value, _)| value).filter(|value| value.is_finite());
+        NumericStateStats {
+            is_numeric: true,
+            min: finite.clone().reduce(f64::min),
+            max: finite.reduce(f64::max),
+            true_chunks: chunks(true_values),
+            false_chunks: chunks(false_values),
+            outlier_chunks: 0,
+            cumulative: false,
+            cycle,
+        }
+    }
 
-    format!("{}|{}", true_key, false_key)
-}
\ No newline at end of file
+    fn search(stats: &NumericStateStats, search: ThresholdSearch) -> OptimalThresholds {
+        find_numeric_thresholds(stats, search, None, None, ScoringMetric::AbsoluteDifference, &CancellationFlag::default())
+            .unwrap()
+    }
+
+    fn discrimination(stats: &NumericStateStats, thresholds: &OptimalThresholds) -> f64 {
+        let share = |chunks: &[ValueDuration]| SortedDurations::new(chunks).fraction_matching(thresholds);
+        (share(&stats.true_chunks) - share(&stats.false_chunks)).abs()
+    }
+
+    #[test]
+    fn skewed_values_get_the_cut_the_grid_misses() {
+        // One far reading stretches the range so no grid step falls between 0.25 and 0.75
+        let stats = stats(&[(0.25, 1000)], &[(0.0, 100), (0.75, 800), (100.0, 100)], None);
+
+        let exact = search(&stats, ThresholdSearch::Full);
+        assert_eq!((exact.above, exact.below), (Some(0.125), Some(0.5)));
+        assert_eq!(discrimination(&stats, &exact), 1.0);
+
+        let preview = search(&stats, ThresholdSearch::Preview);
+        assert!(discrimination(&stats, &preview) < 0.5);
+    }
+
+    #[test]
+    fn true_time_in_the_middle_gives_a_range() {
+        let stats = stats(&[(20.0, 10)], &[(10.0, 5), (30.0, 5)], None);
+
+        let thresholds = search(&stats, ThresholdSearch::Full);
+        assert_eq!((thresholds.above, thresholds.below), (Some(15.0), Some(25.0)));
+    }
+
+    #[test]
+    fn cyclic_range_wraps_around_to_the_true_side() {
+        // North-facing readings are TRUE, south-facing FALSE
+        let stats = stats(&[(350.0, 5), (10.0, 5)], &[(180.0, 10)], Some(360.0));
+
+        let thresholds = search(&stats, ThresholdSearch::Full);
+        assert_eq!((thresholds.above, thresholds.below), (Some(265.0), Some(95.0)));
+        assert!(value_matches_thresholds(350.0, &thresholds));
+        assert!(value_matches_thresholds(10.0, &thresholds));
+        assert!(!value_matches_thresholds(180.0, &thresholds));
+        assert_eq!(discrimination(&stats, &thresholds), 1.0);
+    }
+
+    #[test]
+    fn repeated_values_are_merged_into_one_cut() {
+        let stats = stats(&[(1.0, 4), (1.0, 3), (1.0, 3)], &[(3.0, 6), (3.0, 4)], None);
+        assert_eq!(SortedDurations::new(&stats.true_chunks).values(), &[1.0]);
+
+        let thresholds = search(&stats, ThresholdSearch::Full);
+        assert_eq!((thresholds.above, thresholds.below), (None, Some(2.0)));
+    }
+
+    #[test]
+    fn nan_readings_do_not_become_thresholds() {
+        let stats = stats(&[(1.0, 10), (f64::NAN, 1)], &[(3.0, 10), (f64::NAN, 1)], None);
+
+        let thresholds = search(&stats, ThresholdSearch::Full);
+        assert_eq!((thresholds.above, thresholds.below), (None, Some(2.0)));
+    }
+
+    #[test]
+    fn values_shared_by_both_classes_never_sit_on_a_threshold() {
+        let identical = stats(&[(5.0, 10)], &[(5.0, 10)], None);
+        let thresholds = search(&identical, ThresholdSearch::Full);
+        assert_eq!((thresholds.above, thresholds.below), (None, None));
+
+        let mixed = stats(&[(1.0, 7), (5.0, 3)], &[(1.0, 3), (5.0, 7)], None);
+        let thresholds = search(&mixed, ThresholdSearch::Full);
+        assert_eq!((thresholds.above, thresholds.below), (None, Some(3.0)));
+        assert!((discrimination(&mixed, &thresholds) - 0.4).abs() < 1e-12);
+    }
+}
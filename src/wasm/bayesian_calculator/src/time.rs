@@ -0,0 +1,113 @@
+use crate::types::Timestamp;
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, TimeZone, Utc};
+use chrono_tz::Tz;
+
+const HOUR_MS: i64 = 3_600_000;
+
+/// Look up an IANA time zone such as `Europe/Berlin`
+pub fn parse_zone(name: &str) -> Result<Tz, String> {
+    name.parse::<Tz>().map_err(|e| format!("Unknown time zone '{}': {}", name, e))
+}
+
+/// Instant of a local wall-clock time, `hours` after local midnight of `date`
+///
+/// Hours of 24 or more roll into the following days. A time skipped by a spring-forward
+/// transition resolves to the same elapsed time after the last valid instant before
+/// the gap (02:30 becomes 03:30), and a time repeated by a fall-back transition
+/// resolves to its first occurrence, so recurring windows never produce invalid or
+/// duplicate local times.
+pub fn local_instant(zone: Tz, date: NaiveDate, hours: f64) -> i64 {
+    let whole_days = hours.div_euclid(24.0);
+    let date = date + Duration::days(whole_days as i64);
+    let offset_ms = ((hours - whole_days * 24.0) * HOUR_MS as f64).round() as i64;
+    naive_instant(zone, date.and_time(NaiveTime::MIN) + Duration::milliseconds(offset_ms))
+}
+
+/// Instant of a local wall-clock date and time, resolving DST gaps and overlaps like
+/// `local_instant`
+pub fn naive_instant(zone: Tz, local: NaiveDateTime) -> i64 {
+    match zone.from_local_datetime(&local) {
+        LocalResult::Single(instant) => instant.timestamp_millis(),
+        LocalResult::Ambiguous(earliest, _) => earliest.timestamp_millis(),
+        LocalResult::None => skipped_instant(zone, local),
+    }
+}
+
+/// Resolve a local time inside a DST gap by keeping its distance from the gap start
+fn skipped_instant(zone: Tz, local: NaiveDateTime) -> i64 {
+    // Gaps are at most a few hours; walk back to the last valid local time before it
+    let mut before = local;
+    for _ in 0..(4 * 60) {
+        before -= Duration::minutes(1);
+        if let Some(instant) = zone.from_local_datetime(&before).earliest() {
+            return instant.timestamp_millis() + (local - before).num_milliseconds();
+        }
+    }
+    Utc.from_utc_datetime(&local).timestamp_millis()
+}
+
+/// Read an ISO 8601 time without an offset (`2024-03-10T18:00:00`) as wall-clock
+/// time in `zone`
+///
+/// Numbers and strings with an offset already name an instant and are returned as
+/// they are, as are strings that cannot be read either way.
+pub fn localize(timestamp: &Timestamp, zone: Tz) -> Timestamp {
+    let Timestamp::Iso(iso) = timestamp else {
+        return timestamp.clone();
+    };
+    if DateTime::parse_from_rfc3339(iso).is_ok() {
+        return timestamp.clone();
+    }
+
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(iso, format).ok())
+        .map_or_else(|| timestamp.clone(), |local| Timestamp::from_millis(naive_instant(zone, local)))
+}
+
+/// Local calendar date of an instant
+pub fn local_date(zone: Tz, millis: i64) -> NaiveDate {
+    zone.timestamp_millis_opt(millis)
+        .single()
+        .map(|instant| instant.date_naive())
+        .unwrap_or_default()
+}
+
+/// UTC ISO 8601 string with millisecond precision, as HA writes timestamps
+pub fn format_millis(millis: i64) -> String {
+    DateTime::<Utc>::from_timestamp_millis(millis)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Sunrise and sunset on a UTC calendar date at the given coordinates
+///
+/// Uses the standard sunrise equation with the -0.833° horizon HA's `sun.sun` uses,
+/// accurate to a minute or two. `None` during polar day or polar night.
+pub fn sun_times(date: NaiveDate, latitude: f64, longitude: f64) -> Option<(i64, i64)> {
+    const UNIX_EPOCH_JULIAN: f64 = 2_440_587.5;
+    const J2000: f64 = 2_451_545.0;
+
+    // NaiveDate::default() is the Unix epoch date
+    let days_since_epoch = (date - NaiveDate::default()).num_days() as f64;
+    let julian_noon = UNIX_EPOCH_JULIAN + days_since_epoch + 0.5;
+    let cycle = (julian_noon - J2000 + 0.0008).round();
+    let mean_solar_noon = cycle - longitude / 360.0;
+
+    let anomaly = (357.5291 + 0.985_600_28 * mean_solar_noon).rem_euclid(360.0).to_radians();
+    let center = 1.9148 * anomaly.sin() + 0.02 * (2.0 * anomaly).sin() + 0.0003 * (3.0 * anomaly).sin();
+    let ecliptic_longitude = (anomaly.to_degrees() + center + 180.0 + 102.9372).rem_euclid(360.0).to_radians();
+    let transit = J2000 + mean_solar_noon + 0.0053 * anomaly.sin() - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+    let declination = (ecliptic_longitude.sin() * 23.4397_f64.to_radians().sin()).asin();
+    let latitude = latitude.to_radians();
+    let cos_hour_angle = ((-0.833_f64).to_radians().sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+    if !(-1.0..=1.0).contains(&cos_hour_angle) {
+        return None;
+    }
+
+    let half_day = cos_hour_angle.acos().to_degrees() / 360.0;
+    let to_millis = |julian: f64| ((julian - UNIX_EPOCH_JULIAN) * 86_400_000.0).round() as i64;
+    Some((to_millis(transit - half_day), to_millis(transit + half_day)))
+}
@@ -0,0 +1,249 @@
+use crate::sensor_analysis::{split_duration, true_weight};
+use crate::types::TimePeriod;
+use std::ops::Range;
+
+/// What a history entry does to the value in effect
+#[derive(Debug, Clone, Copy)]
+pub enum Reading<T> {
+    /// The entity took this value
+    Value(T),
+    /// Not a usable value; the one before stays in effect
+    Keep,
+    /// Time not counted towards any value until the next usable one
+    Gap,
+}
+
+/// Labeled periods with their boundaries indexed once per analysis
+///
+/// Shared by the categorical and numeric chunk sweeps of every entity and by the
+/// posterior replay, so each answers "which period covers T" and "what was the
+/// value at T" the same way.
+pub struct TimelineEngine<'a> {
+    periods: &'a [TimePeriod],
+    /// (start, end) of each period, in input order
+    spans: Vec<(i64, i64)>,
+    /// Indices of the non-empty periods sorted by start
+    by_start: Vec<usize>,
+}
+
+impl<'a> TimelineEngine<'a> {
+    pub fn new(periods: &'a [TimePeriod]) -> Self {
+        let spans: Vec<(i64, i64)> = periods
+            .iter()
+            .map(|period| (period.start.millis(), period.end.millis()))
+            .collect();
+        let mut by_start: Vec<usize> = (0..periods.len()).filter(|&i| spans[i].1 > spans[i].0).collect();
+        by_start.sort_by_key(|&i| spans[i].0);
+
+        Self { periods, spans, by_start }
+    }
+
+    pub fn periods(&self) -> &'a [TimePeriod] {
+        self.periods
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.periods.is_empty()
+    }
+
+    /// Earliest start and latest end of the non-empty periods
+    pub fn span(&self) -> Option<(i64, i64)> {
+        let start = self.spans[*self.by_start.first()?].0;
+        let end = self.by_start.iter().map(|&i| self.spans[i].1).max()?;
+        Some((start, end))
+    }
+
+    /// Start and end of every non-empty period, in order of start
+    pub fn boundaries(&self) -> impl Iterator<Item = i64> + '_ {
+        self.by_start.iter().flat_map(|&i| [self.spans[i].0, self.spans[i].1])
+    }
+
+    /// Period covering `time`; where periods overlap, the one that started last
+    pub fn period_at(&self, time: i64) -> Option<&'a TimePeriod> {
+        let index = self.by_start.partition_point(|&i| self.spans[i].0 <= time);
+        let latest = self.by_start[index.checked_sub(1)?];
+        (time < self.spans[latest].1).then(|| &self.periods[latest])
+    }
+
+    /// Range of the time-sorted `changes` strictly inside each period, in period order
+    fn inside<'b, T>(&'b self, changes: &'b [(i64, T)]) -> impl Iterator<Item = (usize, i64, i64, Range<usize>)> + 'b {
+        self.spans.iter().enumerate().map(|(index, &(start, end))| {
+            let first = changes.partition_point(|&(time, _)| time <= start);
+            let last = changes.partition_point(|&(time, _)| time < end);
+            (index, start, end, first..last.max(first))
+        })
+    }
+
+    /// Walk every period and report each chunk (value, duration, is_true_period, period index) to `visit`
+    ///
+    /// Chunks are those of `slices`. Chunks of a soft-labeled period are reported
+    /// once per class, with the duration split by the period's confidence.
+    pub fn sweep<T: Copy>(&self, changes: &[(i64, Reading<T>)], mut visit: impl FnMut(T, i64, bool, usize)) {
+        self.slices(changes, |value, _, duration, period_index| {
+            let weight = true_weight(&self.periods[period_index]);
+            let (true_duration, false_duration) = split_duration(duration, weight);
+            if true_duration > 0 {
+                visit(value, true_duration, true, period_index);
+            }
+            if false_duration > 0 {
+                visit(value, false_duration, false, period_index);
+            }
+        });
+    }
+
+    /// Walk every period and report each chunk (value, start, duration, period index) to `visit`
+    ///
+    /// The value in effect at a period's start is that of `reading_at`, however long
+    /// before the period it was taken. `Keep` readings inside a period keep the
+    /// previous value and `Gap` readings leave the time until the next value out. Of several changes at the same time the last one in `changes`
+    /// wins; the history caches sort stably, so that is the last history entry.
+    /// Chunks under one second are skipped, but a reading starting one still becomes
+    /// the value in effect. Periods ending at or before their start have no chunks.
+    pub fn slices<T: Copy>(&self, changes: &[(i64, Reading<T>)], mut visit: impl FnMut(T, i64, i64, usize)) {
+        for (period_index, start, end, range) in self.inside(changes) {
+            let mut current_value = reading_at(changes, start);
+            let mut chunk_start = start;
+
+            // Each change inside the period closes the chunk before it
+            let changes = &changes[range];
+            let chunk_ends = changes.iter().map(|&(time, _)| time).chain(std::iter::once(end));
+
+            for (i, chunk_end) in chunk_ends.enumerate() {
+                let duration = chunk_end - chunk_start;
+
+                if duration >= 1000 {
+                    if let Some(value) = current_value {
+                        visit(value, chunk_start, duration, period_index);
+                    }
+                }
+
+                match changes.get(i) {
+                    Some(&(_, Reading::Value(value))) => current_value = Some(value),
+                    Some(&(_, Reading::Gap)) => current_value = None,
+                    _ => {}
+                }
+                chunk_start = chunk_end;
+            }
+        }
+    }
+
+    /// Upper bound on the chunks a sweep over `changes` reports per class, so chunk
+    /// vectors are allocated once
+    pub fn chunk_capacity<T>(&self, changes: &[(i64, T)]) -> usize {
+        self.inside(changes).map(|(_, _, _, range)| range.len() + 1).sum()
+    }
+}
+
+/// Value in effect at `time` in time-sorted `changes`: the last change at or before it
+pub fn value_at<T>(changes: &[(i64, T)], time: i64) -> Option<&T> {
+    let index = changes.partition_point(|&(t, _)| t <= time);
+    changes.get(index.checked_sub(1)?).map(|(_, value)| value)
+}
+
+/// Value in effect at `time` in time-sorted `changes`: that of the last `Value` at or
+/// before it, unless a `Gap` came after it
+fn reading_at<T: Copy>(changes: &[(i64, Reading<T>)], time: i64) -> Option<T> {
+    let index = changes.partition_point(|&(t, _)| t <= time);
+    changes[..index].iter().rev().find_map(|(_, reading)| match reading {
+        Reading::Value(value) => Some(Some(*value)),
+        Reading::Gap => Some(None),
+        Reading::Keep => None,
+    })?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Timestamp;
+
+    const MINUTE: i64 = 60_000;
+
+    fn period(id: &str, start: i64, end: i64) -> TimePeriod {
+        TimePeriod {
+            id: id.to_string(),
+            start: Timestamp::from_millis(start),
+            end: Timestamp::from_millis(end),
+            is_true_period: true,
+            label: None,
+            class: None,
+            confidence: None,
+        }
+    }
+
+    /// (value, start, duration, period index) of every chunk
+    fn slices(periods: &[TimePeriod], changes: &[(i64, Reading<f64>)]) -> Vec<(f64, i64, i64, usize)> {
+        let mut chunks = Vec::new();
+        TimelineEngine::new(periods).slices(changes, |value, start, duration, index| chunks.push((value, start, duration, index)));
+        chunks
+    }
+
+    #[test]
+    fn reversed_and_empty_periods_have_no_chunks() {
+        let periods = [period("reversed", 10 * MINUTE, 0), period("empty", 5 * MINUTE, 5 * MINUTE)];
+        let changes = [(0, Reading::Value(1.0)), (5 * MINUTE, Reading::Value(2.0))];
+
+        assert!(slices(&periods, &changes).is_empty());
+
+        let timeline = TimelineEngine::new(&periods);
+        assert_eq!(timeline.span(), None);
+        assert!(timeline.period_at(5 * MINUTE).is_none());
+    }
+
+    #[test]
+    fn last_of_duplicate_timestamps_wins() {
+        let periods = [period("p", 0, 10 * MINUTE)];
+        // At the period start and inside it
+        let changes = [
+            (0, Reading::Value(1.0)),
+            (0, Reading::Value(2.0)),
+            (5 * MINUTE, Reading::Value(3.0)),
+            (5 * MINUTE, Reading::Value(4.0)),
+        ];
+
+        assert_eq!(slices(&periods, &changes), vec![(2.0, 0, 5 * MINUTE, 0), (4.0, 5 * MINUTE, 5 * MINUTE, 0)]);
+    }
+
+    #[test]
+    fn short_chunks_are_skipped_but_still_set_the_value() {
+        let periods = [period("p", 0, 10 * MINUTE)];
+        let changes = [(0, Reading::Value(1.0)), (MINUTE, Reading::Value(2.0)), (MINUTE + 500, Reading::Keep)];
+
+        assert_eq!(slices(&periods, &changes), vec![(1.0, 0, MINUTE, 0), (2.0, MINUTE + 500, 9 * MINUTE - 500, 0)]);
+    }
+
+    #[test]
+    fn time_before_the_first_reading_is_uncovered() {
+        let periods = [period("p", 0, 10 * MINUTE)];
+        let changes = [(4 * MINUTE, Reading::Value(1.0))];
+
+        assert_eq!(slices(&periods, &changes), vec![(1.0, 4 * MINUTE, 6 * MINUTE, 0)]);
+    }
+
+    #[test]
+    fn keep_before_the_start_keeps_the_earlier_value() {
+        let periods = [period("p", 60 * MINUTE, 120 * MINUTE)];
+        // `on` at 09:00, `unavailable` at 09:30, `off` at 10:30 against a 10:00-11:00 period
+        let changes = [(0, Reading::Value(1.0)), (30 * MINUTE, Reading::Keep), (90 * MINUTE, Reading::Value(0.0))];
+
+        assert_eq!(
+            slices(&periods, &changes),
+            vec![(1.0, 60 * MINUTE, 30 * MINUTE, 0), (0.0, 90 * MINUTE, 30 * MINUTE, 0)]
+        );
+    }
+
+    #[test]
+    fn gap_before_the_start_leaves_time_uncovered() {
+        let periods = [period("p", 60 * MINUTE, 120 * MINUTE)];
+        let changes = [(0, Reading::Value(1.0)), (30 * MINUTE, Reading::Gap), (45 * MINUTE, Reading::Keep)];
+
+        assert!(slices(&periods, &changes).is_empty());
+    }
+
+    #[test]
+    fn history_starting_after_the_period_covers_nothing() {
+        let periods = [period("p", 0, 10 * MINUTE)];
+        let changes = [(20 * MINUTE, Reading::Value(1.0))];
+
+        assert!(slices(&periods, &changes).is_empty());
+    }
+}
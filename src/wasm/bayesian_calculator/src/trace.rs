@@ -0,0 +1,96 @@
+use crate::sensor_analysis;
+use crate::time;
+use crate::timeline::{value_at, TimelineEngine};
+use crate::types::HAHistoryEntry;
+use rustc_hash::FxHashMap;
+use serde::{Deserialize, Serialize};
+use tsify::Tsify;
+
+/// Most buckets a downsampled trace is split into; coarser resolutions are used
+/// beyond that
+const MAX_BUCKETS: i64 = 10_000;
+
+/// An entity's value from this point until the next
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct TracePoint {
+    pub time: String, // ISO 8601 string
+    /// Numeric entities: the time-weighted mean over the bucket; `None` where the
+    /// entity had no number
+    pub value: Option<f64>,
+    /// Categorical entities: the state held longest over the bucket
+    pub state: Option<String>,
+    /// Label of the period covering the point; `None` outside the labeled periods
+    pub in_true_period: Option<bool>,
+}
+
+/// An entity's history across the span of the periods, for drawing over the period shading
+///
+/// Without a resolution every state change inside the span is a point, plus the
+/// value in effect when the span starts. With one, the span is cut into buckets of
+/// `resolution_ms` and each bucket becomes a single point.
+pub fn entity_trace(entity_history: &[HAHistoryEntry], timeline: &TimelineEngine, resolution_ms: Option<i64>) -> Vec<TracePoint> {
+    let Some((start, end)) = timeline.span() else {
+        return Vec::new();
+    };
+    let is_numeric = sensor_analysis::is_numeric_entity(entity_history);
+    let mut changes: Vec<(i64, &str)> = entity_history
+        .iter()
+        .map(|entry| (entry.last_changed.millis(), entry.state.as_str()))
+        .collect();
+    changes.sort_by_key(|&(time, _)| time);
+
+    let point = |time: i64, value: Option<f64>, state: Option<String>| TracePoint {
+        time: time::format_millis(time),
+        value: if is_numeric { value } else { None },
+        state: if is_numeric { None } else { state },
+        in_true_period: timeline.period_at(time).map(|period| period.is_true_period),
+    };
+
+    let Some(resolution) = resolution_ms.filter(|&resolution| resolution > 0) else {
+        let first = changes.partition_point(|&(time, _)| time <= start);
+        let last = changes.partition_point(|&(time, _)| time < end);
+        return std::iter::once((start, value_at(&changes, start).copied()))
+            .chain(changes[first..last.max(first)].iter().map(|&(time, state)| (time, Some(state))))
+            .map(|(time, state)| point(time, state.and_then(|state| state.parse().ok()), state.map(str::to_string)))
+            .collect();
+    };
+
+    let resolution = resolution.max((end - start) / MAX_BUCKETS + 1);
+    let mut points = Vec::new();
+    let mut bucket_start = start;
+    while bucket_start < end {
+        let bucket_end = (bucket_start + resolution).min(end);
+        let mut weighted = (0.0, 0i64);
+        let mut durations: FxHashMap<&str, i64> = FxHashMap::default();
+
+        // The change in effect at the bucket start, then every change inside it
+        let first = changes.partition_point(|&(time, _)| time <= bucket_start).saturating_sub(1);
+        for (i, &(time, state)) in changes.iter().enumerate().skip(first) {
+            if time >= bucket_end {
+                break;
+            }
+            let next = changes.get(i + 1).map_or(bucket_end, |&(next, _)| next.min(bucket_end));
+            let duration = next - time.max(bucket_start);
+            if duration <= 0 {
+                continue;
+            }
+            *durations.entry(state).or_default() += duration;
+            if let Ok(value) = state.parse::<f64>() {
+                weighted.0 += value * duration as f64;
+                weighted.1 += duration;
+            }
+        }
+
+        let value = (weighted.1 > 0).then(|| weighted.0 / weighted.1 as f64);
+        // Ties go to the alphabetically first state so the trace is the same every run
+        let state = durations
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(state, _)| state.to_string());
+        points.push(point(bucket_start, value, state));
+        bucket_start = bucket_end;
+    }
+    points
+}
@@ -0,0 +1,263 @@
+use crate::ensemble;
+use crate::export::{self, best_row_per_entity, Observation};
+use crate::posterior::PredictedMetrics;
+use crate::sensor_analysis::{period_length, split_duration, true_weight};
+use crate::timeline::value_at;
+use crate::types::{EntityProbability, HAHistoryEntry, TimePeriod};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tsify::Tsify;
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase", default)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct TriggerTemplateOptions {
+    /// Predicted F1 the crisp condition must reach for the target to count as dominated by it
+    pub min_f1: f64,
+    /// Percentile of spurious runs the suggested delays should filter out
+    pub dwell_percentile: f64,
+    pub name: String,
+    pub device_class: Option<String>,
+}
+
+impl Default for TriggerTemplateOptions {
+    fn default() -> Self {
+        Self {
+            min_f1: 0.85,
+            dwell_percentile: 0.9,
+            name: "Template Sensor".to_string(),
+            device_class: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct TriggerTemplateSuggestion {
+    pub entity_ids: Vec<String>,
+    /// `single`, `any` (OR of both conditions) or `all` (AND)
+    pub combination: String,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    pub delay_on_seconds: i64,
+    pub delay_off_seconds: i64,
+    pub yaml: String,
+}
+
+/// Propose a trigger-based template binary sensor when one or two crisp conditions dominate
+///
+/// The best rows of the top two entities are tried alone and combined with OR and
+/// AND; the combination with the best predicted F1 is suggested if it reaches
+/// `min_f1`, otherwise `None`. The delays come from dwell times of the chosen
+/// condition in the labeled history: `delay_on` filters the condition flickering on
+/// inside FALSE periods and `delay_off` its dropouts inside TRUE periods. Each is
+/// capped at half the median length of the periods it must not swallow.
+pub fn suggest(
+    results: &[EntityProbability],
+    history: &HashMap<String, Vec<HAHistoryEntry>>,
+    periods: &[TimePeriod],
+    options: &TriggerTemplateOptions,
+) -> Option<TriggerTemplateSuggestion> {
+    let observations: Vec<Observation> = best_row_per_entity(results, 2)
+        .iter()
+        .filter_map(Observation::from_result)
+        .collect();
+    let first = observations.first()?;
+
+    let true_time: i64 = periods.iter().map(|p| split_duration(period_length(p), true_weight(p)).0).sum();
+    let total_time: i64 = periods.iter().map(period_length).sum();
+    if total_time <= 0 {
+        return None;
+    }
+    let prior = true_time as f64 / total_time as f64;
+
+    let mut candidates = vec![("single", vec![first], crisp_metrics(first, prior))];
+    if let Some(second) = observations.get(1) {
+        let (a, b) = (crisp_metrics(first, prior), crisp_metrics(second, prior));
+        candidates.push(("any", vec![first, second], ensemble::combine(&a, &b, prior, false)));
+        candidates.push(("all", vec![first, second], ensemble::combine(&a, &b, prior, true)));
+    }
+
+    let (combination, chosen, metrics) = candidates
+        .into_iter()
+        .max_by(|a, b| a.2.f_beta(1.0).total_cmp(&b.2.f_beta(1.0)))?;
+    if metrics.f_beta(1.0) < options.min_f1 {
+        return None;
+    }
+
+    let all = combination == "all";
+    let runs = condition_runs(&chosen, history, periods, all);
+    let false_on: Vec<i64> = runs.iter().filter(|r| r.on && !r.in_true_period).map(|r| r.duration).collect();
+    let true_off: Vec<i64> = runs.iter().filter(|r| !r.on && r.in_true_period).map(|r| r.duration).collect();
+
+    let delay_on = percentile(false_on, options.dwell_percentile)
+        .min(median_period_length(periods, true) / 2);
+    let delay_off = percentile(true_off, options.dwell_percentile)
+        .min(median_period_length(periods, false) / 2);
+    // Round up to whole seconds so the delay still covers the run it was derived from
+    let delay_on_seconds = (delay_on + 999) / 1000;
+    let delay_off_seconds = (delay_off + 999) / 1000;
+
+    let yaml = generate_yaml(&chosen, all, delay_on_seconds, delay_off_seconds, options);
+
+    Some(TriggerTemplateSuggestion {
+        entity_ids: chosen.iter().map(|o| o.entity_id.clone()).collect(),
+        combination: combination.to_string(),
+        precision: metrics.precision,
+        recall: metrics.recall,
+        f1: metrics.f_beta(1.0),
+        delay_on_seconds,
+        delay_off_seconds,
+        yaml,
+    })
+}
+
+/// A condition used as a detector fires with `prob_given_true` on TRUE and `prob_given_false` on FALSE
+fn crisp_metrics(observation: &Observation, prior: f64) -> PredictedMetrics {
+    let true_positive = prior * observation.prob_given_true;
+    let false_positive = (1.0 - prior) * observation.prob_given_false;
+    let precision = if true_positive + false_positive > 0.0 {
+        true_positive / (true_positive + false_positive)
+    } else {
+        0.0
+    };
+
+    PredictedMetrics {
+        precision,
+        recall: observation.prob_given_true,
+        false_positive_rate: observation.prob_given_false,
+    }
+}
+
+struct ConditionRun {
+    on: bool,
+    in_true_period: bool,
+    duration: i64,
+}
+
+/// Runs of the combined condition strictly inside each period
+///
+/// Runs touching a period boundary are dropped: their true length is unknown and
+/// the transition at the boundary is the one the sensor is meant to follow.
+fn condition_runs(
+    observations: &[&Observation],
+    history: &HashMap<String, Vec<HAHistoryEntry>>,
+    periods: &[TimePeriod],
+    all: bool,
+) -> Vec<ConditionRun> {
+    let timelines: Vec<Vec<(i64, bool)>> = observations
+        .iter()
+        .map(|o| condition_timeline(o, history.get(&o.entity_id).map(Vec::as_slice).unwrap_or(&[])))
+        .collect();
+
+    let mut change_times: Vec<i64> = timelines.iter().flatten().map(|&(time, _)| time).collect();
+    change_times.sort_unstable();
+    change_times.dedup();
+
+    let value_at = |time: i64| {
+        let mut met = timelines
+            .iter()
+            .map(|timeline| value_at(timeline, time).copied().unwrap_or(false));
+        if all { met.all(|m| m) } else { met.any(|m| m) }
+    };
+
+    let mut runs = Vec::new();
+    for period in periods {
+        let (start, end) = (period.start.millis(), period.end.millis());
+        let first_inside = change_times.partition_point(|&t| t <= start);
+        let end_inside = change_times.partition_point(|&t| t < end);
+
+        let mut run_start = start;
+        let mut current = value_at(start);
+        for &time in &change_times[first_inside..end_inside] {
+            let value = value_at(time);
+            if value == current {
+                continue;
+            }
+            if run_start > start {
+                runs.push(ConditionRun {
+                    on: current,
+                    in_true_period: period.is_true_period,
+                    duration: time - run_start,
+                });
+            }
+            run_start = time;
+            current = value;
+        }
+    }
+
+    runs
+}
+
+/// Times at which the observation's condition becomes met or unmet, sorted by time
+fn condition_timeline(observation: &Observation, entity_history: &[HAHistoryEntry]) -> Vec<(i64, bool)> {
+    let mut timeline: Vec<(i64, bool)> = entity_history
+        .iter()
+        .map(|entry| (entry.last_changed.millis(), observation.is_met(&entry.state).unwrap_or(false)))
+        .collect();
+    timeline.sort_by_key(|&(time, _)| time);
+    timeline
+}
+
+fn percentile(mut durations: Vec<i64>, fraction: f64) -> i64 {
+    if durations.is_empty() {
+        return 0;
+    }
+    durations.sort_unstable();
+    let rank = (fraction.clamp(0.0, 1.0) * (durations.len() - 1) as f64).round() as usize;
+    durations[rank]
+}
+
+fn median_period_length(periods: &[TimePeriod], is_true_period: bool) -> i64 {
+    let lengths: Vec<i64> = periods
+        .iter()
+        .filter(|p| p.is_true_period == is_true_period)
+        .map(period_length)
+        .collect();
+    if lengths.is_empty() { i64::MAX } else { percentile(lengths, 0.5) }
+}
+
+fn generate_yaml(
+    observations: &[&Observation],
+    all: bool,
+    delay_on_seconds: i64,
+    delay_off_seconds: i64,
+    options: &TriggerTemplateOptions,
+) -> String {
+    let operator = if all { " and " } else { " or " };
+    let condition = observations
+        .iter()
+        .map(|o| o.jinja_condition())
+        .collect::<Vec<_>>()
+        .join(operator);
+    let mut yaml = String::new();
+
+    yaml.push_str("template:\n");
+    yaml.push_str("  - trigger:\n");
+    yaml.push_str("      - platform: homeassistant\n");
+    yaml.push_str("        event: start\n");
+    yaml.push_str("      - platform: state\n");
+    yaml.push_str("        entity_id:\n");
+    for observation in observations {
+        yaml.push_str(&format!("          - {}\n", observation.entity_id));
+    }
+    yaml.push_str("    binary_sensor:\n");
+    yaml.push_str(&format!("      - name: {}\n", export::yaml_string(&options.name)));
+    if let Some(device_class) = &options.device_class {
+        yaml.push_str(&format!("        device_class: {}\n", export::yaml_string(device_class)));
+    }
+    yaml.push_str("        state: >\n");
+    yaml.push_str(&format!("          {{{{ {} }}}}\n", condition));
+    if delay_on_seconds > 0 {
+        yaml.push_str("        delay_on:\n");
+        yaml.push_str(&format!("          seconds: {}\n", delay_on_seconds));
+    }
+    if delay_off_seconds > 0 {
+        yaml.push_str("        delay_off:\n");
+        yaml.push_str(&format!("          seconds: {}\n", delay_off_seconds));
+    }
+
+    yaml
+}
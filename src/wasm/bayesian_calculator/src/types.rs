@@ -1,15 +1,60 @@
 use serde::{Deserialize, Serialize};
 use tsify::Tsify;
 
+/// A point in time, as an ISO 8601 string or as epoch milliseconds
+///
+/// Epoch numbers are used as they are, skipping the string formatting in JS and the
+/// parsing here, which adds up over long histories.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Tsify)]
+#[serde(untagged)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum Timestamp {
+    EpochMillis(f64),
+    Iso(String),
+}
+
+impl Timestamp {
+    pub fn from_millis(millis: i64) -> Self {
+        Timestamp::EpochMillis(millis as f64)
+    }
+
+    /// Epoch milliseconds; unparseable strings and non-finite numbers give 0
+    ///
+    /// Analyses and replays of selected rows reject such periods and skip such history
+    /// entries up front (see `error::check_timestamps`), so 0 never reaches a timeline.
+    pub fn millis(&self) -> i64 {
+        match self {
+            Timestamp::EpochMillis(millis) if millis.is_finite() => millis.round() as i64,
+            Timestamp::EpochMillis(_) => 0,
+            Timestamp::Iso(iso) => crate::sensor_analysis::parse_timestamp(iso).unwrap_or(0),
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Timestamp::EpochMillis(millis) => millis.is_finite(),
+            Timestamp::Iso(iso) => crate::sensor_analysis::parse_timestamp(iso).is_some(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
 #[serde(rename_all = "camelCase")]
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub struct TimePeriod {
     pub id: String,
-    pub start: String, // ISO 8601 string
-    pub end: String,   // ISO 8601 string
+    pub start: Timestamp,
+    pub end: Timestamp,
     pub is_true_period: bool,
+    /// Free-text name of the period, for display
     pub label: Option<String>,
+    /// Outcome of a multi-class target during the period ("home", "away", "asleep");
+    /// `calculate_per_class` analyses each distinct class against the rest
+    #[serde(default)]
+    pub class: Option<String>,
+    /// Probability the target was on during the period; overrides `is_true_period` when set
+    #[serde(default)]
+    pub confidence: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
@@ -17,11 +62,51 @@ pub struct TimePeriod {
 #[tsify(into_wasm_abi, from_wasm_abi)]
 pub struct HAHistoryEntry {
     pub state: String,
-    pub last_changed: String, // ISO 8601 string
-    pub last_updated: String, // ISO 8601 string
+    pub last_changed: Timestamp,
+    pub last_updated: Timestamp,
     pub attributes: Option<serde_json::Value>,
 }
 
+/// Entry of HA's compressed history, as the websocket `history/history_during_period`
+/// command returns it with `minimal_response` or `no_attributes`
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct CompressedHistoryEntry {
+    /// State
+    pub s: String,
+    /// Last changed, epoch seconds; left out when equal to `lu`
+    pub lc: Option<f64>,
+    /// Last updated, epoch seconds
+    pub lu: f64,
+    /// Attributes, only sent on some entries
+    pub a: Option<serde_json::Value>,
+}
+
+/// A history entry in either format HA returns
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum HistoryEntryInput {
+    Full(HAHistoryEntry),
+    Compressed(CompressedHistoryEntry),
+}
+
+impl From<HistoryEntryInput> for HAHistoryEntry {
+    fn from(entry: HistoryEntryInput) -> Self {
+        match entry {
+            HistoryEntryInput::Full(entry) => entry,
+            HistoryEntryInput::Compressed(entry) => {
+                let millis = |seconds: f64| Timestamp::EpochMillis((seconds * 1000.0).round());
+                HAHistoryEntry {
+                    state: entry.s,
+                    last_changed: millis(entry.lc.unwrap_or(entry.lu)),
+                    last_updated: millis(entry.lu),
+                    attributes: entry.a,
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
 #[serde(rename_all = "camelCase")]
 #[tsify(into_wasm_abi, from_wasm_abi)]
@@ -31,12 +116,211 @@ pub struct EntityProbability {
     pub prob_given_true: f64,
     pub prob_given_false: f64,
     pub discrimination_power: f64,
+    /// Value of `scoringMetric` the rows are ranked by; the discrimination power
+    /// under the default metric
+    #[serde(default)]
+    pub score: f64,
     pub true_occurrences: usize,
     pub false_occurrences: usize,
     pub total_true_periods: usize,
     pub total_false_periods: usize,
-    pub numeric_stats: Option<crate::sensor_analysis::NumericStateStats>,
+    pub numeric_stats: Option<crate::sensor_analysis::NumericStatsSummary>,
     pub optimal_thresholds: Option<crate::threshold::OptimalThresholds>,
+    /// Score of the thresholds shifted across the value range, with `threshold_sensitivity`
+    #[serde(default)]
+    pub threshold_sensitivity: Option<crate::threshold::ThresholdSensitivity>,
+    /// Set for categorical rows of entities in a pooling group
+    pub pooled: Option<crate::pooling::PooledEstimate>,
+    /// Member states when the row covers several merged states (e.g. `playing-or-paused`)
+    pub grouped_states: Option<Vec<String>>,
+    /// Entities whose behaviour across periods closely tracks this one's
+    #[serde(default)]
+    pub correlated_with: Vec<String>,
+    /// Set when the analysis ran with `crossValidation`
+    pub cross_validation: Option<crate::cross_validation::CrossValidation>,
+    /// Spread over the bootstrap resamples, with `bootstrap`
+    #[serde(default)]
+    pub stability: Option<crate::bootstrap::Stability>,
+    /// Wilson score intervals around the unsmoothed probabilities, treating each
+    /// labeled period of the class as one observation
+    pub prob_given_true_interval: Option<ProbabilityInterval>,
+    pub prob_given_false_interval: Option<ProbabilityInterval>,
+    /// Categorical rows: shares of TRUE/FALSE time spent in the state
+    #[serde(default)]
+    pub duration_shares: Option<ClassShares>,
+    /// Categorical rows: shares of TRUE/FALSE periods the state was seen in
+    #[serde(default)]
+    pub occurrence_shares: Option<ClassShares>,
+    /// Categorical rows: the entity being in any other state
+    #[serde(default)]
+    pub absence: Option<Absence>,
+    /// How each labeled period contributed, with `period_breakdown`
+    #[serde(default)]
+    pub period_breakdown: Option<Vec<crate::breakdown::PeriodContribution>>,
+    /// The time slices behind the row in each labeled period, with `include_chunks`
+    #[serde(default)]
+    pub chunks: Option<Vec<crate::breakdown::PeriodChunks>>,
+    /// The row in a sentence, from `explanations`
+    #[serde(default)]
+    pub explanation: Option<String>,
+    /// Recorded data covers less than `minCoverage` of the labeled time, so the
+    /// probabilities rest on a small sample of it
+    #[serde(default)]
+    pub low_coverage: bool,
+    /// Share of the labeled time the entity's recorded data covers
+    #[serde(default)]
+    pub coverage: Option<f64>,
+    /// The row's state or range was seen for less time or in fewer periods than
+    /// `minEvidence` asks for
+    #[serde(default)]
+    pub low_confidence: bool,
+    /// The entity looks derived from the target itself, so using it would make the
+    /// sensor circular
+    #[serde(default)]
+    pub possible_leakage: bool,
+    #[serde(default)]
+    pub unit_of_measurement: Option<String>,
+    #[serde(default)]
+    pub device_class: Option<String>,
+}
+
+/// Unit and device class an entity reports in its attributes
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct SensorAttributes {
+    pub unit_of_measurement: Option<String>,
+    pub device_class: Option<String>,
+    pub state_class: Option<String>,
+}
+
+impl SensorAttributes {
+    /// Read from the first entry with attributes; HA's compressed history only
+    /// includes them on the first entry
+    pub fn from_history(entity_history: &[HAHistoryEntry]) -> Self {
+        let Some(attributes) = entity_history.iter().find_map(|entry| entry.attributes.as_ref()) else {
+            return Self::default();
+        };
+        let text = |key: &str| attributes.get(key).and_then(|value| value.as_str()).map(str::to_string);
+        Self {
+            unit_of_measurement: text("unit_of_measurement"),
+            device_class: text("device_class"),
+            state_class: text("state_class"),
+        }
+    }
+
+    /// Device classes whose states must not be read as numbers even when they parse
+    /// as one: `enum` options like `1`/`2` and `timestamp`/`date` values
+    pub fn is_categorical(&self) -> bool {
+        matches!(self.device_class.as_deref(), Some("enum" | "timestamp" | "date"))
+    }
+
+    /// Value at which an entity's readings wrap around: 360 for angles in degrees
+    pub fn cycle(&self) -> Option<f64> {
+        let angle = self.unit_of_measurement.as_deref() == Some("°") || self.device_class.as_deref() == Some("wind_direction");
+        angle.then_some(360.0)
+    }
+
+    /// Copy onto a row of the entity; numeric rows also get the unit in their description
+    pub fn apply(&self, row: &mut EntityProbability) {
+        row.unit_of_measurement = self.unit_of_measurement.clone();
+        row.device_class = self.device_class.clone();
+        let has_bound = row
+            .optimal_thresholds
+            .as_ref()
+            .is_some_and(|thresholds| thresholds.above.is_some() || thresholds.below.is_some());
+        if let (Some(unit), true) = (&self.unit_of_measurement, has_bound) {
+            row.state = format!("{} {}", row.state, unit);
+        }
+    }
+}
+
+/// P(not state | TRUE) and P(not state | FALSE) of a categorical row
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct Absence {
+    pub prob_given_true: f64,
+    pub prob_given_false: f64,
+    /// Exported as a `state != X` observation, since absence moves the posterior
+    /// further than presence does (`absence_observations`)
+    pub preferred: bool,
+}
+
+/// Unsmoothed P(state | TRUE) and P(state | FALSE) under one `StateProbability` mode
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ClassShares {
+    pub given_true: f64,
+    pub given_false: f64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct ProbabilityInterval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct EntityMetadata {
+    pub entity_category: Option<String>,
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum WarningKind {
+    EntityFiltered,
+    /// An internal consistency check failed (only reported with `debugValidate`)
+    InvariantViolation,
+    /// A numeric entity's data contradicts its declared monotonic direction
+    MonotonicityViolation,
+    /// The selected observations can never push the posterior over the probability threshold
+    NeverTriggers,
+    /// The selected observations can never bring the posterior back under the probability threshold
+    NeverReleases,
+    /// The posterior usually sits so close to 0 or 1 that no single observation can flip the sensor
+    PosteriorSaturation,
+    /// A row's discrimination collapses when a single labeled period is left out
+    Overfit,
+    /// History entries with unreadable timestamps were skipped
+    InvalidTimestamp,
+    /// TRUE and FALSE periods cover the same time, which then counts towards both
+    OverlappingPeriods,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct AnalysisWarning {
+    pub entity_id: Option<String>,
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+/// Event passed to the `calculate_progressive` callback while preview results are refined
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(tag = "type", rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum RefinementEvent {
+    /// Refined rows replacing every preview row of the entity, with the warnings
+    /// found while refining them
+    Updated {
+        entity_id: String,
+        rows: Vec<EntityProbability>,
+        warnings: Vec<AnalysisWarning>,
+    },
+    /// All entities have been refined
+    Complete,
 }
 
 pub struct SensorChunk {
@@ -46,14 +330,18 @@ pub struct SensorChunk {
 }
 
 #[derive(Debug, Clone)]
-pub struct StateChunk {
-    pub state: String,
+pub struct StateChunk<'a> {
+    pub state: &'a str,
     pub duration: i64,
     pub desired_output: bool,
+    pub period_index: usize,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct StateDurationStats {
     pub true_duration: i64,
     pub false_duration: i64,
-}
\ No newline at end of file
+    /// Number of TRUE/FALSE periods the state was seen in
+    pub true_periods: usize,
+    pub false_periods: usize,
+}
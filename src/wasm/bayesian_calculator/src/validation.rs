@@ -0,0 +1,284 @@
+use crate::error;
+use crate::preprocessing::{self, OverlapPolicy};
+use crate::sensor_analysis;
+use crate::types::{AnalysisWarning, EntityProbability, HAHistoryEntry, TimePeriod, WarningKind};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tsify::Tsify;
+
+/// Whether an input problem stops the analysis or only skews it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum IssueSeverity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub enum InputIssueKind {
+    /// A period or history timestamp cannot be read
+    InvalidTimestamp,
+    /// A period ends before it starts
+    ReversedPeriod,
+    /// A period starts and ends at the same time, so it carries no data
+    EmptyPeriod,
+    NoTruePeriods,
+    NoFalsePeriods,
+    /// A TRUE and a FALSE period cover the same time
+    OverlappingPeriods,
+    /// No entity has any history entries
+    EmptyHistory,
+    /// History entries with a blank state
+    EmptyState,
+    /// A period lies entirely outside the recorded history
+    PeriodWithoutHistory,
+}
+
+/// A problem with the input found before the analysis runs
+#[derive(Debug, Clone, Serialize, Deserialize, Tsify)]
+#[serde(rename_all = "camelCase")]
+#[tsify(into_wasm_abi, from_wasm_abi)]
+pub struct InputIssue {
+    pub severity: IssueSeverity,
+    pub kind: InputIssueKind,
+    pub entity_id: Option<String>,
+    pub period_id: Option<String>,
+    pub message: String,
+}
+
+impl InputIssue {
+    fn period(severity: IssueSeverity, kind: InputIssueKind, period: &TimePeriod, message: String) -> Self {
+        Self {
+            severity,
+            kind,
+            entity_id: None,
+            period_id: Some(period.id.clone()),
+            message,
+        }
+    }
+
+    fn entity(severity: IssueSeverity, kind: InputIssueKind, entity_id: &str, message: String) -> Self {
+        Self {
+            severity,
+            kind,
+            entity_id: Some(entity_id.to_string()),
+            period_id: None,
+            message,
+        }
+    }
+
+    fn general(severity: IssueSeverity, kind: InputIssueKind, message: &str) -> Self {
+        Self {
+            severity,
+            kind,
+            entity_id: None,
+            period_id: None,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Everything about the history and periods that would fail or skew an analysis
+///
+/// Errors are what the analysis rejects or what cannot be meant (a period ending
+/// before it starts); warnings are data it skips or that carries no information.
+/// Periods must already be localized, as the analysis does with `periodTimezone`.
+pub fn check_input(
+    history: &HashMap<String, Vec<HAHistoryEntry>>,
+    periods: &[TimePeriod],
+    overlap: Option<OverlapPolicy>,
+) -> Vec<InputIssue> {
+    use IssueSeverity::{Error, Warning};
+    let mut issues = Vec::new();
+
+    let mut valid_periods = Vec::with_capacity(periods.len());
+    for period in periods {
+        if let Some(timestamp) = [&period.start, &period.end].into_iter().find(|t| !t.is_valid()) {
+            let message = format!("Period {} has an unreadable timestamp '{}'", period.id, error::describe(timestamp));
+            issues.push(InputIssue::period(Error, InputIssueKind::InvalidTimestamp, period, message));
+            continue;
+        }
+        match sensor_analysis::period_length(period) {
+            length if length < 0 => {
+                let message = format!("Period {} ends before it starts", period.id);
+                issues.push(InputIssue::period(Error, InputIssueKind::ReversedPeriod, period, message));
+            }
+            0 => {
+                let message = format!("Period {} has no length", period.id);
+                issues.push(InputIssue::period(Warning, InputIssueKind::EmptyPeriod, period, message));
+            }
+            _ => valid_periods.push(period),
+        }
+    }
+
+    if !periods.iter().any(|p| sensor_analysis::true_weight(p) > 0.0) {
+        issues.push(InputIssue::general(Error, InputIssueKind::NoTruePeriods, "Need at least one TRUE period"));
+    }
+    if !periods.iter().any(|p| sensor_analysis::true_weight(p) < 1.0) {
+        issues.push(InputIssue::general(Error, InputIssueKind::NoFalsePeriods, "Need at least one FALSE period"));
+    }
+
+    let owned: Vec<TimePeriod> = valid_periods.iter().map(|&period| period.clone()).collect();
+    for (start, end) in preprocessing::period_overlaps(&owned) {
+        let severity = if overlap == Some(OverlapPolicy::Reject) { Error } else { Warning };
+        let (true_period, false_period) = preprocessing::periods_at(&owned, start).unwrap_or_default();
+        issues.push(InputIssue {
+            severity,
+            kind: InputIssueKind::OverlappingPeriods,
+            entity_id: None,
+            period_id: Some(true_period.clone()),
+            message: format!(
+                "TRUE period {} overlaps FALSE period {} for {} minutes",
+                true_period,
+                false_period,
+                (end - start) / 60_000
+            ),
+        });
+    }
+
+    let mut entity_ids: Vec<&String> = history.keys().collect();
+    entity_ids.sort();
+    let mut first_change: Option<i64> = None;
+    for entity_id in entity_ids {
+        let entries = &history[entity_id];
+        let unreadable = entries.iter().filter(|entry| !entry.last_changed.is_valid()).count();
+        if unreadable > 0 {
+            let message = format!("{} history entries of {} have unreadable timestamps and will be skipped", unreadable, entity_id);
+            issues.push(InputIssue::entity(Warning, InputIssueKind::InvalidTimestamp, entity_id, message));
+        }
+        let blank = entries.iter().filter(|entry| entry.state.trim().is_empty()).count();
+        if blank > 0 {
+            let message = format!("{} history entries of {} have an empty state", blank, entity_id);
+            issues.push(InputIssue::entity(Warning, InputIssueKind::EmptyState, entity_id, message));
+        }
+        let first = entries.iter().filter(|entry| entry.last_changed.is_valid()).map(|entry| entry.last_changed.millis()).min();
+        first_change = first_change.into_iter().chain(first).min();
+    }
+
+    if let Some(first_change) = first_change {
+        // A state holds until the next change, so only periods ending before the first one are uncovered
+        for period in valid_periods.into_iter().filter(|period| period.end.millis() <= first_change) {
+            let message = format!("Period {} ends before the first recorded history entry", period.id);
+            issues.push(InputIssue::period(Warning, InputIssueKind::PeriodWithoutHistory, period, message));
+        }
+    } else {
+        issues.push(InputIssue::general(Error, InputIssueKind::EmptyHistory, "History has no entries"));
+    }
+
+    issues
+}
+
+/// Check the time the statistics' sweep credited to each period
+///
+/// A period's TRUE and FALSE time together can be at most its length, and a
+/// hard-labeled period only credits its own class.
+pub fn check_period_durations(
+    entity_id: &str,
+    periods: &[TimePeriod],
+    durations: &[(i64, i64)],
+) -> Vec<AnalysisWarning> {
+    let mut violations = Vec::new();
+
+    for (period, &(true_duration, false_duration)) in periods.iter().zip(durations) {
+        let length = sensor_analysis::period_length(period);
+        if true_duration < 0 || false_duration < 0 {
+            violations.push(violation(entity_id, format!(
+                "period {} was credited negative time ({} ms TRUE, {} ms FALSE)",
+                period.id, true_duration, false_duration
+            )));
+        }
+        if true_duration + false_duration > length {
+            violations.push(violation(entity_id, format!(
+                "durations in period {} sum to {} ms but the period lasts {} ms",
+                period.id,
+                true_duration + false_duration,
+                length
+            )));
+        }
+        if period.confidence.is_none() {
+            let other_class = if period.is_true_period { false_duration } else { true_duration };
+            if other_class != 0 {
+                violations.push(violation(entity_id, format!(
+                    "{} period {} credited {} ms to the other class",
+                    if period.is_true_period { "TRUE" } else { "FALSE" },
+                    period.id,
+                    other_class
+                )));
+            }
+        }
+    }
+
+    violations
+}
+
+/// Check numeric chunk durations never exceed the labeled time they were taken from
+pub fn check_numeric_stats(
+    entity_id: &str,
+    stats: &sensor_analysis::NumericStateStats,
+    periods: &[TimePeriod],
+) -> Vec<AnalysisWarning> {
+    let mut violations = Vec::new();
+
+    for is_true in [true, false] {
+        let chunks = if is_true { &stats.true_chunks } else { &stats.false_chunks };
+        let chunk_total: i64 = chunks.iter().map(|c| c.duration).sum();
+        let period_total: i64 = periods
+            .iter()
+            .map(|p| {
+                let (true_part, false_part) =
+                    sensor_analysis::split_duration(sensor_analysis::period_length(p), sensor_analysis::true_weight(p));
+                if is_true { true_part } else { false_part }
+            })
+            .sum();
+
+        if chunks.iter().any(|c| c.duration <= 0) {
+            violations.push(violation(entity_id, "numeric chunk with non-positive duration".to_string()));
+        }
+
+        // Soft-labeled chunks are split one by one, so allow a millisecond of rounding each
+        if chunk_total > period_total + chunks.len() as i64 {
+            violations.push(violation(entity_id, format!(
+                "{} chunks cover {} ms but the {} periods only last {} ms",
+                if is_true { "TRUE" } else { "FALSE" },
+                chunk_total,
+                if is_true { "TRUE" } else { "FALSE" },
+                period_total
+            )));
+        }
+    }
+
+    violations
+}
+
+/// Check a result row's probabilities are finite and within [0, 1]
+pub fn check_result(row: &EntityProbability) -> Vec<AnalysisWarning> {
+    let mut violations = Vec::new();
+
+    let fields = [
+        ("probGivenTrue", row.prob_given_true),
+        ("probGivenFalse", row.prob_given_false),
+        ("discriminationPower", row.discrimination_power),
+    ];
+
+    for (name, value) in fields {
+        if !value.is_finite() || !(0.0..=1.0).contains(&value) {
+            violations.push(violation(&row.entity_id, format!(
+                "{} for state '{}' is out of bounds: {}",
+                name, row.state, value
+            )));
+        }
+    }
+
+    violations
+}
+
+fn violation(entity_id: &str, message: String) -> AnalysisWarning {
+    AnalysisWarning {
+        entity_id: Some(entity_id.to_string()),
+        kind: WarningKind::InvariantViolation,
+        message,
+    }
+}
@@ -1,20 +1,22 @@
-import type { TimePeriod, EntityProbability } from '../types/bayesian'
+import type { TimePeriod, EntityProbability, NumericStateStats } from '../types/bayesian'
 import type { HAHistoryResponse } from '../types/homeAssistant'
 import { initWasm, WasmBayesianCalculator } from '../wasm/bayesianCalculatorWasm'
 
 interface WorkerMessage {
-  type: 'ANALYZE_ENTITY' | 'INIT_WASM'
+  type: 'ANALYZE_ENTITY' | 'GET_NUMERIC_STATS' | 'INIT_WASM'
   id: string
   data?: {
     entityHistory: HAHistoryResponse
     periods: TimePeriod[]
+    // Entity whose chunk data GET_NUMERIC_STATS returns
+    entityId?: string
   }
 }
 
 interface WorkerResponse {
   type: 'ANALYSIS_RESULT' | 'ANALYSIS_ERROR' | 'ANALYSIS_PROGRESS' | 'WASM_INITIALIZED'
   id: string
-  data?: EntityProbability[]
+  data?: EntityProbability[] | NumericStateStats | null
   error?: string
   progress?: {
     entityId: string
@@ -25,6 +27,8 @@ interface WorkerResponse {
 
 let wasmCalculator: WasmBayesianCalculator | null = null
 let isWasmInitialized = false
+// Input of the calculation the calculator currently holds statistics for
+let lastAnalysis: { entityHistory: HAHistoryResponse; periods: TimePeriod[] } | null = null
 
 self.onmessage = async function(e: MessageEvent<WorkerMessage>) {
   const { type, data, id } = e.data
@@ -50,6 +54,7 @@ self.onmessage = async function(e: MessageEvent<WorkerMessage>) {
       
       const { entityHistory, periods } = data!
       const result = wasmCalculator.calculateEntityProbabilities(entityHistory, periods)
+      lastAnalysis = { entityHistory, periods }
       
       const response: WorkerResponse = {
         type: 'ANALYSIS_RESULT',
@@ -57,6 +62,29 @@ self.onmessage = async function(e: MessageEvent<WorkerMessage>) {
         data: result
       }
       
+      self.postMessage(response)
+    } else if (type === 'GET_NUMERIC_STATS') {
+      if (!wasmCalculator) {
+        throw new Error('WASM not initialized. Send INIT_WASM message first.')
+      }
+
+      // Another worker may have analysed the entity, or this one moved on since;
+      // re-analyse it then, which reuses the cached thresholds where it can
+      const { entityHistory, periods, entityId } = data!
+      const analysed = lastAnalysis !== null &&
+        entityId! in lastAnalysis.entityHistory &&
+        JSON.stringify(lastAnalysis.periods) === JSON.stringify(periods)
+      if (!analysed) {
+        wasmCalculator.calculateEntityProbabilities(entityHistory, periods)
+        lastAnalysis = { entityHistory, periods }
+      }
+
+      const response: WorkerResponse = {
+        type: 'ANALYSIS_RESULT',
+        id,
+        data: wasmCalculator.getNumericStats(entityId!)
+      }
+
       self.postMessage(response)
     }
   } catch (error) {